@@ -0,0 +1,50 @@
+#version 450
+
+#define MAX_LIGHTS 32
+
+layout(location = 0) in vec3 oPosition;
+layout(location = 1) in vec3 oNormal;
+layout(location = 2) in vec4 oColor;
+
+struct Light {
+    vec4 positionAndRadius;
+    vec4 color;
+};
+
+layout(binding = 1, set = 0) uniform LightsUbo {
+    vec4 cameraPosition;
+    vec4 ambient;
+    uint count;
+    Light lights[MAX_LIGHTS];
+} lightsUbo;
+
+layout(location = 0) out vec4 finalColor;
+
+void main() {
+    vec3 normal = normalize(oNormal);
+    // transparent surfaces are visible from both sides
+    if (!gl_FrontFacing) {
+        normal = -normal;
+    }
+
+    vec3 color = oColor.rgb * lightsUbo.ambient.rgb;
+    for (uint i = 0; i < lightsUbo.count; i++) {
+        Light light = lightsUbo.lights[i];
+
+        vec3 toLight = light.positionAndRadius.xyz - oPosition;
+        float distance = length(toLight);
+        float radius = light.positionAndRadius.w;
+        if (distance > radius) {
+            continue;
+        }
+
+        vec3 lightDir = toLight / distance;
+        float attenuation = pow(clamp(1.0 - distance / radius, 0.0, 1.0), 2.0);
+
+        float diffuse = max(dot(normal, lightDir), 0.0);
+
+        color += oColor.rgb * diffuse * light.color.rgb * attenuation;
+    }
+
+    finalColor = vec4(color, oColor.a);
+}
@@ -0,0 +1,10 @@
+#version 450
+
+layout(location = 0) out vec2 oUV;
+
+// Single triangle covering the whole screen, no vertex buffer required
+void main() {
+    oUV = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+
+    gl_Position = vec4(oUV * 2.0 - 1.0, 1.0, 1.0);
+}
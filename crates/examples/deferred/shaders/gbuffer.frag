@@ -0,0 +1,15 @@
+#version 450
+
+layout(location = 0) in vec3 oPosition;
+layout(location = 1) in vec3 oNormal;
+layout(location = 2) in vec4 oColor;
+
+layout(location = 0) out vec4 outAlbedo;
+layout(location = 1) out vec4 outNormal;
+layout(location = 2) out vec4 outPosition;
+
+void main() {
+    outAlbedo = vec4(oColor.rgb, 1.0);
+    outNormal = vec4(normalize(oNormal), 0.0);
+    outPosition = vec4(oPosition, 1.0);
+}
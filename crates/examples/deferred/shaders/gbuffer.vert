@@ -0,0 +1,21 @@
+#version 450
+
+layout(location = 0) in vec3 vPosition;
+layout(location = 1) in vec3 vNormal;
+layout(location = 2) in vec4 vColor;
+
+layout(binding = 0, set = 0) uniform FrameUbo {
+    mat4 projectionViewMatrix;
+} frameUbo;
+
+layout(location = 0) out vec3 oPosition;
+layout(location = 1) out vec3 oNormal;
+layout(location = 2) out vec4 oColor;
+
+void main() {
+    oPosition = vPosition;
+    oNormal = vNormal;
+    oColor = vColor;
+
+    gl_Position = frameUbo.projectionViewMatrix * vec4(vPosition, 1.0);
+}
@@ -0,0 +1,58 @@
+#version 450
+
+#define MAX_LIGHTS 32
+
+layout(location = 0) in vec2 oUV;
+
+struct Light {
+    vec4 positionAndRadius;
+    vec4 color;
+};
+
+layout(binding = 0, set = 0) uniform sampler2D albedoBuffer;
+layout(binding = 1, set = 0) uniform sampler2D normalBuffer;
+layout(binding = 2, set = 0) uniform sampler2D positionBuffer;
+layout(binding = 3, set = 0) uniform LightsUbo {
+    vec4 cameraPosition;
+    vec4 ambient;
+    uint count;
+    Light lights[MAX_LIGHTS];
+} lightsUbo;
+
+layout(location = 0) out vec4 finalColor;
+
+void main() {
+    vec4 position = texture(positionBuffer, oUV);
+
+    // nothing was rendered there during the gbuffer pass
+    if (position.w == 0.0) {
+        finalColor = vec4(lightsUbo.ambient.rgb, 1.0);
+        return;
+    }
+
+    vec3 albedo = texture(albedoBuffer, oUV).rgb;
+    vec3 normal = normalize(texture(normalBuffer, oUV).xyz);
+    vec3 viewDir = normalize(lightsUbo.cameraPosition.xyz - position.xyz);
+
+    vec3 color = albedo * lightsUbo.ambient.rgb;
+    for (uint i = 0; i < lightsUbo.count; i++) {
+        Light light = lightsUbo.lights[i];
+
+        vec3 toLight = light.positionAndRadius.xyz - position.xyz;
+        float distance = length(toLight);
+        float radius = light.positionAndRadius.w;
+        if (distance > radius) {
+            continue;
+        }
+
+        vec3 lightDir = toLight / distance;
+        float attenuation = pow(clamp(1.0 - distance / radius, 0.0, 1.0), 2.0);
+
+        float diffuse = max(dot(normal, lightDir), 0.0);
+        float specular = pow(max(dot(normal, normalize(lightDir + viewDir)), 0.0), 32.0);
+
+        color += (albedo * diffuse + specular * 0.5) * light.color.rgb * attenuation;
+    }
+
+    finalColor = vec4(color, 1.0);
+}
@@ -0,0 +1,1056 @@
+use std::mem::{offset_of, size_of};
+use std::time::Duration;
+
+use app::anyhow::Result;
+use app::glam::{vec3, Mat4, Vec3};
+use app::vulkan::ash::vk::{self, PipelineBindPoint};
+use app::vulkan::gpu_allocator::MemoryLocation;
+use app::vulkan::utils::create_gpu_only_buffer_from_data;
+use app::vulkan::{
+    Buffer, ClearValue, ColorAttachmentsInfo, CommandBuffer, Context, DepthInfo, DepthTexture,
+    DescriptorPool, DescriptorSet, DescriptorSetLayout, EmptyVertex, GraphicsPipeline,
+    GraphicsPipelineCreateInfo, GraphicsShaderCreateInfo, Image, ImageBarrier, ImageView,
+    PipelineCache, PipelineLayout, RenderingAttachment, Sampler, TimestampQueryPool,
+    WriteDescriptorSet, WriteDescriptorSetKind,
+};
+use app::winit::event::{ElementState, KeyEvent, WindowEvent};
+use app::winit::keyboard::{KeyCode, PhysicalKey};
+use app::{App, AppConfig, BaseApp, SwapchainChange, TurntableConfig};
+use gui::egui;
+
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1080;
+const APP_NAME: &str = "Deferred shading";
+
+const MAX_LIGHTS: usize = 32;
+
+const ALBEDO_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+const NORMAL_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+const POSITION_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
+const UI_FONT_NAME: &str = "mplus-1p";
+const UI_FONT_PATH: &str = "assets/fonts/mplus-1p-regular.ttf";
+
+// Timestamps written around each pass
+const GBUFFER_PASS_BEGIN: u32 = 0;
+const GBUFFER_PASS_END: u32 = 1;
+const LIGHTING_PASS_END: u32 = 2;
+const FORWARD_PASS_END: u32 = 3;
+const TIMESTAMP_COUNT: usize = 4;
+
+fn main() -> Result<()> {
+    app::run::<Deferred>(
+        APP_NAME,
+        WIDTH,
+        HEIGHT,
+        AppConfig {
+            turntable: Some(TurntableConfig {
+                idle_timeout: Duration::from_secs(10),
+                center: Vec3::ZERO,
+                radius: 8.0,
+                speed: 0.2,
+            }),
+            ..Default::default()
+        },
+    )
+}
+
+struct Deferred {
+    frame_ubo: Buffer,
+    lights_ubo: Buffer,
+    opaque_vertex_buffer: Buffer,
+    opaque_vertex_count: u32,
+    transparent_vertex_buffer: Buffer,
+    transparent_vertex_count: u32,
+
+    gbuffer: GBuffer,
+    gbuffer_pass: Pass,
+    lighting_pass: Pass,
+    forward_pass: Pass,
+
+    // one pool per swapchain image since that's how command buffers are allocated
+    timing_query_pools: Vec<TimestampQueryPool<TIMESTAMP_COUNT>>,
+    timings_available: Vec<bool>,
+
+    time: f32,
+    // set by the pause key and applied to the gui state on the next update
+    toggle_lights_animation: bool,
+}
+
+impl App for Deferred {
+    type Gui = Gui;
+
+    fn new(base: &mut BaseApp) -> Result<Self> {
+        // UI text and the monospace values of the stats overlay use the bundled M+ font,
+        // falling back to egui's default fonts
+        base.gui_context.add_font(
+            UI_FONT_NAME,
+            &std::fs::read(UI_FONT_PATH)?,
+            egui::FontFamily::Proportional,
+        );
+        base.gui_context
+            .prioritize_font(egui::FontFamily::Proportional, UI_FONT_NAME)?;
+        base.gui_context
+            .prioritize_font(egui::FontFamily::Monospace, UI_FONT_NAME)?;
+
+        let context = &mut base.context;
+        base.camera.position = vec3(0.0, 4.0, 8.0);
+        base.camera.direction = vec3(0.0, -0.5, -1.0).normalize();
+        base.camera.z_far = 50.0;
+
+        let frame_ubo = context.create_buffer(
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            MemoryLocation::CpuToGpu,
+            size_of::<FrameUbo>() as _,
+        )?;
+        let lights_ubo = context.create_buffer(
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            MemoryLocation::CpuToGpu,
+            size_of::<LightsUbo>() as _,
+        )?;
+
+        let (opaque_vertices, transparent_vertices) = create_scene_geometry();
+        let opaque_vertex_buffer = create_gpu_only_buffer_from_data(
+            context,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            &opaque_vertices,
+        )?;
+        let transparent_vertex_buffer = create_gpu_only_buffer_from_data(
+            context,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            &transparent_vertices,
+        )?;
+
+        let gbuffer = GBuffer::new(context, base.swapchain.extent)?;
+        let gbuffer_pass = create_gbuffer_pass(context, &base.pipeline_cache, &frame_ubo)?;
+        let lighting_pass = create_lighting_pass(
+            context,
+            &base.pipeline_cache,
+            &gbuffer,
+            &lights_ubo,
+            base.swapchain.format,
+        )?;
+        let forward_pass = create_forward_pass(
+            context,
+            &base.pipeline_cache,
+            &frame_ubo,
+            &lights_ubo,
+            base.swapchain.format,
+        )?;
+
+        let timing_query_pools = create_timing_query_pools(context, base.swapchain.images.len())?;
+        let timings_available = vec![false; timing_query_pools.len()];
+
+        Ok(Self {
+            frame_ubo,
+            lights_ubo,
+            opaque_vertex_buffer,
+            opaque_vertex_count: opaque_vertices.len() as _,
+            transparent_vertex_buffer,
+            transparent_vertex_count: transparent_vertices.len() as _,
+
+            gbuffer,
+            gbuffer_pass,
+            lighting_pass,
+            forward_pass,
+
+            timing_query_pools,
+            timings_available,
+
+            time: 0.0,
+            toggle_lights_animation: false,
+        })
+    }
+
+    fn on_window_event(&mut self, _: &mut BaseApp, event: &WindowEvent) {
+        if let WindowEvent::KeyboardInput {
+            event:
+                KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::KeyP),
+                    repeat: false,
+                    ..
+                },
+            ..
+        } = event
+        {
+            self.toggle_lights_animation = !self.toggle_lights_animation;
+        }
+    }
+
+    fn on_recreate_swapchain(&mut self, base: &BaseApp, _: SwapchainChange) -> Result<()> {
+        self.gbuffer = GBuffer::new(&base.context, base.swapchain.extent)?;
+        update_lighting_descriptor_set(
+            &self.lighting_pass.descriptor_set,
+            &self.gbuffer,
+            &self.lights_ubo,
+        );
+
+        self.timing_query_pools =
+            create_timing_query_pools(&base.context, base.swapchain.images.len())?;
+        self.timings_available = vec![false; self.timing_query_pools.len()];
+
+        Ok(())
+    }
+
+    fn update(
+        &mut self,
+        base: &mut BaseApp,
+        gui: &mut <Self as App>::Gui,
+        image_index: usize,
+        delta_time: Duration,
+    ) -> Result<()> {
+        base.camera.move_speed = gui.camera_speed;
+        base.camera.look_sensitivity = gui.look_sensitivity;
+
+        // The command buffer for this image is about to be re-recorded so the
+        // previous submission (and its queries) is complete.
+        if self.timings_available[image_index] {
+            let pool = &self.timing_query_pools[image_index];
+            let timestamps = pool.wait_for_all_results()?;
+            let elapsed = |from: u32, to: u32| {
+                pool.elapsed(timestamps[from as usize], timestamps[to as usize])
+            };
+
+            gui.timings = Some(PassTimings {
+                gbuffer: elapsed(GBUFFER_PASS_BEGIN, GBUFFER_PASS_END),
+                lighting: elapsed(GBUFFER_PASS_END, LIGHTING_PASS_END),
+                forward: elapsed(LIGHTING_PASS_END, FORWARD_PASS_END),
+            });
+        }
+        self.timings_available[image_index] = true;
+
+        if std::mem::take(&mut self.toggle_lights_animation) {
+            gui.animate_lights = !gui.animate_lights;
+        }
+        if gui.animate_lights {
+            self.time += delta_time.as_secs_f32();
+        }
+
+        self.frame_ubo.copy_data_to_buffer(&[FrameUbo {
+            view_proj_matrix: base.camera.projection_matrix() * base.camera.view_matrix(),
+        }])?;
+
+        let mut lights = [Light::default(); MAX_LIGHTS];
+        for (i, light) in lights.iter_mut().take(gui.light_count as _).enumerate() {
+            let ratio = i as f32 / MAX_LIGHTS as f32;
+            let angle = self.time * (0.3 + ratio * 0.4) + ratio * std::f32::consts::TAU * 3.0;
+            let distance = 1.0 + 4.0 * ((i * 7) % MAX_LIGHTS) as f32 / MAX_LIGHTS as f32;
+
+            light.position_and_radius = [
+                angle.cos() * distance,
+                0.5 + (self.time + i as f32).sin().abs() * 0.5,
+                angle.sin() * distance,
+                gui.light_radius,
+            ];
+            light.color = hue_to_rgb(ratio);
+        }
+
+        let camera_position = base.camera.position;
+        self.lights_ubo.copy_data_to_buffer(&[LightsUbo {
+            camera_position: [camera_position.x, camera_position.y, camera_position.z, 1.0],
+            ambient: [0.05, 0.05, 0.05, 1.0],
+            count: gui.light_count,
+            _padding: [0; 3],
+            lights,
+        }])?;
+
+        Ok(())
+    }
+
+    fn record_raster_commands(
+        &self,
+        base: &BaseApp,
+        buffer: &CommandBuffer,
+        image_index: usize,
+    ) -> Result<()> {
+        let timing_query_pool = &self.timing_query_pools[image_index];
+
+        buffer.reset_all_timestamp_queries_from_pool(timing_query_pool);
+        buffer.write_timestamp(
+            vk::PipelineStageFlags2::TOP_OF_PIPE,
+            timing_query_pool,
+            GBUFFER_PASS_BEGIN,
+        );
+
+        // Same passes as the timestamps above, nested in a scene scope for the Full stats panel
+        base.push_gpu_timer(buffer, "scene");
+        base.push_gpu_timer(buffer, "gbuffer");
+
+        // gbuffer pass
+        //
+        // The previous frame's lighting pass may still be sampling the gbuffer
+        // and its forward pass may still be testing against the depth buffer.
+        // Content is discarded since everything is cleared.
+        let gbuffer_color_barrier = |image| ImageBarrier {
+            image,
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            src_access_mask: vk::AccessFlags2::SHADER_READ,
+            dst_access_mask: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            src_stage_mask: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            dst_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        };
+        buffer.pipeline_image_barriers(&[
+            gbuffer_color_barrier(&self.gbuffer.albedo.image),
+            gbuffer_color_barrier(&self.gbuffer.normal.image),
+            gbuffer_color_barrier(&self.gbuffer.position.image),
+            ImageBarrier {
+                image: &self.gbuffer.depth.image,
+                old_layout: vk::ImageLayout::UNDEFINED,
+                new_layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                src_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ,
+                dst_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                src_stage_mask: vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                dst_stage_mask: vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+            },
+        ]);
+
+        buffer.begin_rendering(
+            &[
+                RenderingAttachment::clear(
+                    &self.gbuffer.albedo.view,
+                    ClearValue::ColorFloat([0.0; 4]),
+                ),
+                RenderingAttachment::clear(
+                    &self.gbuffer.normal.view,
+                    ClearValue::ColorFloat([0.0; 4]),
+                ),
+                // w = 0 marks pixels not covered by any geometry
+                RenderingAttachment::clear(
+                    &self.gbuffer.position.view,
+                    ClearValue::ColorFloat([0.0; 4]),
+                ),
+            ],
+            Some(RenderingAttachment::clear(
+                &self.gbuffer.depth.view,
+                ClearValue::Depth(1.0),
+            )),
+            base.swapchain.extent,
+        );
+
+        buffer.bind_graphics_pipeline(&self.gbuffer_pass.pipeline);
+        buffer.bind_vertex_buffer(&self.opaque_vertex_buffer);
+        buffer.set_viewport(base.swapchain.extent);
+        buffer.set_scissor(base.swapchain.extent);
+        buffer.bind_descriptor_sets(
+            PipelineBindPoint::GRAPHICS,
+            &self.gbuffer_pass.pipeline_layout,
+            0,
+            &[&self.gbuffer_pass.descriptor_set],
+        );
+        buffer.draw(self.opaque_vertex_count);
+
+        buffer.end_rendering();
+
+        buffer.write_timestamp(
+            vk::PipelineStageFlags2::ALL_GRAPHICS,
+            timing_query_pool,
+            GBUFFER_PASS_END,
+        );
+        base.pop_gpu_timer(buffer);
+        base.push_gpu_timer(buffer, "lighting");
+
+        // lighting pass
+        //
+        // Gbuffer color attachments become shader inputs. The depth buffer stays
+        // an attachment but the forward pass must see the gbuffer pass writes.
+        let gbuffer_read_barrier = |image| ImageBarrier {
+            image,
+            old_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            src_access_mask: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags2::SHADER_READ,
+            src_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            dst_stage_mask: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+        };
+        buffer.pipeline_image_barriers(&[
+            gbuffer_read_barrier(&self.gbuffer.albedo.image),
+            gbuffer_read_barrier(&self.gbuffer.normal.image),
+            gbuffer_read_barrier(&self.gbuffer.position.image),
+            ImageBarrier {
+                image: &self.gbuffer.depth.image,
+                old_layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                new_layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                src_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ,
+                src_stage_mask: vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                dst_stage_mask: vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+            },
+        ]);
+
+        buffer.begin_rendering(
+            &[RenderingAttachment::dont_care(
+                &base.swapchain.views[image_index],
+            )],
+            None,
+            base.swapchain.extent,
+        );
+
+        buffer.bind_graphics_pipeline(&self.lighting_pass.pipeline);
+        buffer.set_viewport(base.swapchain.extent);
+        buffer.set_scissor(base.swapchain.extent);
+        buffer.bind_descriptor_sets(
+            PipelineBindPoint::GRAPHICS,
+            &self.lighting_pass.pipeline_layout,
+            0,
+            &[&self.lighting_pass.descriptor_set],
+        );
+        buffer.draw(3);
+
+        buffer.end_rendering();
+
+        buffer.write_timestamp(
+            vk::PipelineStageFlags2::ALL_GRAPHICS,
+            timing_query_pool,
+            LIGHTING_PASS_END,
+        );
+        base.pop_gpu_timer(buffer);
+        base.push_gpu_timer(buffer, "forward");
+
+        // forward pass
+        //
+        // Transparent geometry is blended on top of the lit image. Color
+        // writes from different render passes are not ordered so we need
+        // a barrier even if the layout does not change.
+        buffer.pipeline_image_barriers(&[ImageBarrier {
+            image: &base.swapchain.images[image_index],
+            old_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            src_access_mask: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags2::COLOR_ATTACHMENT_READ
+                | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            src_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            dst_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        }]);
+
+        buffer.begin_rendering(
+            &[RenderingAttachment::load(
+                &base.swapchain.views[image_index],
+            )],
+            Some(
+                RenderingAttachment::load(&self.gbuffer.depth.view)
+                    .store_op(vk::AttachmentStoreOp::DONT_CARE),
+            ),
+            base.swapchain.extent,
+        );
+
+        buffer.bind_graphics_pipeline(&self.forward_pass.pipeline);
+        buffer.bind_vertex_buffer(&self.transparent_vertex_buffer);
+        buffer.set_viewport(base.swapchain.extent);
+        buffer.set_scissor(base.swapchain.extent);
+        buffer.bind_descriptor_sets(
+            PipelineBindPoint::GRAPHICS,
+            &self.forward_pass.pipeline_layout,
+            0,
+            &[&self.forward_pass.descriptor_set],
+        );
+        buffer.draw(self.transparent_vertex_count);
+
+        buffer.end_rendering();
+
+        buffer.write_timestamp(
+            vk::PipelineStageFlags2::ALL_GRAPHICS,
+            timing_query_pool,
+            FORWARD_PASS_END,
+        );
+        base.pop_gpu_timer(buffer);
+        base.pop_gpu_timer(buffer);
+
+        Ok(())
+    }
+}
+
+struct Gui {
+    light_count: u32,
+    light_radius: f32,
+    animate_lights: bool,
+    camera_speed: f32,
+    look_sensitivity: f32,
+    timings: Option<PassTimings>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PassTimings {
+    gbuffer: Duration,
+    lighting: Duration,
+    forward: Duration,
+}
+
+impl app::Gui for Gui {
+    fn new(base: &BaseApp) -> Result<Self> {
+        Ok(Self {
+            light_count: 16,
+            light_radius: 3.0,
+            animate_lights: true,
+            camera_speed: base.camera.move_speed,
+            look_sensitivity: base.camera.look_sensitivity,
+            timings: None,
+        })
+    }
+
+    fn build(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Deferred")
+            .default_open(true)
+            .show(ctx, |ui| {
+                ui.add(
+                    egui::Slider::new(&mut self.light_count, 0..=MAX_LIGHTS as u32).text("Lights"),
+                );
+                ui.add(egui::Slider::new(&mut self.light_radius, 0.5..=10.0).text("Light radius"));
+                ui.checkbox(&mut self.animate_lights, "Animate lights");
+
+                ui.separator();
+                ui.add(egui::Slider::new(&mut self.camera_speed, 0.5..=20.0).text("Camera speed"));
+                ui.add(
+                    egui::Slider::new(&mut self.look_sensitivity, 0.0005..=0.01)
+                        .text("Look sensitivity"),
+                );
+
+                ui.separator();
+                ui.label("GPU pass timings");
+                match self.timings {
+                    Some(timings) => {
+                        ui.label(format!("gbuffer - {:?}", timings.gbuffer));
+                        ui.label(format!("lighting - {:?}", timings.lighting));
+                        ui.label(format!("forward - {:?}", timings.forward));
+                    }
+                    None => {
+                        ui.label("Waiting for results...");
+                    }
+                }
+            });
+    }
+}
+
+struct GBuffer {
+    albedo: Texture,
+    normal: Texture,
+    position: Texture,
+    depth: DepthTexture,
+}
+
+impl GBuffer {
+    fn new(context: &Context, extent: vk::Extent2D) -> Result<Self> {
+        let color_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED;
+
+        let albedo = Texture::create_framebuffer(
+            context,
+            color_usage,
+            extent,
+            ALBEDO_FORMAT,
+            vk::ImageAspectFlags::COLOR,
+        )?;
+        let normal = Texture::create_framebuffer(
+            context,
+            color_usage,
+            extent,
+            NORMAL_FORMAT,
+            vk::ImageAspectFlags::COLOR,
+        )?;
+        let position = Texture::create_framebuffer(
+            context,
+            color_usage,
+            extent,
+            POSITION_FORMAT,
+            vk::ImageAspectFlags::COLOR,
+        )?;
+        // sampleable so later passes could read scene depth
+        let depth =
+            context.create_depth_texture(DEPTH_FORMAT, extent.width, extent.height, None)?;
+
+        Ok(Self {
+            albedo,
+            normal,
+            position,
+            depth,
+        })
+    }
+}
+
+struct Texture {
+    image: Image,
+    view: ImageView,
+    sampler: Sampler,
+}
+
+impl Texture {
+    fn create_framebuffer(
+        context: &Context,
+        usage: vk::ImageUsageFlags,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        aspect_mask: vk::ImageAspectFlags,
+    ) -> Result<Self> {
+        let image = context.create_image(
+            usage,
+            MemoryLocation::GpuOnly,
+            format,
+            extent.width,
+            extent.height,
+            1,
+        )?;
+
+        let view = image.create_image_view(aspect_mask)?;
+
+        let sampler = context.create_sampler(&Default::default())?;
+
+        Ok(Self {
+            image,
+            view,
+            sampler,
+        })
+    }
+
+    fn sampler_write(&self, binding: u32) -> WriteDescriptorSet<'_> {
+        WriteDescriptorSet {
+            binding,
+            kind: WriteDescriptorSetKind::CombinedImageSampler {
+                view: &self.view,
+                sampler: &self.sampler,
+                layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            },
+        }
+    }
+}
+
+struct Pass {
+    _dsl: DescriptorSetLayout,
+    _descriptor_pool: DescriptorPool,
+    descriptor_set: DescriptorSet,
+    pipeline_layout: PipelineLayout,
+    pipeline: GraphicsPipeline,
+}
+
+fn create_timing_query_pools(
+    context: &Context,
+    count: usize,
+) -> Result<Vec<TimestampQueryPool<TIMESTAMP_COUNT>>> {
+    (0..count)
+        .map(|_| context.create_timestamp_query_pool())
+        .collect()
+}
+
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+#[repr(C)]
+struct FrameUbo {
+    view_proj_matrix: Mat4,
+}
+
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+#[repr(C)]
+struct LightsUbo {
+    camera_position: [f32; 4],
+    ambient: [f32; 4],
+    count: u32,
+    _padding: [u32; 3],
+    lights: [Light; MAX_LIGHTS],
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+#[allow(dead_code)]
+#[repr(C)]
+struct Light {
+    position_and_radius: [f32; 4],
+    color: [f32; 4],
+}
+
+fn hue_to_rgb(hue: f32) -> [f32; 4] {
+    let h = hue * 6.0;
+    let r = (h - 3.0).abs() - 1.0;
+    let g = 2.0 - (h - 2.0).abs();
+    let b = 2.0 - (h - 4.0).abs();
+
+    [r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0), 1.0]
+}
+
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+#[repr(C)]
+struct Vertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+    color: [f32; 4],
+}
+
+impl app::vulkan::Vertex for Vertex {
+    fn bindings() -> Vec<vk::VertexInputBindingDescription> {
+        vec![vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: size_of::<Vertex>() as _,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }]
+    }
+
+    fn attributes() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![
+            vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: offset_of!(Vertex, position) as _,
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: 1,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: offset_of!(Vertex, normal) as _,
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: 2,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: offset_of!(Vertex, color) as _,
+            },
+        ]
+    }
+}
+
+fn create_scene_geometry() -> (Vec<Vertex>, Vec<Vertex>) {
+    let mut opaque = vec![];
+
+    // floor
+    push_quad(
+        &mut opaque,
+        Vec3::ZERO,
+        Vec3::Y,
+        Vec3::Z * 6.0,
+        Vec3::X * 6.0,
+        [0.8, 0.8, 0.8, 1.0],
+    );
+
+    // grid of cubes
+    for x in -2..=2 {
+        for z in -2..=2 {
+            let height = 0.25 + 0.15 * ((x + z) as f32).abs();
+            push_box(
+                &mut opaque,
+                vec3(x as f32 * 1.5, height, z as f32 * 1.5),
+                vec3(0.3, height, 0.3),
+                [0.9, 0.9, 0.9, 1.0],
+            );
+        }
+    }
+
+    // transparent panes
+    let mut transparent = vec![];
+    let panes = [
+        (vec3(-2.25, 0.6, 0.75), [1.0, 0.2, 0.2, 0.4]),
+        (vec3(0.0, 0.6, 2.25), [0.2, 1.0, 0.2, 0.4]),
+        (vec3(2.25, 0.6, 0.75), [0.2, 0.2, 1.0, 0.4]),
+    ];
+    for (center, color) in panes {
+        push_quad(
+            &mut transparent,
+            center,
+            Vec3::Z,
+            Vec3::X * 0.6,
+            Vec3::Y * 0.6,
+            color,
+        );
+    }
+
+    (opaque, transparent)
+}
+
+/// Pushes two counter-clockwise triangles. `u` and `v` are half extents and `u x v` must be `normal`.
+fn push_quad(
+    vertices: &mut Vec<Vertex>,
+    center: Vec3,
+    normal: Vec3,
+    u: Vec3,
+    v: Vec3,
+    color: [f32; 4],
+) {
+    let corners = [
+        center - u - v,
+        center + u - v,
+        center + u + v,
+        center - u + v,
+    ];
+
+    for index in [0, 1, 2, 0, 2, 3] {
+        vertices.push(Vertex {
+            position: corners[index].to_array(),
+            normal: normal.to_array(),
+            color,
+        });
+    }
+}
+
+fn push_box(vertices: &mut Vec<Vertex>, center: Vec3, half_extent: Vec3, color: [f32; 4]) {
+    let faces = [
+        (Vec3::X, Vec3::Y, Vec3::Z),
+        (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+        (Vec3::Y, Vec3::Z, Vec3::X),
+        (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+        (Vec3::Z, Vec3::X, Vec3::Y),
+        (Vec3::NEG_Z, Vec3::Y, Vec3::X),
+    ];
+
+    for (normal, u, v) in faces {
+        push_quad(
+            vertices,
+            center + normal * half_extent,
+            normal,
+            u * half_extent,
+            v * half_extent,
+            color,
+        );
+    }
+}
+
+fn create_gbuffer_pass(
+    context: &Context,
+    pipeline_cache: &PipelineCache,
+    frame_ubo: &Buffer,
+) -> Result<Pass> {
+    let bindings = [vk::DescriptorSetLayoutBinding::default()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::VERTEX)];
+    let dsl = context.create_descriptor_set_layout(&bindings)?;
+
+    let pool_sizes = [vk::DescriptorPoolSize::default()
+        .ty(vk::DescriptorType::UNIFORM_BUFFER)
+        .descriptor_count(1)];
+    let descriptor_pool = context.create_descriptor_pool(1, &pool_sizes)?;
+    let descriptor_set = descriptor_pool.allocate_set(&dsl)?;
+
+    descriptor_set.bind_uniform_buffer(0, frame_ubo);
+
+    let pipeline_layout = context.create_pipeline_layout(&[&dsl])?;
+
+    let opaque_blend = vk::PipelineColorBlendAttachmentState {
+        color_write_mask: vk::ColorComponentFlags::RGBA,
+        ..Default::default()
+    };
+
+    let pipeline = context.create_graphics_pipeline::<Vertex>(
+        &pipeline_layout,
+        GraphicsPipelineCreateInfo {
+            shaders: &[
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/gbuffer.vert.spv")[..],
+                    stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
+                },
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/gbuffer.frag.spv")[..],
+                    stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ],
+            cull_mode: vk::CullModeFlags::BACK,
+            color_attachments: ColorAttachmentsInfo {
+                formats: &[ALBEDO_FORMAT, NORMAL_FORMAT, POSITION_FORMAT],
+                blends: &[opaque_blend; 3],
+            },
+            depth: Some(DepthInfo {
+                format: DEPTH_FORMAT,
+                ..Default::default()
+            }),
+            dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
+        },
+        Some(pipeline_cache),
+    )?;
+
+    Ok(Pass {
+        _dsl: dsl,
+        _descriptor_pool: descriptor_pool,
+        descriptor_set,
+        pipeline_layout,
+        pipeline,
+    })
+}
+
+fn create_lighting_pass(
+    context: &Context,
+    pipeline_cache: &PipelineCache,
+    gbuffer: &GBuffer,
+    lights_ubo: &Buffer,
+    color_attachment_format: vk::Format,
+) -> Result<Pass> {
+    let sampler_binding = |binding| {
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(binding)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+    };
+    let bindings = [
+        sampler_binding(0),
+        sampler_binding(1),
+        sampler_binding(2),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(3)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+    ];
+    let dsl = context.create_descriptor_set_layout(&bindings)?;
+
+    let pool_sizes = [
+        vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(3),
+        vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1),
+    ];
+    let descriptor_pool = context.create_descriptor_pool(1, &pool_sizes)?;
+    let descriptor_set = descriptor_pool.allocate_set(&dsl)?;
+
+    update_lighting_descriptor_set(&descriptor_set, gbuffer, lights_ubo);
+
+    let pipeline_layout = context.create_pipeline_layout(&[&dsl])?;
+
+    // The fullscreen triangle is generated in the vertex shader
+    let pipeline = context.create_graphics_pipeline::<EmptyVertex>(
+        &pipeline_layout,
+        GraphicsPipelineCreateInfo {
+            shaders: &[
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/fullscreen.vert.spv")[..],
+                    stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
+                },
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/lighting.frag.spv")[..],
+                    stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ],
+            color_attachments: ColorAttachmentsInfo {
+                formats: &[color_attachment_format],
+                blends: &[vk::PipelineColorBlendAttachmentState {
+                    color_write_mask: vk::ColorComponentFlags::RGBA,
+                    ..Default::default()
+                }],
+            },
+            dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
+        },
+        Some(pipeline_cache),
+    )?;
+
+    Ok(Pass {
+        _dsl: dsl,
+        _descriptor_pool: descriptor_pool,
+        descriptor_set,
+        pipeline_layout,
+        pipeline,
+    })
+}
+
+fn update_lighting_descriptor_set(
+    descriptor_set: &DescriptorSet,
+    gbuffer: &GBuffer,
+    lights_ubo: &Buffer,
+) {
+    descriptor_set.update(&[
+        gbuffer.albedo.sampler_write(0),
+        gbuffer.normal.sampler_write(1),
+        gbuffer.position.sampler_write(2),
+        WriteDescriptorSet {
+            binding: 3,
+            kind: WriteDescriptorSetKind::UniformBuffer { buffer: lights_ubo },
+        },
+    ]);
+}
+
+fn create_forward_pass(
+    context: &Context,
+    pipeline_cache: &PipelineCache,
+    frame_ubo: &Buffer,
+    lights_ubo: &Buffer,
+    color_attachment_format: vk::Format,
+) -> Result<Pass> {
+    let bindings = [
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+    ];
+    let dsl = context.create_descriptor_set_layout(&bindings)?;
+
+    let pool_sizes = [vk::DescriptorPoolSize::default()
+        .ty(vk::DescriptorType::UNIFORM_BUFFER)
+        .descriptor_count(2)];
+    let descriptor_pool = context.create_descriptor_pool(1, &pool_sizes)?;
+    let descriptor_set = descriptor_pool.allocate_set(&dsl)?;
+
+    descriptor_set.update(&[
+        WriteDescriptorSet {
+            binding: 0,
+            kind: WriteDescriptorSetKind::UniformBuffer { buffer: frame_ubo },
+        },
+        WriteDescriptorSet {
+            binding: 1,
+            kind: WriteDescriptorSetKind::UniformBuffer { buffer: lights_ubo },
+        },
+    ]);
+
+    let pipeline_layout = context.create_pipeline_layout(&[&dsl])?;
+
+    let pipeline = context.create_graphics_pipeline::<Vertex>(
+        &pipeline_layout,
+        GraphicsPipelineCreateInfo {
+            shaders: &[
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/gbuffer.vert.spv")[..],
+                    stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
+                },
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/forward.frag.spv")[..],
+                    stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ],
+            color_attachments: ColorAttachmentsInfo {
+                formats: &[color_attachment_format],
+                blends: &[vk::PipelineColorBlendAttachmentState {
+                    blend_enable: vk::TRUE,
+                    src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+                    dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                    color_blend_op: vk::BlendOp::ADD,
+                    src_alpha_blend_factor: vk::BlendFactor::ONE,
+                    dst_alpha_blend_factor: vk::BlendFactor::ZERO,
+                    alpha_blend_op: vk::BlendOp::ADD,
+                    color_write_mask: vk::ColorComponentFlags::RGBA,
+                }],
+            },
+            depth: Some(DepthInfo {
+                format: DEPTH_FORMAT,
+                enable_depth_write: false,
+                ..Default::default()
+            }),
+            dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
+        },
+        Some(pipeline_cache),
+    )?;
+
+    Ok(Pass {
+        _dsl: dsl,
+        _descriptor_pool: descriptor_pool,
+        descriptor_set,
+        pipeline_layout,
+        pipeline,
+    })
+}
@@ -5,14 +5,13 @@ use app::anyhow::Result;
 use app::glam::{vec3, Mat4};
 use app::vulkan::ash::vk;
 use app::vulkan::gpu_allocator::MemoryLocation;
-use app::vulkan::utils::create_gpu_only_buffer_from_data;
 use app::vulkan::{
-    Buffer, BufferBarrier, ClearValue, ColorAttachmentsInfo, ComputePipeline,
+    Buffer, BufferBarrier, ColorAttachmentsInfo, CommandBuffer, ComputePipeline,
     ComputePipelineCreateInfo, Context, DescriptorPool, DescriptorSet, DescriptorSetLayout,
-    GraphicsPipeline, GraphicsPipelineCreateInfo, GraphicsShaderCreateInfo, PipelineLayout,
-    RenderingAttachment, Vertex, WriteDescriptorSet, WriteDescriptorSetKind,
+    GraphicsPipeline, GraphicsPipelineCreateInfo, GraphicsShaderCreateInfo, PipelineCache,
+    PipelineLayout, RenderingAttachment, Vertex, WriteDescriptorSet, WriteDescriptorSetKind,
 };
-use app::{log, App, BaseApp};
+use app::{log, App, AppConfig, Background, BaseApp, FixedTimestep, GroundGridConfig};
 use gui::egui::{self, Widget};
 use rand::Rng;
 
@@ -22,17 +21,42 @@ const APP_NAME: &str = "GPU Particles";
 
 const DISPATCH_GROUP_SIZE_X: u32 = 256;
 const MAX_PARTICLE_COUNT: u32 = DISPATCH_GROUP_SIZE_X * 32_768; // 8M particles
+/// Fraction of the device local memory the particle buffer may use.
+const PARTICLES_MEMORY_BUDGET: f64 = 0.25;
 const MIN_PARTICLE_SIZE: f32 = 1.0;
 const MAX_PARTICLE_SIZE: f32 = 3.0;
 const MIN_ATTRACTOR_STRENGTH: u32 = 0;
 const MAX_ATTRACTOR_STRENGTH: u32 = 100;
+const PARTICLES_STAGING_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+/// The simulation always advances by this step so it behaves the same at any frame rate.
+const SIMULATION_STEP: Duration = Duration::from_micros(1_000_000 / 120);
+const DEFAULT_MAX_SUBSTEPS: u32 = 8;
+const MAX_SUBSTEPS: u32 = 32;
+const GRID_SPACING: f32 = 0.25;
+const GRID_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 0.5];
 
 fn main() -> Result<()> {
-    app::run::<Particles>(APP_NAME, WIDTH, HEIGHT, Default::default())
+    app::run::<Particles>(
+        APP_NAME,
+        WIDTH,
+        HEIGHT,
+        AppConfig {
+            ground_grid: Some(GroundGridConfig { depth_format: None }),
+            fixed_timestep: Some(FixedTimestep {
+                step: SIMULATION_STEP,
+                max_steps_per_frame: MAX_SUBSTEPS,
+            }),
+            ..Default::default()
+        },
+    )
 }
 struct Particles {
     particle_count: u32,
     attractor_center: [f32; 3],
+    /// Fixed updates run since the last frame.
+    pending_substeps: u32,
+    substeps: u32,
+    show_grid: bool,
     particles_buffer: Buffer,
     compute_ubo_buffer: Buffer,
     _compute_descriptor_pool: DescriptorPool,
@@ -54,7 +78,7 @@ impl App for Particles {
     fn new(base: &mut BaseApp) -> Result<Self> {
         let context = &mut base.context;
 
-        let particles_buffer = create_particle_buffer(context)?;
+        let particles_buffer = create_particle_buffer(context, particle_capacity(context))?;
         let compute_ubo_buffer = context.create_buffer(
             vk::BufferUsageFlags::UNIFORM_BUFFER,
             MemoryLocation::CpuToGpu,
@@ -117,7 +141,10 @@ impl App for Particles {
             &compute_pipeline_layout,
             ComputePipelineCreateInfo {
                 shader_source: &include_bytes!("../shaders/shader.comp.spv")[..],
+                entry_point: "main",
+                specialization: Some(&[(0, &DISPATCH_GROUP_SIZE_X.to_ne_bytes())]),
             },
+            Some(&base.pipeline_cache),
         )?;
 
         let graphics_ubo_buffer = context.create_buffer(
@@ -146,25 +173,31 @@ impl App for Particles {
         let graphics_descriptor_set =
             graphics_descriptor_pool.allocate_set(&graphics_descriptor_layout)?;
 
-        graphics_descriptor_set.update(&[WriteDescriptorSet {
-            binding: 0,
-            kind: WriteDescriptorSetKind::UniformBuffer {
-                buffer: &graphics_ubo_buffer,
-            },
-        }]);
+        graphics_descriptor_set.bind_uniform_buffer(0, &graphics_ubo_buffer);
 
         let graphics_pipeline_layout =
             context.create_pipeline_layout(&[&graphics_descriptor_layout])?;
 
-        let graphics_pipeline =
-            create_graphics_pipeline(context, &graphics_pipeline_layout, base.swapchain.format)?;
+        let graphics_pipeline = create_graphics_pipeline(
+            context,
+            &base.pipeline_cache,
+            &graphics_pipeline_layout,
+            base.swapchain.format,
+        )?;
 
         base.camera.position.z = 2.0;
         base.camera.z_far = 100.0;
+        base.set_background(Some(Background::Gradient {
+            top: [0.02, 0.02, 0.05],
+            bottom: [0.0, 0.0, 0.0],
+        }));
 
         Ok(Self {
             particle_count: 0,
             attractor_center: [0.0; 3],
+            pending_substeps: 0,
+            substeps: 0,
+            show_grid: false,
             particles_buffer,
             compute_ubo_buffer,
             _compute_descriptor_pool: compute_descriptor_pool,
@@ -181,19 +214,31 @@ impl App for Particles {
         })
     }
 
+    fn fixed_update(&mut self, _: &mut BaseApp, _: Duration) -> Result<()> {
+        // Steps are dispatched with the frame's commands since the simulation runs on the GPU
+        self.pending_substeps += 1;
+
+        Ok(())
+    }
+
     fn update(
         &mut self,
         base: &mut BaseApp,
         gui: &mut <Self as App>::Gui,
         _: usize,
-        delta_time: Duration,
+        _: Duration,
     ) -> Result<()> {
         self.particle_count = gui.particle_count;
+        self.show_grid = gui.show_grid;
         self.attractor_center = gui
             .new_attractor_position
             .take()
             .unwrap_or(self.attractor_center);
 
+        // Steps above the limit set in the UI are dropped so the simulation slows down
+        // instead of exploding when the frame rate drops too low
+        self.substeps = std::mem::take(&mut self.pending_substeps).min(gui.max_substeps);
+
         self.compute_ubo_buffer.copy_data_to_buffer(&[ComputeUbo {
             attractor_center: [
                 self.attractor_center[0],
@@ -206,7 +251,7 @@ impl App for Particles {
             color3: gui.color3,
             attractor_strength: gui.attractor_strength,
             particle_count: self.particle_count,
-            elapsed: delta_time.as_secs_f32(),
+            elapsed: SIMULATION_STEP.as_secs_f32(),
         }])?;
 
         self.graphics_ubo_buffer
@@ -218,9 +263,12 @@ impl App for Particles {
         Ok(())
     }
 
-    fn record_raster_commands(&self, base: &BaseApp, image_index: usize) -> Result<()> {
-        let buffer = &base.command_buffers[image_index];
-
+    fn record_raster_commands(
+        &self,
+        base: &BaseApp,
+        buffer: &CommandBuffer,
+        image_index: usize,
+    ) -> Result<()> {
         buffer.bind_compute_pipeline(&self.compute_pipeline);
         buffer.bind_descriptor_sets(
             vk::PipelineBindPoint::COMPUTE,
@@ -228,7 +276,18 @@ impl App for Particles {
             0,
             &[&self.compute_descriptor_set],
         );
-        buffer.dispatch(self.particle_count / DISPATCH_GROUP_SIZE_X, 1, 1);
+        for step in 0..self.substeps {
+            if step > 0 {
+                buffer.pipeline_buffer_barriers(&[BufferBarrier {
+                    buffer: &self.particles_buffer,
+                    src_access_mask: vk::AccessFlags2::SHADER_WRITE,
+                    src_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    dst_access_mask: vk::AccessFlags2::SHADER_READ | vk::AccessFlags2::SHADER_WRITE,
+                    dst_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+                }]);
+            }
+            buffer.dispatch(self.particle_count / DISPATCH_GROUP_SIZE_X, 1, 1);
+        }
 
         buffer.pipeline_buffer_barriers(&[BufferBarrier {
             buffer: &self.particles_buffer,
@@ -239,14 +298,19 @@ impl App for Particles {
         }]);
 
         buffer.begin_rendering(
-            &[RenderingAttachment {
-                view: &base.swapchain.views[image_index],
-                load_op: vk::AttachmentLoadOp::CLEAR,
-                clear_value: Some(ClearValue::ColorFloat([0.0, 0.0, 0.0, 1.0])),
-            }],
+            // Drawn over the background
+            &[RenderingAttachment::load(
+                &base.swapchain.views[image_index],
+            )],
             None,
             base.swapchain.extent,
         );
+        buffer.set_viewport(base.swapchain.extent);
+        buffer.set_scissor(base.swapchain.extent);
+        // Drawn first since particles don't write depth
+        if self.show_grid {
+            base.draw_grid(buffer, GRID_SPACING, GRID_COLOR)?;
+        }
         buffer.bind_graphics_pipeline(&self.graphics_pipeline);
         buffer.bind_descriptor_sets(
             vk::PipelineBindPoint::GRAPHICS,
@@ -255,22 +319,17 @@ impl App for Particles {
             &[&self.graphics_descriptor_set],
         );
         buffer.bind_vertex_buffer(&self.particles_buffer);
-        buffer.set_viewport(base.swapchain.extent);
-        buffer.set_scissor(base.swapchain.extent);
         buffer.draw(self.particle_count / DISPATCH_GROUP_SIZE_X * DISPATCH_GROUP_SIZE_X);
         buffer.end_rendering();
 
         Ok(())
     }
-
-    fn on_recreate_swapchain(&mut self, _: &BaseApp) -> Result<()> {
-        Ok(())
-    }
 }
 
 #[derive(Debug, Clone, Copy)]
 struct Gui {
     particle_count: u32,
+    max_particle_count: u32,
     particle_size: f32,
     attractor_position: [f32; 3],
     new_attractor_position: Option<[f32; 3]>,
@@ -278,12 +337,17 @@ struct Gui {
     color1: [f32; 4],
     color2: [f32; 4],
     color3: [f32; 4],
+    max_substeps: u32,
+    show_grid: bool,
 }
 
 impl app::Gui for Gui {
-    fn new(_: &BaseApp) -> Result<Self> {
+    fn new(base: &BaseApp) -> Result<Self> {
+        let max_particle_count = particle_capacity(&base.context);
+
         Ok(Gui {
-            particle_count: MAX_PARTICLE_COUNT / 20,
+            particle_count: max_particle_count / 20,
+            max_particle_count,
             particle_size: MIN_PARTICLE_SIZE,
             attractor_position: [0.0; 3],
             new_attractor_position: None,
@@ -291,13 +355,15 @@ impl app::Gui for Gui {
             color1: [1.0, 0.0, 0.0, 1.0],
             color2: [0.0, 1.0, 0.0, 1.0],
             color3: [0.0, 0.0, 1.0, 1.0],
+            max_substeps: DEFAULT_MAX_SUBSTEPS,
+            show_grid: false,
         })
     }
 
     fn build(&mut self, ctx: &egui::Context) {
         egui::Window::new("Particles").show(ctx, |ui| {
             ui.label("Particles");
-            egui::Slider::new(&mut self.particle_count, 0..=MAX_PARTICLE_COUNT)
+            egui::Slider::new(&mut self.particle_count, 0..=self.max_particle_count)
                 .text("Count")
                 .ui(ui);
             egui::Slider::new(
@@ -363,6 +429,17 @@ impl app::Gui for Gui {
                 self.attractor_position = new_position;
                 self.new_attractor_position = Some(new_position);
             }
+
+            ui.label("Simulation");
+            ui.label(format!(
+                "Fixed step: {:.2}ms",
+                SIMULATION_STEP.as_secs_f32() * 1000.0
+            ));
+            egui::Slider::new(&mut self.max_substeps, 1..=MAX_SUBSTEPS)
+                .text("Max steps per frame")
+                .ui(ui);
+
+            ui.checkbox(&mut self.show_grid, "Show grid");
         });
     }
 }
@@ -426,12 +503,28 @@ impl Vertex for Particle {
     }
 }
 
-fn create_particle_buffer(context: &Context) -> Result<Buffer> {
+/// Number of particles fitting in the memory budget, up to [`MAX_PARTICLE_COUNT`].
+fn particle_capacity(context: &Context) -> u32 {
+    let budget = (context.device_local_memory_size() as f64 * PARTICLES_MEMORY_BUDGET) as u64;
+    let count = (budget / size_of::<Particle>() as u64).min(MAX_PARTICLE_COUNT as _) as u32;
+
+    // Whole dispatch groups only
+    count / DISPATCH_GROUP_SIZE_X * DISPATCH_GROUP_SIZE_X
+}
+
+fn create_particle_buffer(context: &Context, particle_count: u32) -> Result<Buffer> {
     let start = Instant::now();
 
+    if particle_count < MAX_PARTICLE_COUNT {
+        log::warn!(
+            "Particle count capped to {particle_count} to fit {} MiB of device local memory",
+            context.device_local_memory_size() / (1024 * 1024)
+        );
+    }
+
     let num_cpus = num_cpus::get();
-    let particles_per_cpu = (MAX_PARTICLE_COUNT as f32 / num_cpus as f32).ceil() as usize;
-    let remaining = MAX_PARTICLE_COUNT as usize % particles_per_cpu;
+    let particles_per_cpu = (particle_count as f32 / num_cpus as f32).ceil() as usize;
+    let remaining = particle_count as usize % particles_per_cpu;
 
     let mut handles = vec![];
     for i in 0..num_cpus {
@@ -480,11 +573,13 @@ fn create_particle_buffer(context: &Context) -> Result<Buffer> {
         .flatten()
         .collect::<Vec<_>>();
 
-    let vertex_buffer = create_gpu_only_buffer_from_data(
-        context,
+    // Uploaded in chunks instead of staging the whole 384MB at once
+    let mut uploader = context.create_uploader(PARTICLES_STAGING_SIZE)?;
+    let vertex_buffer = uploader.upload_buffer(
         vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER,
         &particles,
     )?;
+    uploader.flush()?;
 
     let time = Instant::now() - start;
     log::info!("Generated particles in {time:?}");
@@ -494,6 +589,7 @@ fn create_particle_buffer(context: &Context) -> Result<Buffer> {
 
 fn create_graphics_pipeline(
     context: &Context,
+    pipeline_cache: &PipelineCache,
     layout: &PipelineLayout,
     color_attachment_format: vk::Format,
 ) -> Result<GraphicsPipeline> {
@@ -504,15 +600,16 @@ fn create_graphics_pipeline(
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/shader.vert.spv")[..],
                     stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
                 },
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/shader.frag.spv")[..],
                     stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
                 },
             ],
             primitive_topology: vk::PrimitiveTopology::POINT_LIST,
             cull_mode: vk::CullModeFlags::BACK,
-            extent: None,
             color_attachments: ColorAttachmentsInfo {
                 formats: &[color_attachment_format],
                 blends: &[vk::PipelineColorBlendAttachmentState {
@@ -526,8 +623,9 @@ fn create_graphics_pipeline(
                     color_write_mask: vk::ColorComponentFlags::RGBA,
                 }],
             },
-            depth: None,
             dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
         },
+        Some(pipeline_cache),
     )
 }
@@ -5,17 +5,41 @@ layout(location = 0) in vec2 oUV;
 layout(binding = 0, set = 0) uniform Ubo {
     float userNits;
     float referenceNits;
+    uint transferFunction;
 } ubo;
 
 layout(location = 0) out vec4 finalColor;
 
+const uint TRANSFER_FUNCTION_SCRGB_LINEAR = 0;
+const uint TRANSFER_FUNCTION_PQ = 1;
+
 // https://learn.microsoft.com/en-us/windows/win32/direct3darticles/high-dynamic-range#step-3-perform-the-hdr-tonemapping-operation
 const float NITS_TO_WHITE = 12.5 / 1000.0;
 
+// SMPTE ST 2084 inverse EOTF, maps [0, 10000] nits to [0, 1]
+const float PQ_MAX_NITS = 10000.0;
+const float PQ_M1 = 2610.0 / 16384.0;
+const float PQ_M2 = 2523.0 / 4096.0 * 128.0;
+const float PQ_C1 = 3424.0 / 4096.0;
+const float PQ_C2 = 2413.0 / 4096.0 * 32.0;
+const float PQ_C3 = 2392.0 / 4096.0 * 32.0;
+
+float nitsToPq(float nits) {
+    float y = pow(clamp(nits / PQ_MAX_NITS, 0.0, 1.0), PQ_M1);
+    return pow((PQ_C1 + PQ_C2 * y) / (1.0 + PQ_C3 * y), PQ_M2);
+}
+
+float encode(float nits) {
+    if (ubo.transferFunction == TRANSFER_FUNCTION_PQ) {
+        return nitsToPq(nits);
+    }
+    return nits * NITS_TO_WHITE;
+}
+
 void main() {
     if (oUV.x < 0.5) {
-        finalColor = vec4(vec3(ubo.userNits * NITS_TO_WHITE), 1.0);
+        finalColor = vec4(vec3(encode(ubo.userNits)), 1.0);
     } else {
-        finalColor = vec4(vec3 (ubo.referenceNits * NITS_TO_WHITE), 1.0);
+        finalColor = vec4(vec3(encode(ubo.referenceNits)), 1.0);
     }
 }
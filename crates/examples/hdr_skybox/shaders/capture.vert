@@ -0,0 +1,15 @@
+#version 450
+
+layout(location = 0) in vec3 vPosition;
+
+layout(push_constant) uniform Constants {
+  mat4 projectionViewMatrix;
+} constants;
+
+layout(location = 0) out vec3 oPosition;
+
+void main() {
+    oPosition = vPosition;
+
+    gl_Position = constants.projectionViewMatrix * vec4(vPosition, 1.0);
+}
@@ -0,0 +1,20 @@
+#version 450
+
+layout(location = 0) in vec3 oPosition;
+layout(location = 1) in vec3 oViewDirection;
+
+layout(binding = 1, set = 0) uniform samplerCube reflectionSampler;
+
+layout(location = 0) out vec4 finalColor;
+
+void main() {
+    // Each face of the unit cube gets the color of its axis
+    vec3 faceAxis = step(vec3(0.999), abs(oPosition));
+    vec3 faceColor = 0.1 + 0.8 * faceAxis;
+
+    // The object is only scaled uniformly so the object space normal is also the world space one
+    vec3 normal = faceAxis * sign(oPosition);
+    vec3 reflected = texture(reflectionSampler, reflect(normalize(oViewDirection), normal)).rgb;
+
+    finalColor = vec4(mix(faceColor, reflected, 0.75), 1.0);
+}
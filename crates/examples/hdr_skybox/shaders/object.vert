@@ -0,0 +1,21 @@
+#version 450
+
+layout(location = 0) in vec3 vPosition;
+
+layout(binding = 0, set = 0) uniform Ubo {
+  mat4 projectionViewMatrix;
+  mat4 modelMatrix;
+  vec4 cameraPosition;
+} ubo;
+
+layout(location = 0) out vec3 oPosition;
+layout(location = 1) out vec3 oViewDirection;
+
+void main() {
+    oPosition = vPosition;
+
+    vec4 worldPosition = ubo.modelMatrix * vec4(vPosition, 1.0);
+    oViewDirection = worldPosition.xyz - ubo.cameraPosition.xyz;
+
+    gl_Position = ubo.projectionViewMatrix * worldPosition;
+}
@@ -6,6 +6,7 @@ layout(binding = 0, set = 0) uniform sampler2D skyboxSampler;
 
 layout(binding = 1, set = 0) uniform Ubo {
   int toneMapMode;
+  uint transferFunction;
 } ubo;
 
 layout(location = 0) out vec4 finalColor;
@@ -13,6 +14,30 @@ layout(location = 0) out vec4 finalColor;
 const int TONEMAP_MODE_ACESFILMREC2020 = 1;
 const int TONEMAP_MODE_ACESFILM = 2;
 
+const uint TRANSFER_FUNCTION_PQ = 1;
+
+// scRGB values are 80 nits at 1.0
+const float SCRGB_WHITE_NITS = 80.0;
+
+const mat3 REC709_TO_REC2020 = mat3(
+    0.6274, 0.0691, 0.0164,
+    0.3293, 0.9195, 0.0880,
+    0.0433, 0.0114, 0.8956
+);
+
+// SMPTE ST 2084 inverse EOTF, maps [0, 10000] nits to [0, 1]
+const float PQ_MAX_NITS = 10000.0;
+const float PQ_M1 = 2610.0 / 16384.0;
+const float PQ_M2 = 2523.0 / 4096.0 * 128.0;
+const float PQ_C1 = 3424.0 / 4096.0;
+const float PQ_C2 = 2413.0 / 4096.0 * 32.0;
+const float PQ_C3 = 2392.0 / 4096.0 * 32.0;
+
+vec3 nitsToPq(vec3 nits) {
+    vec3 y = pow(clamp(nits / PQ_MAX_NITS, 0.0, 1.0), vec3(PQ_M1));
+    return pow((PQ_C1 + PQ_C2 * y) / (1.0 + PQ_C3 * y), vec3(PQ_M2));
+}
+
 // https://knarkowicz.wordpress.com/2016/08/31/hdr-display-first-steps/
 vec3 ACESFilmRec2020(vec3 x) {
     float a = 15.8f;
@@ -43,5 +68,9 @@ void main() {
         tonemapped = ACESFilm(texColor.rgb);
     }
 
+    if (ubo.transferFunction == TRANSFER_FUNCTION_PQ) {
+        tonemapped = nitsToPq(REC709_TO_REC2020 * tonemapped * SCRGB_WHITE_NITS);
+    }
+
     finalColor = vec4(tonemapped, 1.0);
 }
@@ -1,19 +1,20 @@
+use std::f32::consts::FRAC_1_PI;
 use std::mem::{offset_of, size_of, size_of_val};
 use std::path::Path;
 use std::time::Duration;
 
 use app::anyhow::Result;
-use app::glam::Mat4;
+use app::glam::{Mat4, Vec3};
 use app::vulkan::ash::vk::{self, PipelineBindPoint};
 use app::vulkan::gpu_allocator::MemoryLocation;
-use app::vulkan::utils::create_gpu_only_buffer_from_data;
+use app::vulkan::utils::{compute_mip_levels, create_gpu_only_buffer_from_data};
 use app::vulkan::{
-    Buffer, ColorAttachmentsInfo, CommandBuffer, Context, DescriptorPool, DescriptorSet,
-    DescriptorSetLayout, GraphicsPipeline, GraphicsPipelineCreateInfo, GraphicsShaderCreateInfo,
-    Image, ImageBarrier, ImageView, PipelineLayout, RenderingAttachment, Sampler, Vertex,
-    WriteDescriptorSet, WriteDescriptorSetKind,
+    Buffer, ClearValue, ColorAttachmentsInfo, CommandBuffer, Context, DepthInfo, DescriptorPool,
+    DescriptorSet, DescriptorSetLayout, GraphicsPipeline, GraphicsPipelineCreateInfo,
+    GraphicsShaderCreateInfo, Image, ImageBarrier, ImageView, PipelineCache, PipelineLayout,
+    RenderingAttachment, Sampler, SamplerInfo, Vertex, WriteDescriptorSet, WriteDescriptorSetKind,
 };
-use app::{App, AppConfig, BaseApp};
+use app::{App, AppConfig, BaseApp, Cubemap, SwapchainChange};
 use gui::egui;
 use rfd::FileDialog;
 
@@ -25,15 +26,29 @@ const MIN_NITS: f32 = 0.0;
 const MAX_NITS: f32 = 2000.0;
 
 const HDR_FRAMEBUFFER_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+const DEPTH_BUFFER_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
+const OBJECT_SCALE: f32 = 0.25;
+const REFLECTION_CUBEMAP_SIZE: u32 = 256;
+
+const THUMBNAIL_WIDTH: f32 = 256.0;
+
+const SKYBOX_PASS_LABEL_COLOR: [f32; 4] = [0.2, 0.4, 0.8, 1.0];
+const TONEMAP_PASS_LABEL_COLOR: [f32; 4] = [0.8, 0.6, 0.2, 1.0];
+const CALIBRATION_PASS_LABEL_COLOR: [f32; 4] = [0.6, 0.6, 0.6, 1.0];
 
 const SDR_SURFACE_FORMAT: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
     format: vk::Format::R8G8B8A8_SRGB,
     color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
 };
-const HDR_SURFACE_FORMAT: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
+const SCRGB_SURFACE_FORMAT: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
     format: vk::Format::R16G16B16A16_SFLOAT,
     color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
 };
+const HDR10_SURFACE_FORMAT: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
+    format: vk::Format::A2B10G10R10_UNORM_PACK32,
+    color_space: vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+};
 
 fn main() -> Result<()> {
     app::run::<Skybox>(
@@ -42,22 +57,36 @@ fn main() -> Result<()> {
         HEIGHT,
         AppConfig {
             required_instance_extensions: &["VK_EXT_swapchain_colorspace"],
+            // the UI pass loads the scene depth buffer
+            gui_depth_format: Some(DEPTH_BUFFER_FORMAT),
             ..Default::default()
         },
     )
 }
 
 struct Skybox {
-    hdr_enabled: bool,
+    surface_format: vk::SurfaceFormatKHR,
+    trilinear_filtering: bool,
+    show_object: bool,
     app_mode: AppMode,
+    framebuffer_texture: Option<egui::TextureId>,
+
+    depth_buffer: DepthBuffer,
 
     skybox_vertex_buffer: Buffer,
     skybox_index_buffer: Buffer,
     skybox_texture: Texture,
+    skybox_trilinear_sampler: Sampler,
     skybox_pass_ubo: Buffer,
     skybox_pass_framebuffer: Texture,
     skybox_pass: Pass,
 
+    object_pass_ubo: Buffer,
+    object_pass: Pass,
+    reflection_cubemap: Cubemap,
+    _reflection_sampler: Sampler,
+    capture_pass: CapturePass,
+
     quad_vertex_buffer: Buffer,
     quad_index_buffer: Buffer,
 
@@ -79,6 +108,7 @@ impl App for Skybox {
         let skybox_index_buffer = create_skybox_index_buffer(context)?;
 
         let skybox_texture = Texture::from_hdr_file(context, "assets/images/studio_2k.hdr")?;
+        let skybox_trilinear_sampler = create_trilinear_sampler(context)?;
 
         let skybox_pass_ubo = context.create_buffer(
             vk::BufferUsageFlags::UNIFORM_BUFFER,
@@ -88,14 +118,41 @@ impl App for Skybox {
 
         let skybox_pass_framebuffer =
             Texture::framebuffer(context, base.swapchain.extent, HDR_FRAMEBUFFER_FORMAT)?;
+        let depth_buffer = DepthBuffer::create(context, base.swapchain.extent)?;
 
         let skybox_pass = create_skybox_pass(
             context,
-            &skybox_texture,
+            &base.pipeline_cache,
+            &skybox_texture.view,
+            &skybox_texture.sampler,
             &skybox_pass_ubo,
             skybox_pass_framebuffer.image.format,
         )?;
 
+        // test object, drawn in the skybox pass and reflecting the environment captured around it
+        let reflection_cubemap = Cubemap::new(
+            context,
+            HDR_FRAMEBUFFER_FORMAT,
+            None,
+            REFLECTION_CUBEMAP_SIZE,
+        )?;
+        let reflection_sampler = context.create_sampler_from_info(&SamplerInfo::linear_clamp())?;
+        let capture_pass = create_capture_pass(context, &base.pipeline_cache, &skybox_pass.dsl)?;
+
+        let object_pass_ubo = context.create_buffer(
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            MemoryLocation::CpuToGpu,
+            size_of::<ObjectUbo>() as _,
+        )?;
+        let object_pass = create_object_pass(
+            context,
+            &base.pipeline_cache,
+            &object_pass_ubo,
+            &reflection_cubemap.view,
+            &reflection_sampler,
+            skybox_pass_framebuffer.image.format,
+        )?;
+
         // fullscreen quad geom
         let quad_vertex_buffer = create_quad_vertex_buffer(context)?;
         let quad_index_buffer = create_quad_index_buffer(context)?;
@@ -108,9 +165,10 @@ impl App for Skybox {
         )?;
         let tonemap_pass = create_tonemap_pass(
             context,
+            &base.pipeline_cache,
             &tonemap_pass_ubo,
             &skybox_pass_framebuffer,
-            HDR_FRAMEBUFFER_FORMAT,
+            base.swapchain.format,
         )?;
 
         // calibration pass
@@ -119,20 +177,36 @@ impl App for Skybox {
             MemoryLocation::CpuToGpu,
             size_of::<CalibrationUbo>() as _,
         )?;
-        let calibration_pass =
-            create_calibration_pass(context, &calibration_pass_ubo, HDR_FRAMEBUFFER_FORMAT)?;
+        let calibration_pass = create_calibration_pass(
+            context,
+            &base.pipeline_cache,
+            &calibration_pass_ubo,
+            base.swapchain.format,
+        )?;
 
         Ok(Self {
-            hdr_enabled: false,
+            surface_format: SDR_SURFACE_FORMAT,
+            trilinear_filtering: false,
+            show_object: false,
             app_mode: AppMode::Scene,
+            framebuffer_texture: None,
+
+            depth_buffer,
 
             skybox_vertex_buffer,
             skybox_index_buffer,
             skybox_texture,
+            skybox_trilinear_sampler,
             skybox_pass_ubo,
             skybox_pass,
             skybox_pass_framebuffer,
 
+            object_pass_ubo,
+            object_pass,
+            reflection_cubemap,
+            _reflection_sampler: reflection_sampler,
+            capture_pass,
+
             quad_vertex_buffer,
             quad_index_buffer,
 
@@ -144,40 +218,42 @@ impl App for Skybox {
         })
     }
 
-    fn on_recreate_swapchain(&mut self, base: &BaseApp) -> Result<()> {
-        // rebuilt framebuffers
-        self.skybox_pass_framebuffer =
-            Texture::framebuffer(&base.context, base.swapchain.extent, HDR_FRAMEBUFFER_FORMAT)?;
-
-        // update descriptors sets
-        self.tonemap_pass
-            .descriptor_set
-            .update(&[WriteDescriptorSet {
-                binding: 0,
-                kind: WriteDescriptorSetKind::CombinedImageSampler {
-                    view: &self.skybox_pass_framebuffer.view,
-                    sampler: &self.skybox_pass_framebuffer.sampler,
-                    layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                },
-            }]);
+    fn on_recreate_swapchain(&mut self, base: &BaseApp, change: SwapchainChange) -> Result<()> {
+        // gui textures are unregistered by the base app
+        self.framebuffer_texture = None;
+
+        if change.extent_changed {
+            // rebuilt framebuffers
+            self.skybox_pass_framebuffer =
+                Texture::framebuffer(&base.context, base.swapchain.extent, HDR_FRAMEBUFFER_FORMAT)?;
+            self.depth_buffer = DepthBuffer::create(&base.context, base.swapchain.extent)?;
+
+            // update descriptors sets
+            self.tonemap_pass.descriptor_set.bind_texture(
+                0,
+                &self.skybox_pass_framebuffer.view,
+                &self.skybox_pass_framebuffer.sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            );
+        }
 
         // rebuild pipelines
-        let format = if self.hdr_enabled {
-            HDR_FRAMEBUFFER_FORMAT
-        } else {
-            base.swapchain.format
-        };
-        self.tonemap_pass.pipeline = create_tonemap_pass_pipeline(
-            &base.context,
-            &self.tonemap_pass.pipeline_layout,
-            format,
-        )?;
-
-        self.calibration_pass.pipeline = create_calibration_pass_pipeline(
-            &base.context,
-            &self.calibration_pass.pipeline_layout,
-            format,
-        )?;
+        if change.format_changed() {
+            let format = base.swapchain.format;
+            self.tonemap_pass.pipeline = create_tonemap_pass_pipeline(
+                &base.context,
+                &base.pipeline_cache,
+                &self.tonemap_pass.pipeline_layout,
+                format,
+            )?;
+
+            self.calibration_pass.pipeline = create_calibration_pass_pipeline(
+                &base.context,
+                &base.pipeline_cache,
+                &self.calibration_pass.pipeline_layout,
+                format,
+            )?;
+        }
 
         Ok(())
     }
@@ -189,21 +265,23 @@ impl App for Skybox {
         _: usize,
         _: Duration,
     ) -> Result<()> {
-        // toggle hdr
-        if self.hdr_enabled != ui.enable_hdr {
-            self.hdr_enabled = ui.enable_hdr;
-
-            // reset to scene mode and no tone mapper
-            ui.app_mode = AppMode::Scene;
-            ui.tonemap_mode = TonemapMode::None;
+        // toggle hdr or switch hdr surface
+        let surface_format = if ui.enable_hdr {
+            ui.hdr_surface_format
+        } else {
+            SDR_SURFACE_FORMAT
+        };
+        if self.surface_format != surface_format {
+            if (self.surface_format == SDR_SURFACE_FORMAT) != (surface_format == SDR_SURFACE_FORMAT)
+            {
+                // reset to scene mode and no tone mapper
+                ui.app_mode = AppMode::Scene;
+                ui.tonemap_mode = TonemapMode::None;
+            }
 
-            // request swapchain chang
-            let new_format = if self.hdr_enabled {
-                HDR_SURFACE_FORMAT
-            } else {
-                SDR_SURFACE_FORMAT
-            };
-            base.request_swapchain_format_change(new_format);
+            // request swapchain change
+            self.surface_format = surface_format;
+            base.request_swapchain_format_change(surface_format);
         }
 
         // open file dialog to select an hdr file
@@ -212,18 +290,10 @@ impl App for Skybox {
                 log::info!("Loading new environment from file {file:?}");
                 match Texture::from_hdr_file(&base.context, file) {
                     Ok(texture) => {
-                        self.skybox_pass
-                            .descriptor_set
-                            .update(&[WriteDescriptorSet {
-                                binding: 1,
-                                kind: WriteDescriptorSetKind::CombinedImageSampler {
-                                    view: &texture.view,
-                                    sampler: &texture.sampler,
-                                    layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                                },
-                            }]);
-
+                        // the previous set and texture might still be in use
+                        base.wait_for_gpu()?;
                         self.skybox_texture = texture;
+                        self.rebuild_skybox_descriptor_set()?;
                     }
                     Err(e) => {
                         log::error!("Failed to load environment: {e}");
@@ -232,93 +302,235 @@ impl App for Skybox {
             }
         }
 
+        // toggle skybox filtering
+        if self.trilinear_filtering != ui.trilinear_filtering {
+            self.trilinear_filtering = ui.trilinear_filtering;
+
+            base.wait_for_gpu()?;
+            self.rebuild_skybox_descriptor_set()?;
+        }
+
         // update app mode
         self.app_mode = ui.app_mode;
+        self.show_object = ui.show_object;
+
+        // register the hdr framebuffer to display it in the ui
+        if ui.show_framebuffer && self.framebuffer_texture.is_none() {
+            self.framebuffer_texture = Some(base.gui_context.register_texture(
+                &self.skybox_pass_framebuffer.view,
+                &self.skybox_pass_framebuffer.sampler,
+            )?);
+        }
+        let extent = base.swapchain.extent;
+        ui.framebuffer_thumbnail = self.framebuffer_texture.map(|id| {
+            let height = THUMBNAIL_WIDTH * extent.height as f32 / extent.width as f32;
+            (id, egui::vec2(THUMBNAIL_WIDTH, height))
+        });
 
         // update UBOs
-        self.skybox_pass_ubo.copy_data_to_buffer(&[SkyboxUbo {
-            view_proj_matrix: base.camera.projection_matrix() * base.camera.view_matrix_at_center(),
-        }])?;
+        self.skybox_pass_ubo.map()?.write(
+            0,
+            &[SkyboxUbo {
+                view_proj_matrix: base.camera.projection_matrix()
+                    * base.camera.view_matrix_at_center(),
+            }],
+        )?;
 
-        self.tonemap_pass_ubo.copy_data_to_buffer(&[TonemapUbo {
-            tonemap_mode: ui.tonemap_mode as u32,
-        }])?;
+        if self.show_object {
+            self.object_pass_ubo.map()?.write(
+                0,
+                &[ObjectUbo {
+                    view_proj_matrix: base.camera.projection_matrix() * base.camera.view_matrix(),
+                    model_matrix: Mat4::from_scale(Vec3::splat(OBJECT_SCALE)),
+                    camera_position: base.camera.position.extend(1.0).to_array(),
+                }],
+            )?;
+        }
+
+        let transfer_function = TransferFunction::from_color_space(base.swapchain.color_space);
+        self.tonemap_pass_ubo.map()?.write(
+            0,
+            &[TonemapUbo {
+                tonemap_mode: ui.tonemap_mode as u32,
+                transfer_function: transfer_function as u32,
+            }],
+        )?;
 
         if let AppMode::Calibration(mode) = self.app_mode {
             let calibration_ubo = match mode {
                 CalibrationMode::MinNits => CalibrationUbo {
                     user_nits: ui.calibration_min_nits,
                     reference_nits: MIN_NITS,
+                    transfer_function: transfer_function as u32,
                 },
                 CalibrationMode::MaxNits => CalibrationUbo {
                     user_nits: ui.calibration_max_nits,
                     reference_nits: MAX_NITS,
+                    transfer_function: transfer_function as u32,
                 },
             };
             self.calibration_pass_ubo
-                .copy_data_to_buffer(&[calibration_ubo])?;
+                .map()?
+                .write(0, &[calibration_ubo])?;
         }
 
         Ok(())
     }
 
-    fn record_raster_commands(&self, base: &BaseApp, image_index: usize) -> Result<()> {
-        match self.app_mode {
+    fn record_raster_commands(
+        &self,
+        base: &BaseApp,
+        buffer: &CommandBuffer,
+        image_index: usize,
+    ) -> Result<()> {
+        let depth_layout = match self.app_mode {
             AppMode::Scene => {
-                // skybox pass outputs to an hdr framebuffer the used for tonemapping
-                self.cmd_skybox_pass(&base.command_buffers[image_index]);
+                // skybox pass (and test object) outputs to an hdr framebuffer the used for tonemapping
+                self.cmd_skybox_pass(buffer);
 
                 // tonemap pass outputs to hdr framebuffer
                 self.cmd_tonemap_pass(
-                    &base.command_buffers[image_index],
+                    buffer,
                     &base.swapchain.views[image_index],
                     base.swapchain.extent,
                 );
+
+                vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
             }
             AppMode::Calibration(_) => {
                 // calibration pass outputs to hdr framebuffer
                 self.cmd_calibration_pass(
-                    &base.command_buffers[image_index],
+                    buffer,
                     &base.swapchain.views[image_index],
                     base.swapchain.extent,
                 );
+
+                // depth was not written this frame
+                vk::ImageLayout::UNDEFINED
             }
-        }
+        };
+
+        // the UI pass loads the depth buffer after the skybox pass wrote it
+        buffer.pipeline_image_barriers(&[ImageBarrier {
+            image: &self.depth_buffer.image,
+            old_layout: depth_layout,
+            new_layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            src_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            src_stage_mask: vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+            dst_stage_mask: vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+        }]);
 
         Ok(())
     }
+
+    fn gui_depth_attachment(&self, _: usize) -> Option<&ImageView> {
+        Some(&self.depth_buffer.view)
+    }
 }
 
 impl Skybox {
+    /// Replaces the skybox descriptor set with one sampling the current texture
+    /// with the selected filtering. The previous set must not be in use anymore.
+    fn rebuild_skybox_descriptor_set(&mut self) -> Result<()> {
+        let sampler = if self.trilinear_filtering {
+            &self.skybox_trilinear_sampler
+        } else {
+            &self.skybox_texture.sampler
+        };
+
+        let descriptor_set = self
+            .skybox_pass
+            .descriptor_pool
+            .allocate_set(&self.skybox_pass.dsl)?;
+        write_skybox_descriptors(
+            &descriptor_set,
+            &self.skybox_pass_ubo,
+            &self.skybox_texture.view,
+            sampler,
+        );
+
+        let old_set = std::mem::replace(&mut self.skybox_pass.descriptor_set, descriptor_set);
+        self.skybox_pass.descriptor_pool.free_sets([old_set])
+    }
+
     fn cmd_skybox_pass(&self, buffer: &CommandBuffer) {
-        buffer.pipeline_image_barriers(&[ImageBarrier {
-            image: &self.skybox_pass_framebuffer.image,
-            old_layout: vk::ImageLayout::UNDEFINED,
-            new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            src_access_mask: vk::AccessFlags2::SHADER_READ,
-            dst_access_mask: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
-            src_stage_mask: vk::PipelineStageFlags2::FRAGMENT_SHADER,
-            dst_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
-        }]);
+        buffer.begin_debug_label("Skybox pass", SKYBOX_PASS_LABEL_COLOR);
+        buffer.bind_vertex_buffer(&self.skybox_vertex_buffer);
+        buffer.bind_index_buffer(&self.skybox_index_buffer, vk::IndexType::UINT16);
+
+        // the object reflects the environment, captured from its center. Only the skybox is
+        // captured so the capture position does not matter
+        if self.show_object {
+            self.reflection_cubemap
+                .render(buffer, Vec3::ZERO, |_, view_proj_matrix| {
+                    buffer.bind_graphics_pipeline(&self.capture_pass.pipeline);
+                    buffer.bind_descriptor_sets(
+                        PipelineBindPoint::GRAPHICS,
+                        &self.capture_pass.pipeline_layout,
+                        0,
+                        &[&self.skybox_pass.descriptor_set],
+                    );
+                    buffer.push_constants(
+                        &self.capture_pass.pipeline_layout,
+                        vk::ShaderStageFlags::VERTEX,
+                        0,
+                        &CaptureConstants { view_proj_matrix },
+                    );
+                    buffer.draw_indexed(36);
+                });
+        }
+
+        buffer.pipeline_image_barriers(&[
+            ImageBarrier {
+                image: &self.skybox_pass_framebuffer.image,
+                old_layout: vk::ImageLayout::UNDEFINED,
+                new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                src_access_mask: vk::AccessFlags2::SHADER_READ,
+                dst_access_mask: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                src_stage_mask: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                dst_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            },
+            ImageBarrier {
+                image: &self.depth_buffer.image,
+                old_layout: vk::ImageLayout::UNDEFINED,
+                new_layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                src_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                src_stage_mask: vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                dst_stage_mask: vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS,
+            },
+        ]);
 
         let extent = self.skybox_pass_framebuffer.image.extent2d();
 
         buffer.begin_rendering(
-            &[RenderingAttachment {
-                view: &self.skybox_pass_framebuffer.view,
-                load_op: vk::AttachmentLoadOp::DONT_CARE,
-                clear_value: None,
-            }],
-            None,
+            &[RenderingAttachment::dont_care(
+                &self.skybox_pass_framebuffer.view,
+            )],
+            Some(RenderingAttachment::clear(
+                &self.depth_buffer.view,
+                ClearValue::Depth(1.0),
+            )),
             extent,
         );
-        self.skybox_pass.bind(buffer);
-        buffer.bind_vertex_buffer(&self.skybox_vertex_buffer);
-        buffer.bind_index_buffer(&self.skybox_index_buffer, vk::IndexType::UINT16);
-        buffer.set_viewport(extent);
         buffer.set_scissor(extent);
+
+        // opaque geometry first so the skybox is only shaded where nothing was drawn
+        if self.show_object {
+            self.object_pass.bind(buffer);
+            buffer.set_viewport(extent);
+            buffer.draw_indexed(36);
+        }
+
+        // the skybox is pinned to the far plane and only passes where the depth buffer is still cleared
+        self.skybox_pass.bind(buffer);
+        buffer.set_viewport_with_depth_range(extent, 1.0, 1.0);
         buffer.draw_indexed(36);
         buffer.end_rendering();
+        buffer.end_debug_label();
     }
 
     fn cmd_tonemap_pass(
@@ -327,6 +539,7 @@ impl Skybox {
         target_view: &ImageView,
         target_extent: vk::Extent2D,
     ) {
+        buffer.begin_debug_label("Tonemap pass", TONEMAP_PASS_LABEL_COLOR);
         buffer.pipeline_image_barriers(&[ImageBarrier {
             image: &self.skybox_pass_framebuffer.image,
             old_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
@@ -338,6 +551,7 @@ impl Skybox {
         }]);
 
         self.cmd_fullscreen_pass(buffer, &self.tonemap_pass, target_view, target_extent);
+        buffer.end_debug_label();
     }
 
     fn cmd_calibration_pass(
@@ -346,7 +560,9 @@ impl Skybox {
         target_view: &ImageView,
         target_extent: vk::Extent2D,
     ) {
+        buffer.begin_debug_label("Calibration pass", CALIBRATION_PASS_LABEL_COLOR);
         self.cmd_fullscreen_pass(buffer, &self.calibration_pass, target_view, target_extent);
+        buffer.end_debug_label();
     }
 
     fn cmd_fullscreen_pass(
@@ -357,11 +573,7 @@ impl Skybox {
         target_extent: vk::Extent2D,
     ) {
         buffer.begin_rendering(
-            &[RenderingAttachment {
-                view: target_view,
-                load_op: vk::AttachmentLoadOp::DONT_CARE,
-                clear_value: None,
-            }],
+            &[RenderingAttachment::dont_care(target_view)],
             None,
             target_extent,
         );
@@ -395,10 +607,35 @@ enum CalibrationMode {
     MaxNits,
 }
 
+/// Encoding of the colors written to the swapchain, which depends on the surface color space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TransferFunction {
+    /// Linear values where 1.0 is 80 nits, for `EXTENDED_SRGB_LINEAR_EXT` surfaces.
+    /// sRGB surfaces also take linear values since they encode them on write.
+    ScRgbLinear = 0,
+    /// SMPTE ST 2084 perceptual quantizer, for `HDR10_ST2084_EXT` surfaces.
+    Pq,
+}
+
+impl TransferFunction {
+    fn from_color_space(color_space: vk::ColorSpaceKHR) -> Self {
+        match color_space {
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT => Self::Pq,
+            _ => Self::ScRgbLinear,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 struct Gui {
-    supports_hdr: bool,
+    supports_scrgb: bool,
+    supports_hdr10: bool,
     enable_hdr: bool,
+    hdr_surface_format: vk::SurfaceFormatKHR,
+    trilinear_filtering: bool,
+    show_object: bool,
+    show_framebuffer: bool,
+    framebuffer_thumbnail: Option<(egui::TextureId, egui::Vec2)>,
     open_file_picker: bool,
     app_mode: AppMode,
     tonemap_mode: TonemapMode,
@@ -408,14 +645,23 @@ struct Gui {
 
 impl app::Gui for Gui {
     fn new(base: &BaseApp) -> Result<Self> {
-        let supports_hdr = base
-            .context
-            .supported_surface_formats()
-            .contains(&HDR_SURFACE_FORMAT);
+        let surface_formats = base.context.supported_surface_formats();
+        let supports_scrgb = surface_formats.contains(&SCRGB_SURFACE_FORMAT);
+        let supports_hdr10 = surface_formats.contains(&HDR10_SURFACE_FORMAT);
 
         Ok(Gui {
-            supports_hdr,
+            supports_scrgb,
+            supports_hdr10,
             enable_hdr: false,
+            hdr_surface_format: if supports_scrgb {
+                SCRGB_SURFACE_FORMAT
+            } else {
+                HDR10_SURFACE_FORMAT
+            },
+            trilinear_filtering: false,
+            show_object: false,
+            show_framebuffer: false,
+            framebuffer_thumbnail: None,
             open_file_picker: false,
             app_mode: AppMode::Scene,
             tonemap_mode: TonemapMode::None,
@@ -426,9 +672,24 @@ impl app::Gui for Gui {
 
     fn build(&mut self, ctx: &egui::Context) {
         egui::Window::new("Settings").show(ctx, |ui| {
-            ui.add_enabled_ui(self.supports_hdr, |ui| {
+            ui.add_enabled_ui(self.supports_scrgb || self.supports_hdr10, |ui| {
                 ui.checkbox(&mut self.enable_hdr, "Enable HDR");
             });
+            if self.enable_hdr && self.supports_scrgb && self.supports_hdr10 {
+                ui.radio_value(
+                    &mut self.hdr_surface_format,
+                    SCRGB_SURFACE_FORMAT,
+                    "scRGB linear",
+                );
+                ui.radio_value(
+                    &mut self.hdr_surface_format,
+                    HDR10_SURFACE_FORMAT,
+                    "PQ (HDR10)",
+                );
+            }
+
+            ui.checkbox(&mut self.trilinear_filtering, "Trilinear filtering");
+            ui.checkbox(&mut self.show_object, "Show test object");
 
             self.open_file_picker = ui.button("Pick HDRi file").clicked();
             ui.label(format!("Min nits: {}", self.calibration_min_nits));
@@ -463,6 +724,13 @@ impl app::Gui for Gui {
                 } else {
                     ui.radio_value(&mut self.tonemap_mode, TonemapMode::ACESFilm, "ACESFilm");
                 }
+
+                ui.separator();
+                ui.checkbox(&mut self.show_framebuffer, "Show HDR framebuffer");
+                if let (true, Some(thumbnail)) = (self.show_framebuffer, self.framebuffer_thumbnail)
+                {
+                    ui.image(thumbnail);
+                }
             }
 
             if let AppMode::Calibration(mode) = self.app_mode {
@@ -505,20 +773,30 @@ impl Texture {
         let height = img.height();
         let pixels = img.into_rgba32f().into_raw();
 
-        let staging = context.create_buffer(
-            vk::BufferUsageFlags::TRANSFER_SRC,
-            MemoryLocation::CpuToGpu,
-            size_of_val(pixels.as_slice()) as _,
-        )?;
-
-        staging.copy_data_to_buffer(&pixels)?;
-
-        let image = context.create_image(
-            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+        let face_size = (width / 4).max(1);
+        let faces = equirectangular_to_cube_faces(&pixels, width, height, face_size);
+
+        let staging_buffers = faces
+            .iter()
+            .map(|face| {
+                let staging = context.create_buffer(
+                    vk::BufferUsageFlags::TRANSFER_SRC,
+                    MemoryLocation::CpuToGpu,
+                    size_of_val(face.as_slice()) as _,
+                )?;
+                staging.copy_data_to_buffer(face)?;
+                Ok(staging)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let image = context.create_cube_image(
+            vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::TRANSFER_DST
+                | vk::ImageUsageFlags::SAMPLED,
             MemoryLocation::GpuOnly,
             vk::Format::R32G32B32A32_SFLOAT,
-            width,
-            height,
+            face_size,
+            compute_mip_levels(face_size, face_size),
         )?;
 
         context.execute_one_time_commands(|cmd| {
@@ -532,20 +810,20 @@ impl Texture {
                 dst_stage_mask: vk::PipelineStageFlags2::TRANSFER,
             }]);
 
-            cmd.copy_buffer_to_image(&staging, &image, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+            for (face, staging) in staging_buffers.iter().enumerate() {
+                cmd.copy_buffer_to_image_layer(
+                    staging,
+                    &image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    face as _,
+                );
+            }
 
-            cmd.pipeline_image_barriers(&[ImageBarrier {
-                image: &image,
-                old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
-                dst_access_mask: vk::AccessFlags2::SHADER_READ,
-                src_stage_mask: vk::PipelineStageFlags2::TRANSFER,
-                dst_stage_mask: vk::PipelineStageFlags2::FRAGMENT_SHADER,
-            }]);
-        })?;
+            image.generate_mipmaps(context, cmd)
+        })??;
 
-        let view = image.create_image_view(vk::ImageAspectFlags::COLOR)?;
+        let view = image.create_cube_image_view(vk::ImageAspectFlags::COLOR)?;
+        // nearest filtering of the first mip level, see create_trilinear_sampler for the smooth version
         let sampler = context.create_sampler(&Default::default())?;
 
         Ok(Self {
@@ -562,6 +840,7 @@ impl Texture {
             format,
             extent.width,
             extent.height,
+            1,
         )?;
 
         let view = image.create_image_view(vk::ImageAspectFlags::COLOR)?;
@@ -576,9 +855,85 @@ impl Texture {
     }
 }
 
+/// Linear filtering across all mip levels of the skybox, which removes the aliasing at the horizon.
+fn create_trilinear_sampler(context: &Context) -> Result<Sampler> {
+    context.create_sampler_from_info(&SamplerInfo::linear_clamp())
+}
+
+/// Resamples an equirectangular RGBA image into the six faces of a cubemap
+/// (+X, -X, +Y, -Y, +Z, -Z) using bilinear filtering.
+fn equirectangular_to_cube_faces(
+    pixels: &[f32],
+    width: u32,
+    height: u32,
+    face_size: u32,
+) -> Vec<Vec<f32>> {
+    let sample = |u: f32, v: f32| {
+        let x = u * width as f32 - 0.5;
+        let y = v * height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+
+        let texel = |x: i64, y: i64| {
+            let x = x.rem_euclid(width as i64) as usize;
+            let y = y.clamp(0, height as i64 - 1) as usize;
+            let index = (y * width as usize + x) * 4;
+            &pixels[index..index + 4]
+        };
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let (p00, p10) = (texel(x0, y0), texel(x0 + 1, y0));
+        let (p01, p11) = (texel(x0, y0 + 1), texel(x0 + 1, y0 + 1));
+
+        let mut color = [0.0; 4];
+        for c in 0..4 {
+            let top = p00[c] + (p10[c] - p00[c]) * tx;
+            let bottom = p01[c] + (p11[c] - p01[c]) * tx;
+            color[c] = top + (bottom - top) * ty;
+        }
+        color
+    };
+
+    std::thread::scope(|scope| {
+        let handles = (0..6)
+            .map(|face| {
+                scope.spawn(move || {
+                    let mut data = Vec::with_capacity((face_size * face_size * 4) as usize);
+                    for y in 0..face_size {
+                        for x in 0..face_size {
+                            let sc = 2.0 * (x as f32 + 0.5) / face_size as f32 - 1.0;
+                            let tc = 2.0 * (y as f32 + 0.5) / face_size as f32 - 1.0;
+                            // inverse of the face selection table of the Vulkan spec
+                            let direction = match face {
+                                0 => Vec3::new(1.0, -tc, -sc),
+                                1 => Vec3::new(-1.0, -tc, sc),
+                                2 => Vec3::new(sc, 1.0, tc),
+                                3 => Vec3::new(sc, -1.0, -tc),
+                                4 => Vec3::new(sc, -tc, 1.0),
+                                _ => Vec3::new(-sc, -tc, -1.0),
+                            }
+                            .normalize();
+
+                            // same mapping the skybox used when sampling the equirectangular map
+                            let u = 0.5 + direction.z.atan2(direction.x) * FRAC_1_PI * 0.5;
+                            let v = 0.5 + (-direction.y).asin() * FRAC_1_PI;
+                            data.extend_from_slice(&sample(u, v));
+                        }
+                    }
+                    data
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("Failed to generate cubemap face"))
+            .collect()
+    })
+}
+
 struct Pass {
-    _dsl: DescriptorSetLayout,
-    _descriptor_pool: DescriptorPool,
+    dsl: DescriptorSetLayout,
+    descriptor_pool: DescriptorPool,
     descriptor_set: DescriptorSet,
     pipeline_layout: PipelineLayout,
     pipeline: GraphicsPipeline,
@@ -678,9 +1033,33 @@ fn create_skybox_index_buffer(context: &Context) -> Result<Buffer> {
     Ok(buffer)
 }
 
+fn write_skybox_descriptors(
+    descriptor_set: &DescriptorSet,
+    ubo_buffer: &Buffer,
+    view: &ImageView,
+    sampler: &Sampler,
+) {
+    descriptor_set.update(&[
+        WriteDescriptorSet {
+            binding: 0,
+            kind: WriteDescriptorSetKind::UniformBuffer { buffer: ubo_buffer },
+        },
+        WriteDescriptorSet {
+            binding: 1,
+            kind: WriteDescriptorSetKind::CombinedImageSampler {
+                view,
+                sampler,
+                layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            },
+        },
+    ]);
+}
+
 fn create_skybox_pass(
     context: &Context,
-    texture: &Texture,
+    pipeline_cache: &PipelineCache,
+    view: &ImageView,
+    sampler: &Sampler,
     ubo_buffer: &Buffer,
     color_attachment_format: vk::Format,
 ) -> Result<Pass> {
@@ -698,32 +1077,24 @@ fn create_skybox_pass(
     ];
     let dsl = context.create_descriptor_set_layout(&bindings)?;
 
+    // room for two sets because the new set is allocated before the previous one
+    // is freed when loading a new environment
     let pool_sizes = [
         vk::DescriptorPoolSize::default()
             .ty(vk::DescriptorType::UNIFORM_BUFFER)
-            .descriptor_count(1),
+            .descriptor_count(2),
         vk::DescriptorPoolSize::default()
             .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-            .descriptor_count(1),
+            .descriptor_count(2),
     ];
 
-    let descriptor_pool = context.create_descriptor_pool(2, &pool_sizes)?;
+    let descriptor_pool = context.create_descriptor_pool_with_flags(
+        2,
+        &pool_sizes,
+        vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+    )?;
     let descriptor_set = descriptor_pool.allocate_set(&dsl)?;
-
-    descriptor_set.update(&[
-        WriteDescriptorSet {
-            binding: 0,
-            kind: WriteDescriptorSetKind::UniformBuffer { buffer: ubo_buffer },
-        },
-        WriteDescriptorSet {
-            binding: 1,
-            kind: WriteDescriptorSetKind::CombinedImageSampler {
-                view: &texture.view,
-                sampler: &texture.sampler,
-                layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            },
-        },
-    ]);
+    write_skybox_descriptors(&descriptor_set, ubo_buffer, view, sampler);
 
     let pipeline_layout = context.create_pipeline_layout(&[&dsl])?;
 
@@ -734,15 +1105,15 @@ fn create_skybox_pass(
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/skybox.vert.spv")[..],
                     stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
                 },
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/skybox.frag.spv")[..],
                     stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
                 },
             ],
-            primitive_topology: vk::PrimitiveTopology::TRIANGLE_LIST,
             cull_mode: vk::CullModeFlags::BACK,
-            extent: None,
             color_attachments: ColorAttachmentsInfo {
                 formats: &[color_attachment_format],
                 blends: &[vk::PipelineColorBlendAttachmentState {
@@ -750,20 +1121,209 @@ fn create_skybox_pass(
                     ..Default::default()
                 }],
             },
-            depth: None,
+            depth: Some(DepthInfo {
+                format: DEPTH_BUFFER_FORMAT,
+                enable_depth_write: false,
+                ..Default::default()
+            }),
             dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
         },
+        Some(pipeline_cache),
     )?;
 
     Ok(Pass {
-        _dsl: dsl,
-        _descriptor_pool: descriptor_pool,
+        dsl,
+        descriptor_pool,
         descriptor_set,
         pipeline_layout,
         pipeline,
     })
 }
 
+struct DepthBuffer {
+    image: Image,
+    view: ImageView,
+}
+
+impl DepthBuffer {
+    fn create(context: &Context, extent: vk::Extent2D) -> Result<Self> {
+        let image = context.create_image(
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            MemoryLocation::GpuOnly,
+            DEPTH_BUFFER_FORMAT,
+            extent.width,
+            extent.height,
+            1,
+        )?;
+        let view = image.create_image_view(vk::ImageAspectFlags::DEPTH)?;
+
+        Ok(Self { image, view })
+    }
+}
+
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+#[repr(C)]
+struct ObjectUbo {
+    view_proj_matrix: Mat4,
+    model_matrix: Mat4,
+    camera_position: [f32; 4],
+}
+
+/// Pass drawing a cube in front of the skybox, reusing the skybox geometry seen from the outside.
+fn create_object_pass(
+    context: &Context,
+    pipeline_cache: &PipelineCache,
+    ubo_buffer: &Buffer,
+    reflection_view: &ImageView,
+    reflection_sampler: &Sampler,
+    color_attachment_format: vk::Format,
+) -> Result<Pass> {
+    let bindings = [
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX),
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+    ];
+    let dsl = context.create_descriptor_set_layout(&bindings)?;
+
+    let pool_sizes = [
+        vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1),
+        vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1),
+    ];
+    let descriptor_pool = context.create_descriptor_pool(1, &pool_sizes)?;
+    let descriptor_set = descriptor_pool.allocate_set(&dsl)?;
+    descriptor_set.bind_uniform_buffer(0, ubo_buffer);
+    descriptor_set.bind_texture(
+        1,
+        reflection_view,
+        reflection_sampler,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    );
+
+    let pipeline_layout = context.create_pipeline_layout(&[&dsl])?;
+
+    let pipeline = context.create_graphics_pipeline::<SkyboxVertex>(
+        &pipeline_layout,
+        GraphicsPipelineCreateInfo {
+            shaders: &[
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/object.vert.spv")[..],
+                    stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
+                },
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/object.frag.spv")[..],
+                    stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ],
+            // skybox triangles face inwards
+            cull_mode: vk::CullModeFlags::FRONT,
+            color_attachments: ColorAttachmentsInfo {
+                formats: &[color_attachment_format],
+                blends: &[vk::PipelineColorBlendAttachmentState {
+                    color_write_mask: vk::ColorComponentFlags::RGBA,
+                    ..Default::default()
+                }],
+            },
+            depth: Some(DepthInfo {
+                format: DEPTH_BUFFER_FORMAT,
+                ..Default::default()
+            }),
+            dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
+        },
+        Some(pipeline_cache),
+    )?;
+
+    Ok(Pass {
+        dsl,
+        descriptor_pool,
+        descriptor_set,
+        pipeline_layout,
+        pipeline,
+    })
+}
+
+struct CapturePass {
+    pipeline_layout: PipelineLayout,
+    pipeline: GraphicsPipeline,
+}
+
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+#[repr(C)]
+struct CaptureConstants {
+    view_proj_matrix: Mat4,
+}
+
+/// Pass rendering the skybox into each face of the reflection cubemap.
+///
+/// It uses the skybox descriptor set but gets the view projection matrix of the face from
+/// push constants since all faces are recorded at once.
+fn create_capture_pass(
+    context: &Context,
+    pipeline_cache: &PipelineCache,
+    skybox_dsl: &DescriptorSetLayout,
+) -> Result<CapturePass> {
+    let pipeline_layout = context.create_pipeline_layout_with_push_constants(
+        &[skybox_dsl],
+        &[vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: size_of::<CaptureConstants>() as _,
+        }],
+    )?;
+
+    let pipeline = context.create_graphics_pipeline::<SkyboxVertex>(
+        &pipeline_layout,
+        GraphicsPipelineCreateInfo {
+            shaders: &[
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/capture.vert.spv")[..],
+                    stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
+                },
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/skybox.frag.spv")[..],
+                    stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ],
+            // cubemap faces are not flipped so the skybox winding is reversed, it covers the
+            // whole face anyway
+            cull_mode: vk::CullModeFlags::NONE,
+            color_attachments: ColorAttachmentsInfo {
+                formats: &[HDR_FRAMEBUFFER_FORMAT],
+                blends: &[vk::PipelineColorBlendAttachmentState {
+                    color_write_mask: vk::ColorComponentFlags::RGBA,
+                    ..Default::default()
+                }],
+            },
+            dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
+        },
+        Some(pipeline_cache),
+    )?;
+
+    Ok(CapturePass {
+        pipeline_layout,
+        pipeline,
+    })
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 #[allow(dead_code)]
@@ -840,10 +1400,12 @@ fn create_quad_index_buffer(context: &Context) -> Result<Buffer> {
 #[repr(C)]
 struct TonemapUbo {
     tonemap_mode: u32,
+    transfer_function: u32,
 }
 
 fn create_tonemap_pass(
     context: &Context,
+    pipeline_cache: &PipelineCache,
     ubo: &Buffer,
     skybox_framebuffer: &Texture,
     color_attachment_format: vk::Format,
@@ -891,12 +1453,16 @@ fn create_tonemap_pass(
 
     let pipeline_layout = context.create_pipeline_layout(&[&dsl])?;
 
-    let pipeline =
-        create_tonemap_pass_pipeline(context, &pipeline_layout, color_attachment_format)?;
+    let pipeline = create_tonemap_pass_pipeline(
+        context,
+        pipeline_cache,
+        &pipeline_layout,
+        color_attachment_format,
+    )?;
 
     Ok(Pass {
-        _dsl: dsl,
-        _descriptor_pool: descriptor_pool,
+        dsl,
+        descriptor_pool,
         descriptor_set,
         pipeline_layout,
         pipeline,
@@ -905,6 +1471,7 @@ fn create_tonemap_pass(
 
 fn create_tonemap_pass_pipeline(
     context: &Context,
+    pipeline_cache: &PipelineCache,
     layout: &PipelineLayout,
     color_attachment_format: vk::Format,
 ) -> Result<GraphicsPipeline> {
@@ -915,15 +1482,15 @@ fn create_tonemap_pass_pipeline(
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/fullscreen.vert.spv")[..],
                     stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
                 },
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/tonemap.frag.spv")[..],
                     stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
                 },
             ],
-            primitive_topology: vk::PrimitiveTopology::TRIANGLE_LIST,
             cull_mode: vk::CullModeFlags::BACK,
-            extent: None,
             color_attachments: ColorAttachmentsInfo {
                 formats: &[color_attachment_format],
                 blends: &[vk::PipelineColorBlendAttachmentState {
@@ -931,9 +1498,10 @@ fn create_tonemap_pass_pipeline(
                     ..Default::default()
                 }],
             },
-            depth: None,
             dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
         },
+        Some(pipeline_cache),
     )?;
 
     Ok(pipeline)
@@ -945,10 +1513,12 @@ fn create_tonemap_pass_pipeline(
 struct CalibrationUbo {
     user_nits: f32,
     reference_nits: f32,
+    transfer_function: u32,
 }
 
 fn create_calibration_pass(
     context: &Context,
+    pipeline_cache: &PipelineCache,
     ubo: &Buffer,
     color_attachment_format: vk::Format,
 ) -> Result<Pass> {
@@ -966,19 +1536,20 @@ fn create_calibration_pass(
     let descriptor_pool = context.create_descriptor_pool(1, &pool_sizes)?;
     let descriptor_set = descriptor_pool.allocate_set(&dsl)?;
 
-    descriptor_set.update(&[WriteDescriptorSet {
-        binding: 0,
-        kind: WriteDescriptorSetKind::UniformBuffer { buffer: ubo },
-    }]);
+    descriptor_set.bind_uniform_buffer(0, ubo);
 
     let pipeline_layout = context.create_pipeline_layout(&[&dsl])?;
 
-    let pipeline =
-        create_calibration_pass_pipeline(context, &pipeline_layout, color_attachment_format)?;
+    let pipeline = create_calibration_pass_pipeline(
+        context,
+        pipeline_cache,
+        &pipeline_layout,
+        color_attachment_format,
+    )?;
 
     Ok(Pass {
-        _dsl: dsl,
-        _descriptor_pool: descriptor_pool,
+        dsl,
+        descriptor_pool,
         descriptor_set,
         pipeline_layout,
         pipeline,
@@ -987,6 +1558,7 @@ fn create_calibration_pass(
 
 fn create_calibration_pass_pipeline(
     context: &Context,
+    pipeline_cache: &PipelineCache,
     layout: &PipelineLayout,
     color_attachment_format: vk::Format,
 ) -> Result<GraphicsPipeline> {
@@ -997,15 +1569,15 @@ fn create_calibration_pass_pipeline(
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/fullscreen.vert.spv")[..],
                     stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
                 },
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/calibration.frag.spv")[..],
                     stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
                 },
             ],
-            primitive_topology: vk::PrimitiveTopology::TRIANGLE_LIST,
             cull_mode: vk::CullModeFlags::BACK,
-            extent: None,
             color_attachments: ColorAttachmentsInfo {
                 formats: &[color_attachment_format],
                 blends: &[vk::PipelineColorBlendAttachmentState {
@@ -1013,9 +1585,10 @@ fn create_calibration_pass_pipeline(
                     ..Default::default()
                 }],
             },
-            depth: None,
             dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
         },
+        Some(pipeline_cache),
     )?;
 
     Ok(pipeline)
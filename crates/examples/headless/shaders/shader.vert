@@ -0,0 +1,29 @@
+#version 450
+
+layout(push_constant) uniform Constants {
+    float angle;
+} constants;
+
+layout(location = 0) out vec3 oColor;
+
+const vec2 POSITIONS[3] = vec2[](
+    vec2(0.0, -0.6),
+    vec2(0.6, 0.5),
+    vec2(-0.6, 0.5)
+);
+
+const vec3 COLORS[3] = vec3[](
+    vec3(1.0, 0.0, 0.0),
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, 0.0, 1.0)
+);
+
+void main() {
+    oColor = COLORS[gl_VertexIndex];
+
+    float c = cos(constants.angle);
+    float s = sin(constants.angle);
+    vec2 position = mat2(c, s, -s, c) * POSITIONS[gl_VertexIndex];
+
+    gl_Position = vec4(position, 0.0, 1.0);
+}
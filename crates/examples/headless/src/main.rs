@@ -0,0 +1,140 @@
+use std::f32::consts::TAU;
+use std::mem::size_of;
+use std::path::Path;
+
+use app::anyhow::Result;
+use app::vulkan::ash::vk;
+use app::vulkan::gpu_allocator::MemoryLocation;
+use app::vulkan::{
+    ClearValue, ColorAttachmentsInfo, Context, ContextBuilder, DeviceFeatures, EmptyVertex,
+    GraphicsPipeline, GraphicsPipelineCreateInfo, GraphicsShaderCreateInfo, ImageBarrier,
+    PipelineLayout, RenderingAttachment, VERSION_1_3,
+};
+use app::{log, save_image_to_png};
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 600;
+const APP_NAME: &str = "Headless";
+
+const DEFAULT_FRAME_COUNT: u32 = 8;
+const OUTPUT_DIR: &str = "headless_frames";
+// 8 bits RGBA so it can be written as PNG without conversion
+const COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+/// Renders a rotating triangle offscreen, without window or surface, and saves each frame as
+/// a numbered PNG. The frame count can be passed as argument.
+fn main() -> Result<()> {
+    app::setup_logs(APP_NAME, false);
+
+    let frame_count = match std::env::args().nth(1) {
+        Some(arg) => arg.parse()?,
+        None => DEFAULT_FRAME_COUNT,
+    };
+
+    let context = ContextBuilder::headless()
+        .vulkan_version(VERSION_1_3)
+        .app_name(APP_NAME)
+        .required_device_features(DeviceFeatures {
+            dynamic_rendering: true,
+            synchronization2: true,
+            ..Default::default()
+        })
+        .build()?;
+
+    let target = context.create_image(
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+        MemoryLocation::GpuOnly,
+        COLOR_FORMAT,
+        WIDTH,
+        HEIGHT,
+        1,
+    )?;
+    let target_view = target.create_image_view(vk::ImageAspectFlags::COLOR)?;
+    let extent = target.extent2d();
+
+    let pipeline_layout = context.create_pipeline_layout_with_push_constants(
+        &[],
+        &[vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: size_of::<f32>() as _,
+        }],
+    )?;
+    let pipeline = create_pipeline(&context, &pipeline_layout)?;
+
+    std::fs::create_dir_all(OUTPUT_DIR)?;
+
+    for frame in 0..frame_count {
+        let angle = TAU * frame as f32 / frame_count as f32;
+
+        context.execute_one_time_commands(|cmd| {
+            // previous content is overwritten
+            cmd.pipeline_image_barriers(&[ImageBarrier {
+                image: &target,
+                old_layout: vk::ImageLayout::UNDEFINED,
+                new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                src_access_mask: vk::AccessFlags2::NONE,
+                dst_access_mask: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                src_stage_mask: vk::PipelineStageFlags2::NONE,
+                dst_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            }]);
+
+            cmd.begin_rendering(
+                &[RenderingAttachment::clear(
+                    &target_view,
+                    ClearValue::ColorFloat([0.1, 0.1, 0.1, 1.0]),
+                )],
+                None,
+                extent,
+            );
+            cmd.bind_graphics_pipeline(&pipeline);
+            cmd.set_viewport(extent);
+            cmd.set_scissor(extent);
+            cmd.push_constants(&pipeline_layout, vk::ShaderStageFlags::VERTEX, 0, &angle);
+            cmd.draw(3);
+            cmd.end_rendering();
+        })?;
+
+        // waits for the copy so the target can be rendered to again
+        let path = Path::new(OUTPUT_DIR).join(format!("frame_{frame:05}.png"));
+        save_image_to_png(
+            &context,
+            &target,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            &path,
+        )?;
+        log::info!("Saved frame {frame} to {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn create_pipeline(context: &Context, layout: &PipelineLayout) -> Result<GraphicsPipeline> {
+    context.create_graphics_pipeline::<EmptyVertex>(
+        layout,
+        GraphicsPipelineCreateInfo {
+            shaders: &[
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/shader.vert.spv")[..],
+                    stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
+                },
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/shader.frag.spv")[..],
+                    stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ],
+            color_attachments: ColorAttachmentsInfo {
+                formats: &[COLOR_FORMAT],
+                blends: &[vk::PipelineColorBlendAttachmentState {
+                    color_write_mask: vk::ColorComponentFlags::RGBA,
+                    ..Default::default()
+                }],
+            },
+            dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
+        },
+        None,
+    )
+}
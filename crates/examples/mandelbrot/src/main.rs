@@ -5,8 +5,9 @@ use app::anyhow::Result;
 use app::vulkan::ash::vk;
 use app::vulkan::utils::create_gpu_only_buffer_from_data;
 use app::vulkan::{
-    Buffer, ColorAttachmentsInfo, Context, GraphicsPipeline, GraphicsPipelineCreateInfo,
-    GraphicsShaderCreateInfo, PipelineLayout, RenderingAttachment,
+    Buffer, ClearValue, ColorAttachmentsInfo, CommandBuffer, Context, GraphicsPipeline,
+    GraphicsPipelineCreateInfo, GraphicsShaderCreateInfo, PipelineCache, PipelineLayout,
+    RenderingAttachment,
 };
 use app::{App, BaseApp};
 
@@ -33,7 +34,12 @@ impl App for Mandelbrot {
 
         let pipeline_layout = context.create_pipeline_layout(&[])?;
 
-        let pipeline = create_pipeline(context, &pipeline_layout, base.swapchain.format)?;
+        let pipeline = create_pipeline(
+            context,
+            &base.pipeline_cache,
+            &pipeline_layout,
+            base.swapchain.format,
+        )?;
 
         Ok(Self {
             vertex_buffer,
@@ -42,10 +48,6 @@ impl App for Mandelbrot {
         })
     }
 
-    fn on_recreate_swapchain(&mut self, _: &BaseApp) -> Result<()> {
-        Ok(())
-    }
-
     fn update(
         &mut self,
         _: &mut BaseApp,
@@ -56,15 +58,17 @@ impl App for Mandelbrot {
         Ok(())
     }
 
-    fn record_raster_commands(&self, base: &BaseApp, image_index: usize) -> Result<()> {
-        let buffer = &base.command_buffers[image_index];
-
+    fn record_raster_commands(
+        &self,
+        base: &BaseApp,
+        buffer: &CommandBuffer,
+        image_index: usize,
+    ) -> Result<()> {
         buffer.begin_rendering(
-            &[RenderingAttachment {
-                view: &base.swapchain.views[image_index],
-                load_op: vk::AttachmentLoadOp::CLEAR,
-                clear_value: None,
-            }],
+            &[RenderingAttachment::clear(
+                &base.swapchain.views[image_index],
+                ClearValue::ColorFloat([1.0; 4]),
+            )],
             None,
             base.swapchain.extent,
         );
@@ -150,6 +154,7 @@ fn create_vertex_buffer(context: &Context) -> Result<Buffer> {
 
 fn create_pipeline(
     context: &Context,
+    pipeline_cache: &PipelineCache,
     layout: &PipelineLayout,
     color_attachment_format: vk::Format,
 ) -> Result<GraphicsPipeline> {
@@ -160,15 +165,15 @@ fn create_pipeline(
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/shader.vert.spv")[..],
                     stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
                 },
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/shader.frag.spv")[..],
                     stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
                 },
             ],
-            primitive_topology: vk::PrimitiveTopology::TRIANGLE_LIST,
             cull_mode: vk::CullModeFlags::BACK,
-            extent: None,
             color_attachments: ColorAttachmentsInfo {
                 formats: &[color_attachment_format],
                 blends: &[vk::PipelineColorBlendAttachmentState {
@@ -176,8 +181,9 @@ fn create_pipeline(
                     ..Default::default()
                 }],
             },
-            depth: None,
             dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
         },
+        Some(pipeline_cache),
     )
 }
@@ -0,0 +1,35 @@
+#version 450
+#extension GL_EXT_nonuniform_qualifier : require
+
+const vec3 LIGHT_DIR = normalize(vec3(-0.4, 1.0, 0.6));
+const float AMBIENT = 0.15;
+
+layout(location = 0) in vec3 oNormal;
+layout(location = 1) in vec4 oColor;
+layout(location = 2) in vec2 oUvs;
+
+// All the textures of the model, the binding has a variable descriptor count
+layout(binding = 1, set = 0) uniform sampler2D textures[];
+
+layout(push_constant) uniform NodeConstants {
+    layout(offset = 80) int baseColorTextureIndex;
+} node;
+
+layout(location = 0) out vec4 finalColor;
+
+void main() {
+    vec3 normal = normalize(oNormal);
+    // primitives are not culled so back faces are lit as front faces
+    if (!gl_FrontFacing) {
+        normal = -normal;
+    }
+
+    // The index is the same for the whole draw so it doesn't need to be nonuniformEXT
+    vec4 color = oColor;
+    if (node.baseColorTextureIndex >= 0) {
+        color *= texture(textures[node.baseColorTextureIndex], oUvs);
+    }
+
+    float diffuse = max(dot(normal, LIGHT_DIR), 0.0);
+    finalColor = vec4(color.rgb * (AMBIENT + diffuse), color.a);
+}
@@ -0,0 +1,27 @@
+#version 450
+
+layout(location = 0) in vec4 vPosition;
+layout(location = 1) in vec4 vNormal;
+layout(location = 2) in vec4 vColor;
+layout(location = 3) in vec2 vUvs;
+
+layout(binding = 0, set = 0) uniform FrameUbo {
+    mat4 viewProjMatrix;
+} frameUbo;
+
+layout(push_constant) uniform NodeConstants {
+    mat4 modelMatrix;
+    vec4 baseColor;
+} node;
+
+layout(location = 0) out vec3 oNormal;
+layout(location = 1) out vec4 oColor;
+layout(location = 2) out vec2 oUvs;
+
+void main() {
+    oNormal = mat3(node.modelMatrix) * vNormal.xyz;
+    oColor = vColor * node.baseColor;
+    oUvs = vUvs;
+
+    gl_Position = frameUbo.viewProjMatrix * node.modelMatrix * vec4(vPosition.xyz, 1.0);
+}
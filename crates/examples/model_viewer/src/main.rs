@@ -0,0 +1,529 @@
+use std::mem::{offset_of, size_of};
+use std::time::Duration;
+
+use app::anyhow::Result;
+use app::glam::{vec3, Mat4, Vec4};
+use app::vulkan::ash::vk;
+use app::vulkan::gpu_allocator::MemoryLocation;
+use app::vulkan::utils::create_gpu_only_buffer_from_data;
+use app::vulkan::{
+    Buffer, ClearValue, ColorAttachmentsInfo, CommandBuffer, CommandPool, Context, DepthInfo,
+    DescriptorPool, DescriptorSet, DescriptorSetLayout, GraphicsPipeline,
+    GraphicsPipelineCreateInfo, GraphicsShaderCreateInfo, Image, ImageBarrier, ImageView,
+    PipelineCache, PipelineLayout, RenderingAttachment, RenderingInheritance, Sampler, SamplerInfo,
+    WriteDescriptorSet, WriteDescriptorSetKind,
+};
+use app::{log, App, AppConfig, BaseApp, SwapchainChange};
+
+const WIDTH: u32 = 1024;
+const HEIGHT: u32 = 576;
+const APP_NAME: &str = "Model viewer";
+
+const DEFAULT_MODEL_PATH: &str = "./assets/models/shadows.glb";
+
+const DEPTH_BUFFER_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
+/// Nodes are split between this many threads, each recording a secondary command buffer.
+const RECORDING_THREADS: usize = 2;
+
+/// Clamped to what the device supports.
+const MAX_ANISOTROPY: f32 = 16.0;
+
+/// Upper bound of the bindless texture array, the actual size is the model's image count.
+const MAX_TEXTURES: u32 = 1024;
+
+fn main() -> Result<()> {
+    app::run::<ModelViewer>(
+        APP_NAME,
+        WIDTH,
+        HEIGHT,
+        AppConfig {
+            enable_descriptor_indexing: true,
+            enable_sampler_anisotropy: true,
+            ..Default::default()
+        },
+    )
+}
+
+struct ModelViewer {
+    nodes: Vec<gltf::Node>,
+    texture_images: Vec<usize>,
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    frame_ubo: Buffer,
+    _images: Vec<Image>,
+    _views: Vec<ImageView>,
+    _sampler: Sampler,
+    depth_buffer: DepthBuffer,
+    _dsl: DescriptorSetLayout,
+    _descriptor_pool: DescriptorPool,
+    descriptor_set: DescriptorSet,
+    pipeline_layout: PipelineLayout,
+    pipeline: GraphicsPipeline,
+    recorders: Vec<NodeRecorder>,
+}
+
+/// Command pools are not thread safe so each recording thread has its own.
+struct NodeRecorder {
+    _pool: CommandPool,
+    /// One per swapchain image, like the primary command buffers.
+    buffers: Vec<CommandBuffer>,
+}
+
+impl NodeRecorder {
+    fn new(context: &Context, image_count: usize) -> Result<Self> {
+        let pool = context.create_command_pool(
+            context.graphics_queue_family,
+            Some(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER),
+        )?;
+        let buffers =
+            pool.allocate_command_buffers(vk::CommandBufferLevel::SECONDARY, image_count as _)?;
+
+        Ok(Self {
+            _pool: pool,
+            buffers,
+        })
+    }
+}
+
+impl App for ModelViewer {
+    type Gui = ();
+
+    fn new(base: &mut BaseApp) -> Result<Self> {
+        let context = &mut base.context;
+        base.camera.position = vec3(-1.0, 1.5, 3.0);
+        base.camera.direction = vec3(1.0, -0.5, -3.0);
+
+        // The model to display can be passed as the first argument
+        let model_path = std::env::args()
+            .nth(1)
+            .unwrap_or_else(|| DEFAULT_MODEL_PATH.to_string());
+        log::info!("Loading model {model_path}");
+        let model = gltf::load_file(model_path)?;
+        if model.images.len() > MAX_TEXTURES as usize {
+            app::anyhow::bail!(
+                "Model has {} images but at most {MAX_TEXTURES} are supported",
+                model.images.len()
+            );
+        }
+
+        let vertex_buffer = create_gpu_only_buffer_from_data(
+            context,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            &model.vertices,
+        )?;
+        let index_buffer = create_gpu_only_buffer_from_data(
+            context,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            &model.indices,
+        )?;
+
+        let frame_ubo = context.create_buffer(
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            MemoryLocation::CpuToGpu,
+            size_of::<FrameUbo>() as _,
+        )?;
+
+        let (images, views) = upload_images(context, &model.images)?;
+        let sampler =
+            context.create_sampler_from_info(&SamplerInfo::anisotropic(MAX_ANISOTROPY))?;
+
+        let depth_buffer = DepthBuffer::create(context, base.swapchain.extent)?;
+
+        // All textures are bound in a single array indexed from the push constants
+        let texture_count = (images.len() as u32).max(1);
+        let dsl = context.create_descriptor_set_layout_with_flags(
+            &[
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::VERTEX),
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(1)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .descriptor_count(MAX_TEXTURES)
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+            ],
+            &[
+                vk::DescriptorBindingFlags::empty(),
+                vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                    | vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT,
+            ],
+            vk::DescriptorSetLayoutCreateFlags::empty(),
+        )?;
+
+        let descriptor_pool = context.create_descriptor_pool(
+            1,
+            &[
+                vk::DescriptorPoolSize::default()
+                    .ty(vk::DescriptorType::UNIFORM_BUFFER)
+                    .descriptor_count(1),
+                vk::DescriptorPoolSize::default()
+                    .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .descriptor_count(texture_count),
+            ],
+        )?;
+        let descriptor_set =
+            descriptor_pool.allocate_set_with_variable_count(&dsl, texture_count)?;
+        descriptor_set.update(&[
+            WriteDescriptorSet {
+                binding: 0,
+                kind: WriteDescriptorSetKind::UniformBuffer { buffer: &frame_ubo },
+            },
+            WriteDescriptorSet {
+                binding: 1,
+                kind: WriteDescriptorSetKind::CombinedImageSamplerArray {
+                    views: &views.iter().collect::<Vec<_>>(),
+                    sampler: &sampler,
+                    layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                },
+            },
+        ]);
+
+        let pipeline_layout = context.create_pipeline_layout_with_push_constants(
+            &[&dsl],
+            &[vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: size_of::<NodeConstants>() as _,
+            }],
+        )?;
+
+        let pipeline = create_pipeline(
+            context,
+            &base.pipeline_cache,
+            &pipeline_layout,
+            base.swapchain.format,
+        )?;
+
+        let recorders = (0..RECORDING_THREADS)
+            .map(|_| NodeRecorder::new(context, base.swapchain.images.len()))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Materials reference textures, the shader indexes images directly
+        let texture_images = model.textures.iter().map(|t| t.image_index).collect();
+
+        Ok(Self {
+            nodes: model.nodes,
+            texture_images,
+            vertex_buffer,
+            index_buffer,
+            frame_ubo,
+            _images: images,
+            _views: views,
+            _sampler: sampler,
+            depth_buffer,
+            _dsl: dsl,
+            _descriptor_pool: descriptor_pool,
+            descriptor_set,
+            pipeline_layout,
+            pipeline,
+            recorders,
+        })
+    }
+
+    fn on_recreate_swapchain(&mut self, base: &BaseApp, change: SwapchainChange) -> Result<()> {
+        if change.extent_changed {
+            self.depth_buffer = DepthBuffer::create(&base.context, base.swapchain.extent)?;
+        }
+
+        if change.format_changed() {
+            self.pipeline = create_pipeline(
+                &base.context,
+                &base.pipeline_cache,
+                &self.pipeline_layout,
+                base.swapchain.format,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn update(
+        &mut self,
+        base: &mut BaseApp,
+        _: &mut <Self as App>::Gui,
+        _: usize,
+        _: Duration,
+    ) -> Result<()> {
+        self.frame_ubo.map()?.write(
+            0,
+            &[FrameUbo {
+                view_proj_matrix: base.camera.projection_matrix() * base.camera.view_matrix(),
+            }],
+        )?;
+
+        Ok(())
+    }
+
+    fn record_raster_commands(
+        &self,
+        base: &BaseApp,
+        buffer: &CommandBuffer,
+        image_index: usize,
+    ) -> Result<()> {
+        buffer.pipeline_image_barriers(&[ImageBarrier {
+            image: &self.depth_buffer.image,
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            src_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            src_stage_mask: vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+            dst_stage_mask: vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS,
+        }]);
+
+        // Nodes are recorded in parallel then executed in the pass
+        // BaseApp can't be shared between threads
+        let format = base.swapchain.format;
+        let extent = base.swapchain.extent;
+        let chunk_size = self.nodes.len().div_ceil(RECORDING_THREADS).max(1);
+        let secondary_buffers = std::thread::scope(|scope| {
+            let handles = self
+                .nodes
+                .chunks(chunk_size)
+                .zip(&self.recorders)
+                .map(|(nodes, recorder)| {
+                    let secondary = &recorder.buffers[image_index];
+                    scope.spawn(move || {
+                        self.record_nodes(secondary, format, extent, nodes)?;
+                        Ok::<_, app::anyhow::Error>(secondary)
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("Node recording thread panicked"))
+                .collect::<Result<Vec<_>>>()
+        })?;
+
+        buffer.begin_rendering_with_flags(
+            &[RenderingAttachment::clear(
+                &base.swapchain.views[image_index],
+                ClearValue::ColorFloat([0.1, 0.1, 0.1, 1.0]),
+            )],
+            Some(
+                RenderingAttachment::clear(&self.depth_buffer.view, ClearValue::Depth(1.0))
+                    .store_op(vk::AttachmentStoreOp::DONT_CARE),
+            ),
+            base.swapchain.extent,
+            vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS,
+        );
+        buffer.execute_commands(&secondary_buffers);
+        buffer.end_rendering();
+
+        Ok(())
+    }
+}
+
+impl ModelViewer {
+    /// Records the draws of `nodes` in a secondary command buffer.
+    fn record_nodes(
+        &self,
+        buffer: &CommandBuffer,
+        color_format: vk::Format,
+        extent: vk::Extent2D,
+        nodes: &[gltf::Node],
+    ) -> Result<()> {
+        buffer.begin_secondary(
+            Some(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+            &RenderingInheritance {
+                color_formats: &[color_format],
+                depth_format: Some(DEPTH_BUFFER_FORMAT),
+                samples: vk::SampleCountFlags::TYPE_1,
+            },
+        )?;
+
+        buffer.bind_graphics_pipeline(&self.pipeline);
+        buffer.bind_vertex_buffer(&self.vertex_buffer);
+        buffer.bind_index_buffer(&self.index_buffer, vk::IndexType::UINT32);
+        buffer.set_viewport(extent);
+        buffer.set_scissor(extent);
+        buffer.bind_descriptor_sets(
+            vk::PipelineBindPoint::GRAPHICS,
+            &self.pipeline_layout,
+            0,
+            &[&self.descriptor_set],
+        );
+
+        for node in nodes {
+            let constants = NodeConstants {
+                model_matrix: Mat4::from_cols_array_2d(&node.transform),
+                base_color: Vec4::from(node.mesh.material.base_color),
+                base_color_texture_index: node
+                    .mesh
+                    .material
+                    .base_color_texture_index
+                    .map_or(-1, |i| self.texture_images[i] as _),
+            };
+            buffer.push_constants(
+                &self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                &constants,
+            );
+            buffer.draw_indexed_instanced(
+                node.mesh.index_count,
+                1,
+                node.mesh.index_offset,
+                node.mesh.vertex_offset as _,
+                0,
+            );
+        }
+
+        buffer.end()
+    }
+}
+
+/// Vertex input layout of [`gltf::Vertex`] which is uploaded as is.
+struct ModelVertex;
+
+impl app::vulkan::Vertex for ModelVertex {
+    fn bindings() -> Vec<vk::VertexInputBindingDescription> {
+        vec![vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: size_of::<gltf::Vertex>() as _,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }]
+    }
+
+    fn attributes() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![
+            vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: 0,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: offset_of!(gltf::Vertex, position) as _,
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: 1,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: offset_of!(gltf::Vertex, normal) as _,
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: 2,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: offset_of!(gltf::Vertex, color) as _,
+            },
+            vk::VertexInputAttributeDescription {
+                binding: 0,
+                location: 3,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: offset_of!(gltf::Vertex, uvs) as _,
+            },
+        ]
+    }
+}
+
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+#[repr(C)]
+struct FrameUbo {
+    view_proj_matrix: Mat4,
+}
+
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+#[repr(C)]
+struct NodeConstants {
+    model_matrix: Mat4,
+    base_color: Vec4,
+    /// Index in the texture array or -1 when the material has no base color texture.
+    base_color_texture_index: i32,
+}
+
+fn upload_images(
+    context: &Context,
+    images: &[gltf::Image],
+) -> Result<(Vec<Image>, Vec<ImageView>)> {
+    if images.is_empty() {
+        return Ok((vec![], vec![]));
+    }
+
+    let staging_size = images.iter().map(|i| i.pixels.len()).sum::<usize>();
+    let mut uploader = context.create_uploader(staging_size as _)?;
+
+    let mut uploaded_images = Vec::with_capacity(images.len());
+    let mut views = Vec::with_capacity(images.len());
+    for i in images {
+        let image = context.create_image(
+            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+            MemoryLocation::GpuOnly,
+            vk::Format::R8G8B8A8_SRGB,
+            i.width,
+            i.height,
+            1,
+        )?;
+        uploader.upload_image(&image, &i.pixels, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)?;
+
+        views.push(image.create_image_view(vk::ImageAspectFlags::COLOR)?);
+        uploaded_images.push(image);
+    }
+    uploader.flush()?;
+
+    Ok((uploaded_images, views))
+}
+
+struct DepthBuffer {
+    image: Image,
+    view: ImageView,
+}
+
+impl DepthBuffer {
+    fn create(context: &Context, extent: vk::Extent2D) -> Result<Self> {
+        let image = context.create_image(
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            MemoryLocation::GpuOnly,
+            DEPTH_BUFFER_FORMAT,
+            extent.width,
+            extent.height,
+            1,
+        )?;
+        let view = image.create_image_view(vk::ImageAspectFlags::DEPTH)?;
+
+        Ok(Self { image, view })
+    }
+}
+
+fn create_pipeline(
+    context: &Context,
+    pipeline_cache: &PipelineCache,
+    layout: &PipelineLayout,
+    color_attachment_format: vk::Format,
+) -> Result<GraphicsPipeline> {
+    context.create_graphics_pipeline::<ModelVertex>(
+        layout,
+        GraphicsPipelineCreateInfo {
+            shaders: &[
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/model.vert.spv")[..],
+                    stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
+                },
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/model.frag.spv")[..],
+                    stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ],
+            // gltf materials can be double sided
+            color_attachments: ColorAttachmentsInfo {
+                formats: &[color_attachment_format],
+                blends: &[vk::PipelineColorBlendAttachmentState {
+                    color_write_mask: vk::ColorComponentFlags::RGBA,
+                    ..Default::default()
+                }],
+            },
+            depth: Some(DepthInfo {
+                format: DEPTH_BUFFER_FORMAT,
+                ..Default::default()
+            }),
+            dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
+        },
+        Some(pipeline_cache),
+    )
+}
@@ -0,0 +1,515 @@
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+
+use app::anyhow::{anyhow, Result};
+use app::vulkan::ash::vk;
+use app::vulkan::gpu_allocator::MemoryLocation;
+use app::vulkan::utils::create_gpu_only_buffer_from_data;
+use app::vulkan::{
+    Buffer, BufferBarrier, ClearValue, CommandBuffer, ComputePipeline, ComputePipelineCreateInfo,
+    Context, DescriptorPool, DescriptorSet, DescriptorSetLayout, PipelineLayout,
+    RenderingAttachment, TimestampQueryPool, WriteDescriptorSet, WriteDescriptorSetKind,
+};
+use app::{log, App, BaseApp};
+use gui::egui::{self, Widget};
+use rand::Rng;
+
+const WIDTH: u32 = 1024;
+const HEIGHT: u32 = 576;
+const APP_NAME: &str = "Parallel sum";
+
+const WORKGROUP_SIZE: u32 = 256;
+// Each invocation reduces two elements
+const ELEMENTS_PER_WORKGROUP: u32 = WORKGROUP_SIZE * 2;
+const MAX_VALUE_COUNT: u32 = 1 << 24; // 16M values
+const MAX_VALUE: u32 = 1_000;
+// Each pass divides the element count by ELEMENTS_PER_WORKGROUP, 3 passes reduce 16M values
+const MAX_PASS_COUNT: u32 = 4;
+
+fn main() -> Result<()> {
+    app::run::<ParallelSum>(APP_NAME, WIDTH, HEIGHT, Default::default())
+}
+
+struct ParallelSum {
+    values: Vec<u32>,
+    _values_buffer: Buffer,
+    partials_buffers: [Buffer; 2],
+    dispatches_buffer: Buffer,
+    readback_buffer: Buffer,
+    _descriptor_pool: DescriptorPool,
+    _descriptor_layout: DescriptorSetLayout,
+    first_pass_descriptor_set: DescriptorSet,
+    ping_pong_descriptor_sets: [DescriptorSet; 2],
+    pipeline_layout: PipelineLayout,
+    pipeline: ComputePipeline,
+    timing_query_pool: TimestampQueryPool<2>,
+}
+
+impl App for ParallelSum {
+    type Gui = Gui;
+
+    fn new(base: &mut BaseApp) -> Result<Self> {
+        let context = &mut base.context;
+
+        let values = generate_values();
+        let values_buffer = create_gpu_only_buffer_from_data(
+            context,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            &values,
+        )?;
+
+        // The first pass produces the most partial results, later passes only shrink it
+        let partials_size = (MAX_VALUE_COUNT.div_ceil(ELEMENTS_PER_WORKGROUP) as usize
+            * size_of::<Partial>()) as vk::DeviceSize;
+        let partials_buffers = [
+            create_partials_buffer(context, partials_size)?,
+            create_partials_buffer(context, partials_size)?,
+        ];
+        // One slot per pass plus the one written by the last pass
+        let dispatches_buffer = context.create_buffer(
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+            MemoryLocation::GpuOnly,
+            ((MAX_PASS_COUNT + 1) as usize * size_of::<Dispatch>()) as _,
+        )?;
+        // Only the final partial is read back
+        let readback_buffer = context.create_buffer(
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+            size_of::<Partial>() as _,
+        )?;
+
+        let descriptor_pool = context.create_descriptor_pool(
+            3,
+            &[vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 12,
+            }],
+        )?;
+
+        let descriptor_layout = context.create_descriptor_set_layout(
+            &(0..4)
+                .map(|binding| vk::DescriptorSetLayoutBinding {
+                    binding,
+                    descriptor_count: 1,
+                    descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                    ..Default::default()
+                })
+                .collect::<Vec<_>>(),
+        )?;
+
+        // First pass reads the values and writes partials[0] (binding 1 is unused)
+        let first_pass_descriptor_set = descriptor_pool.allocate_set(&descriptor_layout)?;
+        update_descriptor_set(
+            &first_pass_descriptor_set,
+            &values_buffer,
+            &partials_buffers[1],
+            &partials_buffers[0],
+            &dispatches_buffer,
+        );
+
+        // Next passes alternate between partials[0] -> partials[1] and partials[1] -> partials[0]
+        let ping_pong_descriptor_sets = [
+            descriptor_pool.allocate_set(&descriptor_layout)?,
+            descriptor_pool.allocate_set(&descriptor_layout)?,
+        ];
+        update_descriptor_set(
+            &ping_pong_descriptor_sets[0],
+            &values_buffer,
+            &partials_buffers[0],
+            &partials_buffers[1],
+            &dispatches_buffer,
+        );
+        update_descriptor_set(
+            &ping_pong_descriptor_sets[1],
+            &values_buffer,
+            &partials_buffers[1],
+            &partials_buffers[0],
+            &dispatches_buffer,
+        );
+
+        let pipeline_layout = context.create_pipeline_layout_with_push_constants(
+            &[&descriptor_layout],
+            &[vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                offset: 0,
+                size: size_of::<PushConstants>() as _,
+            }],
+        )?;
+
+        let pipeline = context.create_compute_pipeline(
+            &pipeline_layout,
+            ComputePipelineCreateInfo {
+                shader_source: &include_bytes!("../shaders/reduce.comp.spv")[..],
+                entry_point: "main",
+                specialization: None,
+            },
+            Some(&base.pipeline_cache),
+        )?;
+
+        let timing_query_pool = context.create_timestamp_query_pool()?;
+
+        Ok(Self {
+            values,
+            _values_buffer: values_buffer,
+            partials_buffers,
+            dispatches_buffer,
+            readback_buffer,
+            _descriptor_pool: descriptor_pool,
+            _descriptor_layout: descriptor_layout,
+            first_pass_descriptor_set,
+            ping_pong_descriptor_sets,
+            pipeline_layout,
+            pipeline,
+            timing_query_pool,
+        })
+    }
+
+    fn update(
+        &mut self,
+        base: &mut BaseApp,
+        gui: &mut <Self as App>::Gui,
+        _: usize,
+        _: Duration,
+    ) -> Result<()> {
+        if !gui.run_requested && !gui.run_every_frame {
+            return Ok(());
+        }
+        gui.run_requested = false;
+
+        let count = gui.value_count.clamp(1, MAX_VALUE_COUNT);
+
+        let pass_count = base
+            .context
+            .execute_one_time_commands(|cmd| self.record_reduction(cmd, count))?;
+
+        let [gpu] = self.readback_buffer.read_data_to_vec::<Partial>()?[..] else {
+            return Err(anyhow!("Expected a single partial result"));
+        };
+        let timestamps = self.timing_query_pool.wait_for_all_results()?;
+        let gpu_time = self.timing_query_pool.elapsed(timestamps[0], timestamps[1]);
+
+        let start = Instant::now();
+        let cpu = cpu_reduce(&self.values[..count as usize]);
+        let cpu_time = start.elapsed();
+
+        if gpu != cpu {
+            log::error!("GPU result {gpu:?} does not match CPU result {cpu:?}");
+        }
+
+        gui.result = Some(ReductionResult {
+            count,
+            gpu,
+            cpu,
+            gpu_time,
+            cpu_time,
+            pass_count,
+        });
+
+        Ok(())
+    }
+
+    fn record_raster_commands(
+        &self,
+        base: &BaseApp,
+        buffer: &CommandBuffer,
+        image_index: usize,
+    ) -> Result<()> {
+        buffer.begin_rendering(
+            &[RenderingAttachment::clear(
+                &base.swapchain.views[image_index],
+                ClearValue::ColorFloat([0.0, 0.0, 0.0, 1.0]),
+            )],
+            None,
+            base.swapchain.extent,
+        );
+        buffer.end_rendering();
+
+        Ok(())
+    }
+}
+
+impl ParallelSum {
+    /// Records as many reduction passes as needed to reduce `count` values to a single
+    /// partial result and copies it to the readback buffer. Returns the number of passes.
+    ///
+    /// Only the first pass is sized on the CPU. Each pass writes the dispatch arguments and
+    /// element count of the next one, which is dispatched indirectly. The pass count still
+    /// has to be known when recording and is derived from `count`.
+    fn record_reduction(&self, cmd: &CommandBuffer, count: u32) -> u32 {
+        cmd.reset_all_timestamp_queries_from_pool(&self.timing_query_pool);
+        cmd.write_timestamp(
+            vk::PipelineStageFlags2::TOP_OF_PIPE,
+            &self.timing_query_pool,
+            0,
+        );
+
+        cmd.bind_compute_pipeline(&self.pipeline);
+
+        let mut count = count;
+        let mut pass = 0;
+        loop {
+            debug_assert!((pass as u32) < MAX_PASS_COUNT, "Too many reduction passes");
+            let group_count = count.div_ceil(ELEMENTS_PER_WORKGROUP);
+            let descriptor_set = match pass {
+                0 => &self.first_pass_descriptor_set,
+                _ => &self.ping_pong_descriptor_sets[(pass - 1) % 2],
+            };
+            let output = &self.partials_buffers[pass % 2];
+
+            cmd.bind_descriptor_sets(
+                vk::PipelineBindPoint::COMPUTE,
+                &self.pipeline_layout,
+                0,
+                &[descriptor_set],
+            );
+            cmd.push_constants(
+                &self.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                &PushConstants {
+                    count,
+                    pass: pass as _,
+                },
+            );
+            if pass == 0 {
+                cmd.dispatch(group_count, 1, 1);
+            } else {
+                cmd.dispatch_indirect(&self.dispatches_buffer, (pass * size_of::<Dispatch>()) as _);
+            }
+
+            // The next pass reads what this one wrote, or the final result gets copied back
+            let last = group_count == 1;
+            let (dst_access_mask, dst_stage_mask) = if last {
+                (
+                    vk::AccessFlags2::TRANSFER_READ,
+                    vk::PipelineStageFlags2::TRANSFER,
+                )
+            } else {
+                (
+                    vk::AccessFlags2::SHADER_READ,
+                    vk::PipelineStageFlags2::COMPUTE_SHADER,
+                )
+            };
+            cmd.pipeline_buffer_barriers(&[
+                BufferBarrier {
+                    buffer: output,
+                    src_access_mask: vk::AccessFlags2::SHADER_WRITE,
+                    src_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    dst_access_mask,
+                    dst_stage_mask,
+                },
+                // The next dispatch reads its arguments and its shader reads its element count
+                BufferBarrier {
+                    buffer: &self.dispatches_buffer,
+                    src_access_mask: vk::AccessFlags2::SHADER_WRITE,
+                    src_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    dst_access_mask: vk::AccessFlags2::INDIRECT_COMMAND_READ
+                        | vk::AccessFlags2::SHADER_READ,
+                    dst_stage_mask: vk::PipelineStageFlags2::DRAW_INDIRECT
+                        | vk::PipelineStageFlags2::COMPUTE_SHADER,
+                },
+            ]);
+
+            if last {
+                cmd.write_timestamp(
+                    vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    &self.timing_query_pool,
+                    1,
+                );
+                cmd.copy_buffer_regions(
+                    output,
+                    &self.readback_buffer,
+                    &[vk::BufferCopy::default().size(size_of::<Partial>() as _)],
+                );
+                break;
+            }
+
+            count = group_count;
+            pass += 1;
+        }
+
+        pass as u32 + 1
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Gui {
+    value_count: u32,
+    run_requested: bool,
+    run_every_frame: bool,
+    result: Option<ReductionResult>,
+}
+
+impl app::Gui for Gui {
+    fn new(_: &BaseApp) -> Result<Self> {
+        Ok(Gui {
+            value_count: MAX_VALUE_COUNT / 4,
+            run_requested: true,
+            run_every_frame: false,
+            result: None,
+        })
+    }
+
+    fn build(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Parallel sum").show(ctx, |ui| {
+            egui::Slider::new(&mut self.value_count, 1..=MAX_VALUE_COUNT)
+                .logarithmic(true)
+                .text("Count")
+                .ui(ui);
+            ui.horizontal(|ui| {
+                if ui.button("Run").clicked() {
+                    self.run_requested = true;
+                }
+                ui.checkbox(&mut self.run_every_frame, "Run every frame");
+            });
+
+            let Some(result) = self.result else {
+                return;
+            };
+
+            ui.separator();
+            egui::Grid::new("results").striped(true).show(ui, |ui| {
+                ui.label("");
+                ui.label("GPU");
+                ui.label("CPU");
+                ui.end_row();
+
+                ui.label("Sum");
+                ui.label(result.gpu.sum.to_string());
+                ui.label(result.cpu.sum.to_string());
+                ui.end_row();
+
+                ui.label("Min");
+                ui.label(result.gpu.min.to_string());
+                ui.label(result.cpu.min.to_string());
+                ui.end_row();
+
+                ui.label("Max");
+                ui.label(result.gpu.max.to_string());
+                ui.label(result.cpu.max.to_string());
+                ui.end_row();
+
+                ui.label("Time");
+                ui.label(format!("{:.3} ms", result.gpu_time.as_secs_f64() * 1000.0));
+                ui.label(format!("{:.3} ms", result.cpu_time.as_secs_f64() * 1000.0));
+                ui.end_row();
+            });
+
+            ui.label(format!(
+                "{} values reduced in {} passes",
+                result.count, result.pass_count
+            ));
+            if result.gpu == result.cpu {
+                ui.colored_label(egui::Color32::GREEN, "GPU and CPU results match");
+            } else {
+                ui.colored_label(egui::Color32::RED, "GPU and CPU results differ");
+            }
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ReductionResult {
+    count: u32,
+    gpu: Partial,
+    cpu: Partial,
+    gpu_time: Duration,
+    cpu_time: Duration,
+    pass_count: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+struct Partial {
+    sum: u32,
+    min: u32,
+    max: u32,
+    _padding: u32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct PushConstants {
+    /// Element count of the first pass, later passes read theirs from [`Dispatch`].
+    count: u32,
+    pass: u32,
+}
+
+/// `vk::DispatchIndirectCommand` followed by the element count of the pass.
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+#[repr(C)]
+struct Dispatch {
+    group_count_x: u32,
+    group_count_y: u32,
+    group_count_z: u32,
+    count: u32,
+}
+
+fn generate_values() -> Vec<u32> {
+    let start = Instant::now();
+
+    let mut rng = rand::thread_rng();
+    let values = (0..MAX_VALUE_COUNT)
+        .map(|_| rng.gen_range(0..=MAX_VALUE))
+        .collect::<Vec<_>>();
+
+    let time = Instant::now() - start;
+    log::info!("Generated values in {time:?}");
+
+    values
+}
+
+fn cpu_reduce(values: &[u32]) -> Partial {
+    values.iter().fold(
+        Partial {
+            sum: 0,
+            min: u32::MAX,
+            max: 0,
+            _padding: 0,
+        },
+        |acc, &v| Partial {
+            // The shader sum wraps on overflow so mirror that here
+            sum: acc.sum.wrapping_add(v),
+            min: acc.min.min(v),
+            max: acc.max.max(v),
+            _padding: 0,
+        },
+    )
+}
+
+fn create_partials_buffer(context: &Context, size: vk::DeviceSize) -> Result<Buffer> {
+    context.create_buffer(
+        vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
+        MemoryLocation::GpuOnly,
+        size,
+    )
+}
+
+fn update_descriptor_set(
+    set: &DescriptorSet,
+    values: &Buffer,
+    input: &Buffer,
+    output: &Buffer,
+    dispatches: &Buffer,
+) {
+    set.update(&[
+        WriteDescriptorSet {
+            binding: 0,
+            kind: WriteDescriptorSetKind::StorageBuffer { buffer: values },
+        },
+        WriteDescriptorSet {
+            binding: 1,
+            kind: WriteDescriptorSetKind::StorageBuffer { buffer: input },
+        },
+        WriteDescriptorSet {
+            binding: 2,
+            kind: WriteDescriptorSetKind::StorageBuffer { buffer: output },
+        },
+        WriteDescriptorSet {
+            binding: 3,
+            kind: WriteDescriptorSetKind::StorageBuffer { buffer: dispatches },
+        },
+    ]);
+}
@@ -1,9 +1,9 @@
 use app::anyhow::Result;
 use app::glam::{vec3, Mat4};
-use app::vulkan::ash::vk::{self, Packed24_8};
+use app::vulkan::ash::vk;
 use app::vulkan::gpu_allocator::MemoryLocation;
 use app::vulkan::utils::*;
-use app::{vulkan::*, AppConfig, BaseApp};
+use app::{vulkan::*, AppConfig, BaseApp, SwapchainChange};
 use app::{App, ImageAndView};
 use gltf::Vertex;
 use gui::egui::{self, Widget};
@@ -57,7 +57,7 @@ impl App for Reflections {
 
         let top_as = create_top_as(context, &bottom_as)?;
 
-        let pipeline_res = create_pipeline(context, &model)?;
+        let pipeline_res = create_pipeline(context, &base.pipeline_cache, &model)?;
 
         let sbt = context.create_shader_binding_table(&pipeline_res.pipeline)?;
 
@@ -151,20 +151,14 @@ impl App for Reflections {
         Ok(())
     }
 
-    fn on_recreate_swapchain(&mut self, base: &BaseApp) -> Result<()> {
+    fn on_recreate_swapchain(&mut self, base: &BaseApp, _: SwapchainChange) -> Result<()> {
         base.storage_images
             .iter()
             .enumerate()
             .for_each(|(index, img)| {
                 let set = &self.descriptor_res.dynamic_sets[index];
 
-                set.update(&[WriteDescriptorSet {
-                    binding: 1,
-                    kind: WriteDescriptorSetKind::StorageImage {
-                        layout: vk::ImageLayout::GENERAL,
-                        view: &img.view,
-                    },
-                }]);
+                set.bind_storage_image(1, &img.view, vk::ImageLayout::GENERAL);
             });
 
         Ok(())
@@ -241,7 +235,6 @@ struct BottomAS {
 
 struct TopAS {
     inner: AccelerationStructure,
-    _instance_buffer: Buffer,
 }
 
 struct PipelineRes {
@@ -355,6 +348,7 @@ fn create_model(context: &Context) -> Result<Model> {
             vk::Format::R8G8B8A8_SRGB,
             width,
             height,
+            1,
         )?;
 
         context.execute_one_time_commands(|cmd| {
@@ -397,6 +391,7 @@ fn create_model(context: &Context) -> Result<Model> {
             vk::Format::R8G8B8A8_SRGB,
             1,
             1,
+            1,
         )?;
 
         context.execute_one_time_commands(|cmd| {
@@ -546,11 +541,14 @@ fn create_bottom_as(context: &mut Context, model: &Model) -> Result<BottomAS> {
         &geometry_infos,
     )?;
 
-    let inner = context.create_bottom_level_acceleration_structure(
+    let inner = context.create_bottom_level_acceleration_structure_with_flags(
         &as_geometries,
         &as_ranges,
         &max_primitive_counts,
+        vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+            | vk::BuildAccelerationStructureFlagsKHR::ALLOW_COMPACTION,
     )?;
+    let inner = context.compact_blas(inner)?;
 
     Ok(BottomAS {
         inner,
@@ -559,60 +557,20 @@ fn create_bottom_as(context: &mut Context, model: &Model) -> Result<BottomAS> {
 }
 
 fn create_top_as(context: &mut Context, bottom_as: &BottomAS) -> Result<TopAS> {
-    #[rustfmt::skip]
-    let transform_matrix = vk::TransformMatrixKHR { matrix: [
-        1.0, 0.0, 0.0, 0.0,
-        0.0, 1.0, 0.0, 0.0,
-        0.0, 0.0, 1.0, 0.0
-    ]};
-
-    let as_instance = vk::AccelerationStructureInstanceKHR {
-        transform: transform_matrix,
-        instance_custom_index_and_mask: Packed24_8::new(0, 0xFF),
-        instance_shader_binding_table_record_offset_and_flags: Packed24_8::new(
-            0,
-            vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw() as _,
-        ),
-        acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
-            device_handle: bottom_as.inner.address,
-        },
-    };
-
-    let instance_buffer = create_gpu_only_buffer_from_data(
-        context,
-        vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
-            | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
-        &[as_instance],
-    )?;
-    let instance_buffer_addr = instance_buffer.get_device_address();
-
-    let as_struct_geo = vk::AccelerationStructureGeometryKHR::default()
-        .geometry_type(vk::GeometryTypeKHR::INSTANCES)
-        .flags(vk::GeometryFlagsKHR::OPAQUE)
-        .geometry(vk::AccelerationStructureGeometryDataKHR {
-            instances: vk::AccelerationStructureGeometryInstancesDataKHR::default()
-                .array_of_pointers(false)
-                .data(vk::DeviceOrHostAddressConstKHR {
-                    device_address: instance_buffer_addr,
-                }),
-        });
-
-    let as_ranges = vk::AccelerationStructureBuildRangeInfoKHR::default()
-        .first_vertex(0)
-        .primitive_count(1)
-        .primitive_offset(0)
-        .transform_offset(0);
-
     let inner =
-        context.create_top_level_acceleration_structure(&[as_struct_geo], &[as_ranges], &[1])?;
+        context.create_top_level_acceleration_structure_from_instances(&[TlasInstance {
+            flags: vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE,
+            ..TlasInstance::new(bottom_as.inner.address)
+        }])?;
 
-    Ok(TopAS {
-        inner,
-        _instance_buffer: instance_buffer,
-    })
+    Ok(TopAS { inner })
 }
 
-fn create_pipeline(context: &Context, model: &Model) -> Result<PipelineRes> {
+fn create_pipeline(
+    context: &Context,
+    pipeline_cache: &PipelineCache,
+    model: &Model,
+) -> Result<PipelineRes> {
     // descriptor and pipeline layouts
     let static_layout_bindings = [
         vk::DescriptorSetLayoutBinding::default()
@@ -668,21 +626,25 @@ fn create_pipeline(context: &Context, model: &Model) -> Result<PipelineRes> {
         RayTracingShaderCreateInfo {
             source: &include_bytes!("../shaders/raygen.rgen.spv")[..],
             stage: vk::ShaderStageFlags::RAYGEN_KHR,
+            entry_point: "main",
             group: RayTracingShaderGroup::RayGen,
         },
         RayTracingShaderCreateInfo {
             source: &include_bytes!("../shaders/miss.rmiss.spv")[..],
             stage: vk::ShaderStageFlags::MISS_KHR,
+            entry_point: "main",
             group: RayTracingShaderGroup::Miss,
         },
         RayTracingShaderCreateInfo {
             source: &include_bytes!("../shaders/shadow.rmiss.spv")[..],
             stage: vk::ShaderStageFlags::MISS_KHR,
+            entry_point: "main",
             group: RayTracingShaderGroup::Miss,
         },
         RayTracingShaderCreateInfo {
             source: &include_bytes!("../shaders/closesthit.rchit.spv")[..],
             stage: vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+            entry_point: "main",
             group: RayTracingShaderGroup::ClosestHit,
         },
     ];
@@ -692,7 +654,11 @@ fn create_pipeline(context: &Context, model: &Model) -> Result<PipelineRes> {
         max_ray_recursion_depth: 2,
     };
 
-    let pipeline = context.create_ray_tracing_pipeline(&pipeline_layout, pipeline_create_info)?;
+    let pipeline = context.create_ray_tracing_pipeline(
+        &pipeline_layout,
+        pipeline_create_info,
+        Some(pipeline_cache),
+    )?;
 
     Ok(PipelineRes {
         pipeline,
@@ -771,24 +737,11 @@ fn create_descriptor_sets(
         let view = &model.views[*image_index];
         let sampler = &model.samplers[*sampler_index];
 
-        static_set.update(&[WriteDescriptorSet {
-            binding: 6,
-            kind: WriteDescriptorSetKind::CombinedImageSampler {
-                view,
-                sampler,
-                layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            },
-        }]);
+        static_set.bind_texture(6, view, sampler, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
     }
 
     dynamic_sets.iter().enumerate().for_each(|(index, set)| {
-        set.update(&[WriteDescriptorSet {
-            binding: 1,
-            kind: WriteDescriptorSetKind::StorageImage {
-                layout: vk::ImageLayout::GENERAL,
-                view: &storage_imgs[index].view,
-            },
-        }]);
+        set.bind_storage_image(1, &storage_imgs[index].view, vk::ImageLayout::GENERAL);
     });
 
     Ok(DescriptorRes {
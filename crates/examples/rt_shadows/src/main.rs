@@ -1,13 +1,13 @@
 use app::anyhow::Result;
 use app::glam::{vec3, Mat4};
-use app::vulkan::ash::vk::{self, Packed24_8};
+use app::vulkan::ash::vk;
 use app::vulkan::gpu_allocator::MemoryLocation;
 use app::vulkan::utils::*;
-use app::{vulkan::*, AppConfig, BaseApp};
+use app::{vulkan::*, AppConfig, BaseApp, SwapchainChange};
 use app::{App, ImageAndView};
 use gltf::Vertex;
 use gui::egui::{self, Widget};
-use std::mem::{size_of, size_of_val};
+use std::mem::size_of;
 use std::time::Duration;
 
 const WIDTH: u32 = 1920;
@@ -15,6 +15,7 @@ const HEIGHT: u32 = 1080;
 const APP_NAME: &str = "Ray traced shadows";
 
 const MODEL_PATH: &str = "./assets/models/shadows.glb";
+const TEXTURES_STAGING_SIZE: usize = 32 * 1024 * 1024;
 
 fn main() -> Result<()> {
     app::run::<Shadows>(
@@ -56,7 +57,7 @@ impl App for Shadows {
 
         let top_as = create_top_as(context, &bottom_as)?;
 
-        let pipeline_res = create_pipeline(context, &model)?;
+        let pipeline_res = create_pipeline(context, &base.pipeline_cache, &model)?;
 
         let sbt = context.create_shader_binding_table(&pipeline_res.pipeline)?;
 
@@ -149,20 +150,14 @@ impl App for Shadows {
         Ok(())
     }
 
-    fn on_recreate_swapchain(&mut self, base: &BaseApp) -> Result<()> {
+    fn on_recreate_swapchain(&mut self, base: &BaseApp, _: SwapchainChange) -> Result<()> {
         base.storage_images
             .iter()
             .enumerate()
             .for_each(|(index, img)| {
                 let set = &self.descriptor_res.dynamic_sets[index];
 
-                set.update(&[WriteDescriptorSet {
-                    binding: 1,
-                    kind: WriteDescriptorSetKind::StorageImage {
-                        layout: vk::ImageLayout::GENERAL,
-                        view: &img.view,
-                    },
-                }]);
+                set.bind_storage_image(1, &img.view, vk::ImageLayout::GENERAL);
             });
 
         Ok(())
@@ -228,7 +223,6 @@ struct BottomAS {
 
 struct TopAS {
     inner: AccelerationStructure,
-    _instance_buffer: Buffer,
 }
 
 struct PipelineRes {
@@ -321,50 +315,24 @@ fn create_model(context: &Context) -> Result<Model> {
     let mut images = vec![];
     let mut views = vec![];
 
+    // Textures are batched in as few submissions as the staging buffer allows
+    let largest_image_size = model.images.iter().map(|i| i.pixels.len()).max();
+    let total_images_size = model.images.iter().map(|i| i.pixels.len()).sum::<usize>();
+    let staging_size = total_images_size
+        .min(TEXTURES_STAGING_SIZE)
+        .max(largest_image_size.unwrap_or(1));
+    let mut uploader = context.create_uploader(staging_size as _)?;
     model.images.iter().try_for_each::<_, Result<_>>(|i| {
-        let width = i.width;
-        let height = i.height;
-        let pixels = i.pixels.as_slice();
-
-        let staging = context.create_buffer(
-            vk::BufferUsageFlags::TRANSFER_SRC,
-            MemoryLocation::CpuToGpu,
-            size_of_val(pixels) as _,
-        )?;
-
-        staging.copy_data_to_buffer(pixels)?;
-
         let image = context.create_image(
             vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
             MemoryLocation::GpuOnly,
             vk::Format::R8G8B8A8_SRGB,
-            width,
-            height,
+            i.width,
+            i.height,
+            1,
         )?;
 
-        context.execute_one_time_commands(|cmd| {
-            cmd.pipeline_image_barriers(&[ImageBarrier {
-                image: &image,
-                old_layout: vk::ImageLayout::UNDEFINED,
-                new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                src_access_mask: vk::AccessFlags2::NONE,
-                dst_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
-                src_stage_mask: vk::PipelineStageFlags2::NONE,
-                dst_stage_mask: vk::PipelineStageFlags2::TRANSFER,
-            }]);
-
-            cmd.copy_buffer_to_image(&staging, &image, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
-
-            cmd.pipeline_image_barriers(&[ImageBarrier {
-                image: &image,
-                old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
-                dst_access_mask: vk::AccessFlags2::SHADER_READ,
-                src_stage_mask: vk::PipelineStageFlags2::TRANSFER,
-                dst_stage_mask: vk::PipelineStageFlags2::RAY_TRACING_SHADER_KHR,
-            }]);
-        })?;
+        uploader.upload_image(&image, &i.pixels, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)?;
 
         let view = image.create_image_view(vk::ImageAspectFlags::COLOR)?;
 
@@ -373,6 +341,7 @@ fn create_model(context: &Context) -> Result<Model> {
 
         Ok(())
     })?;
+    uploader.flush()?;
 
     // Dummy textures
     if images.is_empty() {
@@ -382,6 +351,7 @@ fn create_model(context: &Context) -> Result<Model> {
             vk::Format::R8G8B8A8_SRGB,
             1,
             1,
+            1,
         )?;
 
         context.execute_one_time_commands(|cmd| {
@@ -530,11 +500,14 @@ fn create_bottom_as(context: &mut Context, model: &Model) -> Result<BottomAS> {
         &geometry_infos,
     )?;
 
-    let inner = context.create_bottom_level_acceleration_structure(
+    let inner = context.create_bottom_level_acceleration_structure_with_flags(
         &as_geometries,
         &as_ranges,
         &max_primitive_counts,
+        vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+            | vk::BuildAccelerationStructureFlagsKHR::ALLOW_COMPACTION,
     )?;
+    let inner = context.compact_blas(inner)?;
 
     Ok(BottomAS {
         inner,
@@ -543,60 +516,20 @@ fn create_bottom_as(context: &mut Context, model: &Model) -> Result<BottomAS> {
 }
 
 fn create_top_as(context: &mut Context, bottom_as: &BottomAS) -> Result<TopAS> {
-    #[rustfmt::skip]
-    let transform_matrix = vk::TransformMatrixKHR { matrix: [
-        1.0, 0.0, 0.0, 0.0,
-        0.0, 1.0, 0.0, 0.0,
-        0.0, 0.0, 1.0, 0.0
-    ]};
-
-    let as_instance = vk::AccelerationStructureInstanceKHR {
-        transform: transform_matrix,
-        instance_custom_index_and_mask: Packed24_8::new(0, 0xFF),
-        instance_shader_binding_table_record_offset_and_flags: Packed24_8::new(
-            0,
-            vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw() as _,
-        ),
-        acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
-            device_handle: bottom_as.inner.address,
-        },
-    };
-
-    let instance_buffer = create_gpu_only_buffer_from_data(
-        context,
-        vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
-            | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
-        &[as_instance],
-    )?;
-    let instance_buffer_addr = instance_buffer.get_device_address();
-
-    let as_struct_geo = vk::AccelerationStructureGeometryKHR::default()
-        .geometry_type(vk::GeometryTypeKHR::INSTANCES)
-        .flags(vk::GeometryFlagsKHR::OPAQUE)
-        .geometry(vk::AccelerationStructureGeometryDataKHR {
-            instances: vk::AccelerationStructureGeometryInstancesDataKHR::default()
-                .array_of_pointers(false)
-                .data(vk::DeviceOrHostAddressConstKHR {
-                    device_address: instance_buffer_addr,
-                }),
-        });
-
-    let as_ranges = vk::AccelerationStructureBuildRangeInfoKHR::default()
-        .first_vertex(0)
-        .primitive_count(1)
-        .primitive_offset(0)
-        .transform_offset(0);
-
     let inner =
-        context.create_top_level_acceleration_structure(&[as_struct_geo], &[as_ranges], &[1])?;
+        context.create_top_level_acceleration_structure_from_instances(&[TlasInstance {
+            flags: vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE,
+            ..TlasInstance::new(bottom_as.inner.address)
+        }])?;
 
-    Ok(TopAS {
-        inner,
-        _instance_buffer: instance_buffer,
-    })
+    Ok(TopAS { inner })
 }
 
-fn create_pipeline(context: &Context, model: &Model) -> Result<PipelineRes> {
+fn create_pipeline(
+    context: &Context,
+    pipeline_cache: &PipelineCache,
+    model: &Model,
+) -> Result<PipelineRes> {
     // descriptor and pipeline layouts
     let static_layout_bindings = [
         vk::DescriptorSetLayoutBinding::default()
@@ -652,21 +585,25 @@ fn create_pipeline(context: &Context, model: &Model) -> Result<PipelineRes> {
         RayTracingShaderCreateInfo {
             source: &include_bytes!("../shaders/raygen.rgen.spv")[..],
             stage: vk::ShaderStageFlags::RAYGEN_KHR,
+            entry_point: "main",
             group: RayTracingShaderGroup::RayGen,
         },
         RayTracingShaderCreateInfo {
             source: &include_bytes!("../shaders/miss.rmiss.spv")[..],
             stage: vk::ShaderStageFlags::MISS_KHR,
+            entry_point: "main",
             group: RayTracingShaderGroup::Miss,
         },
         RayTracingShaderCreateInfo {
             source: &include_bytes!("../shaders/shadow.rmiss.spv")[..],
             stage: vk::ShaderStageFlags::MISS_KHR,
+            entry_point: "main",
             group: RayTracingShaderGroup::Miss,
         },
         RayTracingShaderCreateInfo {
             source: &include_bytes!("../shaders/closesthit.rchit.spv")[..],
             stage: vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+            entry_point: "main",
             group: RayTracingShaderGroup::ClosestHit,
         },
     ];
@@ -676,7 +613,11 @@ fn create_pipeline(context: &Context, model: &Model) -> Result<PipelineRes> {
         max_ray_recursion_depth: 2,
     };
 
-    let pipeline = context.create_ray_tracing_pipeline(&pipeline_layout, pipeline_create_info)?;
+    let pipeline = context.create_ray_tracing_pipeline(
+        &pipeline_layout,
+        pipeline_create_info,
+        Some(pipeline_cache),
+    )?;
 
     Ok(PipelineRes {
         pipeline,
@@ -755,24 +696,11 @@ fn create_descriptor_sets(
         let view = &model.views[*image_index];
         let sampler = &model.samplers[*sampler_index];
 
-        static_set.update(&[WriteDescriptorSet {
-            binding: 6,
-            kind: WriteDescriptorSetKind::CombinedImageSampler {
-                view,
-                sampler,
-                layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            },
-        }]);
+        static_set.bind_texture(6, view, sampler, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
     }
 
     dynamic_sets.iter().enumerate().for_each(|(index, set)| {
-        set.update(&[WriteDescriptorSet {
-            binding: 1,
-            kind: WriteDescriptorSetKind::StorageImage {
-                layout: vk::ImageLayout::GENERAL,
-                view: &storage_imgs[index].view,
-            },
-        }]);
+        set.bind_storage_image(1, &storage_imgs[index].view, vk::ImageLayout::GENERAL);
     });
 
     Ok(DescriptorRes {
@@ -1,7 +1,9 @@
 use app::anyhow::Result;
-use app::vulkan::ash::vk::{self, Packed24_8};
+use app::glam::Mat3;
+use app::vulkan::ash::vk;
+use app::vulkan::gpu_allocator::MemoryLocation;
 use app::vulkan::utils::*;
-use app::{vulkan::*, AppConfig, BaseApp};
+use app::{vulkan::*, AppConfig, BaseApp, SwapchainChange};
 use app::{App, ImageAndView};
 use std::mem::size_of;
 use std::time::Duration;
@@ -9,6 +11,8 @@ use std::time::Duration;
 const WIDTH: u32 = 1024;
 const HEIGHT: u32 = 576;
 const APP_NAME: &str = "Ray traced triangle";
+/// Rotation speed of the triangle instance in radians per second.
+const ROTATION_SPEED: f32 = 0.5;
 
 fn main() -> Result<()> {
     app::run::<Triangle>(
@@ -24,7 +28,8 @@ fn main() -> Result<()> {
 
 struct Triangle {
     _bottom_as: BottomAS,
-    _top_as: TopAS,
+    top_as: TopAS,
+    angle: f32,
     pipeline_res: PipelineRes,
     sbt: ShaderBindingTable,
     descriptor_res: DescriptorRes,
@@ -38,9 +43,9 @@ impl App for Triangle {
 
         let bottom_as = create_bottom_as(context)?;
 
-        let top_as = create_top_as(context, &bottom_as)?;
+        let top_as = create_top_as(context, &bottom_as, base.swapchain.images.len())?;
 
-        let pipeline_res = create_pipeline(context)?;
+        let pipeline_res = create_pipeline(context, &base.pipeline_cache)?;
 
         let sbt = context.create_shader_binding_table(&pipeline_res.pipeline)?;
 
@@ -53,7 +58,8 @@ impl App for Triangle {
 
         Ok(Self {
             _bottom_as: bottom_as,
-            _top_as: top_as,
+            top_as,
+            angle: 0.0,
             pipeline_res,
             sbt,
             descriptor_res,
@@ -64,9 +70,14 @@ impl App for Triangle {
         &mut self,
         _: &mut BaseApp,
         _: &mut <Self as App>::Gui,
-        _: usize,
-        _: Duration,
+        image_index: usize,
+        delta_time: Duration,
     ) -> Result<()> {
+        self.angle += ROTATION_SPEED * delta_time.as_secs_f32();
+
+        let instance = triangle_instance(self.top_as.bottom_as_address, self.angle);
+        self.top_as.instance_buffers[image_index].copy_data_to_buffer(&[instance])?;
+
         Ok(())
     }
 
@@ -79,6 +90,15 @@ impl App for Triangle {
         let static_set = &self.descriptor_res.static_set;
         let dynamic_set = &self.descriptor_res.dynamic_sets[image_index];
 
+        // Refit the TLAS with this frame's instance transform instead of rebuilding it
+        self.top_as.inner.update(
+            buffer,
+            &[instances_geometry(
+                &self.top_as.instance_buffers[image_index],
+            )],
+            &[instances_build_range()],
+        )?;
+
         buffer.bind_rt_pipeline(&self.pipeline_res.pipeline);
 
         buffer.bind_descriptor_sets(
@@ -97,20 +117,14 @@ impl App for Triangle {
         Ok(())
     }
 
-    fn on_recreate_swapchain(&mut self, base: &BaseApp) -> Result<()> {
+    fn on_recreate_swapchain(&mut self, base: &BaseApp, _: SwapchainChange) -> Result<()> {
         base.storage_images
             .iter()
             .enumerate()
             .for_each(|(index, img)| {
                 let set = &self.descriptor_res.dynamic_sets[index];
 
-                set.update(&[WriteDescriptorSet {
-                    binding: 1,
-                    kind: WriteDescriptorSetKind::StorageImage {
-                        layout: vk::ImageLayout::GENERAL,
-                        view: &img.view,
-                    },
-                }]);
+                set.bind_storage_image(1, &img.view, vk::ImageLayout::GENERAL);
             });
 
         Ok(())
@@ -125,7 +139,30 @@ struct BottomAS {
 
 struct TopAS {
     inner: AccelerationStructure,
-    _instance_buffer: Buffer,
+    bottom_as_address: u64,
+    /// One per swapchain image, written by the CPU while other frames are in flight.
+    instance_buffers: Vec<Buffer>,
+}
+
+/// Instance of the triangle rotated by `angle` radians around the Z axis.
+fn triangle_instance(bottom_as_address: u64, angle: f32) -> vk::AccelerationStructureInstanceKHR {
+    let rotation = Mat3::from_rotation_z(angle);
+    let [x, y, z] = [rotation.row(0), rotation.row(1), rotation.row(2)];
+
+    #[rustfmt::skip]
+    let transform_matrix = vk::TransformMatrixKHR { matrix: [
+        x.x, x.y, x.z, 0.0,
+        y.x, y.y, y.z, 0.0,
+        z.x, z.y, z.z, 0.0
+    ]};
+
+    // Single hit group at SBT offset 0, see ShaderBindingTable for how the offset selects it
+    TlasInstance {
+        transform: transform_matrix,
+        flags: vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE,
+        ..TlasInstance::new(bottom_as_address)
+    }
+    .into()
 }
 
 struct PipelineRes {
@@ -211,67 +248,60 @@ fn create_bottom_as(context: &mut Context) -> Result<BottomAS> {
     })
 }
 
-fn create_top_as(context: &mut Context, bottom_as: &BottomAS) -> Result<TopAS> {
-    #[rustfmt::skip]
-    let transform_matrix = vk::TransformMatrixKHR { matrix: [
-        1.0, 0.0, 0.0, 0.0,
-        0.0, 1.0, 0.0, 0.0,
-        0.0, 0.0, 1.0, 0.0
-    ]};
+fn create_top_as(context: &mut Context, bottom_as: &BottomAS, image_count: usize) -> Result<TopAS> {
+    let instance_buffers = (0..image_count)
+        .map(|_| {
+            context.create_buffer(
+                vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                    | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
+                MemoryLocation::CpuToGpu,
+                size_of::<vk::AccelerationStructureInstanceKHR>() as _,
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
 
-    let as_instance = vk::AccelerationStructureInstanceKHR {
-        transform: transform_matrix,
-        instance_custom_index_and_mask: Packed24_8::new(0, 0xFF),
-        instance_shader_binding_table_record_offset_and_flags: Packed24_8::new(
-            0,
-            vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE
-                .as_raw()
-                .try_into()
-                .unwrap(),
-        ),
-        acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
-            device_handle: bottom_as.inner.address,
-        },
-    };
+    let bottom_as_address = bottom_as.inner.address;
+    for buffer in &instance_buffers {
+        buffer.copy_data_to_buffer(&[triangle_instance(bottom_as_address, 0.0)])?;
+    }
 
-    let instance_buffer = create_gpu_only_buffer_from_data(
-        context,
-        vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
-            | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
-        &[as_instance],
+    let inner = context.create_top_level_acceleration_structure_with_flags(
+        &[instances_geometry(&instance_buffers[0])],
+        &[instances_build_range()],
+        &[1],
+        vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+            | vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE,
     )?;
-    let instance_buffer_addr = instance_buffer.get_device_address();
 
-    let as_struct_geo = vk::AccelerationStructureGeometryKHR::default()
+    Ok(TopAS {
+        inner,
+        bottom_as_address,
+        instance_buffers,
+    })
+}
+
+fn instances_geometry(instance_buffer: &Buffer) -> vk::AccelerationStructureGeometryKHR<'static> {
+    vk::AccelerationStructureGeometryKHR::default()
         .geometry_type(vk::GeometryTypeKHR::INSTANCES)
         .flags(vk::GeometryFlagsKHR::OPAQUE)
         .geometry(vk::AccelerationStructureGeometryDataKHR {
             instances: vk::AccelerationStructureGeometryInstancesDataKHR::default()
                 .array_of_pointers(false)
                 .data(vk::DeviceOrHostAddressConstKHR {
-                    device_address: instance_buffer_addr,
+                    device_address: instance_buffer.get_device_address(),
                 }),
-        });
+        })
+}
 
-    let build_range_info = vk::AccelerationStructureBuildRangeInfoKHR::default()
+fn instances_build_range() -> vk::AccelerationStructureBuildRangeInfoKHR {
+    vk::AccelerationStructureBuildRangeInfoKHR::default()
         .first_vertex(0)
         .primitive_count(1)
         .primitive_offset(0)
-        .transform_offset(0);
-
-    let inner = context.create_top_level_acceleration_structure(
-        &[as_struct_geo],
-        &[build_range_info],
-        &[1],
-    )?;
-
-    Ok(TopAS {
-        inner,
-        _instance_buffer: instance_buffer,
-    })
+        .transform_offset(0)
 }
 
-fn create_pipeline(context: &Context) -> Result<PipelineRes> {
+fn create_pipeline(context: &Context, pipeline_cache: &PipelineCache) -> Result<PipelineRes> {
     // descriptor and pipeline layouts
     let static_layout_bindings = [vk::DescriptorSetLayoutBinding::default()
         .binding(0)
@@ -296,16 +326,19 @@ fn create_pipeline(context: &Context) -> Result<PipelineRes> {
         RayTracingShaderCreateInfo {
             source: &include_bytes!("../shaders/raygen.rgen.spv")[..],
             stage: vk::ShaderStageFlags::RAYGEN_KHR,
+            entry_point: "main",
             group: RayTracingShaderGroup::RayGen,
         },
         RayTracingShaderCreateInfo {
             source: &include_bytes!("../shaders/miss.rmiss.spv")[..],
             stage: vk::ShaderStageFlags::MISS_KHR,
+            entry_point: "main",
             group: RayTracingShaderGroup::Miss,
         },
         RayTracingShaderCreateInfo {
             source: &include_bytes!("../shaders/closesthit.rchit.spv")[..],
             stage: vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+            entry_point: "main",
             group: RayTracingShaderGroup::ClosestHit,
         },
     ];
@@ -315,7 +348,11 @@ fn create_pipeline(context: &Context) -> Result<PipelineRes> {
         max_ray_recursion_depth: 1,
     };
 
-    let pipeline = context.create_ray_tracing_pipeline(&pipeline_layout, pipeline_create_info)?;
+    let pipeline = context.create_ray_tracing_pipeline(
+        &pipeline_layout,
+        pipeline_create_info,
+        Some(pipeline_cache),
+    )?;
 
     Ok(PipelineRes {
         pipeline,
@@ -355,13 +392,7 @@ fn create_descriptor_sets(
     }]);
 
     dynamic_sets.iter().enumerate().for_each(|(index, set)| {
-        set.update(&[WriteDescriptorSet {
-            binding: 1,
-            kind: WriteDescriptorSetKind::StorageImage {
-                layout: vk::ImageLayout::GENERAL,
-                view: &storage_imgs[index].view,
-            },
-        }]);
+        set.bind_storage_image(1, &storage_imgs[index].view, vk::ImageLayout::GENERAL);
     });
 
     Ok(DescriptorRes {
@@ -1,9 +1,13 @@
 #version 450
 
+layout(push_constant) uniform Constants {
+    vec4 tint;
+} constants;
+
 layout(location = 0) in vec3 oColor;
 
 layout(location = 0) out vec4 finalColor;
 
 void main() {
-    finalColor = vec4(oColor, 1.0);
+    finalColor = vec4(oColor * constants.tint.rgb, 1.0);
 }
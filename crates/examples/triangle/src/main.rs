@@ -5,70 +5,135 @@ use app::anyhow::Result;
 use app::vulkan::ash::vk;
 use app::vulkan::utils::create_gpu_only_buffer_from_data;
 use app::vulkan::{
-    Buffer, ColorAttachmentsInfo, Context, GraphicsPipeline, GraphicsPipelineCreateInfo,
-    GraphicsShaderCreateInfo, PipelineLayout, RenderingAttachment,
+    read_shader_file, Buffer, ColorAttachmentsInfo, CommandBuffer, Context, GraphicsPipeline,
+    GraphicsPipelineCreateInfo, GraphicsShaderCreateInfo, PipelineLayout, RenderingAttachment,
 };
-use app::{App, BaseApp};
+use app::winit::event::{ElementState, KeyEvent, WindowEvent};
+use app::winit::keyboard::{KeyCode, PhysicalKey};
+use app::{log, App, AppConfig, Background, BaseApp, FramePacing};
+use gui::egui;
 
 const WIDTH: u32 = 1024;
 const HEIGHT: u32 = 576;
 const APP_NAME: &str = "Triangle";
 
+// Read when reloading shaders so recompiled SPIR-V is picked up without rebuilding
+const VERTEX_SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/shader.vert.spv");
+const FRAGMENT_SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/shader.frag.spv");
+
 fn main() -> Result<()> {
-    app::run::<Triangle>(APP_NAME, WIDTH, HEIGHT, Default::default())
+    app::run::<Triangle>(
+        APP_NAME,
+        WIDTH,
+        HEIGHT,
+        AppConfig {
+            frame_pacing: FramePacing::TimelineSemaphore,
+            ..Default::default()
+        },
+    )
 }
 struct Triangle {
     vertex_buffer: Buffer,
-    _pipeline_layout: PipelineLayout,
+    pipeline_layout: PipelineLayout,
     pipeline: GraphicsPipeline,
+    tint: [f32; 3],
 }
 
 impl App for Triangle {
-    type Gui = ();
+    type Gui = Gui;
 
     fn new(base: &mut BaseApp) -> Result<Self> {
         let context = &mut base.context;
 
         let vertex_buffer = create_vertex_buffer(context)?;
 
-        let pipeline_layout = context.create_pipeline_layout(&[])?;
+        let pipeline_layout = context.create_pipeline_layout_with_push_constants(
+            &[],
+            &[vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: size_of::<Constants>() as _,
+            }],
+        )?;
+
+        let shaders = shaders(
+            include_bytes!("../shaders/shader.vert.spv"),
+            include_bytes!("../shaders/shader.frag.spv"),
+        );
+        let pipeline = context.create_graphics_pipeline::<Vertex>(
+            &pipeline_layout,
+            pipeline_create_info(&shaders, &[base.swapchain.format]),
+            Some(&base.pipeline_cache),
+        )?;
 
-        let pipeline = create_pipeline(context, &pipeline_layout, base.swapchain.format)?;
+        base.set_background(Some(Background::Gradient {
+            top: [0.3, 0.3, 0.35],
+            bottom: [0.05, 0.05, 0.08],
+        }));
 
         Ok(Self {
             vertex_buffer,
-            _pipeline_layout: pipeline_layout,
+            pipeline_layout,
             pipeline,
+            tint: NO_TINT,
         })
     }
 
-    fn on_recreate_swapchain(&mut self, _: &BaseApp) -> Result<()> {
-        Ok(())
+    fn on_window_event(&mut self, base: &mut BaseApp, event: &WindowEvent) {
+        if let WindowEvent::KeyboardInput {
+            event:
+                KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::F5),
+                    repeat: false,
+                    ..
+                },
+            ..
+        } = event
+        {
+            if let Err(err) = self.reload_shaders(base) {
+                log::error!("Failed to reload shaders, keeping the previous ones. Cause: {err}");
+            }
+        }
     }
 
     fn update(
         &mut self,
         _: &mut BaseApp,
-        _: &mut <Self as App>::Gui,
+        gui: &mut <Self as App>::Gui,
         _: usize,
         _: Duration,
     ) -> Result<()> {
+        self.tint = gui.tint;
+
         Ok(())
     }
 
-    fn record_raster_commands(&self, base: &BaseApp, image_index: usize) -> Result<()> {
-        let buffer = &base.command_buffers[image_index];
-
+    fn record_raster_commands(
+        &self,
+        base: &BaseApp,
+        buffer: &CommandBuffer,
+        image_index: usize,
+    ) -> Result<()> {
         buffer.begin_rendering(
-            &[RenderingAttachment {
-                view: &base.swapchain.views[image_index],
-                load_op: vk::AttachmentLoadOp::CLEAR,
-                clear_value: None,
-            }],
+            // Drawn over the background
+            &[RenderingAttachment::load(
+                &base.swapchain.views[image_index],
+            )],
             None,
             base.swapchain.extent,
         );
         buffer.bind_graphics_pipeline(&self.pipeline);
+        let [r, g, b] = self.tint;
+        let constants = Constants {
+            tint: [r, g, b, 1.0],
+        };
+        buffer.push_constants(
+            &self.pipeline_layout,
+            vk::ShaderStageFlags::FRAGMENT,
+            0,
+            &constants,
+        );
         buffer.bind_vertex_buffer(&self.vertex_buffer);
         buffer.set_viewport(base.swapchain.extent);
         buffer.set_scissor(base.swapchain.extent);
@@ -79,6 +144,61 @@ impl App for Triangle {
     }
 }
 
+impl Triangle {
+    fn reload_shaders(&mut self, base: &BaseApp) -> Result<()> {
+        let vertex_source = read_shader_file(VERTEX_SHADER_PATH)?;
+        let fragment_source = read_shader_file(FRAGMENT_SHADER_PATH)?;
+        let shaders = shaders(&vertex_source, &fragment_source);
+
+        // a broken shader leaves the current pipeline untouched
+        self.pipeline.reload::<Vertex>(
+            &base.context,
+            &self.pipeline_layout,
+            pipeline_create_info(&shaders, &[base.swapchain.format]),
+            Some(&base.pipeline_cache),
+        )?;
+        log::info!("Reloaded shaders");
+
+        Ok(())
+    }
+}
+
+/// Tint applied when no tint key is held, leaving the vertex colors untouched.
+const NO_TINT: [f32; 3] = [1.0; 3];
+const TINT_KEYS: [(egui::Key, [f32; 3]); 3] = [
+    (egui::Key::Num1, [1.0, 0.2, 0.2]),
+    (egui::Key::Num2, [0.2, 1.0, 0.2]),
+    (egui::Key::Num3, [0.2, 0.2, 1.0]),
+];
+
+/// Tints the triangle while 1, 2 or 3 is held. There is no window, keys are read from the
+/// inputs egui receives.
+struct Gui {
+    tint: [f32; 3],
+}
+
+impl app::Gui for Gui {
+    fn new(_: &BaseApp) -> Result<Self> {
+        Ok(Self { tint: NO_TINT })
+    }
+
+    fn build(&mut self, ctx: &egui::Context) {
+        self.tint = ctx.input(|i| {
+            TINT_KEYS
+                .iter()
+                .find(|(key, _)| i.key_down(*key))
+                .map_or(NO_TINT, |(_, tint)| *tint)
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+#[repr(C)]
+struct Constants {
+    tint: [f32; 4],
+}
+
 #[derive(Debug, Clone, Copy)]
 #[allow(dead_code)]
 #[repr(C)]
@@ -136,36 +256,48 @@ fn create_vertex_buffer(context: &Context) -> Result<Buffer> {
     Ok(vertex_buffer)
 }
 
-fn create_pipeline(
-    context: &Context,
-    layout: &PipelineLayout,
-    color_attachment_format: vk::Format,
-) -> Result<GraphicsPipeline> {
-    context.create_graphics_pipeline::<Vertex>(
-        layout,
-        GraphicsPipelineCreateInfo {
-            shaders: &[
-                GraphicsShaderCreateInfo {
-                    source: &include_bytes!("../shaders/shader.vert.spv")[..],
-                    stage: vk::ShaderStageFlags::VERTEX,
-                },
-                GraphicsShaderCreateInfo {
-                    source: &include_bytes!("../shaders/shader.frag.spv")[..],
-                    stage: vk::ShaderStageFlags::FRAGMENT,
-                },
-            ],
-            primitive_topology: vk::PrimitiveTopology::TRIANGLE_LIST,
-            cull_mode: vk::CullModeFlags::BACK,
-            extent: None,
-            color_attachments: ColorAttachmentsInfo {
-                formats: &[color_attachment_format],
-                blends: &[vk::PipelineColorBlendAttachmentState {
-                    color_write_mask: vk::ColorComponentFlags::RGBA,
-                    ..Default::default()
-                }],
-            },
-            depth: None,
-            dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+/// Opaque blending, every color component written.
+const COLOR_BLEND: vk::PipelineColorBlendAttachmentState = vk::PipelineColorBlendAttachmentState {
+    blend_enable: vk::FALSE,
+    src_color_blend_factor: vk::BlendFactor::ONE,
+    dst_color_blend_factor: vk::BlendFactor::ZERO,
+    color_blend_op: vk::BlendOp::ADD,
+    src_alpha_blend_factor: vk::BlendFactor::ONE,
+    dst_alpha_blend_factor: vk::BlendFactor::ZERO,
+    alpha_blend_op: vk::BlendOp::ADD,
+    color_write_mask: vk::ColorComponentFlags::RGBA,
+};
+
+fn shaders<'a>(
+    vertex_source: &'a [u8],
+    fragment_source: &'a [u8],
+) -> [GraphicsShaderCreateInfo<'a>; 2] {
+    [
+        GraphicsShaderCreateInfo {
+            source: vertex_source,
+            stage: vk::ShaderStageFlags::VERTEX,
+            ..Default::default()
         },
-    )
+        GraphicsShaderCreateInfo {
+            source: fragment_source,
+            stage: vk::ShaderStageFlags::FRAGMENT,
+            ..Default::default()
+        },
+    ]
+}
+
+fn pipeline_create_info<'a>(
+    shaders: &'a [GraphicsShaderCreateInfo<'a>],
+    color_attachment_formats: &'a [vk::Format],
+) -> GraphicsPipelineCreateInfo<'a> {
+    GraphicsPipelineCreateInfo {
+        shaders,
+        cull_mode: vk::CullModeFlags::BACK,
+        color_attachments: ColorAttachmentsInfo {
+            formats: color_attachment_formats,
+            blends: &[COLOR_BLEND],
+        },
+        dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+        ..Default::default()
+    }
 }
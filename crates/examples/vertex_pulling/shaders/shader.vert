@@ -0,0 +1,20 @@
+#version 450
+
+struct Vertex {
+    vec4 position;
+    vec4 color;
+};
+
+layout(std430, set = 0, binding = 0) readonly buffer Vertices {
+    Vertex vertices[];
+};
+
+layout(location = 0) out vec3 oColor;
+
+void main() {
+    Vertex vertex = vertices[gl_VertexIndex];
+
+    oColor = vertex.color.rgb;
+
+    gl_Position = vec4(vertex.position.xyz, 1.0);
+}
@@ -0,0 +1,199 @@
+use std::time::Duration;
+
+use app::anyhow::Result;
+use app::vulkan::ash::vk;
+use app::vulkan::utils::create_gpu_only_buffer_from_data;
+use app::vulkan::{
+    Buffer, ClearValue, ColorAttachmentsInfo, CommandBuffer, Context, DescriptorPool,
+    DescriptorSet, DescriptorSetLayout, EmptyVertex, GraphicsPipeline, GraphicsPipelineCreateInfo,
+    GraphicsShaderCreateInfo, PipelineCache, PipelineLayout, RenderingAttachment,
+};
+use app::{App, BaseApp, SwapchainChange};
+
+const WIDTH: u32 = 1024;
+const HEIGHT: u32 = 576;
+const APP_NAME: &str = "Vertex pulling";
+
+fn main() -> Result<()> {
+    app::run::<VertexPulling>(APP_NAME, WIDTH, HEIGHT, Default::default())
+}
+
+struct VertexPulling {
+    vertex_count: u32,
+    _vertex_buffer: Buffer,
+    _descriptor_pool: DescriptorPool,
+    _descriptor_layout: DescriptorSetLayout,
+    descriptor_set: DescriptorSet,
+    pipeline_layout: PipelineLayout,
+    pipeline: GraphicsPipeline,
+}
+
+impl App for VertexPulling {
+    type Gui = ();
+
+    fn new(base: &mut BaseApp) -> Result<Self> {
+        let context = &mut base.context;
+
+        let vertices = create_vertices();
+        let vertex_buffer = create_gpu_only_buffer_from_data(
+            context,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            &vertices,
+        )?;
+
+        let descriptor_pool = context.create_descriptor_pool(
+            1,
+            &[vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+            }],
+        )?;
+
+        let descriptor_layout =
+            context.create_descriptor_set_layout(&[vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_count: 1,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            }])?;
+
+        let descriptor_set = descriptor_pool.allocate_set(&descriptor_layout)?;
+        descriptor_set.bind_storage_buffer(0, &vertex_buffer);
+
+        let pipeline_layout = context.create_pipeline_layout(&[&descriptor_layout])?;
+
+        let pipeline = create_pipeline(
+            context,
+            &base.pipeline_cache,
+            &pipeline_layout,
+            base.swapchain.format,
+        )?;
+
+        Ok(Self {
+            vertex_count: vertices.len() as _,
+            _vertex_buffer: vertex_buffer,
+            _descriptor_pool: descriptor_pool,
+            _descriptor_layout: descriptor_layout,
+            descriptor_set,
+            pipeline_layout,
+            pipeline,
+        })
+    }
+
+    fn on_recreate_swapchain(&mut self, base: &BaseApp, change: SwapchainChange) -> Result<()> {
+        if change.format_changed() {
+            self.pipeline = create_pipeline(
+                &base.context,
+                &base.pipeline_cache,
+                &self.pipeline_layout,
+                base.swapchain.format,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn update(
+        &mut self,
+        _: &mut BaseApp,
+        _: &mut <Self as App>::Gui,
+        _: usize,
+        _: Duration,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn record_raster_commands(
+        &self,
+        base: &BaseApp,
+        buffer: &CommandBuffer,
+        image_index: usize,
+    ) -> Result<()> {
+        buffer.begin_rendering(
+            &[RenderingAttachment::clear(
+                &base.swapchain.views[image_index],
+                ClearValue::ColorFloat([1.0; 4]),
+            )],
+            None,
+            base.swapchain.extent,
+        );
+        buffer.bind_graphics_pipeline(&self.pipeline);
+        buffer.bind_descriptor_sets(
+            vk::PipelineBindPoint::GRAPHICS,
+            &self.pipeline_layout,
+            0,
+            &[&self.descriptor_set],
+        );
+        buffer.set_viewport(base.swapchain.extent);
+        buffer.set_scissor(base.swapchain.extent);
+        // No vertex buffer is bound, the vertex shader reads vertices[gl_VertexIndex]
+        buffer.draw(self.vertex_count);
+        buffer.end_rendering();
+
+        Ok(())
+    }
+}
+
+/// Matches the std430 layout of the vertex shader's `Vertex` struct.
+/// vec3 are padded to 16 bytes in std430 so vec4 are used to avoid implicit padding.
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+#[repr(C)]
+struct Vertex {
+    position: [f32; 4],
+    color: [f32; 4],
+}
+
+fn create_vertices() -> Vec<Vertex> {
+    vec![
+        Vertex {
+            position: [-1.0, 1.0, 0.0, 1.0],
+            color: [1.0, 0.0, 0.0, 1.0],
+        },
+        Vertex {
+            position: [1.0, 1.0, 0.0, 1.0],
+            color: [0.0, 1.0, 0.0, 1.0],
+        },
+        Vertex {
+            position: [0.0, -1.0, 0.0, 1.0],
+            color: [0.0, 0.0, 1.0, 1.0],
+        },
+    ]
+}
+
+fn create_pipeline(
+    context: &Context,
+    pipeline_cache: &PipelineCache,
+    layout: &PipelineLayout,
+    color_attachment_format: vk::Format,
+) -> Result<GraphicsPipeline> {
+    context.create_graphics_pipeline::<EmptyVertex>(
+        layout,
+        GraphicsPipelineCreateInfo {
+            shaders: &[
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/shader.vert.spv")[..],
+                    stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
+                },
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/shader.frag.spv")[..],
+                    stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ],
+            cull_mode: vk::CullModeFlags::BACK,
+            color_attachments: ColorAttachmentsInfo {
+                formats: &[color_attachment_format],
+                blends: &[vk::PipelineColorBlendAttachmentState {
+                    color_write_mask: vk::ColorComponentFlags::RGBA,
+                    ..Default::default()
+                }],
+            },
+            dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
+        },
+        Some(pipeline_cache),
+    )
+}
@@ -7,12 +7,12 @@ use app::vulkan::ash::vk::{self, PipelineBindPoint};
 use app::vulkan::gpu_allocator::MemoryLocation;
 use app::vulkan::utils::{compute_aligned_size_of, create_gpu_only_buffer_from_data};
 use app::vulkan::{
-    Buffer, ClearValue, ColorAttachmentsInfo, Context, DepthInfo, DescriptorPool, DescriptorSet,
-    DescriptorSetLayout, GraphicsPipeline, GraphicsPipelineCreateInfo, GraphicsShaderCreateInfo,
-    Image, ImageBarrier, ImageView, PipelineLayout, RenderingAttachment, Sampler,
-    WriteDescriptorSet, WriteDescriptorSetKind,
+    Buffer, ClearValue, ColorAttachmentsInfo, CommandBuffer, Context, DepthInfo, DescriptorPool,
+    DescriptorSet, DescriptorSetLayout, GraphicsPipeline, GraphicsPipelineCreateInfo,
+    GraphicsShaderCreateInfo, Image, ImageBarrier, ImageView, PipelineCache, PipelineLayout,
+    RenderingAttachment, ResolveAttachment, Sampler, WriteDescriptorSet, WriteDescriptorSetKind,
 };
-use app::{App, AppConfig, BaseApp};
+use app::{anyhow, App, AppConfig, BaseApp, SwapchainChange};
 use gui::egui::{self, Widget};
 
 const WIDTH: u32 = 1920;
@@ -25,6 +25,18 @@ const DEPTH_BUFFER_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
 const WEIGHT_COLORS_FB_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
 const REVEAL_FB_FORMAT: vk::Format = vk::Format::R8_UNORM;
 
+// Colors of the passes in debugging tools like RenderDoc
+const OPAQUE_PASS_LABEL_COLOR: [f32; 4] = [0.2, 0.4, 0.8, 1.0];
+const TRANSPARENT_PASS_LABEL_COLOR: [f32; 4] = [0.8, 0.4, 0.2, 1.0];
+const COMPOSITE_PASS_LABEL_COLOR: [f32; 4] = [0.2, 0.8, 0.4, 1.0];
+
+const SAMPLE_COUNTS: [(vk::SampleCountFlags, &str); 4] = [
+    (vk::SampleCountFlags::TYPE_1, "1x"),
+    (vk::SampleCountFlags::TYPE_2, "2x"),
+    (vk::SampleCountFlags::TYPE_4, "4x"),
+    (vk::SampleCountFlags::TYPE_8, "8x"),
+];
+
 fn main() -> Result<()> {
     app::run::<Triangle>(
         APP_NAME,
@@ -32,6 +44,9 @@ fn main() -> Result<()> {
         HEIGHT,
         AppConfig {
             enable_independent_blend: true,
+            enable_fill_mode_non_solid: true,
+            // blending weighted colors is only correct on linear values
+            linear_workflow: true,
             ..Default::default()
         },
     )
@@ -41,8 +56,11 @@ struct Triangle {
 
     frame_ubo: Buffer,
     instance_ubo: Buffer,
-    ubo_alignment: vk::DeviceSize,
     vertex_buffer: Buffer,
+    samples: vk::SampleCountFlags,
+    wireframe: bool,
+    opaque_cull_mode: vk::CullModeFlags,
+    transparent_cull_mode: vk::CullModeFlags,
     opaque_pass: Pass,
     depth_buffer: Texture,
 
@@ -50,6 +68,8 @@ struct Triangle {
     weighted_colors_fb: Texture,
     reveal_fb: Texture,
 
+    msaa_targets: Option<MsaaTargets>,
+
     quad_vertex_buffer: Buffer,
 
     composite_pass: Pass,
@@ -71,28 +91,39 @@ impl App for Triangle {
         let ubo_alignment = context
             .physical_device_limits()
             .min_uniform_buffer_offset_alignment;
-        let instance_ubo = context.create_buffer(
+        let instance_ubo = context.create_buffer_aligned(
             vk::BufferUsageFlags::UNIFORM_BUFFER,
             MemoryLocation::CpuToGpu,
             MAX_INSTANCES as vk::DeviceSize * compute_aligned_size_of::<InstanceUbo>(ubo_alignment),
+            ubo_alignment,
         )?;
 
         let vertex_buffer = create_vertex_buffer(context)?;
 
-        let geometry_pass =
-            create_opaque_pass(context, &frame_ubo, &instance_ubo, base.swapchain.format)?;
+        let samples = vk::SampleCountFlags::TYPE_1;
+        let wireframe = false;
 
-        let transparent_pass = create_transparent_pass(context, &frame_ubo, &instance_ubo)?;
+        let geometry_pass = create_opaque_pass(
+            context,
+            &base.pipeline_cache,
+            &frame_ubo,
+            &instance_ubo,
+            base.swapchain.format,
+            samples,
+            polygon_mode(wireframe),
+        )?;
 
-        let depth_buffer = Texture::create_framebuffer(
+        let transparent_pass = create_transparent_pass(
             context,
-            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
-            base.swapchain.extent,
-            DEPTH_BUFFER_FORMAT,
-            vk::ImageAspectFlags::DEPTH,
-            false,
+            &base.pipeline_cache,
+            &frame_ubo,
+            &instance_ubo,
+            samples,
+            polygon_mode(wireframe),
         )?;
 
+        let depth_buffer = create_depth_buffer(context, base.swapchain.extent, samples)?;
+
         let weighted_colors_fb = Texture::create_framebuffer(
             context,
             vk::ImageUsageFlags::COLOR_ATTACHMENT,
@@ -100,6 +131,7 @@ impl App for Triangle {
             WEIGHT_COLORS_FB_FORMAT,
             vk::ImageAspectFlags::COLOR,
             true,
+            vk::SampleCountFlags::TYPE_1,
         )?;
 
         let reveal_fb = Texture::create_framebuffer(
@@ -109,11 +141,20 @@ impl App for Triangle {
             REVEAL_FB_FORMAT,
             vk::ImageAspectFlags::COLOR,
             true,
+            vk::SampleCountFlags::TYPE_1,
+        )?;
+
+        let msaa_targets = MsaaTargets::create(
+            context,
+            base.swapchain.extent,
+            base.swapchain.format,
+            samples,
         )?;
 
         let quad_vertex_buffer = create_quad_vertex_buffer(context)?;
         let composite_pass = create_composite_pass(
             context,
+            &base.pipeline_cache,
             &weighted_colors_fb,
             &reveal_fb,
             base.swapchain.format,
@@ -124,8 +165,11 @@ impl App for Triangle {
 
             frame_ubo,
             instance_ubo,
-            ubo_alignment,
             vertex_buffer,
+            samples,
+            wireframe,
+            opaque_cull_mode: vk::CullModeFlags::NONE,
+            transparent_cull_mode: vk::CullModeFlags::NONE,
             opaque_pass: geometry_pass,
             depth_buffer,
 
@@ -133,67 +177,15 @@ impl App for Triangle {
             weighted_colors_fb,
             reveal_fb,
 
+            msaa_targets,
+
             quad_vertex_buffer,
             composite_pass,
         })
     }
 
-    fn on_recreate_swapchain(&mut self, base: &BaseApp) -> Result<()> {
-        self.depth_buffer = Texture::create_framebuffer(
-            &base.context,
-            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
-            base.swapchain.extent,
-            DEPTH_BUFFER_FORMAT,
-            vk::ImageAspectFlags::DEPTH,
-            false,
-        )?;
-
-        self.weighted_colors_fb = Texture::create_framebuffer(
-            &base.context,
-            vk::ImageUsageFlags::COLOR_ATTACHMENT,
-            base.swapchain.extent,
-            WEIGHT_COLORS_FB_FORMAT,
-            vk::ImageAspectFlags::COLOR,
-            true,
-        )?;
-
-        self.reveal_fb = Texture::create_framebuffer(
-            &base.context,
-            vk::ImageUsageFlags::COLOR_ATTACHMENT,
-            base.swapchain.extent,
-            REVEAL_FB_FORMAT,
-            vk::ImageAspectFlags::COLOR,
-            true,
-        )?;
-
-        self.composite_pass.descriptor_set.update(&[
-            WriteDescriptorSet {
-                binding: 0,
-                kind: WriteDescriptorSetKind::CombinedImageSampler {
-                    view: &self.weighted_colors_fb.view,
-                    sampler: self
-                        .weighted_colors_fb
-                        .sampler
-                        .as_ref()
-                        .expect("weighted_colors_fb should have a sampler"),
-                    layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                },
-            },
-            WriteDescriptorSet {
-                binding: 1,
-                kind: WriteDescriptorSetKind::CombinedImageSampler {
-                    view: &self.reveal_fb.view,
-                    sampler: self
-                        .reveal_fb
-                        .sampler
-                        .as_ref()
-                        .expect("reveal_fb should have a sampler"),
-                    layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                },
-            },
-        ]);
-
-        Ok(())
+    fn on_recreate_swapchain(&mut self, base: &BaseApp, _: SwapchainChange) -> Result<()> {
+        self.recreate_framebuffers(base)
     }
 
     fn update(
@@ -203,23 +195,52 @@ impl App for Triangle {
         _: usize,
         _: Duration,
     ) -> Result<()> {
+        if ui.samples != self.samples {
+            self.set_samples(base, ui.samples)?;
+        }
+
+        if ui.wireframe != self.wireframe {
+            self.set_wireframe(base, ui.wireframe)?;
+        }
+
+        self.opaque_cull_mode = cull_mode(ui.cull_opaque_back_faces);
+        self.transparent_cull_mode = cull_mode(ui.cull_transparent_back_faces);
+
         self.instances.clear();
         self.instances.extend_from_slice(&ui.instances);
 
-        self.instance_ubo
-            .copy_data_to_buffer_with_alignment(&self.instances, self.ubo_alignment)?;
+        let stride = self.instance_ubo.aligned_size_of::<InstanceUbo>();
+        let mut instance_ubo = self.instance_ubo.map()?;
+        for (i, instance) in self.instances.iter().enumerate() {
+            instance_ubo.write(i as vk::DeviceSize * stride, std::slice::from_ref(instance))?;
+        }
 
-        self.frame_ubo.copy_data_to_buffer(&[FrameUbo {
-            view_proj_matrix: base.camera.projection_matrix() * base.camera.view_matrix(),
-        }])?;
+        self.frame_ubo.map()?.write(
+            0,
+            &[FrameUbo {
+                view_proj_matrix: base.camera.projection_matrix() * base.camera.view_matrix(),
+            }],
+        )?;
 
         Ok(())
     }
 
-    fn record_raster_commands(&self, base: &BaseApp, image_index: usize) -> Result<()> {
-        let buffer = &base.command_buffers[image_index];
-
-        buffer.pipeline_image_barriers(&[
+    fn record_raster_commands(
+        &self,
+        base: &BaseApp,
+        buffer: &CommandBuffer,
+        image_index: usize,
+    ) -> Result<()> {
+        let mut barriers = vec![
+            ImageBarrier {
+                image: &self.depth_buffer.image,
+                old_layout: vk::ImageLayout::UNDEFINED,
+                new_layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                src_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                src_stage_mask: vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                dst_stage_mask: vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS,
+            },
             ImageBarrier {
                 image: &self.weighted_colors_fb.image,
                 old_layout: vk::ImageLayout::UNDEFINED,
@@ -238,20 +259,39 @@ impl App for Triangle {
                 src_stage_mask: vk::PipelineStageFlags2::FRAGMENT_SHADER,
                 dst_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
             },
-        ]);
+        ];
+        if let Some(msaa) = &self.msaa_targets {
+            for image in [
+                &msaa.color.image,
+                &msaa.weighted_colors.image,
+                &msaa.reveal.image,
+            ] {
+                barriers.push(ImageBarrier {
+                    image,
+                    old_layout: vk::ImageLayout::UNDEFINED,
+                    new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    src_access_mask: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                    dst_access_mask: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                    src_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                    dst_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                });
+            }
+        }
+        buffer.pipeline_image_barriers(&barriers);
 
         // opaque pass
+        buffer.begin_debug_label("Opaque pass", OPAQUE_PASS_LABEL_COLOR);
         buffer.begin_rendering(
-            &[RenderingAttachment {
-                view: &base.swapchain.views[image_index],
-                load_op: vk::AttachmentLoadOp::CLEAR,
-                clear_value: Some(ClearValue::ColorFloat([0.0, 0.0, 0.0, 1.0])),
-            }],
-            Some(RenderingAttachment {
-                view: &self.depth_buffer.view,
-                load_op: vk::AttachmentLoadOp::CLEAR,
-                clear_value: Some(ClearValue::Depth(1.0)),
-            }),
+            &[cleared_target(
+                self.msaa_targets.as_ref().map(|t| &t.color.view),
+                &base.swapchain.views[image_index],
+                ClearValue::ColorFloat([0.0, 0.0, 0.0, 1.0]),
+            )],
+            // Depth is loaded by the transparent pass
+            Some(RenderingAttachment::clear(
+                &self.depth_buffer.view,
+                ClearValue::Depth(1.0),
+            )),
             base.swapchain.extent,
         );
 
@@ -259,6 +299,7 @@ impl App for Triangle {
         buffer.bind_vertex_buffer(&self.vertex_buffer);
         buffer.set_viewport(base.swapchain.extent);
         buffer.set_scissor(base.swapchain.extent);
+        buffer.set_cull_mode(self.opaque_cull_mode);
 
         for (i, _) in self
             .instances
@@ -266,7 +307,7 @@ impl App for Triangle {
             .enumerate()
             .filter(|(_, i)| i.color[3] == 1.0)
         {
-            let offset = compute_aligned_size_of::<InstanceUbo>(self.ubo_alignment) as u32;
+            let offset = self.instance_ubo.aligned_size_of::<InstanceUbo>() as u32;
             buffer.bind_descriptor_sets_with_dynamic_offsets(
                 PipelineBindPoint::GRAPHICS,
                 &self.opaque_pass.pipeline_layout,
@@ -278,26 +319,27 @@ impl App for Triangle {
         }
 
         buffer.end_rendering();
+        buffer.end_debug_label();
 
         // transparent pass
+        buffer.begin_debug_label("Transparent pass", TRANSPARENT_PASS_LABEL_COLOR);
         buffer.begin_rendering(
             &[
-                RenderingAttachment {
-                    view: &self.weighted_colors_fb.view,
-                    load_op: vk::AttachmentLoadOp::CLEAR,
-                    clear_value: Some(ClearValue::ColorFloat([0.0; 4])),
-                },
-                RenderingAttachment {
-                    view: &self.reveal_fb.view,
-                    load_op: vk::AttachmentLoadOp::CLEAR,
-                    clear_value: Some(ClearValue::ColorFloat([1.0; 4])),
-                },
+                cleared_target(
+                    self.msaa_targets.as_ref().map(|t| &t.weighted_colors.view),
+                    &self.weighted_colors_fb.view,
+                    ClearValue::ColorFloat([0.0; 4]),
+                ),
+                cleared_target(
+                    self.msaa_targets.as_ref().map(|t| &t.reveal.view),
+                    &self.reveal_fb.view,
+                    ClearValue::ColorFloat([1.0; 4]),
+                ),
             ],
-            Some(RenderingAttachment {
-                view: &self.depth_buffer.view,
-                load_op: vk::AttachmentLoadOp::LOAD,
-                clear_value: None,
-            }),
+            Some(
+                RenderingAttachment::load(&self.depth_buffer.view)
+                    .store_op(vk::AttachmentStoreOp::DONT_CARE),
+            ),
             base.swapchain.extent,
         );
 
@@ -305,6 +347,7 @@ impl App for Triangle {
         buffer.bind_vertex_buffer(&self.vertex_buffer);
         buffer.set_viewport(base.swapchain.extent);
         buffer.set_scissor(base.swapchain.extent);
+        buffer.set_cull_mode(self.transparent_cull_mode);
 
         for (i, _) in self
             .instances
@@ -312,7 +355,7 @@ impl App for Triangle {
             .enumerate()
             .filter(|(_, i)| i.color[3] < 1.0)
         {
-            let offset = compute_aligned_size_of::<InstanceUbo>(self.ubo_alignment) as u32;
+            let offset = self.instance_ubo.aligned_size_of::<InstanceUbo>() as u32;
             buffer.bind_descriptor_sets_with_dynamic_offsets(
                 PipelineBindPoint::GRAPHICS,
                 &self.transparent_pass.pipeline_layout,
@@ -324,8 +367,10 @@ impl App for Triangle {
         }
 
         buffer.end_rendering();
+        buffer.end_debug_label();
 
         // composite pass
+        buffer.begin_debug_label("Composite pass", COMPOSITE_PASS_LABEL_COLOR);
         buffer.pipeline_image_barriers(&[
             ImageBarrier {
                 image: &self.weighted_colors_fb.image,
@@ -348,11 +393,9 @@ impl App for Triangle {
         ]);
 
         buffer.begin_rendering(
-            &[RenderingAttachment {
-                view: &base.swapchain.views[image_index],
-                load_op: vk::AttachmentLoadOp::LOAD,
-                clear_value: None,
-            }],
+            &[RenderingAttachment::load(
+                &base.swapchain.views[image_index],
+            )],
             None,
             base.swapchain.extent,
         );
@@ -371,6 +414,112 @@ impl App for Triangle {
         buffer.draw(6);
 
         buffer.end_rendering();
+        buffer.end_debug_label();
+
+        Ok(())
+    }
+}
+
+impl Triangle {
+    fn recreate_framebuffers(&mut self, base: &BaseApp) -> Result<()> {
+        self.depth_buffer =
+            create_depth_buffer(&base.context, base.swapchain.extent, self.samples)?;
+
+        self.weighted_colors_fb = Texture::create_framebuffer(
+            &base.context,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            base.swapchain.extent,
+            WEIGHT_COLORS_FB_FORMAT,
+            vk::ImageAspectFlags::COLOR,
+            true,
+            vk::SampleCountFlags::TYPE_1,
+        )?;
+
+        self.reveal_fb = Texture::create_framebuffer(
+            &base.context,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            base.swapchain.extent,
+            REVEAL_FB_FORMAT,
+            vk::ImageAspectFlags::COLOR,
+            true,
+            vk::SampleCountFlags::TYPE_1,
+        )?;
+
+        self.msaa_targets = MsaaTargets::create(
+            &base.context,
+            base.swapchain.extent,
+            base.swapchain.format,
+            self.samples,
+        )?;
+
+        self.composite_pass.descriptor_set.update(&[
+            WriteDescriptorSet {
+                binding: 0,
+                kind: WriteDescriptorSetKind::CombinedImageSampler {
+                    view: &self.weighted_colors_fb.view,
+                    sampler: self
+                        .weighted_colors_fb
+                        .sampler
+                        .as_ref()
+                        .expect("weighted_colors_fb should have a sampler"),
+                    layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                },
+            },
+            WriteDescriptorSet {
+                binding: 1,
+                kind: WriteDescriptorSetKind::CombinedImageSampler {
+                    view: &self.reveal_fb.view,
+                    sampler: self
+                        .reveal_fb
+                        .sampler
+                        .as_ref()
+                        .expect("reveal_fb should have a sampler"),
+                    layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                },
+            },
+        ]);
+
+        Ok(())
+    }
+
+    fn set_samples(&mut self, base: &BaseApp, samples: vk::SampleCountFlags) -> Result<()> {
+        if !base.context.physical_device.supports_sample_count(samples) {
+            return Err(anyhow::anyhow!(
+                "Sample count {samples:?} is not supported by the device"
+            ));
+        }
+
+        self.samples = samples;
+        self.recreate_passes(base)?;
+        self.recreate_framebuffers(base)
+    }
+
+    fn set_wireframe(&mut self, base: &BaseApp, wireframe: bool) -> Result<()> {
+        self.wireframe = wireframe;
+        self.recreate_passes(base)
+    }
+
+    fn recreate_passes(&mut self, base: &BaseApp) -> Result<()> {
+        // Pipelines and framebuffers might still be in use by frames in flight
+        base.context.device_wait_idle()?;
+
+        self.opaque_pass = create_opaque_pass(
+            &base.context,
+            &base.pipeline_cache,
+            &self.frame_ubo,
+            &self.instance_ubo,
+            base.swapchain.format,
+            self.samples,
+            polygon_mode(self.wireframe),
+        )?;
+        self.transparent_pass = create_transparent_pass(
+            &base.context,
+            &base.pipeline_cache,
+            &self.frame_ubo,
+            &self.instance_ubo,
+            self.samples,
+            polygon_mode(self.wireframe),
+        )?;
 
         Ok(())
     }
@@ -379,10 +528,15 @@ impl App for Triangle {
 struct Gui {
     instances: Vec<InstanceUbo>,
     new_instance: InstanceUbo,
+    samples: vk::SampleCountFlags,
+    supported_samples: vk::SampleCountFlags,
+    wireframe: bool,
+    cull_opaque_back_faces: bool,
+    cull_transparent_back_faces: bool,
 }
 
 impl app::Gui for Gui {
-    fn new(_: &BaseApp) -> Result<Self> {
+    fn new(base: &BaseApp) -> Result<Self> {
         Ok(Self {
             instances: vec![
                 InstanceUbo::new([1.0, 1.0, 1.0, 0.5], [0.0, 0.0, 0.0]),
@@ -390,6 +544,11 @@ impl app::Gui for Gui {
                 InstanceUbo::new([0.0, 0.0, 1.0, 0.5], [-0.3, 0.0, 0.2]),
             ],
             new_instance: InstanceUbo::new([1.0, 1.0, 1.0, 1.0], [0.0, 0.0, 0.0]),
+            samples: vk::SampleCountFlags::TYPE_1,
+            supported_samples: base.context.physical_device.supported_sample_counts(),
+            wireframe: false,
+            cull_opaque_back_faces: false,
+            cull_transparent_back_faces: false,
         })
     }
 
@@ -397,6 +556,27 @@ impl app::Gui for Gui {
         let mut instance_index_to_remove = None;
 
         egui::SidePanel::left("cfg").show(ctx, |ui| {
+            let selected_label = SAMPLE_COUNTS
+                .iter()
+                .find(|(samples, _)| *samples == self.samples)
+                .map_or("", |(_, label)| label);
+            egui::ComboBox::from_label("MSAA")
+                .selected_text(selected_label)
+                .show_ui(ui, |ui| {
+                    for (samples, label) in SAMPLE_COUNTS {
+                        ui.add_enabled_ui(self.supported_samples.contains(samples), |ui| {
+                            ui.selectable_value(&mut self.samples, samples, label);
+                        });
+                    }
+                });
+            ui.checkbox(&mut self.wireframe, "Wireframe");
+            ui.checkbox(&mut self.cull_opaque_back_faces, "Cull opaque back faces");
+            ui.checkbox(
+                &mut self.cull_transparent_back_faces,
+                "Cull transparent back faces",
+            );
+
+            ui.separator();
             for (i, instance) in self.instances.iter_mut().enumerate() {
                 ui.horizontal(|ui| {
                     ui.color_edit_button_rgba_unmultiplied(&mut instance.color);
@@ -529,18 +709,20 @@ impl Texture {
         format: vk::Format,
         aspect_mask: vk::ImageAspectFlags,
         sampled: bool,
+        samples: vk::SampleCountFlags,
     ) -> Result<Self> {
         let usage = if sampled {
             usage | vk::ImageUsageFlags::SAMPLED
         } else {
             usage
         };
-        let image = context.create_image(
+        let image = context.create_multisampled_image(
             usage,
             MemoryLocation::GpuOnly,
             format,
             extent.width,
             extent.height,
+            samples,
         )?;
 
         let view = image.create_image_view(aspect_mask)?;
@@ -557,6 +739,79 @@ impl Texture {
     }
 }
 
+/// Multisampled color targets, only created when the sample count is greater than 1.
+struct MsaaTargets {
+    color: Texture,
+    weighted_colors: Texture,
+    reveal: Texture,
+}
+
+impl MsaaTargets {
+    fn create(
+        context: &Context,
+        extent: vk::Extent2D,
+        color_format: vk::Format,
+        samples: vk::SampleCountFlags,
+    ) -> Result<Option<Self>> {
+        if samples == vk::SampleCountFlags::TYPE_1 {
+            return Ok(None);
+        }
+
+        let create_target = |format| {
+            Texture::create_framebuffer(
+                context,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                extent,
+                format,
+                vk::ImageAspectFlags::COLOR,
+                false,
+                samples,
+            )
+        };
+
+        Ok(Some(Self {
+            color: create_target(color_format)?,
+            weighted_colors: create_target(WEIGHT_COLORS_FB_FORMAT)?,
+            reveal: create_target(REVEAL_FB_FORMAT)?,
+        }))
+    }
+}
+
+fn create_depth_buffer(
+    context: &Context,
+    extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
+) -> Result<Texture> {
+    Texture::create_framebuffer(
+        context,
+        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        extent,
+        DEPTH_BUFFER_FORMAT,
+        vk::ImageAspectFlags::DEPTH,
+        false,
+        samples,
+    )
+}
+
+/// When multisampling, passes render to the multisampled target which is resolved
+/// into the single sampled one at the end of the pass. Only the resolved image is
+/// used afterwards so the multisampled one is not stored.
+fn cleared_target<'a>(
+    msaa_view: Option<&'a ImageView>,
+    view: &'a ImageView,
+    clear_value: ClearValue,
+) -> RenderingAttachment<'a> {
+    match msaa_view {
+        Some(msaa_view) => RenderingAttachment::clear(msaa_view, clear_value)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .resolve(Some(ResolveAttachment {
+                view,
+                mode: vk::ResolveModeFlags::AVERAGE,
+            })),
+        None => RenderingAttachment::clear(view, clear_value),
+    }
+}
+
 struct Pass {
     _dsl: DescriptorSetLayout,
     _descriptor_pool: DescriptorPool,
@@ -640,11 +895,32 @@ fn create_vertex_buffer(context: &Context) -> Result<Buffer> {
     Ok(vertex_buffer)
 }
 
+/// Quads are wound counter clockwise when seen from +Z, so back faces are the ones seen from -Z.
+/// Culling them skips the quads seen from behind when they are only meant to be seen from the front.
+fn cull_mode(cull_back_faces: bool) -> vk::CullModeFlags {
+    if cull_back_faces {
+        vk::CullModeFlags::BACK
+    } else {
+        vk::CullModeFlags::NONE
+    }
+}
+
+fn polygon_mode(wireframe: bool) -> vk::PolygonMode {
+    if wireframe {
+        vk::PolygonMode::LINE
+    } else {
+        vk::PolygonMode::FILL
+    }
+}
+
 fn create_opaque_pass(
     context: &Context,
+    pipeline_cache: &PipelineCache,
     frame_ubo: &Buffer,
     instance_ubo: &Buffer,
     color_attachment_format: vk::Format,
+    samples: vk::SampleCountFlags,
+    polygon_mode: vk::PolygonMode,
 ) -> Result<Pass> {
     let bindings = [
         vk::DescriptorSetLayoutBinding::default()
@@ -695,15 +971,16 @@ fn create_opaque_pass(
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/geom.vert.spv")[..],
                     stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
                 },
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/shader.frag.spv")[..],
                     stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
                 },
             ],
-            primitive_topology: vk::PrimitiveTopology::TRIANGLE_LIST,
-            cull_mode: vk::CullModeFlags::NONE,
-            extent: None,
+            polygon_mode,
+            samples,
             color_attachments: ColorAttachmentsInfo {
                 formats: &[color_attachment_format],
                 blends: &[vk::PipelineColorBlendAttachmentState {
@@ -713,11 +990,17 @@ fn create_opaque_pass(
             },
             depth: Some(DepthInfo {
                 format: DEPTH_BUFFER_FORMAT,
-                enable_depth_test: true,
-                enable_depth_write: true,
+                ..Default::default()
             }),
-            dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            // Cull mode is set per pass when recording
+            dynamic_states: Some(&[
+                vk::DynamicState::SCISSOR,
+                vk::DynamicState::VIEWPORT,
+                vk::DynamicState::CULL_MODE,
+            ]),
+            ..Default::default()
         },
+        Some(pipeline_cache),
     )?;
 
     Ok(Pass {
@@ -731,8 +1014,11 @@ fn create_opaque_pass(
 
 fn create_transparent_pass(
     context: &Context,
+    pipeline_cache: &PipelineCache,
     frame_ubo: &Buffer,
     instance_ubo: &Buffer,
+    samples: vk::SampleCountFlags,
+    polygon_mode: vk::PolygonMode,
 ) -> Result<Pass> {
     let bindings = [
         vk::DescriptorSetLayoutBinding::default()
@@ -783,15 +1069,16 @@ fn create_transparent_pass(
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/geom.vert.spv")[..],
                     stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
                 },
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/wboit.frag.spv")[..],
                     stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
                 },
             ],
-            primitive_topology: vk::PrimitiveTopology::TRIANGLE_LIST,
-            cull_mode: vk::CullModeFlags::NONE,
-            extent: None,
+            polygon_mode,
+            samples,
             color_attachments: ColorAttachmentsInfo {
                 formats: &[WEIGHT_COLORS_FB_FORMAT, REVEAL_FB_FORMAT],
                 blends: &[
@@ -817,11 +1104,18 @@ fn create_transparent_pass(
             },
             depth: Some(DepthInfo {
                 format: DEPTH_BUFFER_FORMAT,
-                enable_depth_test: true,
                 enable_depth_write: false,
+                ..Default::default()
             }),
-            dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            // Cull mode is set per pass when recording
+            dynamic_states: Some(&[
+                vk::DynamicState::SCISSOR,
+                vk::DynamicState::VIEWPORT,
+                vk::DynamicState::CULL_MODE,
+            ]),
+            ..Default::default()
         },
+        Some(pipeline_cache),
     )?;
 
     Ok(Pass {
@@ -835,6 +1129,7 @@ fn create_transparent_pass(
 
 fn create_composite_pass(
     context: &Context,
+    pipeline_cache: &PipelineCache,
     weighted_colors_fb: &Texture,
     reveal_fb: &Texture,
     color_attachment_format: vk::Format,
@@ -894,15 +1189,14 @@ fn create_composite_pass(
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/fullscreen.vert.spv")[..],
                     stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
                 },
                 GraphicsShaderCreateInfo {
                     source: &include_bytes!("../shaders/composite.frag.spv")[..],
                     stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
                 },
             ],
-            primitive_topology: vk::PrimitiveTopology::TRIANGLE_LIST,
-            cull_mode: vk::CullModeFlags::NONE,
-            extent: None,
             color_attachments: ColorAttachmentsInfo {
                 formats: &[color_attachment_format],
                 blends: &[vk::PipelineColorBlendAttachmentState {
@@ -914,9 +1208,10 @@ fn create_composite_pass(
                     ..Default::default()
                 }],
             },
-            depth: None,
             dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
         },
+        Some(pipeline_cache),
     )?;
 
     Ok(Pass {
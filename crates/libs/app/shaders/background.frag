@@ -0,0 +1,14 @@
+#version 450
+
+layout(push_constant) uniform Constants {
+    vec4 top;
+    vec4 bottom;
+} constants;
+
+layout(location = 0) in vec2 oUV;
+
+layout(location = 0) out vec4 finalColor;
+
+void main() {
+    finalColor = mix(constants.top, constants.bottom, clamp(oUV.y, 0.0, 1.0));
+}
@@ -0,0 +1,40 @@
+#version 450
+
+layout(push_constant) uniform Constants {
+    mat4 inverseViewProj;
+    vec4 viewProjRowZ;
+    vec4 viewProjRowW;
+    vec4 color;
+    float spacing;
+    float fadeDistance;
+} constants;
+
+layout(location = 0) in vec3 iNearPoint;
+layout(location = 1) in vec3 iFarPoint;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    // Intersection of the view ray with the y = 0 plane
+    float t = -iNearPoint.y / (iFarPoint.y - iNearPoint.y);
+    vec3 position = iNearPoint + t * (iFarPoint - iNearPoint);
+
+    // Lines are one pixel wide whatever the distance, using screen space derivatives.
+    // Derivatives are computed before discarding so they stay defined.
+    vec2 coord = position.xz / constants.spacing;
+    vec2 derivative = fwidth(coord);
+    vec2 grid = abs(fract(coord - 0.5) - 0.5) / derivative;
+    float line = 1.0 - min(min(grid.x, grid.y), 1.0);
+
+    float fade = 1.0 - smoothstep(0.0, constants.fadeDistance, distance(position, iNearPoint));
+    float alpha = constants.color.a * line * fade;
+
+    if (t <= 0.0 || alpha <= 0.0) {
+        discard;
+    }
+
+    vec4 homogeneousPosition = vec4(position, 1.0);
+    gl_FragDepth = dot(constants.viewProjRowZ, homogeneousPosition) / dot(constants.viewProjRowW, homogeneousPosition);
+
+    outColor = vec4(constants.color.rgb, alpha);
+}
@@ -0,0 +1,29 @@
+#version 450
+
+layout(push_constant) uniform Constants {
+    mat4 inverseViewProj;
+    vec4 viewProjRowZ;
+    vec4 viewProjRowW;
+    vec4 color;
+    float spacing;
+    float fadeDistance;
+} constants;
+
+layout(location = 0) out vec3 oNearPoint;
+layout(location = 1) out vec3 oFarPoint;
+
+vec3 unproject(vec2 xy, float z) {
+    vec4 position = constants.inverseViewProj * vec4(xy, z, 1.0);
+    return position.xyz / position.w;
+}
+
+void main() {
+    // Fullscreen triangle
+    vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    vec2 xy = uv * 2.0 - 1.0;
+
+    oNearPoint = unproject(xy, 0.0);
+    oFarPoint = unproject(xy, 1.0);
+
+    gl_Position = vec4(xy, 0.0, 1.0);
+}
@@ -0,0 +1,151 @@
+use std::mem::size_of;
+
+use anyhow::Result;
+use vulkan::{
+    ash::vk, ClearValue, ColorAttachmentsInfo, CommandBuffer, Context, EmptyVertex,
+    GraphicsPipeline, GraphicsPipelineCreateInfo, GraphicsShaderCreateInfo, ImageView,
+    PipelineCache, PipelineLayout, RenderingAttachment,
+};
+
+/// What the swapchain image is filled with before the app records its rasterization commands.
+///
+/// Colors are linear. Apps loading the swapchain attachment instead of clearing it draw over it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Background {
+    Solid([f32; 3]),
+    /// Vertical gradient from the top to the bottom of the screen.
+    Gradient {
+        top: [f32; 3],
+        bottom: [f32; 3],
+    },
+}
+
+/// Renders the [`Background`] into a target.
+///
+/// Solid backgrounds only clear the target. Gradients are drawn with a fullscreen triangle.
+pub(crate) struct BackgroundPass {
+    pipeline_layout: PipelineLayout,
+    pipeline: GraphicsPipeline,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+#[repr(C)]
+struct BackgroundConstants {
+    top: [f32; 4],
+    bottom: [f32; 4],
+}
+
+impl BackgroundPass {
+    pub(crate) fn new(
+        context: &Context,
+        pipeline_cache: Option<&PipelineCache>,
+        color_format: vk::Format,
+    ) -> Result<Self> {
+        let pipeline_layout = context.create_pipeline_layout_with_push_constants(
+            &[],
+            &[vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: size_of::<BackgroundConstants>() as _,
+            }],
+        )?;
+        let pipeline = create_pipeline(context, pipeline_cache, &pipeline_layout, color_format)?;
+
+        Ok(Self {
+            pipeline_layout,
+            pipeline,
+        })
+    }
+
+    /// Recreates the pipeline for a new color attachment format.
+    pub(crate) fn set_color_format(
+        &mut self,
+        context: &Context,
+        pipeline_cache: Option<&PipelineCache>,
+        color_format: vk::Format,
+    ) -> Result<()> {
+        self.pipeline =
+            create_pipeline(context, pipeline_cache, &self.pipeline_layout, color_format)?;
+
+        Ok(())
+    }
+
+    /// Records a rendering pass filling `view`, which must be in the color attachment layout.
+    pub(crate) fn draw(
+        &self,
+        cmd: &CommandBuffer,
+        view: &ImageView,
+        extent: vk::Extent2D,
+        background: Background,
+    ) {
+        match background {
+            Background::Solid([r, g, b]) => {
+                cmd.begin_rendering(
+                    &[RenderingAttachment::clear(
+                        view,
+                        ClearValue::ColorFloat([r, g, b, 1.0]),
+                    )],
+                    None,
+                    extent,
+                );
+            }
+            Background::Gradient { top, bottom } => {
+                let [tr, tg, tb] = top;
+                let [br, bg, bb] = bottom;
+                let constants = BackgroundConstants {
+                    top: [tr, tg, tb, 1.0],
+                    bottom: [br, bg, bb, 1.0],
+                };
+
+                cmd.begin_rendering(&[RenderingAttachment::dont_care(view)], None, extent);
+                cmd.bind_graphics_pipeline(&self.pipeline);
+                cmd.set_viewport(extent);
+                cmd.set_scissor(extent);
+                cmd.push_constants(
+                    &self.pipeline_layout,
+                    vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    &constants,
+                );
+                cmd.draw(3);
+            }
+        }
+        cmd.end_rendering();
+    }
+}
+
+fn create_pipeline(
+    context: &Context,
+    pipeline_cache: Option<&PipelineCache>,
+    layout: &PipelineLayout,
+    color_format: vk::Format,
+) -> Result<GraphicsPipeline> {
+    context.create_graphics_pipeline::<EmptyVertex>(
+        layout,
+        GraphicsPipelineCreateInfo {
+            shaders: &[
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/background.vert.spv")[..],
+                    stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
+                },
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/background.frag.spv")[..],
+                    stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ],
+            color_attachments: ColorAttachmentsInfo {
+                formats: &[color_format],
+                blends: &[vk::PipelineColorBlendAttachmentState {
+                    color_write_mask: vk::ColorComponentFlags::RGBA,
+                    ..Default::default()
+                }],
+            },
+            dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
+        },
+        pipeline_cache,
+    )
+}
@@ -1,23 +1,30 @@
 use std::time::Duration;
 
 use glam::{vec3, Mat3, Mat4, Quat, Vec3};
+use vulkan::ash::vk;
 use winit::{
-    event::{DeviceEvent, ElementState, KeyEvent, MouseButton, WindowEvent},
+    event::{DeviceEvent, ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
     keyboard::{KeyCode, PhysicalKey},
 };
 
-const MOVE_SPEED: f32 = 3.0;
-const ANGLE_PER_POINT: f32 = 0.001745;
-
-const FORWARD_KEYCODE: KeyCode = KeyCode::KeyW;
-const BACKWARD_KEYCODE: KeyCode = KeyCode::KeyS;
-const RIGHT_KEYCODE: KeyCode = KeyCode::KeyD;
-const LEFT_KEYCODE: KeyCode = KeyCode::KeyA;
-const UP_KEYCODE: KeyCode = KeyCode::Space;
-const DOWN_KEYCODE: KeyCode = KeyCode::ControlLeft;
+const DEFAULT_MOVE_SPEED: f32 = 3.0;
+const DEFAULT_LOOK_SENSITIVITY: f32 = 0.001745;
+const FAST_MOVE_MULTIPLIER: f32 = 4.0;
+const ZOOM_PER_SCROLL_LINE: f32 = 0.1;
+const PIXELS_PER_SCROLL_LINE: f32 = 20.0;
+const MIN_ORBIT_DISTANCE: f32 = 0.1;
+const DEFAULT_ORBIT_DISTANCE: f32 = 3.0;
 
 const UP: Vec3 = vec3(0.0, 1.0, 0.0);
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Free-fly camera moved with WASD and rotated with right-drag.
+    Fly,
+    /// Rotates around `orbit_target` with right-drag and zooms with the scroll wheel.
+    Orbit,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Camera {
     pub position: Vec3,
@@ -26,6 +33,13 @@ pub struct Camera {
     pub aspect_ratio: f32,
     pub z_near: f32,
     pub z_far: f32,
+    pub mode: CameraMode,
+    pub orbit_target: Vec3,
+    pub orbit_distance: f32,
+    /// Units per second.
+    pub move_speed: f32,
+    /// Radians per point of cursor movement.
+    pub look_sensitivity: f32,
 }
 
 impl Camera {
@@ -44,20 +58,72 @@ impl Camera {
             aspect_ratio,
             z_near,
             z_far,
+            mode: CameraMode::Fly,
+            orbit_target: Vec3::ZERO,
+            orbit_distance: DEFAULT_ORBIT_DISTANCE,
+            move_speed: DEFAULT_MOVE_SPEED,
+            look_sensitivity: DEFAULT_LOOK_SENSITIVITY,
+        }
+    }
+
+    /// Switches between fly and orbit modes. When entering orbit mode the target is
+    /// placed in front of the camera so the view does not jump.
+    pub fn toggle_mode(self) -> Self {
+        match self.mode {
+            CameraMode::Fly => Self {
+                mode: CameraMode::Orbit,
+                orbit_target: self.position + self.direction * self.orbit_distance,
+                ..self
+            },
+            CameraMode::Orbit => Self {
+                mode: CameraMode::Fly,
+                ..self
+            },
         }
     }
 
     pub fn update(self, controls: &Controls, delta_time: Duration) -> Self {
+        match self.mode {
+            CameraMode::Fly => self.update_fly(controls, delta_time),
+            CameraMode::Orbit => self.update_orbit(controls),
+        }
+    }
+
+    fn update_orbit(self, controls: &Controls) -> Self {
+        let direction = if controls.look_around {
+            self.rotate_direction(controls.cursor_delta)
+        } else {
+            self.direction
+        };
+
+        let orbit_distance = (self.orbit_distance
+            * (1.0 - controls.scroll_delta * ZOOM_PER_SCROLL_LINE))
+            .max(MIN_ORBIT_DISTANCE);
+
+        Self {
+            position: self.orbit_target - direction * orbit_distance,
+            direction,
+            orbit_distance,
+            ..self
+        }
+    }
+
+    fn rotate_direction(&self, cursor_delta: [f32; 2]) -> Vec3 {
+        let side = self.direction.cross(UP);
+        let side_rot = Quat::from_axis_angle(side, -cursor_delta[1] * self.look_sensitivity);
+        let y_rot = Quat::from_rotation_y(-cursor_delta[0] * self.look_sensitivity);
+        let rot = Mat3::from_quat(side_rot * y_rot);
+
+        (rot * self.direction).normalize()
+    }
+
+    fn update_fly(self, controls: &Controls, delta_time: Duration) -> Self {
         let delta_time = delta_time.as_secs_f32();
         let side = self.direction.cross(UP);
 
         // Update direction
         let new_direction = if controls.look_around {
-            let side_rot = Quat::from_axis_angle(side, -controls.cursor_delta[1] * ANGLE_PER_POINT);
-            let y_rot = Quat::from_rotation_y(-controls.cursor_delta[0] * ANGLE_PER_POINT);
-            let rot = Mat3::from_quat(side_rot * y_rot);
-
-            (rot * self.direction).normalize()
+            self.rotate_direction(controls.cursor_delta)
         } else {
             self.direction
         };
@@ -90,13 +156,37 @@ impl Camera {
             direction.normalize()
         };
 
+        let speed = if controls.fast {
+            self.move_speed * FAST_MOVE_MULTIPLIER
+        } else {
+            self.move_speed
+        };
+
         Self {
-            position: self.position + direction * MOVE_SPEED * delta_time,
+            position: self.position + direction * speed * delta_time,
             direction: new_direction,
             ..self
         }
     }
 
+    /// Orbits around `center` at `radius` on the horizontal plane, keeping the current height
+    /// and looking at the center. `speed` is in radians per second.
+    pub fn turntable(self, center: Vec3, radius: f32, speed: f32, delta_time: Duration) -> Self {
+        let offset = self.position - center;
+        let angle = offset.z.atan2(offset.x) + speed * delta_time.as_secs_f32();
+        let position = vec3(
+            center.x + radius * angle.cos(),
+            self.position.y,
+            center.z + radius * angle.sin(),
+        );
+
+        Self {
+            position,
+            direction: (center - position).normalize(),
+            ..self
+        }
+    }
+
     pub fn view_matrix(&self) -> Mat4 {
         Mat4::look_at_rh(
             self.position,
@@ -117,6 +207,27 @@ impl Camera {
             self.z_far,
         )
     }
+
+    /// World space ray going through `cursor`, in pixels from the top left corner of a
+    /// viewport of size `extent`.
+    ///
+    /// Returns the origin of the ray, on the near plane, and its normalized direction.
+    /// When `extent` is empty (minimized window) the camera position and direction are returned.
+    pub fn screen_ray(&self, cursor: [f32; 2], extent: vk::Extent2D) -> (Vec3, Vec3) {
+        if extent.width == 0 || extent.height == 0 {
+            return (self.position, self.direction);
+        }
+
+        // The projection already flips y so screen and ndc y both point down
+        let ndc_x = 2.0 * cursor[0] / extent.width as f32 - 1.0;
+        let ndc_y = 2.0 * cursor[1] / extent.height as f32 - 1.0;
+
+        let inverse_view_proj = (self.projection_matrix() * self.view_matrix()).inverse();
+        let near = inverse_view_proj.project_point3(vec3(ndc_x, ndc_y, 0.0));
+        let far = inverse_view_proj.project_point3(vec3(ndc_x, ndc_y, 1.0));
+
+        (near, (far - near).normalize())
+    }
 }
 
 #[rustfmt::skip]
@@ -152,43 +263,85 @@ pub fn perspective(fovy: f32, aspect: f32, near: f32, far: f32) -> Mat4 {
     ])
 }
 
+/// Keys are physical key positions so the default WASD layout stays usable
+/// on non QWERTY keyboards (ZQSD on AZERTY for example).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBindings {
+    pub forward: KeyCode,
+    pub backward: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub fast: KeyCode,
+    pub look: MouseButton,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            forward: KeyCode::KeyW,
+            backward: KeyCode::KeyS,
+            left: KeyCode::KeyA,
+            right: KeyCode::KeyD,
+            up: KeyCode::Space,
+            down: KeyCode::ControlLeft,
+            fast: KeyCode::ShiftLeft,
+            look: MouseButton::Right,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Controls {
+    pub key_bindings: KeyBindings,
     pub go_forward: bool,
     pub go_backward: bool,
     pub strafe_right: bool,
     pub strafe_left: bool,
     pub go_up: bool,
     pub go_down: bool,
+    pub fast: bool,
     pub look_around: bool,
     pub cursor_delta: [f32; 2],
+    /// Scrolled lines since the last frame, positive when scrolling up.
+    pub scroll_delta: f32,
 }
 
 impl Default for Controls {
     fn default() -> Self {
+        Self::new(KeyBindings::default())
+    }
+}
+
+impl Controls {
+    pub fn new(key_bindings: KeyBindings) -> Self {
         Self {
+            key_bindings,
             go_forward: false,
             go_backward: false,
             strafe_right: false,
             strafe_left: false,
             go_up: false,
             go_down: false,
+            fast: false,
             look_around: false,
             cursor_delta: [0.0; 2],
+            scroll_delta: 0.0,
         }
     }
-}
 
-impl Controls {
     pub fn reset(self) -> Self {
         Self {
             cursor_delta: [0.0; 2],
+            scroll_delta: 0.0,
             ..self
         }
     }
 
     pub fn handle_window_event(self, evt: &WindowEvent) -> Self {
         let mut new_state = self;
+        let bindings = self.key_bindings;
 
         match evt {
             WindowEvent::KeyboardInput {
@@ -199,20 +352,30 @@ impl Controls {
                         ..
                     },
                 ..
-            } => match *code {
-                FORWARD_KEYCODE => new_state.go_forward = *state == ElementState::Pressed,
-                BACKWARD_KEYCODE => new_state.go_backward = *state == ElementState::Pressed,
-                RIGHT_KEYCODE => new_state.strafe_right = *state == ElementState::Pressed,
-                LEFT_KEYCODE => new_state.strafe_left = *state == ElementState::Pressed,
-                UP_KEYCODE => new_state.go_up = *state == ElementState::Pressed,
-                DOWN_KEYCODE => new_state.go_down = *state == ElementState::Pressed,
-                _ => (),
-            },
-            WindowEvent::MouseInput { state, button, .. } => {
-                if *button == MouseButton::Right {
-                    new_state.look_around = *state == ElementState::Pressed;
+            } => {
+                let pressed = *state == ElementState::Pressed;
+                match *code {
+                    c if c == bindings.forward => new_state.go_forward = pressed,
+                    c if c == bindings.backward => new_state.go_backward = pressed,
+                    c if c == bindings.right => new_state.strafe_right = pressed,
+                    c if c == bindings.left => new_state.strafe_left = pressed,
+                    c if c == bindings.up => new_state.go_up = pressed,
+                    c if c == bindings.down => new_state.go_down = pressed,
+                    c if c == bindings.fast => new_state.fast = pressed,
+                    _ => (),
                 }
             }
+            WindowEvent::MouseInput { state, button, .. } if *button == bindings.look => {
+                new_state.look_around = *state == ElementState::Pressed;
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                new_state.scroll_delta += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => {
+                        position.y as f32 / PIXELS_PER_SCROLL_LINE
+                    }
+                };
+            }
             _ => {}
         };
 
@@ -231,3 +394,81 @@ impl Controls {
         new_state
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forward_displacement(camera: Camera, fast: bool) -> f32 {
+        let controls = Controls {
+            go_forward: true,
+            fast,
+            ..Controls::default()
+        };
+        let moved = camera.update(&controls, Duration::from_millis(100));
+
+        moved.position.distance(camera.position)
+    }
+
+    fn camera() -> Camera {
+        Camera::new(Vec3::ZERO, Vec3::NEG_Z, 60.0, 1.0, 0.1, 100.0)
+    }
+
+    #[test]
+    fn doubling_move_speed_doubles_displacement() {
+        let slow = camera();
+        let fast = Camera {
+            move_speed: slow.move_speed * 2.0,
+            ..slow
+        };
+
+        let slow_displacement = forward_displacement(slow, false);
+        let fast_displacement = forward_displacement(fast, false);
+
+        assert!((fast_displacement - 2.0 * slow_displacement).abs() < 1e-5);
+    }
+
+    #[test]
+    fn sprinting_multiplies_displacement() {
+        let camera = camera();
+
+        let walk = forward_displacement(camera, false);
+        let sprint = forward_displacement(camera, true);
+
+        assert!((sprint - FAST_MOVE_MULTIPLIER * walk).abs() < 1e-5);
+    }
+
+    #[test]
+    fn screen_center_ray_follows_camera_direction() {
+        let camera = Camera::new(
+            vec3(1.0, 2.0, 3.0),
+            vec3(1.0, -0.5, -1.0),
+            60.0,
+            1.5,
+            0.1,
+            100.0,
+        );
+        let extent = vk::Extent2D {
+            width: 1200,
+            height: 800,
+        };
+
+        let (_, direction) = camera.screen_ray([600.0, 400.0], extent);
+
+        assert!(direction.distance(camera.direction) < 1e-4);
+    }
+
+    #[test]
+    fn empty_extent_ray_is_camera_ray() {
+        let camera = camera();
+        let extent = vk::Extent2D {
+            width: 0,
+            height: 0,
+        };
+
+        let (origin, direction) = camera.screen_ray([0.0, 0.0], extent);
+
+        assert_eq!(origin, camera.position);
+        assert_eq!(direction, camera.direction);
+    }
+}
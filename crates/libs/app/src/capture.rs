@@ -0,0 +1,266 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use vulkan::{
+    ash::vk, gpu_allocator::MemoryLocation, Buffer, BufferBarrier, Context, Image, ImageBarrier,
+};
+
+/// Copies the first mip level and layer of `image` and writes it as a PNG at `path`.
+///
+/// Meant for offscreen rendering, with a headless context for example. `image` must be in
+/// `layout`, have been created with `TRANSFER_SRC` usage and have an 8 bits RGBA or BGRA format.
+/// It is left in `layout`.
+pub fn save_image_to_png(
+    context: &Context,
+    image: &Image,
+    layout: vk::ImageLayout,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    check_png_support()?;
+    is_bgra_format(image.format)?;
+
+    let extent = vk::Extent2D {
+        width: image.extent.width,
+        height: image.extent.height,
+    };
+    let buffer = context.create_buffer(
+        vk::BufferUsageFlags::TRANSFER_DST,
+        MemoryLocation::GpuToCpu,
+        (extent.width * extent.height * 4) as _,
+    )?;
+
+    context.execute_one_time_commands(|cmd| {
+        cmd.pipeline_image_barriers(&[ImageBarrier {
+            image,
+            old_layout: layout,
+            new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            src_access_mask: vk::AccessFlags2::MEMORY_WRITE,
+            dst_access_mask: vk::AccessFlags2::TRANSFER_READ,
+            src_stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+            dst_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+        }]);
+
+        cmd.copy_image_to_buffer(
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            &buffer,
+            vk::ImageAspectFlags::COLOR,
+            vk::Rect2D {
+                offset: vk::Offset2D::default(),
+                extent,
+            },
+            0,
+        );
+
+        cmd.pipeline_image_barriers(&[ImageBarrier {
+            image,
+            old_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            new_layout: layout,
+            src_access_mask: vk::AccessFlags2::empty(),
+            dst_access_mask: vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
+            src_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+            dst_stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+        }]);
+        cmd.pipeline_buffer_barriers(&[BufferBarrier {
+            buffer: &buffer,
+            src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+            dst_access_mask: vk::AccessFlags2::HOST_READ,
+            src_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+            dst_stage_mask: vk::PipelineStageFlags2::HOST,
+        }]);
+    })?;
+
+    write_png(
+        path.as_ref(),
+        &buffer,
+        image.format,
+        extent.width,
+        extent.height,
+    )
+}
+
+pub(crate) fn screenshot_path() -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    PathBuf::from(format!("screenshot_{timestamp}.png"))
+}
+
+/// Whether `format` stores pixels as BGRA, which needs swizzling before writing a PNG.
+pub(crate) fn is_bgra_format(format: vk::Format) -> Result<bool> {
+    match format {
+        vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => Ok(true),
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => Ok(false),
+        _ => Err(anyhow::anyhow!(
+            "Cannot write images with format {format:?} to PNG"
+        )),
+    }
+}
+
+/// Fails when the crate was built without the `screenshot` feature which encodes PNGs.
+pub(crate) fn check_png_support() -> Result<()> {
+    if cfg!(feature = "screenshot") {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "Saving PNGs requires the screenshot feature of the app crate"
+        ))
+    }
+}
+
+/// Writes the tightly packed pixels in `buffer` as a PNG.
+pub(crate) fn write_png(
+    path: &Path,
+    buffer: &Buffer,
+    format: vk::Format,
+    width: u32,
+    height: u32,
+) -> Result<()> {
+    let pixels = read_rgba_pixels(buffer, format, width, height)?;
+    save_png(path, &pixels, width, height)
+}
+
+/// Reads the tightly packed pixels in `buffer` as RGBA.
+///
+/// Both UNORM and SRGB images hold the encoded values that are displayed, so the bytes
+/// are kept as is, only swizzled for BGRA formats. Alpha is forced to opaque.
+fn read_rgba_pixels(
+    buffer: &Buffer,
+    format: vk::Format,
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>> {
+    let is_bgra = is_bgra_format(format)?;
+
+    let mut data = buffer.read_data_to_vec::<u8>()?;
+    data.truncate((width * height * 4) as _);
+    for pixel in data.chunks_exact_mut(4) {
+        if is_bgra {
+            pixel.swap(0, 2);
+        }
+        pixel[3] = u8::MAX;
+    }
+
+    Ok(data)
+}
+
+#[cfg(feature = "screenshot")]
+fn save_png(path: &Path, pixels: &[u8], width: u32, height: u32) -> Result<()> {
+    image::save_buffer(path, pixels, width, height, image::ExtendedColorType::Rgba8)?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "screenshot"))]
+fn save_png(_: &Path, _: &[u8], _: u32, _: u32) -> Result<()> {
+    check_png_support()
+}
+
+/// Time step the app advances by for each captured frame, for a 60 fps video.
+pub(crate) const FRAME_SEQUENCE_TIMESTEP: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Copy of a swapchain image waiting for its frame to complete before being saved.
+pub(crate) struct FrameReadback {
+    pub path: PathBuf,
+    pub buffer: Buffer,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+}
+
+/// Saves consecutive frames as numbered PNGs in a directory.
+///
+/// Frames are read back once their in flight slot is reused so rendering doesn't wait
+/// for the copy, and encoded on background threads.
+pub(crate) struct FrameSequenceCapture {
+    dir: PathBuf,
+    frame_count: u32,
+    /// Frames whose copy was recorded.
+    recorded_count: u32,
+    /// Frames that were read back and handed to a writer.
+    saved_count: u32,
+    writers: VecDeque<JoinHandle<Result<()>>>,
+}
+
+impl FrameSequenceCapture {
+    pub(crate) fn new(dir: PathBuf, frame_count: u32) -> Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            frame_count,
+            recorded_count: 0,
+            saved_count: 0,
+            writers: VecDeque::new(),
+        })
+    }
+
+    /// Path of the next frame to copy, or `None` once all frames were recorded.
+    pub(crate) fn next_frame_path(&mut self) -> Option<PathBuf> {
+        if self.recorded_count == self.frame_count {
+            return None;
+        }
+
+        let path = self
+            .dir
+            .join(format!("frame_{:05}.png", self.recorded_count));
+        self.recorded_count += 1;
+        Some(path)
+    }
+
+    /// Reads back a completed frame and encodes it on a background thread.
+    pub(crate) fn save(&mut self, readback: FrameReadback) -> Result<()> {
+        let FrameReadback {
+            path,
+            buffer,
+            format,
+            extent,
+        } = readback;
+        let pixels = read_rgba_pixels(&buffer, format, extent.width, extent.height)?;
+
+        // Bound the memory held by frames waiting to be encoded
+        let max_writers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        if self.writers.len() >= max_writers {
+            if let Some(writer) = self.writers.pop_front() {
+                join_writer(writer)?;
+            }
+        }
+
+        self.writers.push_back(std::thread::spawn(move || {
+            save_png(&path, &pixels, extent.width, extent.height)
+        }));
+        self.saved_count += 1;
+
+        Ok(())
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.saved_count == self.frame_count
+    }
+
+    /// Waits for the frames being encoded.
+    pub(crate) fn finish(self) -> Result<()> {
+        for writer in self.writers {
+            join_writer(writer)?;
+        }
+
+        log::info!(
+            "Captured {} frames to {}",
+            self.saved_count,
+            self.dir.display()
+        );
+
+        Ok(())
+    }
+}
+
+fn join_writer(writer: JoinHandle<Result<()>>) -> Result<()> {
+    writer
+        .join()
+        .map_err(|_| anyhow::anyhow!("Frame writer thread panicked"))?
+}
@@ -0,0 +1,176 @@
+use std::f32::consts::FRAC_PI_2;
+
+use anyhow::Result;
+use glam::{vec3, Mat4, Vec3};
+use vulkan::{
+    ash::vk, gpu_allocator::MemoryLocation, ClearValue, CommandBuffer, Context, Image,
+    ImageBarrier, ImageView, RenderingAttachment,
+};
+
+/// Direction and up vector of each face in the +X, -X, +Y, -Y, +Z, -Z layer order.
+const FACES: [(Vec3, Vec3); 6] = [
+    (vec3(1.0, 0.0, 0.0), vec3(0.0, -1.0, 0.0)),
+    (vec3(-1.0, 0.0, 0.0), vec3(0.0, -1.0, 0.0)),
+    (vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0)),
+    (vec3(0.0, -1.0, 0.0), vec3(0.0, 0.0, -1.0)),
+    (vec3(0.0, 0.0, 1.0), vec3(0.0, -1.0, 0.0)),
+    (vec3(0.0, 0.0, -1.0), vec3(0.0, -1.0, 0.0)),
+];
+
+/// Cube render target for dynamic environment capture.
+///
+/// Each face is rendered in its own rendering pass. The face projection is not flipped on y
+/// like [`crate::Camera::projection_matrix`] so that faces match the cube map orientation, which
+/// means triangles wind the other way. Pipelines used to render into the cubemap must use the
+/// opposite front face (or no culling).
+pub struct Cubemap {
+    pub image: Image,
+    /// Cube view to sample the cubemap.
+    pub view: ImageView,
+    face_views: Vec<ImageView>,
+    depth: Option<(Image, ImageView)>,
+    pub size: u32,
+    pub z_near: f32,
+    pub z_far: f32,
+    pub clear_color: [f32; 4],
+}
+
+impl Cubemap {
+    pub fn new(
+        context: &Context,
+        format: vk::Format,
+        depth_format: Option<vk::Format>,
+        size: u32,
+    ) -> Result<Self> {
+        let image = context.create_cube_image(
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            MemoryLocation::GpuOnly,
+            format,
+            size,
+            1,
+        )?;
+        let view = image.create_cube_image_view(vk::ImageAspectFlags::COLOR)?;
+        let face_views = (0..6)
+            .map(|face| image.create_layer_image_view(vk::ImageAspectFlags::COLOR, face))
+            .collect::<Result<Vec<_>>>()?;
+
+        let depth = depth_format
+            .map(|format| {
+                let image = context.create_image(
+                    vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                    MemoryLocation::GpuOnly,
+                    format,
+                    size,
+                    size,
+                    1,
+                )?;
+                let view = image.create_image_view(vk::ImageAspectFlags::DEPTH)?;
+                Ok::<_, anyhow::Error>((image, view))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            image,
+            view,
+            face_views,
+            depth,
+            size,
+            z_near: 0.1,
+            z_far: 100.0,
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+        })
+    }
+
+    /// View projection matrix of `face` for a capture from `position`.
+    pub fn face_view_proj(&self, face: usize, position: Vec3) -> Mat4 {
+        let (direction, up) = FACES[face];
+        let view = Mat4::look_at_rh(position, position + direction, up);
+        let proj = Mat4::perspective_rh(FRAC_PI_2, 1.0, self.z_near, self.z_far);
+
+        proj * view
+    }
+
+    /// Renders the six faces of the cubemap from `position`.
+    ///
+    /// `render_face` is called once per face with the face index and its view projection
+    /// matrix, between begin and end rendering, with viewport and scissor covering the face.
+    /// The cubemap is left in `SHADER_READ_ONLY_OPTIMAL` for fragment shaders.
+    pub fn render<F>(&self, cmd: &CommandBuffer, position: Vec3, mut render_face: F)
+    where
+        F: FnMut(usize, Mat4),
+    {
+        let mut barriers = vec![ImageBarrier {
+            image: &self.image,
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            src_access_mask: vk::AccessFlags2::SHADER_READ,
+            dst_access_mask: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            src_stage_mask: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            dst_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        }];
+        if let Some((depth, _)) = &self.depth {
+            barriers.push(ImageBarrier {
+                image: depth,
+                old_layout: vk::ImageLayout::UNDEFINED,
+                new_layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                src_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                src_stage_mask: vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                dst_stage_mask: vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS,
+            });
+        }
+        cmd.pipeline_image_barriers(&barriers);
+
+        let extent = vk::Extent2D {
+            width: self.size,
+            height: self.size,
+        };
+
+        for (face, face_view) in self.face_views.iter().enumerate() {
+            // Faces share the depth buffer
+            if face > 0 {
+                if let Some((depth, _)) = &self.depth {
+                    cmd.pipeline_image_barriers(&[ImageBarrier {
+                        image: depth,
+                        old_layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                        new_layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                        src_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                        dst_access_mask: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                            | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                        src_stage_mask: vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                        dst_stage_mask: vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS,
+                    }]);
+                }
+            }
+
+            cmd.begin_rendering(
+                &[RenderingAttachment::clear(
+                    face_view,
+                    ClearValue::ColorFloat(self.clear_color),
+                )],
+                self.depth.as_ref().map(|(_, view)| {
+                    RenderingAttachment::clear(view, ClearValue::Depth(1.0))
+                        .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                }),
+                extent,
+            );
+            cmd.set_viewport(extent);
+            cmd.set_scissor(extent);
+
+            render_face(face, self.face_view_proj(face, position));
+
+            cmd.end_rendering();
+        }
+
+        cmd.pipeline_image_barriers(&[ImageBarrier {
+            image: &self.image,
+            old_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            src_access_mask: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags2::SHADER_READ,
+            src_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            dst_stage_mask: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+        }]);
+    }
+}
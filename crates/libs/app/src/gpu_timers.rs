@@ -0,0 +1,195 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use vulkan::{ash::vk, CommandBuffer, Context, TimestampQueryPool};
+
+/// Queries per pool. Pools are added when a frame writes more timestamps than fit.
+const QUERIES_PER_POOL: usize = 64;
+
+/// GPU time of a scope opened with [`crate::BaseApp::push_gpu_timer`].
+#[derive(Debug, Clone)]
+pub(crate) struct GpuTimerScope {
+    pub(crate) name: &'static str,
+    /// Number of scopes this one is nested in.
+    pub(crate) depth: usize,
+    pub(crate) duration: Duration,
+}
+
+struct RecordedScope {
+    name: &'static str,
+    depth: usize,
+    /// `None` when the frame ran out of queries.
+    begin: Option<u32>,
+    end: Option<u32>,
+}
+
+#[derive(Default)]
+struct FrameTimers {
+    pools: Vec<TimestampQueryPool<QUERIES_PER_POOL>>,
+    scopes: Vec<RecordedScope>,
+    open_scopes: Vec<usize>,
+    query_count: u32,
+}
+
+/// Nested named GPU timers written to timestamp queries owned by each frame in flight.
+///
+/// Timestamps are read back without waiting when the frame slot is reused, so timings lag
+/// a few frames behind and are missing until the first recorded frames complete. A frame
+/// writing more timestamps than its pools hold loses the extra scopes and the pools of the
+/// next frames are grown to fit.
+pub(crate) struct GpuTimers {
+    frames: Vec<FrameTimers>,
+    current_frame: usize,
+    enabled: bool,
+    required_queries: u32,
+    results: Vec<GpuTimerScope>,
+}
+
+impl GpuTimers {
+    pub(crate) fn new(frame_count: u32) -> Self {
+        Self {
+            frames: (0..frame_count).map(|_| FrameTimers::default()).collect(),
+            current_frame: 0,
+            enabled: false,
+            required_queries: 0,
+            results: Vec::new(),
+        }
+    }
+
+    /// Reads back the scopes last recorded for `frame`, whose GPU work must be complete,
+    /// and gets its queries ready for recording.
+    pub(crate) fn begin_frame(
+        &mut self,
+        context: &Context,
+        frame: usize,
+        enabled: bool,
+    ) -> Result<()> {
+        self.current_frame = frame;
+        self.enabled = enabled;
+
+        let timers = &mut self.frames[frame];
+        if !enabled {
+            self.results.clear();
+        } else if let Some(results) = read_results(timers)? {
+            self.results = results;
+        }
+
+        timers.scopes.clear();
+        timers.open_scopes.clear();
+        timers.query_count = 0;
+
+        if enabled {
+            let pool_count = (self.required_queries as usize).div_ceil(QUERIES_PER_POOL);
+            while timers.pools.len() < pool_count {
+                timers.pools.push(context.create_timestamp_query_pool()?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resets the queries of the current frame. Must be recorded outside of a rendering pass,
+    /// before any scope is pushed.
+    pub(crate) fn cmd_reset(&self, buffer: &CommandBuffer) {
+        if !self.enabled {
+            return;
+        }
+
+        for pool in &self.frames[self.current_frame].pools {
+            buffer.reset_all_timestamp_queries_from_pool(pool);
+        }
+    }
+
+    pub(crate) fn push(&mut self, buffer: &CommandBuffer, name: &'static str) {
+        if !self.enabled {
+            return;
+        }
+
+        let begin = self.write_timestamp(buffer, vk::PipelineStageFlags2::TOP_OF_PIPE);
+
+        let timers = &mut self.frames[self.current_frame];
+        timers.open_scopes.push(timers.scopes.len());
+        timers.scopes.push(RecordedScope {
+            name,
+            depth: timers.open_scopes.len() - 1,
+            begin,
+            end: None,
+        });
+    }
+
+    pub(crate) fn pop(&mut self, buffer: &CommandBuffer) {
+        if !self.enabled {
+            return;
+        }
+
+        let Some(index) = self.frames[self.current_frame].open_scopes.pop() else {
+            log::error!("GPU timer popped without a matching push");
+            return;
+        };
+
+        let end = self.write_timestamp(buffer, vk::PipelineStageFlags2::ALL_COMMANDS);
+        self.frames[self.current_frame].scopes[index].end = end;
+    }
+
+    /// Timings of the last frame that could be read back, in the order scopes were pushed.
+    pub(crate) fn results(&self) -> &[GpuTimerScope] {
+        &self.results
+    }
+
+    fn write_timestamp(
+        &mut self,
+        buffer: &CommandBuffer,
+        stage: vk::PipelineStageFlags2,
+    ) -> Option<u32> {
+        let timers = &mut self.frames[self.current_frame];
+        let query = timers.query_count;
+        timers.query_count += 1;
+        self.required_queries = self.required_queries.max(timers.query_count);
+
+        let pool = timers.pools.get(query as usize / QUERIES_PER_POOL)?;
+        buffer.write_timestamp(stage, pool, query % QUERIES_PER_POOL as u32);
+
+        Some(query)
+    }
+}
+
+/// Returns `None` when nothing was recorded or the timestamps are not available yet.
+fn read_results(timers: &FrameTimers) -> Result<Option<Vec<GpuTimerScope>>> {
+    let Some(first_pool) = timers.pools.first() else {
+        return Ok(None);
+    };
+    if timers.scopes.is_empty() || !timers.open_scopes.is_empty() {
+        return Ok(None);
+    }
+
+    let mut timestamps = Vec::with_capacity(timers.query_count as _);
+    for (index, pool) in timers.pools.iter().enumerate() {
+        let written = (timers.query_count as usize)
+            .saturating_sub(index * QUERIES_PER_POOL)
+            .min(QUERIES_PER_POOL);
+        if written == 0 {
+            break;
+        }
+
+        match pool.try_get_range_results(0, written as _)? {
+            Some(results) => timestamps.extend(results),
+            None => return Ok(None),
+        }
+    }
+
+    let results = timers
+        .scopes
+        .iter()
+        .filter_map(|scope| {
+            let begin = *timestamps.get(scope.begin? as usize)?;
+            let end = *timestamps.get(scope.end? as usize)?;
+            Some(GpuTimerScope {
+                name: scope.name,
+                depth: scope.depth,
+                duration: first_pool.elapsed(begin, end),
+            })
+        })
+        .collect();
+
+    Ok(Some(results))
+}
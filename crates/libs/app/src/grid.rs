@@ -0,0 +1,156 @@
+use std::mem::size_of;
+
+use anyhow::Result;
+use vulkan::{
+    ash::vk, ColorAttachmentsInfo, CommandBuffer, Context, DepthInfo, EmptyVertex,
+    GraphicsPipeline, GraphicsPipelineCreateInfo, GraphicsShaderCreateInfo, PipelineCache,
+    PipelineLayout,
+};
+
+use crate::Camera;
+
+/// Infinite looking grid on the XZ plane.
+///
+/// The grid is drawn with a fullscreen triangle, each fragment intersecting its view ray with
+/// the plane. Lines are anti-aliased using screen space derivatives and fade with the distance
+/// to the camera. It is alpha blended so it should be drawn after opaque geometry.
+pub struct GroundGrid {
+    pipeline_layout: PipelineLayout,
+    pipeline: GraphicsPipeline,
+    depth_format: Option<vk::Format>,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+#[repr(C)]
+struct GridConstants {
+    inverse_view_proj: [f32; 16],
+    view_proj_row_z: [f32; 4],
+    view_proj_row_w: [f32; 4],
+    color: [f32; 4],
+    spacing: f32,
+    fade_distance: f32,
+}
+
+impl GroundGrid {
+    /// Creates a grid rendering to `color_format` targets.
+    ///
+    /// With a `depth_format`, the grid is depth tested against the depth attachment of the
+    /// rendering pass it is drawn in, but does not write to it.
+    pub fn new(
+        context: &Context,
+        pipeline_cache: Option<&PipelineCache>,
+        color_format: vk::Format,
+        depth_format: Option<vk::Format>,
+    ) -> Result<Self> {
+        let pipeline_layout = context.create_pipeline_layout_with_push_constants(
+            &[],
+            &[vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                offset: 0,
+                size: size_of::<GridConstants>() as _,
+            }],
+        )?;
+        let pipeline = create_pipeline(
+            context,
+            pipeline_cache,
+            &pipeline_layout,
+            color_format,
+            depth_format,
+        )?;
+
+        Ok(Self {
+            pipeline_layout,
+            pipeline,
+            depth_format,
+        })
+    }
+
+    /// Recreates the pipeline for a new color attachment format.
+    pub fn set_color_format(
+        &mut self,
+        context: &Context,
+        pipeline_cache: Option<&PipelineCache>,
+        color_format: vk::Format,
+    ) -> Result<()> {
+        self.pipeline = create_pipeline(
+            context,
+            pipeline_cache,
+            &self.pipeline_layout,
+            color_format,
+            self.depth_format,
+        )?;
+
+        Ok(())
+    }
+
+    /// Records the grid draw. Must be called between begin and end rendering,
+    /// after viewport and scissor were set.
+    pub fn draw(&self, cmd: &CommandBuffer, camera: &Camera, spacing: f32, color: [f32; 4]) {
+        let view_proj = camera.projection_matrix() * camera.view_matrix();
+        let constants = GridConstants {
+            inverse_view_proj: view_proj.inverse().to_cols_array(),
+            view_proj_row_z: view_proj.row(2).to_array(),
+            view_proj_row_w: view_proj.row(3).to_array(),
+            color,
+            spacing,
+            fade_distance: camera.z_far,
+        };
+
+        cmd.bind_graphics_pipeline(&self.pipeline);
+        cmd.push_constants(
+            &self.pipeline_layout,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            0,
+            &constants,
+        );
+        cmd.draw(3);
+    }
+}
+
+fn create_pipeline(
+    context: &Context,
+    pipeline_cache: Option<&PipelineCache>,
+    layout: &PipelineLayout,
+    color_format: vk::Format,
+    depth_format: Option<vk::Format>,
+) -> Result<GraphicsPipeline> {
+    context.create_graphics_pipeline::<EmptyVertex>(
+        layout,
+        GraphicsPipelineCreateInfo {
+            shaders: &[
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/grid.vert.spv")[..],
+                    stage: vk::ShaderStageFlags::VERTEX,
+                    ..Default::default()
+                },
+                GraphicsShaderCreateInfo {
+                    source: &include_bytes!("../shaders/grid.frag.spv")[..],
+                    stage: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ],
+            color_attachments: ColorAttachmentsInfo {
+                formats: &[color_format],
+                blends: &[vk::PipelineColorBlendAttachmentState {
+                    blend_enable: vk::TRUE,
+                    src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+                    dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                    color_blend_op: vk::BlendOp::ADD,
+                    src_alpha_blend_factor: vk::BlendFactor::ONE,
+                    dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                    alpha_blend_op: vk::BlendOp::ADD,
+                    color_write_mask: vk::ColorComponentFlags::RGBA,
+                }],
+            },
+            depth: depth_format.map(|format| DepthInfo {
+                format,
+                enable_depth_write: false,
+                ..Default::default()
+            }),
+            dynamic_states: Some(&[vk::DynamicState::SCISSOR, vk::DynamicState::VIEWPORT]),
+            ..Default::default()
+        },
+        pipeline_cache,
+    )
+}
@@ -2,33 +2,63 @@ pub extern crate anyhow;
 pub extern crate glam;
 pub extern crate log;
 pub extern crate vulkan;
+pub extern crate winit;
 
+mod background;
 mod camera;
+mod capture;
+mod cubemap;
+mod gpu_timers;
+mod grid;
+
+pub use background::Background;
+pub use camera::{Camera, CameraMode, KeyBindings};
+pub use capture::save_image_to_png;
+pub use cubemap::Cubemap;
+pub use grid::GroundGrid;
 
 use anyhow::Result;
 use ash::vk::{self};
-use camera::{Camera, Controls};
-use glam::vec3;
+use background::BackgroundPass;
+use camera::Controls;
+use capture::{
+    check_png_support, is_bgra_format, screenshot_path, write_png, FrameReadback,
+    FrameSequenceCapture, FRAME_SEQUENCE_TIMESTEP,
+};
+use glam::{vec3, Vec3};
 use gpu_allocator::MemoryLocation;
+use gpu_timers::GpuTimers;
 use gui::{
     egui::{self, Align2, ClippedPrimitive, FullOutput, TextureId},
     GuiContext,
 };
 use std::{
+    cell::RefCell,
     fs::OpenOptions,
+    path::PathBuf,
     time::{Duration, Instant},
 };
 use vulkan::*;
 use winit::{
     application::ApplicationHandler,
     dpi::PhysicalSize,
-    event::{ElementState, KeyEvent, MouseButton, WindowEvent},
+    event::{ElementState, KeyEvent, WindowEvent},
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
     keyboard::{KeyCode, PhysicalKey},
-    window::Window,
+    window::{Fullscreen, Window},
 };
 
-const IN_FLIGHT_FRAMES: u32 = 2;
+const DEFAULT_IN_FLIGHT_FRAMES: u32 = 2;
+const DEFAULT_PRESENT_MODE: vk::PresentModeKHR = vk::PresentModeKHR::IMMEDIATE;
+const FRAME_TIMES_CSV_PATH: &str = "frame_times.csv";
+const PIPELINE_CACHE_PATH: &str = "pipeline_cache.bin";
+const DEFAULT_GPU_WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time a paused app advances by when stepping a single frame.
+const PAUSED_STEP: Duration = FRAME_SEQUENCE_TIMESTEP;
+const RAY_TRACING_LABEL_COLOR: [f32; 4] = [0.8, 0.2, 0.6, 1.0];
+const BACKGROUND_LABEL_COLOR: [f32; 4] = [0.4, 0.4, 0.4, 1.0];
+const RASTER_LABEL_COLOR: [f32; 4] = [0.2, 0.6, 0.8, 1.0];
+const UI_LABEL_COLOR: [f32; 4] = [0.8, 0.8, 0.2, 1.0];
 
 pub struct BaseApp {
     raytracing_enabled: bool,
@@ -36,22 +66,151 @@ pub struct BaseApp {
     pub command_pool: CommandPool,
     pub storage_images: Vec<ImageAndView>,
     pub command_buffers: Vec<CommandBuffer>,
+    gui_command_buffers: Option<Vec<CommandBuffer>>,
     in_flight_frames: InFlightFrames,
+    gpu_timers: RefCell<GpuTimers>,
     pub camera: Camera,
     stats_display_mode: StatsDisplayMode,
+    gui_debug_enabled: bool,
+    gui_debug_visible: bool,
 
     pub gui_context: GuiContext,
+    /// Loaded from `pipeline_cache.bin` at startup and saved back on exit.
+    pub pipeline_cache: PipelineCache,
+    ground_grid: Option<GroundGrid>,
+    background: Option<Background>,
+    background_pass: BackgroundPass,
+    gpu_wait_timeout: Option<Duration>,
+    initialize_swapchain_layouts: bool,
+    linear_workflow: bool,
+    gui_depth_format: Option<vk::Format>,
 
     pub context: Context, // make sure it's dropped last
 
     requested_swapchain_format: Option<vk::SurfaceFormatKHR>,
+    requested_present_mode: Option<vk::PresentModeKHR>,
+    requested_screenshot: Option<PathBuf>,
+    frame_capture: Option<FrameSequenceCapture>,
 }
 
-#[derive(Debug, Default, Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
 pub struct AppConfig<'a, 'b> {
     pub enable_raytracing: bool,
     pub required_instance_extensions: &'a [&'b str],
     pub enable_independent_blend: bool,
+    pub enable_fill_mode_non_solid: bool,
+    pub enable_wide_lines: bool,
+    /// Required to create samplers with [`SamplerInfo::max_anisotropy`].
+    pub enable_sampler_anisotropy: bool,
+    /// Required to create graphics pipelines with a geometry shader stage.
+    pub enable_geometry_shader: bool,
+    /// Required to create graphics pipelines with [`GraphicsPipelineCreateInfo::tessellation`].
+    pub enable_tessellation_shader: bool,
+    /// Required to use `gl_DrawID`, `gl_BaseInstance` and `gl_BaseVertex` in shaders.
+    pub enable_shader_draw_parameters: bool,
+    /// Enables runtime descriptor arrays and partially bound, variable count and update
+    /// after bind descriptor bindings, for bindless resources.
+    pub enable_descriptor_indexing: bool,
+    pub in_flight_frames: u32,
+    /// Allows toggling egui's debug on hover and inspection window with F12.
+    pub enable_gui_debug: bool,
+    /// Automatically orbit the camera when there was no input for a while.
+    pub turntable: Option<TurntableConfig>,
+    pub key_bindings: KeyBindings,
+    /// Records the GUI into secondary command buffers executed in the UI rendering pass.
+    pub record_gui_in_secondary_command_buffers: bool,
+    /// Creates a ground grid targeting the swapchain, drawn with [`BaseApp::draw_grid`].
+    pub ground_grid: Option<GroundGridConfig>,
+    /// Maximum time [`BaseApp::wait_for_gpu`] waits for the GPU to be idle before failing.
+    /// Keeps the app from hanging on exit when a shader is stuck. Waits forever when `None`.
+    pub gpu_wait_timeout: Option<Duration>,
+    pub frame_pacing: FramePacing,
+    /// Transitions swapchain images to `PRESENT_SRC_KHR` when the swapchain is (re)created
+    /// instead of leaving them in `UNDEFINED` until their first frame.
+    pub initialize_swapchain_layouts: bool,
+    /// Guarantees colors stay linear until they are presented. Shaders output linear values,
+    /// intermediate color targets should be float formats and the swapchain uses an sRGB format
+    /// (or a float format with a linear extended sRGB color space) so the encoding happens
+    /// when writing to it. The ray tracing storage image is blitted instead of copied into the
+    /// swapchain for the same reason. App creation fails if the surface has no such format.
+    pub linear_workflow: bool,
+    /// Format of a depth attachment bound to the UI rendering pass. Apps setting it must
+    /// return a view of that format from [`App::gui_depth_attachment`].
+    pub gui_depth_format: Option<vk::Format>,
+    /// Calls [`App::fixed_update`] with a constant step before each frame's update.
+    pub fixed_timestep: Option<FixedTimestep>,
+}
+
+/// How the CPU waits for a frame in flight to complete before reusing its resources.
+///
+/// Both modes still use a pair of binary semaphores per frame since the swapchain
+/// doesn't support timeline semaphores for acquisition and presentation.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum FramePacing {
+    /// One fence per frame in flight, waited for then reset before recording the frame.
+    #[default]
+    Fences,
+    /// A single timeline semaphore shared by all frames in flight. Frame `n` signals value `n`
+    /// and the frame reusing its resources waits for that value. There is nothing to reset
+    /// and the same semaphore can tell how far the GPU is with [`Semaphore::value`].
+    TimelineSemaphore,
+}
+
+/// Step of [`App::fixed_update`], which is called as many times as whole steps fit in the
+/// time elapsed since the last frame. The leftover time is carried over to the next frame.
+#[derive(Debug, Copy, Clone)]
+pub struct FixedTimestep {
+    pub step: Duration,
+    /// Guards against the spiral of death, where steps take longer than the time they simulate
+    /// so each frame has more steps to catch up on than the previous one. Once a frame reaches
+    /// this many steps, the time left is dropped and the app slows down instead.
+    pub max_steps_per_frame: u32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct GroundGridConfig {
+    /// Format of the depth attachment of the rendering pass the grid is drawn in.
+    /// The grid is not depth tested when `None`.
+    pub depth_format: Option<vk::Format>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct TurntableConfig {
+    /// Time without input before the camera starts orbiting.
+    pub idle_timeout: Duration,
+    pub center: Vec3,
+    pub radius: f32,
+    /// Rotation speed in radians per second.
+    pub speed: f32,
+}
+
+impl Default for AppConfig<'_, '_> {
+    fn default() -> Self {
+        Self {
+            enable_raytracing: false,
+            required_instance_extensions: &[],
+            enable_independent_blend: false,
+            enable_fill_mode_non_solid: false,
+            enable_wide_lines: false,
+            enable_sampler_anisotropy: false,
+            enable_geometry_shader: false,
+            enable_tessellation_shader: false,
+            enable_shader_draw_parameters: false,
+            enable_descriptor_indexing: false,
+            in_flight_frames: DEFAULT_IN_FLIGHT_FRAMES,
+            enable_gui_debug: false,
+            turntable: None,
+            key_bindings: KeyBindings::default(),
+            record_gui_in_secondary_command_buffers: false,
+            ground_grid: None,
+            gpu_wait_timeout: Some(DEFAULT_GPU_WAIT_TIMEOUT),
+            frame_pacing: FramePacing::default(),
+            initialize_swapchain_layouts: false,
+            linear_workflow: false,
+            gui_depth_format: None,
+            fixed_timestep: None,
+        }
+    }
 }
 
 pub trait App: Sized {
@@ -67,6 +226,14 @@ pub trait App: Sized {
         delta_time: Duration,
     ) -> Result<()>;
 
+    /// Called zero or more times per frame, before [`App::update`], when
+    /// [`AppConfig::fixed_timestep`] is set. `step` is always the configured step so
+    /// simulations advance the same way at any frame rate.
+    fn fixed_update(&mut self, base: &mut BaseApp, step: Duration) -> Result<()> {
+        let _ = (base, step);
+        Ok(())
+    }
+
     fn record_raytracing_commands(
         &self,
         base: &BaseApp,
@@ -81,15 +248,56 @@ pub trait App: Sized {
         Ok(())
     }
 
-    fn record_raster_commands(&self, base: &BaseApp, image_index: usize) -> Result<()> {
+    fn record_raster_commands(
+        &self,
+        base: &BaseApp,
+        buffer: &CommandBuffer,
+        image_index: usize,
+    ) -> Result<()> {
         // prevents reports of unused parameters without needing to use #[allow]
         let _ = base;
+        let _ = buffer;
         let _ = image_index;
 
         Ok(())
     }
 
-    fn on_recreate_swapchain(&mut self, base: &BaseApp) -> Result<()>;
+    /// Called after the swapchain was recreated. Apps can check `change` to only rebuild
+    /// what depends on the extent or format, or ignore it and rebuild everything.
+    ///
+    /// Textures registered with [`GuiContext::register_texture`] are unregistered before
+    /// this is called. Does nothing by default.
+    fn on_recreate_swapchain(&mut self, base: &BaseApp, change: SwapchainChange) -> Result<()> {
+        let _ = (base, change);
+        Ok(())
+    }
+
+    /// Depth attachment loaded in the UI rendering pass when [`AppConfig::gui_depth_format`]
+    /// is set. It must be in `DEPTH_ATTACHMENT_OPTIMAL` layout at the end of the app's
+    /// rasterization commands.
+    fn gui_depth_attachment(&self, image_index: usize) -> Option<&ImageView> {
+        let _ = image_index;
+        None
+    }
+
+    /// Called for every window event before the built-in handling (camera controls, stats,
+    /// screenshots, ...), which still runs afterwards.
+    fn on_window_event(&mut self, base: &mut BaseApp, event: &WindowEvent) {
+        let _ = (base, event);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapchainChange {
+    pub extent_changed: bool,
+    pub format: vk::Format,
+    pub old_format: vk::Format,
+}
+
+impl SwapchainChange {
+    pub fn format_changed(&self) -> bool {
+        self.format != self.old_format
+    }
 }
 
 pub trait Gui: Sized {
@@ -126,6 +334,8 @@ impl StatsDisplayMode {
     }
 }
 
+/// Runs the app until its window is closed. Fails with [`DeviceLost`] or [`GpuTimeout`] when it
+/// exited because the GPU was lost or stuck.
 pub fn run<A: App + 'static>(
     app_name: &str,
     width: u32,
@@ -141,22 +351,32 @@ pub fn run<A: App + 'static>(
         height,
         app_config,
 
-        controls: Controls::default(),
+        controls: Controls::new(app_config.key_bindings),
+        last_input: Instant::now(),
         is_swapchain_dirty: false,
         last_frame: Instant::now(),
         frame_stats: FrameStats::default(),
+        fixed_update_time: Duration::ZERO,
+        paused: false,
+        step_requested: false,
 
         base_app: None,
         window: None,
         app: None,
         gui: None,
+        device_lost: None,
+        gpu_timeout: None,
     };
 
     let event_loop = EventLoop::new().unwrap();
     event_loop.set_control_flow(ControlFlow::Poll);
     event_loop.run_app(&mut wrapper)?;
 
-    Ok(())
+    match (wrapper.device_lost, wrapper.gpu_timeout) {
+        (Some(device_lost), _) => Err(device_lost.into()),
+        (None, Some(gpu_timeout)) => Err(gpu_timeout.into()),
+        (None, None) => Ok(()),
+    }
 }
 
 struct AppWrapper<'a, A: App> {
@@ -166,14 +386,40 @@ struct AppWrapper<'a, A: App> {
     app_config: AppConfig<'a, 'a>, // FIXME: lifetimes ?
 
     controls: Controls,
+    last_input: Instant,
     is_swapchain_dirty: bool,
     last_frame: Instant,
     frame_stats: FrameStats,
+    /// Elapsed time not consumed by fixed updates yet.
+    fixed_update_time: Duration,
+    paused: bool,
+    /// Advance the paused app by one frame on the next tick.
+    step_requested: bool,
 
     window: Option<Window>,
     app: Option<A>,
     gui: Option<A::Gui>,
     base_app: Option<BaseApp>,
+    device_lost: Option<DeviceLost>,
+    gpu_timeout: Option<GpuTimeout>,
+}
+
+impl<A: App> AppWrapper<'_, A> {
+    /// Exits the event loop when the device was lost since it cannot be recovered,
+    /// or when the GPU seems stuck. Panics on any other error.
+    fn handle_error(&mut self, event_loop: &ActiveEventLoop, err: anyhow::Error, message: &str) {
+        if let Some(&device_lost) = err.downcast_ref::<DeviceLost>() {
+            log::error!("{message}. {device_lost}. Exiting.");
+            self.device_lost = Some(device_lost);
+            event_loop.exit();
+        } else if let Some(&gpu_timeout) = err.downcast_ref::<GpuTimeout>() {
+            log::error!("{message}. {gpu_timeout}. Exiting.");
+            self.gpu_timeout = Some(gpu_timeout);
+            event_loop.exit();
+        } else {
+            panic!("{message}. Cause: {err}");
+        }
+    }
 }
 
 impl<A: App> ApplicationHandler for AppWrapper<'_, A> {
@@ -207,12 +453,24 @@ impl<A: App> ApplicationHandler for AppWrapper<'_, A> {
     ) {
         let base_app = self.base_app.as_mut().unwrap();
 
+        self.app.as_mut().unwrap().on_window_event(base_app, &event);
+
         base_app
             .gui_context
             .handle_event(self.window.as_ref().unwrap(), &event);
 
         self.controls = self.controls.handle_window_event(&event);
 
+        if matches!(
+            event,
+            WindowEvent::KeyboardInput { .. }
+                | WindowEvent::MouseInput { .. }
+                | WindowEvent::MouseWheel { .. }
+                | WindowEvent::CursorMoved { .. }
+        ) {
+            self.last_input = Instant::now();
+        }
+
         match event {
             // On resize
             WindowEvent::Resized(..) => {
@@ -222,26 +480,102 @@ impl<A: App> ApplicationHandler for AppWrapper<'_, A> {
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
-                        state,
-                        physical_key,
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyR),
+                        ..
+                    },
+                ..
+            } => {
+                base_app.toggle_stats();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::KeyC),
                         ..
                     },
                 ..
             } => {
-                if matches!(physical_key, PhysicalKey::Code(KeyCode::KeyR))
-                    && state == ElementState::Pressed
-                {
-                    base_app.toggle_stats();
+                base_app.camera = base_app.camera.toggle_mode();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::F2),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                if let Err(err) = base_app.capture_screenshot(screenshot_path()) {
+                    log::error!("Failed to capture screenshot. Cause: {err}");
                 }
             }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::F9),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                self.paused = !self.paused;
+                self.step_requested = false;
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::Period),
+                        ..
+                    },
+                ..
+            } if self.paused => {
+                self.step_requested = true;
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::F11),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let window = self.window.as_ref().unwrap();
+                let fullscreen = match window.fullscreen() {
+                    Some(_) => None,
+                    None => Some(Fullscreen::Borderless(None)),
+                };
+                window.set_fullscreen(fullscreen);
+                // Not all platforms send a resize event when the fullscreen state changes.
+                // A minimized window is left alone by the extent check in about_to_wait
+                self.is_swapchain_dirty = true;
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::F12),
+                        ..
+                    },
+                ..
+            } => {
+                base_app.toggle_gui_debug();
+            }
             // Mouse
-            WindowEvent::MouseInput { state, button, .. } => {
-                if button == MouseButton::Right {
-                    if state == ElementState::Pressed {
-                        self.window.as_ref().unwrap().set_cursor_visible(false);
-                    } else {
-                        self.window.as_ref().unwrap().set_cursor_visible(true);
-                    }
+            WindowEvent::MouseInput { state, button, .. }
+                if button == self.app_config.key_bindings.look =>
+            {
+                if state == ElementState::Pressed {
+                    self.window.as_ref().unwrap().set_cursor_visible(false);
+                } else {
+                    self.window.as_ref().unwrap().set_cursor_visible(true);
                 }
             }
             // Exit app on request to close window
@@ -259,51 +593,124 @@ impl<A: App> ApplicationHandler for AppWrapper<'_, A> {
         self.controls = self.controls.handle_device_event(&event);
     }
 
-    fn about_to_wait(&mut self, _: &ActiveEventLoop) {
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        // Stop ticking while the event loop exits
+        if self.device_lost.is_some() || self.gpu_timeout.is_some() {
+            return;
+        }
+
         let base_app = self.base_app.as_mut().unwrap();
 
-        if self.is_swapchain_dirty || base_app.requested_swapchain_format.is_some() {
+        if self.is_swapchain_dirty
+            || base_app.requested_swapchain_format.is_some()
+            || base_app.requested_present_mode.is_some()
+        {
             let dim = self.window.as_ref().unwrap().inner_size();
             let format = base_app.requested_swapchain_format.take();
+            let present_mode = base_app.requested_present_mode.take();
 
             if dim.width > 0 && dim.height > 0 {
-                base_app
-                    .recreate_swapchain(dim.width, dim.height, format)
-                    .expect("Failed to recreate swapchain");
+                let change = match base_app.recreate_swapchain(
+                    dim.width,
+                    dim.height,
+                    format,
+                    present_mode,
+                ) {
+                    Ok(change) => change,
+                    Err(err) => {
+                        self.handle_error(event_loop, err, "Failed to recreate swapchain");
+                        return;
+                    }
+                };
                 self.app
                     .as_mut()
                     .unwrap()
-                    .on_recreate_swapchain(base_app)
+                    .on_recreate_swapchain(base_app, change)
                     .expect("Error on recreate swapchain callback");
             } else {
                 return;
             }
         }
 
-        base_app.camera = base_app
-            .camera
-            .update(&self.controls, self.frame_stats.frame_time);
+        base_app.camera = match self.app_config.turntable {
+            Some(TurntableConfig {
+                idle_timeout,
+                center,
+                radius,
+                speed,
+            }) if self.last_input.elapsed() >= idle_timeout => {
+                let delta = base_app.frame_delta(self.frame_stats.frame_time);
+                base_app.camera.turntable(center, radius, speed, delta)
+            }
+            _ => {
+                let delta = base_app.frame_delta(self.frame_stats.frame_time);
+                base_app.camera.update(&self.controls, delta)
+            }
+        };
+
+        // The camera keeps moving while paused so the frozen scene can be inspected
+        let delta_time = match (self.paused, std::mem::take(&mut self.step_requested)) {
+            (false, _) => base_app.frame_delta(self.frame_stats.frame_time),
+            (true, true) => PAUSED_STEP,
+            (true, false) => Duration::ZERO,
+        };
+        self.frame_stats.set_delta_time(delta_time, self.paused);
 
-        self.is_swapchain_dirty = base_app
-            .draw(
-                self.window.as_ref().unwrap(),
+        if let Some(fixed_timestep) = self.app_config.fixed_timestep {
+            self.fixed_update_time += delta_time;
+            if let Err(err) = run_fixed_updates(
                 self.app.as_mut().unwrap(),
-                self.gui.as_mut().unwrap(),
-                &mut self.frame_stats,
-            )
-            .expect("Failed to tick");
+                base_app,
+                fixed_timestep,
+                &mut self.fixed_update_time,
+            ) {
+                self.handle_error(event_loop, err, "Failed to run fixed update");
+                return;
+            }
+        }
+
+        let draw_result = base_app.draw(
+            self.window.as_ref().unwrap(),
+            self.app.as_mut().unwrap(),
+            self.gui.as_mut().unwrap(),
+            &mut self.frame_stats,
+        );
+        match draw_result {
+            Ok(is_swapchain_dirty) => self.is_swapchain_dirty = is_swapchain_dirty,
+            Err(err) => self.handle_error(event_loop, err, "Failed to tick"),
+        }
     }
 
     fn exiting(&mut self, _: &ActiveEventLoop) {
-        self.base_app
-            .as_mut()
-            .unwrap()
-            .wait_for_gpu()
-            .expect("Failed to wait for gpu when exiting")
+        // Waiting on a lost or stuck device would only fail again
+        if self.device_lost.is_some() || self.gpu_timeout.is_some() {
+            return;
+        }
+
+        let base_app = self.base_app.as_mut().unwrap();
+        if let Err(err) = base_app.wait_for_gpu() {
+            match err.downcast_ref::<GpuTimeout>() {
+                Some(&gpu_timeout) => {
+                    log::error!("Failed to wait for gpu when exiting. {gpu_timeout}.");
+                    self.gpu_timeout = Some(gpu_timeout);
+                    return;
+                }
+                None => panic!("Failed to wait for gpu when exiting. Cause: {err}"),
+            }
+        }
+
+        base_app.flush_capture();
+
+        if let Err(err) = base_app.pipeline_cache.save(PIPELINE_CACHE_PATH) {
+            log::error!("Failed to save pipeline cache. Cause: {err}");
+        }
     }
 }
 
-fn setup_logs(app_name: &str, log_to_file: bool) {
+/// Logs to the terminal and to `<app_name>.log` when `log_to_file` is set.
+///
+/// Called by [`run`], headless apps driving their own loop call it themselves.
+pub fn setup_logs(app_name: &str, log_to_file: bool) {
     use simplelog::*;
 
     const LEVEL: log::LevelFilter = log::LevelFilter::Debug;
@@ -330,6 +737,34 @@ fn setup_logs(app_name: &str, log_to_file: bool) {
     CombinedLogger::init(loggers).expect("logger");
 }
 
+/// Consumes as many whole steps of `time` as allowed by `fixed_timestep`.
+fn run_fixed_updates<A: App>(
+    app: &mut A,
+    base_app: &mut BaseApp,
+    fixed_timestep: FixedTimestep,
+    time: &mut Duration,
+) -> Result<()> {
+    let FixedTimestep {
+        step,
+        max_steps_per_frame,
+    } = fixed_timestep;
+
+    let mut steps = 0;
+    while *time >= step {
+        if steps == max_steps_per_frame {
+            log::debug!("Fixed update fell behind, dropping {time:?}");
+            *time = Duration::ZERO;
+            break;
+        }
+
+        app.fixed_update(base_app, step)?;
+        *time -= step;
+        steps += 1;
+    }
+
+    Ok(())
+}
+
 fn create_window(
     evt_loop: &ActiveEventLoop,
     app_name: &str,
@@ -357,8 +792,29 @@ impl BaseApp {
             enable_raytracing,
             required_instance_extensions,
             enable_independent_blend,
+            enable_fill_mode_non_solid,
+            enable_wide_lines,
+            enable_sampler_anisotropy,
+            enable_geometry_shader,
+            enable_tessellation_shader,
+            enable_shader_draw_parameters,
+            enable_descriptor_indexing,
+            in_flight_frames,
+            enable_gui_debug,
+            record_gui_in_secondary_command_buffers,
+            ground_grid,
+            gpu_wait_timeout,
+            frame_pacing,
+            initialize_swapchain_layouts,
+            linear_workflow,
+            gui_depth_format,
+            ..
         } = app_config;
 
+        if in_flight_frames == 0 {
+            return Err(anyhow::anyhow!("In flight frames count must be at least 1"));
+        }
+
         // Vulkan context
         let mut required_extensions = vec!["VK_KHR_swapchain"];
         if app_config.enable_raytracing {
@@ -375,11 +831,22 @@ impl BaseApp {
             .required_device_features(DeviceFeatures {
                 ray_tracing_pipeline: enable_raytracing,
                 acceleration_structure: enable_raytracing,
-                runtime_descriptor_array: enable_raytracing,
+                runtime_descriptor_array: enable_raytracing || enable_descriptor_indexing,
                 buffer_device_address: enable_raytracing,
                 dynamic_rendering: true,
                 synchronization2: true,
+                timeline_semaphore: true,
                 independent_blend: enable_independent_blend,
+                fill_mode_non_solid: enable_fill_mode_non_solid,
+                wide_lines: enable_wide_lines,
+                sampler_anisotropy: enable_sampler_anisotropy,
+                geometry_shader: enable_geometry_shader,
+                tessellation_shader: enable_tessellation_shader,
+                shader_draw_parameters: enable_shader_draw_parameters,
+                descriptor_binding_partially_bound: enable_descriptor_indexing,
+                descriptor_binding_variable_descriptor_count: enable_descriptor_indexing,
+                descriptor_binding_update_unused_while_pending: enable_descriptor_indexing,
+                descriptor_binding_update_after_bind: enable_descriptor_indexing,
             })
             .with_raytracing_context(enable_raytracing)
             .build()?;
@@ -393,7 +860,17 @@ impl BaseApp {
             &context,
             window.inner_size().width,
             window.inner_size().height,
+            DEFAULT_PRESENT_MODE,
         )?;
+        if linear_workflow && !is_linear_workflow_format(swapchain.surface_format()) {
+            return Err(anyhow::anyhow!(
+                "Linear workflow requires an sRGB or linear float swapchain format but got {:?}",
+                swapchain.surface_format()
+            ));
+        }
+        if initialize_swapchain_layouts {
+            swapchain.initialize_layouts(&context)?;
+        }
 
         let storage_images = if enable_raytracing {
             create_storage_images(&mut context, swapchain.extent, swapchain.images.len())?
@@ -402,8 +879,23 @@ impl BaseApp {
         };
 
         let command_buffers = create_command_buffers(&command_pool, &swapchain)?;
+        let gui_command_buffers = record_gui_in_secondary_command_buffers
+            .then(|| {
+                command_pool.allocate_command_buffers(
+                    vk::CommandBufferLevel::SECONDARY,
+                    swapchain.images.len() as _,
+                )
+            })
+            .transpose()?;
 
-        let in_flight_frames = InFlightFrames::new(&context, IN_FLIGHT_FRAMES)?;
+        if in_flight_frames as usize > swapchain.images.len() {
+            log::warn!(
+                "Requested {in_flight_frames} in flight frames but the swapchain only has {} images",
+                swapchain.images.len()
+            );
+        }
+        let in_flight_frames = InFlightFrames::new(&context, in_flight_frames, frame_pacing)?;
+        let gpu_timers = RefCell::new(GpuTimers::new(in_flight_frames.count()));
 
         let camera = Camera::new(
             vec3(0.0, 0.0, 1.0),
@@ -414,8 +906,29 @@ impl BaseApp {
             10.0,
         );
 
-        let gui_context =
-            GuiContext::new(&context, swapchain.format, window, IN_FLIGHT_FRAMES as _)?;
+        let gui_context = GuiContext::new(
+            &context,
+            swapchain.format,
+            gui_depth_format,
+            window,
+            in_flight_frames.count() as _,
+        )?;
+
+        let pipeline_cache = context.load_pipeline_cache(PIPELINE_CACHE_PATH)?;
+
+        let ground_grid = ground_grid
+            .map(|config| {
+                GroundGrid::new(
+                    &context,
+                    Some(&pipeline_cache),
+                    swapchain.format,
+                    config.depth_format,
+                )
+            })
+            .transpose()?;
+
+        let background_pass =
+            BackgroundPass::new(&context, Some(&pipeline_cache), swapchain.format)?;
 
         Ok(Self {
             raytracing_enabled: enable_raytracing,
@@ -424,12 +937,27 @@ impl BaseApp {
             swapchain,
             storage_images,
             command_buffers,
+            gui_command_buffers,
             in_flight_frames,
+            gpu_timers,
             camera,
             stats_display_mode: StatsDisplayMode::Basic,
+            gui_debug_enabled: enable_gui_debug,
+            gui_debug_visible: false,
             gui_context,
+            pipeline_cache,
+            ground_grid,
+            background: None,
+            background_pass,
+            gpu_wait_timeout,
+            initialize_swapchain_layouts,
+            linear_workflow,
+            gui_depth_format,
 
             requested_swapchain_format: None,
+            requested_present_mode: None,
+            requested_screenshot: None,
+            frame_capture: None,
         })
     }
 
@@ -437,19 +965,161 @@ impl BaseApp {
         self.requested_swapchain_format = Some(format);
     }
 
+    pub fn request_present_mode_change(&mut self, present_mode: vk::PresentModeKHR) {
+        self.requested_present_mode = Some(present_mode);
+    }
+
+    pub fn available_present_modes(&self) -> &[vk::PresentModeKHR] {
+        self.context.supported_present_modes()
+    }
+
+    /// Switches to `present_mode` if the surface supports it. The swapchain is recreated before the next frame.
+    pub fn set_present_mode(&mut self, present_mode: vk::PresentModeKHR) -> Result<()> {
+        if !self.available_present_modes().contains(&present_mode) {
+            return Err(anyhow::anyhow!(
+                "Present mode {present_mode:?} is not supported"
+            ));
+        }
+
+        if self.swapchain.present_mode != present_mode {
+            self.request_present_mode_change(present_mode);
+        }
+
+        Ok(())
+    }
+
+    /// Saves the next rendered frame, GUI included, as a PNG at `path`.
+    ///
+    /// The swapchain image is copied after the UI pass and written once the frame is done.
+    /// Only 8 bits RGBA and BGRA swapchain formats are supported.
+    pub fn capture_screenshot(&mut self, path: impl Into<PathBuf>) -> Result<()> {
+        self.check_capture_support()?;
+
+        self.requested_screenshot = Some(path.into());
+
+        Ok(())
+    }
+
+    /// Saves the next `frame_count` frames as `frame_00000.png`, `frame_00001.png`, ... in `dir`,
+    /// which is created if needed. Frames can be assembled into a video with ffmpeg for example.
+    ///
+    /// While capturing, the app advances by a fixed time step of 1/60th of a second per frame
+    /// regardless of the actual frame time, so motion is smooth in the video but playback is
+    /// slowed down. The capture stops by itself after `frame_count` frames.
+    pub fn start_capture(&mut self, dir: impl Into<PathBuf>, frame_count: u32) -> Result<()> {
+        self.check_capture_support()?;
+        if self.frame_capture.is_some() {
+            return Err(anyhow::anyhow!("A frame capture is already running"));
+        }
+
+        let dir = dir.into();
+        log::warn!(
+            "Capturing {frame_count} frames to {}. Real-time playback is slowed down until it's done.",
+            dir.display()
+        );
+        self.frame_capture = Some(FrameSequenceCapture::new(dir, frame_count)?);
+
+        Ok(())
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.frame_capture.is_some()
+    }
+
+    fn check_capture_support(&self) -> Result<()> {
+        if !self
+            .swapchain
+            .usage
+            .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+        {
+            return Err(anyhow::anyhow!(
+                "Swapchain images cannot be used as transfer source. Supported usage: {:?}",
+                self.context.surface_supported_usage_flags()?
+            ));
+        }
+        check_png_support()?;
+        is_bgra_format(self.swapchain.format)?;
+
+        Ok(())
+    }
+
+    /// Time the app advances by this frame, fixed while capturing a frame sequence.
+    fn frame_delta(&self, frame_time: Duration) -> Duration {
+        match self.frame_capture {
+            Some(_) => FRAME_SEQUENCE_TIMESTEP,
+            None => frame_time,
+        }
+    }
+
+    /// Hands a completed frame to the running capture, which stops on error or once all
+    /// frames were saved.
+    fn save_captured_frame(&mut self, readback: FrameReadback) {
+        let Some(capture) = self.frame_capture.as_mut() else {
+            return;
+        };
+
+        let result = capture.save(readback);
+        if let Err(err) = &result {
+            log::error!("Failed to save captured frame. Cause: {err}");
+        }
+        if result.is_err() || capture.is_done() {
+            self.finish_capture();
+        }
+    }
+
+    fn finish_capture(&mut self) {
+        if let Some(capture) = self.frame_capture.take() {
+            if let Err(err) = capture.finish() {
+                log::error!("Failed to save captured frames. Cause: {err}");
+            }
+        }
+    }
+
+    /// Saves the frames still waiting in flight. The GPU must be idle.
+    fn flush_capture(&mut self) {
+        for readback in self.in_flight_frames.take_all_frame_readbacks() {
+            self.save_captured_frame(readback);
+        }
+        self.finish_capture();
+    }
+
+    fn create_readback_buffer(&self) -> Result<Buffer> {
+        let extent = self.swapchain.extent;
+        self.context.create_buffer(
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+            (extent.width * extent.height * 4) as _,
+        )
+    }
+
     fn recreate_swapchain(
         &mut self,
         width: u32,
         height: u32,
         format: Option<vk::SurfaceFormatKHR>,
-    ) -> Result<()> {
+        present_mode: Option<vk::PresentModeKHR>,
+    ) -> Result<SwapchainChange> {
         log::debug!("Recreating the swapchain");
 
         self.wait_for_gpu()?;
 
+        let old_extent = self.swapchain.extent;
+        let old_format = self.swapchain.format;
+
+        let format = format.filter(|format| {
+            let keep = !self.linear_workflow || is_linear_workflow_format(*format);
+            if !keep {
+                log::warn!("Swapchain format {format:?} breaks the linear workflow. Keeping current format.");
+            }
+            keep
+        });
+
         // Swapchain and dependent resources
         self.swapchain
-            .update(&self.context, width, height, format)?;
+            .update(&self.context, width, height, format, present_mode)?;
+        if self.initialize_swapchain_layouts {
+            self.swapchain.initialize_layouts(&self.context)?;
+        }
 
         // Recreate storage image for RT and update descriptor set
         if self.raytracing_enabled {
@@ -461,19 +1131,72 @@ impl BaseApp {
             let _ = std::mem::replace(&mut self.storage_images, storage_images);
         }
 
-        // Update ui renderer
+        // Update ui renderer, registered textures might reference views the app is about to recreate
         if let Some(format) = format {
-            self.gui_context.update_framebuffer_params(format.format)?;
+            self.gui_context
+                .update_framebuffer_params(format.format, self.gui_depth_format)?;
+        }
+        self.gui_context.unregister_all_textures()?;
+
+        if self.swapchain.format != old_format {
+            self.background_pass.set_color_format(
+                &self.context,
+                Some(&self.pipeline_cache),
+                self.swapchain.format,
+            )?;
+        }
+
+        if let Some(ground_grid) = self.ground_grid.as_mut() {
+            if self.swapchain.format != old_format {
+                ground_grid.set_color_format(
+                    &self.context,
+                    Some(&self.pipeline_cache),
+                    self.swapchain.format,
+                )?;
+            }
         }
 
         // Update camera aspect ration
         self.camera.aspect_ratio = width as f32 / height as f32;
 
+        Ok(SwapchainChange {
+            extent_changed: self.swapchain.extent != old_extent,
+            format: self.swapchain.format,
+            old_format,
+        })
+    }
+
+    /// Sets what the swapchain image is filled with before [`App::record_raster_commands`].
+    ///
+    /// Apps must load the swapchain attachment instead of clearing it to see it.
+    /// Not drawn when ray tracing is enabled. Defaults to `None`, in which case the pass is
+    /// skipped and apps clear the swapchain attachment themselves.
+    pub fn set_background(&mut self, background: Option<Background>) {
+        self.background = background;
+    }
+
+    /// Draws the ground grid with lines every `spacing` units, from the current camera.
+    ///
+    /// Must be called between begin and end rendering of a pass targeting the swapchain, after
+    /// viewport and scissor were set. It binds its own pipeline. Fails when
+    /// [`AppConfig::ground_grid`] is not set.
+    pub fn draw_grid(&self, buffer: &CommandBuffer, spacing: f32, color: [f32; 4]) -> Result<()> {
+        let ground_grid = self
+            .ground_grid
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Ground grid is not enabled in AppConfig"))?;
+        ground_grid.draw(buffer, &self.camera, spacing, color);
+
         Ok(())
     }
 
+    /// Waits for the GPU to be idle. Fails with [`GpuTimeout`] when it takes longer
+    /// than [`AppConfig::gpu_wait_timeout`].
     pub fn wait_for_gpu(&self) -> Result<()> {
-        self.context.device_wait_idle()
+        match self.gpu_wait_timeout {
+            Some(timeout) => self.context.device_wait_idle_with_timeout(timeout),
+            None => self.context.device_wait_idle(),
+        }
     }
 
     fn draw<B: App>(
@@ -485,15 +1208,25 @@ impl BaseApp {
     ) -> Result<bool> {
         // Drawing the frame
         self.in_flight_frames.next();
-        self.in_flight_frames.fence().wait(None)?;
-
-        // Can't get for gpu time on the first frames or vkGetQueryPoolResults gets stuck
-        // due to VK_QUERY_RESULT_WAIT_BIT
-        let gpu_time = (frame_stats.total_frame_count >= IN_FLIGHT_FRAMES)
-            .then(|| self.in_flight_frames.gpu_frame_time_ms())
-            .transpose()?
-            .unwrap_or_default();
-        frame_stats.set_gpu_time_time(gpu_time);
+        self.in_flight_frames.wait()?;
+
+        // The frame that last used this slot is complete so its capture can be read back
+        if let Some(readback) = self.in_flight_frames.take_frame_readback() {
+            self.save_captured_frame(readback);
+        }
+
+        // Until the results are ready the last known gpu time is kept
+        if let Some(gpu_time) = self
+            .in_flight_frames
+            .gpu_frame_time_ms(frame_stats.total_frame_count)?
+        {
+            frame_stats.set_gpu_time_time(gpu_time);
+        }
+        self.gpu_timers.get_mut().begin_frame(
+            &self.context,
+            self.in_flight_frames.current_frame,
+            matches!(self.stats_display_mode, StatsDisplayMode::Full),
+        )?;
         frame_stats.tick();
 
         let next_image_result = self
@@ -503,10 +1236,11 @@ impl BaseApp {
             Ok(AcquiredImage { index, .. }) => index as usize,
             Err(err) => match err.downcast_ref::<vk::Result>() {
                 Some(&vk::Result::ERROR_OUT_OF_DATE_KHR) => return Ok(true),
+                _ if err.is::<DeviceLost>() => return Err(err),
                 _ => panic!("Error while acquiring next image. Cause: {}", err),
             },
         };
-        self.in_flight_frames.fence().reset()?;
+        self.in_flight_frames.reset()?;
 
         // UI
         if !self.in_flight_frames.gui_textures_to_free().is_empty() {
@@ -525,6 +1259,7 @@ impl BaseApp {
         } = self.gui_context.run(raw_input, |ctx| {
             gui.build(ctx);
             self.build_perf_ui(ctx, frame_stats);
+            self.build_gui_debug_ui(ctx);
         });
 
         self.gui_context
@@ -547,24 +1282,81 @@ impl BaseApp {
 
         let primitives = self.gui_context.tessellate(shapes, pixels_per_point);
 
-        base_app.update(self, gui, image_index, frame_stats.frame_time)?;
+        base_app.update(self, gui, image_index, frame_stats.delta_time)?;
+
+        let screenshot = self
+            .requested_screenshot
+            .take()
+            .map(|path| Ok::<_, anyhow::Error>((path, self.create_readback_buffer()?)))
+            .transpose()?;
+
+        // Screenshots take precedence, the capture then copies the next frame
+        let capture_path = match screenshot {
+            Some(_) => None,
+            None => self
+                .frame_capture
+                .as_mut()
+                .and_then(FrameSequenceCapture::next_frame_path),
+        };
+        let capture = capture_path
+            .map(|path| {
+                Ok::<_, anyhow::Error>(FrameReadback {
+                    path,
+                    buffer: self.create_readback_buffer()?,
+                    format: self.swapchain.format,
+                    extent: self.swapchain.extent,
+                })
+            })
+            .transpose()?;
 
-        self.record_command_buffer(image_index, base_app, pixels_per_point, &primitives)?;
+        self.record_command_buffer(
+            image_index,
+            base_app,
+            pixels_per_point,
+            &primitives,
+            screenshot
+                .as_ref()
+                .map(|(_, buffer)| buffer)
+                .or(capture.as_ref().map(|readback| &readback.buffer)),
+        )?;
 
         let command_buffer = &self.command_buffers[image_index];
-        self.context.graphics_queue.submit(
+        let mut signal_semaphores = vec![SemaphoreSubmitInfo {
+            semaphore: self.in_flight_frames.render_finished_semaphore(),
+            stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+            value: 0,
+        }];
+        signal_semaphores.extend(self.in_flight_frames.timeline_signal());
+        self.context.graphics_queue.submit_with_semaphores(
             command_buffer,
-            Some(SemaphoreSubmitInfo {
+            &[SemaphoreSubmitInfo {
                 semaphore: self.in_flight_frames.image_available_semaphore(),
                 stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
-            }),
-            Some(SemaphoreSubmitInfo {
-                semaphore: self.in_flight_frames.render_finished_semaphore(),
-                stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
-            }),
+                value: 0,
+            }],
+            &signal_semaphores,
             self.in_flight_frames.fence(),
         )?;
 
+        if let Some(readback) = capture {
+            self.in_flight_frames.set_frame_readback(readback);
+        }
+
+        if let Some((path, buffer)) = screenshot {
+            self.in_flight_frames.wait_submitted()?;
+            let extent = self.swapchain.extent;
+            match write_png(
+                &path,
+                &buffer,
+                self.swapchain.format,
+                extent.width,
+                extent.height,
+            ) {
+                Ok(()) => log::info!("Screenshot saved to {}", path.display()),
+                Err(err) => log::error!("Failed to save screenshot. Cause: {err}"),
+            }
+        }
+
         let signal_semaphores = [self.in_flight_frames.render_finished_semaphore()];
         let present_result = self.swapchain.queue_present(
             image_index as _,
@@ -575,6 +1367,7 @@ impl BaseApp {
             Ok(true) => return Ok(true),
             Err(err) => match err.downcast_ref::<vk::Result>() {
                 Some(&vk::Result::ERROR_OUT_OF_DATE_KHR) => return Ok(true),
+                _ if err.is::<DeviceLost>() => return Err(err),
                 _ => panic!("Failed to present queue. Cause: {}", err),
             },
             _ => {}
@@ -584,6 +1377,17 @@ impl BaseApp {
     }
 
     fn build_perf_ui(&self, ctx: &gui::egui::Context, frame_stats: &mut FrameStats) {
+        if frame_stats.paused {
+            egui::Window::new("Paused")
+                .anchor(Align2::CENTER_TOP, [0.0, 5.0])
+                .collapsible(false)
+                .interactable(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label("F9 to resume, . to step a frame");
+                });
+        }
+
         if matches!(
             self.stats_display_mode,
             StatsDisplayMode::Basic | StatsDisplayMode::Full
@@ -595,33 +1399,97 @@ impl BaseApp {
                 .resizable(false)
                 .drag_to_scroll(false)
                 .show(ctx, |ui| {
+                    // Values use the monospace family so their width doesn't change every frame
                     ui.label("Framerate");
-                    ui.label(format!("{} fps", frame_stats.fps_counter));
+                    ui.monospace(format!("{} fps", frame_stats.fps_counter));
                     ui.label("Frametimes");
-                    ui.label(format!("all - {:?}", frame_stats.frame_time));
-                    ui.label(format!("cpu - {:?}", frame_stats.cpu_time));
-                    ui.label(format!("gpu - {:?}", frame_stats.gpu_time));
+                    ui.monospace(format!("all - {:?}", frame_stats.frame_time));
+                    ui.monospace(format!("cpu - {:?}", frame_stats.cpu_time));
+                    ui.monospace(format!("gpu - {:?}", frame_stats.gpu_time));
+
+                    if matches!(self.stats_display_mode, StatsDisplayMode::Full) {
+                        if let Some(stats) = frame_stats.stats(self.in_flight_frames.count()) {
+                            ui.monospace(format!("Over {} frames", stats.sample_count));
+                            ui.monospace(format!("min - {:.3}ms", stats.min_ms));
+                            ui.monospace(format!("max - {:.3}ms", stats.max_ms));
+                            ui.monospace(format!("avg - {:.3}ms", stats.average_ms));
+                            ui.monospace(format!("p99 - {:.3}ms", stats.p99_ms));
+                        }
+
+                        let gpu_timers = self.gpu_timers.borrow();
+                        if !gpu_timers.results().is_empty() {
+                            ui.label("GPU scopes");
+                            for scope in gpu_timers.results() {
+                                ui.monospace(format!(
+                                    "{}{} - {:.3}ms",
+                                    "  ".repeat(scope.depth),
+                                    scope.name,
+                                    scope.duration.as_secs_f64() * 1000.0
+                                ));
+                            }
+                        }
+                    }
                 });
         }
 
         if matches!(self.stats_display_mode, StatsDisplayMode::Full) {
             egui::TopBottomPanel::bottom("frametime_graphs").show(ctx, |ui| {
-                build_frametime_plot(ui, "Frames", &frame_stats.frame_time_ms_log.0);
+                if ui.button("Export CSV").clicked() {
+                    match frame_stats.export_csv(FRAME_TIMES_CSV_PATH) {
+                        Ok(()) => log::info!("Frame times exported to {FRAME_TIMES_CSV_PATH}"),
+                        Err(err) => log::error!("Failed to export frame times: {err}"),
+                    }
+                }
+                build_frametime_plot(ui, "Frames", frame_stats.frame_time_ms_log.as_slice());
                 ui.add_space(5.0);
-                build_frametime_plot(ui, "CPU", &frame_stats.cpu_time_ms_log.0);
+                build_frametime_plot(ui, "CPU", frame_stats.cpu_time_ms_log.as_slice());
                 ui.add_space(5.0);
-                build_frametime_plot(ui, "GPU", &frame_stats.gpu_time_ms_log.0);
+                build_frametime_plot(ui, "GPU", frame_stats.gpu_time_ms_log.as_slice());
             });
         }
     }
 
+    /// Opens a named GPU timer scope nested in the one currently open. Scopes are only
+    /// recorded while the Full stats mode is displayed, which lists their timings as a tree
+    /// under the root "frame" scope.
+    pub fn push_gpu_timer(&self, buffer: &CommandBuffer, name: &'static str) {
+        self.gpu_timers.borrow_mut().push(buffer, name);
+    }
+
+    /// Closes the scope opened by the last [`Self::push_gpu_timer`].
+    pub fn pop_gpu_timer(&self, buffer: &CommandBuffer) {
+        self.gpu_timers.borrow_mut().pop(buffer);
+    }
+
+    fn build_gui_debug_ui(&self, ctx: &gui::egui::Context) {
+        if !self.gui_debug_visible {
+            return;
+        }
+
+        egui::Window::new("GUI debug")
+            .vscroll(true)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "Textures pending free: {}",
+                    self.in_flight_frames.gui_textures_to_free().len()
+                ));
+                ui.collapsing("Textures", |ui| ctx.texture_ui(ui));
+                ui.collapsing("Memory", |ui| ctx.memory_ui(ui));
+                ui.collapsing("Inspection", |ui| ctx.inspection_ui(ui));
+                ui.collapsing("Settings", |ui| ctx.settings_ui(ui));
+            });
+    }
+
     fn record_command_buffer<B: App>(
         &mut self,
         image_index: usize,
         base_app: &B,
         pixels_per_point: f32,
         primitives: &[ClippedPrimitive],
+        screenshot_buffer: Option<&Buffer>,
     ) -> Result<()> {
+        let (swapchain_image, swapchain_view) = self.swapchain.frame(image_index);
+
         self.command_buffers[image_index].reset()?;
 
         self.command_buffers[image_index].begin(None)?;
@@ -635,7 +1503,14 @@ impl BaseApp {
             0,
         );
 
+        self.gpu_timers
+            .borrow()
+            .cmd_reset(&self.command_buffers[image_index]);
+        self.push_gpu_timer(&self.command_buffers[image_index], "frame");
+
         if self.raytracing_enabled {
+            self.command_buffers[image_index]
+                .begin_debug_label("Ray tracing", RAY_TRACING_LABEL_COLOR);
             base_app.record_raytracing_commands(
                 self,
                 &self.command_buffers[image_index],
@@ -646,7 +1521,7 @@ impl BaseApp {
             // Copy ray tracing result into swapchain
             self.command_buffers[image_index].pipeline_image_barriers(&[
                 ImageBarrier {
-                    image: &self.swapchain.images[image_index],
+                    image: swapchain_image,
                     old_layout: vk::ImageLayout::UNDEFINED,
                     new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
                     src_access_mask: vk::AccessFlags2::empty(),
@@ -665,16 +1540,27 @@ impl BaseApp {
                 },
             ]);
 
-            self.command_buffers[image_index].copy_image(
-                storage_image,
-                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
-                &self.swapchain.images[image_index],
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            );
+            // A copy keeps the raw bytes while a blit encodes the linear result to sRGB
+            if self.linear_workflow {
+                self.command_buffers[image_index].blit_image(
+                    storage_image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    swapchain_image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::Filter::NEAREST,
+                );
+            } else {
+                self.command_buffers[image_index].copy_image(
+                    storage_image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    swapchain_image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                );
+            }
 
             self.command_buffers[image_index].pipeline_image_barriers(&[
                 ImageBarrier {
-                    image: &self.swapchain.images[image_index],
+                    image: swapchain_image,
                     old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
                     new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
                     src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
@@ -692,9 +1578,10 @@ impl BaseApp {
                     dst_stage_mask: vk::PipelineStageFlags2::RAY_TRACING_SHADER_KHR,
                 },
             ]);
+            self.command_buffers[image_index].end_debug_label();
         } else {
             self.command_buffers[image_index].pipeline_image_barriers(&[ImageBarrier {
-                image: &self.swapchain.images[image_index],
+                image: swapchain_image,
                 old_layout: vk::ImageLayout::UNDEFINED,
                 new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
                 src_access_mask: vk::AccessFlags2::empty(),
@@ -702,40 +1589,86 @@ impl BaseApp {
                 src_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
                 dst_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
             }]);
+
+            if let Some(background) = self.background {
+                self.command_buffers[image_index]
+                    .begin_debug_label("Background", BACKGROUND_LABEL_COLOR);
+                self.background_pass.draw(
+                    &self.command_buffers[image_index],
+                    swapchain_view,
+                    self.swapchain.extent,
+                    background,
+                );
+                self.command_buffers[image_index].end_debug_label();
+            }
         }
 
         // Rasterization
-        base_app.record_raster_commands(self, image_index)?;
+        self.command_buffers[image_index].begin_debug_label("Raster", RASTER_LABEL_COLOR);
+        base_app.record_raster_commands(self, &self.command_buffers[image_index], image_index)?;
+        self.command_buffers[image_index].end_debug_label();
 
         // UI
-        self.command_buffers[image_index].begin_rendering(
-            &[RenderingAttachment {
-                view: &self.swapchain.views[image_index],
-                load_op: vk::AttachmentLoadOp::DONT_CARE,
-                clear_value: None,
-            }],
-            None,
+        let ui_rendering_flags = if self.gui_command_buffers.is_some() {
+            vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS
+        } else {
+            vk::RenderingFlags::empty()
+        };
+        let ui_depth_attachment = match self.gui_depth_format {
+            Some(_) => Some(RenderingAttachment::load(
+                base_app.gui_depth_attachment(image_index).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "App returned no gui depth attachment but gui_depth_format is set"
+                    )
+                })?,
+            )),
+            None => None,
+        };
+        self.command_buffers[image_index].begin_debug_label("UI", UI_LABEL_COLOR);
+        self.command_buffers[image_index].begin_rendering_with_flags(
+            // The UI is drawn over what the app rendered
+            &[RenderingAttachment::load(swapchain_view)],
+            ui_depth_attachment,
             self.swapchain.extent,
+            ui_rendering_flags,
         );
 
-        self.gui_context.renderer.cmd_draw(
-            self.command_buffers[image_index].inner,
-            self.swapchain.extent,
-            pixels_per_point,
-            primitives,
-        )?;
+        if let Some(gui_command_buffers) = &self.gui_command_buffers {
+            let gui_command_buffer = &gui_command_buffers[image_index];
+            self.gui_context.record_secondary(
+                gui_command_buffer,
+                self.swapchain.extent,
+                pixels_per_point,
+                primitives,
+            )?;
+            self.command_buffers[image_index].execute_commands(&[gui_command_buffer]);
+        } else {
+            self.gui_context.renderer.cmd_draw(
+                self.command_buffers[image_index].inner,
+                self.swapchain.extent,
+                pixels_per_point,
+                primitives,
+            )?;
+        }
 
         self.command_buffers[image_index].end_rendering();
+        self.command_buffers[image_index].end_debug_label();
 
-        self.command_buffers[image_index].pipeline_image_barriers(&[ImageBarrier {
-            image: &self.swapchain.images[image_index],
-            old_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            new_layout: vk::ImageLayout::PRESENT_SRC_KHR,
-            src_access_mask: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
-            dst_access_mask: vk::AccessFlags2::empty(),
-            src_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
-            dst_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
-        }]);
+        if let Some(buffer) = screenshot_buffer {
+            self.record_screenshot_copy(image_index, buffer);
+        } else {
+            self.command_buffers[image_index].pipeline_image_barriers(&[ImageBarrier {
+                image: swapchain_image,
+                old_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                new_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+                src_access_mask: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                dst_access_mask: vk::AccessFlags2::empty(),
+                src_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                dst_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            }]);
+        }
+
+        self.pop_gpu_timer(&self.command_buffers[image_index]);
 
         self.command_buffers[image_index].write_timestamp(
             vk::PipelineStageFlags2::TOP_OF_PIPE,
@@ -748,9 +1681,77 @@ impl BaseApp {
         Ok(())
     }
 
+    /// Copies the swapchain image into `buffer` and leaves the image in `PRESENT_SRC_KHR`.
+    fn record_screenshot_copy(&self, image_index: usize, buffer: &Buffer) {
+        let cmd = &self.command_buffers[image_index];
+        let (image, _) = self.swapchain.frame(image_index);
+
+        cmd.pipeline_image_barriers(&[ImageBarrier {
+            image,
+            old_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            src_access_mask: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags2::TRANSFER_READ,
+            src_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            dst_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+        }]);
+
+        // Row length of 0 means tightly packed rows so there is no padding to strip
+        cmd.copy_image_to_buffer(
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            buffer,
+            vk::ImageAspectFlags::COLOR,
+            vk::Rect2D {
+                offset: vk::Offset2D::default(),
+                extent: self.swapchain.extent,
+            },
+            0,
+        );
+
+        cmd.pipeline_image_barriers(&[ImageBarrier {
+            image,
+            old_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            new_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            src_access_mask: vk::AccessFlags2::empty(),
+            dst_access_mask: vk::AccessFlags2::empty(),
+            src_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+            dst_stage_mask: vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+        }]);
+        cmd.pipeline_buffer_barriers(&[BufferBarrier {
+            buffer,
+            src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+            dst_access_mask: vk::AccessFlags2::HOST_READ,
+            src_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+            dst_stage_mask: vk::PipelineStageFlags2::HOST,
+        }]);
+    }
+
     fn toggle_stats(&mut self) {
         self.stats_display_mode = self.stats_display_mode.next();
     }
+
+    fn toggle_gui_debug(&mut self) {
+        if !self.gui_debug_enabled {
+            return;
+        }
+
+        self.gui_debug_visible = !self.gui_debug_visible;
+        self.gui_context
+            .egui
+            .set_debug_on_hover(self.gui_debug_visible);
+    }
+}
+
+/// sRGB formats encode linear values on write and linear extended sRGB expects linear values.
+fn is_linear_workflow_format(format: vk::SurfaceFormatKHR) -> bool {
+    match format.color_space {
+        vk::ColorSpaceKHR::SRGB_NONLINEAR => is_srgb_format(format.format),
+        vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => {
+            format.format == vk::Format::R16G16B16A16_SFLOAT
+        }
+        _ => false,
+    }
 }
 
 fn create_storage_images(
@@ -767,6 +1768,7 @@ fn create_storage_images(
             vk::Format::R8G8B8A8_UNORM,
             extent.width,
             extent.height,
+            1,
         )?;
 
         let view = image.create_image_view(vk::ImageAspectFlags::COLOR)?;
@@ -801,23 +1803,43 @@ pub struct ImageAndView {
 struct InFlightFrames {
     per_frames: Vec<PerFrame>,
     current_frame: usize,
+    /// Set with [`FramePacing::TimelineSemaphore`], replaces the per frame fences.
+    timeline: Option<FrameTimeline>,
 }
 
 struct PerFrame {
     image_available_semaphore: Semaphore,
     render_finished_semaphore: Semaphore,
-    fence: Fence,
+    fence: Option<Fence>,
     timing_query_pool: TimestampQueryPool<2>,
     gui_textures_to_free: Vec<TextureId>,
+    frame_readback: Option<FrameReadback>,
+}
+
+struct FrameTimeline {
+    semaphore: Semaphore,
+    /// Value signaled by the current frame. Starts at 0 which the semaphore is created with.
+    frame_value: u64,
 }
 
 impl InFlightFrames {
-    fn new(context: &Context, frame_count: u32) -> Result<Self> {
+    fn new(context: &Context, frame_count: u32, frame_pacing: FramePacing) -> Result<Self> {
+        let timeline = match frame_pacing {
+            FramePacing::Fences => None,
+            FramePacing::TimelineSemaphore => Some(FrameTimeline {
+                semaphore: context.create_timeline_semaphore(0)?,
+                frame_value: 0,
+            }),
+        };
+
         let sync_objects = (0..frame_count)
             .map(|_i| {
                 let image_available_semaphore = context.create_semaphore()?;
                 let render_finished_semaphore = context.create_semaphore()?;
-                let fence = context.create_fence(Some(vk::FenceCreateFlags::SIGNALED))?;
+                let fence = timeline
+                    .is_none()
+                    .then(|| context.create_fence(Some(vk::FenceCreateFlags::SIGNALED)))
+                    .transpose()?;
 
                 let timing_query_pool = context.create_timestamp_query_pool()?;
                 let gui_textures_to_free = Vec::new();
@@ -828,6 +1850,7 @@ impl InFlightFrames {
                     fence,
                     timing_query_pool,
                     gui_textures_to_free,
+                    frame_readback: None,
                 })
             })
             .collect::<Result<Vec<_>>>()?;
@@ -835,11 +1858,52 @@ impl InFlightFrames {
         Ok(Self {
             per_frames: sync_objects,
             current_frame: 0,
+            timeline,
         })
     }
 
+    fn count(&self) -> u32 {
+        self.per_frames.len() as _
+    }
+
     fn next(&mut self) {
         self.current_frame = (self.current_frame + 1) % self.per_frames.len();
+        if let Some(timeline) = &mut self.timeline {
+            timeline.frame_value += 1;
+        }
+    }
+
+    /// Waits until the GPU is done with the last frame that used the current frame's resources.
+    fn wait(&self) -> Result<()> {
+        match &self.timeline {
+            Some(timeline) => {
+                // The first frames wait for 0 and return immediately
+                let value = timeline.frame_value.saturating_sub(self.count() as _);
+                timeline.semaphore.wait(value, None)
+            }
+            None => self.fence().map_or(Ok(()), |fence| fence.wait(None)),
+        }
+    }
+
+    fn reset(&self) -> Result<()> {
+        self.fence().map_or(Ok(()), Fence::reset)
+    }
+
+    /// Waits until the GPU is done with the current frame once it's been submitted.
+    fn wait_submitted(&self) -> Result<()> {
+        match &self.timeline {
+            Some(timeline) => timeline.semaphore.wait(timeline.frame_value, None),
+            None => self.fence().map_or(Ok(()), |fence| fence.wait(None)),
+        }
+    }
+
+    /// The timeline value to signal when submitting the current frame.
+    fn timeline_signal(&self) -> Option<SemaphoreSubmitInfo<'_>> {
+        self.timeline.as_ref().map(|timeline| SemaphoreSubmitInfo {
+            semaphore: &timeline.semaphore,
+            stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+            value: timeline.frame_value,
+        })
     }
 
     fn image_available_semaphore(&self) -> &Semaphore {
@@ -850,8 +1914,8 @@ impl InFlightFrames {
         &self.per_frames[self.current_frame].render_finished_semaphore
     }
 
-    fn fence(&self) -> &Fence {
-        &self.per_frames[self.current_frame].fence
+    fn fence(&self) -> Option<&Fence> {
+        self.per_frames[self.current_frame].fence.as_ref()
     }
 
     fn timing_query_pool(&self) -> &TimestampQueryPool<2> {
@@ -866,9 +1930,38 @@ impl InFlightFrames {
         self.per_frames[self.current_frame].gui_textures_to_free = ids;
     }
 
-    fn gpu_frame_time_ms(&self) -> Result<Duration> {
-        let result = self.timing_query_pool().wait_for_all_results()?;
-        let time = Duration::from_nanos(result[1].saturating_sub(result[0]));
+    fn take_frame_readback(&mut self) -> Option<FrameReadback> {
+        self.per_frames[self.current_frame].frame_readback.take()
+    }
+
+    fn set_frame_readback(&mut self, readback: FrameReadback) {
+        self.per_frames[self.current_frame].frame_readback = Some(readback);
+    }
+
+    /// Readbacks of all frames, oldest first.
+    fn take_all_frame_readbacks(&mut self) -> Vec<FrameReadback> {
+        let count = self.per_frames.len();
+        (1..=count)
+            .filter_map(|offset| {
+                let index = (self.current_frame + offset) % count;
+                self.per_frames[index].frame_readback.take()
+            })
+            .collect()
+    }
+
+    /// Gpu time of the frame that last used the current slot, `None` while not available.
+    ///
+    /// The pools of the first frames were never written so they are not read at all, reading
+    /// queries that were never reset is undefined.
+    fn gpu_frame_time_ms(&self, total_frame_count: u32) -> Result<Option<Duration>> {
+        if total_frame_count < self.count() {
+            return Ok(None);
+        }
+
+        let pool = self.timing_query_pool();
+        let time = pool
+            .try_get_results()?
+            .map(|result| pool.elapsed(result[0], result[1]));
 
         Ok(time)
     }
@@ -885,6 +1978,11 @@ struct FrameStats {
     frame_time_ms_log: Queue<f32>,
     cpu_time_ms_log: Queue<f32>,
     gpu_time_ms_log: Queue<f32>,
+    // reused when computing stats to avoid allocating each frame
+    sorted_frame_times_ms: Vec<f32>,
+    /// Time the app advances by this frame, zero while paused except when stepping.
+    delta_time: Duration,
+    paused: bool,
     total_frame_count: u32,
     frame_count: u32,
     fps_counter: u32,
@@ -901,6 +1999,9 @@ impl Default for FrameStats {
             frame_time_ms_log: Queue::new(FrameStats::MAX_LOG_SIZE),
             cpu_time_ms_log: Queue::new(FrameStats::MAX_LOG_SIZE),
             gpu_time_ms_log: Queue::new(FrameStats::MAX_LOG_SIZE),
+            sorted_frame_times_ms: Vec::with_capacity(FrameStats::MAX_LOG_SIZE),
+            delta_time: Default::default(),
+            paused: false,
             total_frame_count: Default::default(),
             frame_count: Default::default(),
             fps_counter: Default::default(),
@@ -944,6 +2045,65 @@ impl FrameStats {
     fn set_gpu_time_time(&mut self, gpu_time: Duration) {
         self.gpu_time = gpu_time;
     }
+
+    fn set_delta_time(&mut self, delta_time: Duration, paused: bool) {
+        self.delta_time = delta_time;
+        self.paused = paused;
+    }
+
+    /// Min, max, average and 99th percentile of the logged frame times.
+    ///
+    /// The first `skipped_frames` frames of the app are ignored since their gpu time
+    /// is not available yet.
+    fn stats(&mut self, skipped_frames: u32) -> Option<FrameTimeStats> {
+        let frames = self.frame_time_ms_log.as_slice();
+        let first_frame_index = self.total_frame_count as usize - frames.len();
+        let skip = (skipped_frames as usize).saturating_sub(first_frame_index);
+        let frames = frames.get(skip..).filter(|f| !f.is_empty())?;
+
+        self.sorted_frame_times_ms.clear();
+        self.sorted_frame_times_ms.extend_from_slice(frames);
+        self.sorted_frame_times_ms.sort_unstable_by(f32::total_cmp);
+        let sorted = &self.sorted_frame_times_ms;
+
+        let p99_index = ((sorted.len() as f32 * 0.99).ceil() as usize).clamp(1, sorted.len()) - 1;
+
+        Some(FrameTimeStats {
+            sample_count: sorted.len(),
+            min_ms: sorted[0],
+            max_ms: sorted[sorted.len() - 1],
+            average_ms: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            p99_ms: sorted[p99_index],
+        })
+    }
+
+    /// Writes the logged frame times as `frame_index,frame_ms,cpu_ms,gpu_ms` rows.
+    fn export_csv(&self, path: &str) -> Result<()> {
+        use std::io::Write;
+
+        let frames = self.frame_time_ms_log.as_slice();
+        let cpu = self.cpu_time_ms_log.as_slice();
+        let gpu = self.gpu_time_ms_log.as_slice();
+        let first_frame_index = self.total_frame_count as usize - frames.len();
+
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writeln!(writer, "frame_index,frame_ms,cpu_ms,gpu_ms")?;
+        for (i, ((frame, cpu), gpu)) in frames.iter().zip(cpu).zip(gpu).enumerate() {
+            writeln!(writer, "{},{frame},{cpu},{gpu}", first_frame_index + i)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FrameTimeStats {
+    sample_count: usize,
+    min_ms: f32,
+    max_ms: f32,
+    average_ms: f32,
+    p99_ms: f32,
 }
 
 #[derive(Debug)]
@@ -960,9 +2120,14 @@ impl<T> Queue<T> {
         }
         self.0.push(value);
     }
+
+    fn as_slice(&self) -> &[T] {
+        &self.0
+    }
 }
 
-fn build_frametime_plot(ui: &mut egui::Ui, id: impl std::hash::Hash, points: &[f32]) {
+#[cfg(feature = "frame-time-plot")]
+fn build_frametime_plot(ui: &mut egui::Ui, id: &str, points: &[f32]) {
     let points: egui_plot::PlotPoints = points
         .iter()
         .enumerate()
@@ -982,3 +2147,10 @@ fn build_frametime_plot(ui: &mut egui::Ui, id: impl std::hash::Hash, points: &[f
             plot.line(egui_plot::Line::new(points));
         });
 }
+
+#[cfg(not(feature = "frame-time-plot"))]
+fn build_frametime_plot(ui: &mut egui::Ui, id: &str, points: &[f32]) {
+    if let Some(last) = points.last() {
+        ui.label(format!("{id}: {last:.2}ms"));
+    }
+}
@@ -14,7 +14,7 @@ use std::{
 };
 
 use glam::{vec4, Vec2, Vec4};
-use gltf::{Primitive, Semantic};
+use gltf::{mesh::Mode, Primitive, Semantic};
 
 #[derive(Debug, Clone)]
 pub struct Model {
@@ -32,6 +32,7 @@ pub struct Node {
     pub mesh: Mesh,
 }
 
+/// A primitive of a gltf mesh. Indices are relative to `vertex_offset`.
 #[derive(Debug, Clone, Copy)]
 pub struct Mesh {
     pub vertex_offset: u32,
@@ -39,6 +40,8 @@ pub struct Mesh {
     pub index_offset: u32,
     pub index_count: u32,
     pub material: Material,
+    /// Index of the material in the gltf document, `None` for the default material.
+    pub material_index: Option<usize>,
 }
 
 #[repr(C)]
@@ -50,6 +53,22 @@ pub struct Vertex {
     pub uvs: Vec2,
 }
 
+impl Model {
+    /// Resolves a texture index, like [`Material::base_color_texture_index`], to the
+    /// texture's image and sampler. Returns `None` if the index is out of range.
+    ///
+    /// Images are decoded to RGBA8 whatever their source (GLB buffer, data uri or
+    /// external file) so they can be uploaded to `R8G8B8A8` Vulkan images.
+    pub fn resolve_texture(&self, texture_index: usize) -> Option<ResolvedTexture<'_>> {
+        let texture = self.textures.get(texture_index)?;
+
+        Some(ResolvedTexture {
+            image: &self.images[texture.image_index],
+            sampler: &self.samplers[texture.sampler_index],
+        })
+    }
+}
+
 pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Model> {
     let (document, buffers, gltf_images) =
         gltf::import(&path).map_err(|e| Error::Load(e.to_string()))?;
@@ -71,14 +90,13 @@ pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Model> {
 
                 // vertices
                 let vertex_reader = reader.read_positions().unwrap();
-                let vertex_offset = vertices.len() as _;
-                let vertex_count = vertex_reader.len() as _;
+                let primitive_vertex_count = vertex_reader.len() as u32;
 
-                let normals = reader
-                    .read_normals()
-                    .unwrap()
-                    .map(|n| vec4(n[0], n[1], n[2], 0.0))
-                    .collect::<Vec<_>>();
+                let normals = reader.read_normals().map(|reader| {
+                    reader
+                        .map(|n| vec4(n[0], n[1], n[2], 0.0))
+                        .collect::<Vec<_>>()
+                });
 
                 let colors = reader
                     .read_colors(0)
@@ -88,26 +106,43 @@ pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Model> {
                     .read_tex_coords(0)
                     .map(|reader| reader.into_f32().map(Vec2::from).collect::<Vec<_>>());
 
-                vertex_reader.enumerate().for_each(|(index, p)| {
-                    let position = vec4(p[0], p[1], p[2], 0.0);
-                    let normal = normals[index];
-                    let color = colors.as_ref().map_or(Vec4::ONE, |colors| colors[index]);
-                    let uvs = uvs.as_ref().map_or(Vec2::ZERO, |uvs| uvs[index]);
-
-                    vertices.push(Vertex {
-                        position,
-                        normal,
-                        color,
-                        uvs,
-                    });
-                });
+                let mut primitive_vertices = vertex_reader
+                    .enumerate()
+                    .map(|(index, p)| {
+                        let position = vec4(p[0], p[1], p[2], 0.0);
+                        let normal = normals
+                            .as_ref()
+                            .map_or(Vec4::ZERO, |normals| normals[index]);
+                        let color = colors.as_ref().map_or(Vec4::ONE, |colors| colors[index]);
+                        let uvs = uvs.as_ref().map_or(Vec2::ZERO, |uvs| uvs[index]);
+
+                        Vertex {
+                            position,
+                            normal,
+                            color,
+                            uvs,
+                        }
+                    })
+                    .collect::<Vec<_>>();
 
-                // indices
-                let index_reader = reader.read_indices().unwrap().into_u32();
-                let index_offset = indices.len() as _;
-                let index_count = index_reader.len() as _;
+                // indices, non indexed primitives get one index per vertex
+                let mut primitive_indices = reader.read_indices().map_or_else(
+                    || (0..primitive_vertex_count).collect(),
+                    |reader| reader.into_u32().collect::<Vec<_>>(),
+                );
 
-                index_reader.for_each(|i| indices.push(i));
+                if normals.is_none() {
+                    (primitive_vertices, primitive_indices) =
+                        with_flat_normals(&primitive_vertices, &primitive_indices);
+                }
+
+                let vertex_offset = vertices.len() as _;
+                let vertex_count = primitive_vertices.len() as _;
+                vertices.extend(primitive_vertices);
+
+                let index_offset = indices.len() as _;
+                let index_count = primitive_indices.len() as _;
+                indices.extend(primitive_indices);
 
                 // material
                 let material = primitive.material().into();
@@ -122,6 +157,7 @@ pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Model> {
                     index_offset,
                     index_count,
                     material,
+                    material_index: primitive.material().index(),
                 });
             }
         }
@@ -171,7 +207,50 @@ pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Model> {
 }
 
 fn is_primitive_supported(primitive: &Primitive) -> bool {
-    primitive.indices().is_some()
-        && primitive.get(&Semantic::Positions).is_some()
-        && primitive.get(&Semantic::Normals).is_some()
+    primitive.mode() == Mode::Triangles && primitive.get(&Semantic::Positions).is_some()
+}
+
+/// Unwelds the vertices of each triangle and gives them the normal of their triangle.
+fn with_flat_normals(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut flat_vertices = Vec::with_capacity(indices.len());
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| vertices[triangle[i] as usize]);
+        let normal = (b.position - a.position)
+            .truncate()
+            .cross((c.position - a.position).truncate())
+            .normalize_or_zero()
+            .extend(0.0);
+
+        flat_vertices.extend([a, b, c].map(|v| Vertex { normal, ..v }));
+    }
+
+    let flat_indices = (0..flat_vertices.len() as u32).collect();
+
+    (flat_vertices, flat_indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_base_color_texture_of_glb() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../../assets/models/shadows.glb"
+        );
+        let model = load_file(path).unwrap();
+
+        let texture_index = model.nodes[0]
+            .mesh
+            .material
+            .base_color_texture_index
+            .unwrap();
+        let texture = model.resolve_texture(texture_index).unwrap();
+
+        assert_eq!(texture.image.width, 1024);
+        assert_eq!(texture.image.height, 1024);
+        assert_eq!(texture.image.pixels.len(), 1024 * 1024 * 4);
+    }
 }
@@ -3,6 +3,8 @@ pub struct Material {
     pub base_color: [f32; 4],
     pub base_color_texture_index: Option<usize>,
     pub metallic_factor: f32,
+    pub metallic_roughness_texture_index: Option<usize>,
+    pub normal_texture_index: Option<usize>,
 }
 
 impl From<gltf::Material<'_>> for Material {
@@ -12,6 +14,10 @@ impl From<gltf::Material<'_>> for Material {
             base_color: pbr.base_color_factor(),
             base_color_texture_index: pbr.base_color_texture().map(|i| i.texture().index()),
             metallic_factor: pbr.metallic_factor(),
+            metallic_roughness_texture_index: pbr
+                .metallic_roughness_texture()
+                .map(|i| i.texture().index()),
+            normal_texture_index: material.normal_texture().map(|i| i.texture().index()),
         }
     }
 }
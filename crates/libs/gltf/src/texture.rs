@@ -1,9 +1,18 @@
+use crate::Image;
+
 #[derive(Debug, Clone, Copy)]
 pub struct Texture {
     pub image_index: usize,
     pub sampler_index: usize,
 }
 
+/// Decoded RGBA8 pixels of a texture and the modes it should be sampled with.
+#[derive(Debug, Clone, Copy)]
+pub struct ResolvedTexture<'a> {
+    pub image: &'a Image,
+    pub sampler: &'a Sampler,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Sampler {
     pub mag_filter: MagFilter,
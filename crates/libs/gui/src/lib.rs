@@ -2,27 +2,46 @@ pub extern crate egui;
 pub extern crate egui_ash_renderer;
 pub extern crate egui_winit;
 
+use std::{collections::HashMap, sync::Arc};
+
 use anyhow::Result;
 use egui::{
-    epaint::{ClippedShape, ImageDelta},
-    ClippedPrimitive, Context as EguiContext, FullOutput, PlatformOutput, RawInput, TextureId,
-    ViewportId,
+    epaint::{ClippedShape, ImageDelta, Primitive},
+    ClippedPrimitive, Context as EguiContext, FontData, FontDefinitions, FontFamily, FullOutput,
+    PlatformOutput, RawInput, TextureId, ViewportId,
 };
 use egui_ash_renderer::{DynamicRendering, Options, Renderer};
 use egui_winit::State as EguiWinit;
-use vulkan::{ash::vk, CommandBuffer, Context as VkContext};
+use vulkan::{
+    ash::vk, CommandBuffer, Context as VkContext, DescriptorPool, DescriptorSet,
+    DescriptorSetLayout, ImageView, RenderingInheritance, Sampler,
+};
 use winit::{event::WindowEvent, window::Window};
 
+/// Maximum number of textures registered with [`GuiContext::register_texture`] at the same time.
+pub const MAX_USER_TEXTURES: u32 = 16;
+
 pub struct GuiContext {
     pub egui: EguiContext,
     pub egui_winit: EguiWinit,
     pub renderer: Renderer,
+    color_attachment_format: vk::Format,
+    depth_attachment_format: Option<vk::Format>,
+    fonts: FontDefinitions,
+    user_texture_sets: HashMap<TextureId, DescriptorSet>,
+    user_texture_pool: DescriptorPool,
+    user_texture_dsl: DescriptorSetLayout,
 }
 
 impl GuiContext {
+    /// `depth_format` is the format of the depth attachment of the rendering pass the GUI is
+    /// drawn in, if any. Depth testing is enabled with it but egui meshes are flat and always
+    /// pass the test so the GUI is not occluded by what is in the depth attachment. It can't be
+    /// toggled afterwards by [`Self::update_framebuffer_params`].
     pub fn new(
         context: &VkContext,
         format: vk::Format,
+        depth_format: Option<vk::Format>,
         window: &Window,
         in_flight_frames: usize,
     ) -> Result<Self> {
@@ -34,22 +53,99 @@ impl GuiContext {
             context.device.inner.clone(),
             DynamicRendering {
                 color_attachment_format: format,
-                depth_attachment_format: None,
+                depth_attachment_format: depth_format,
             },
             Options {
                 in_flight_frames,
+                enable_depth_test: depth_format.is_some(),
                 srgb_framebuffer: true,
                 ..Default::default()
             },
         )?;
 
+        // Same layout as the one of the renderer's pipeline
+        let user_texture_dsl =
+            context.create_descriptor_set_layout(&[vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)])?;
+        let user_texture_pool = context.create_descriptor_pool_with_flags(
+            MAX_USER_TEXTURES,
+            &[vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(MAX_USER_TEXTURES)],
+            vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+        )?;
+
         Ok(Self {
             egui,
             egui_winit: platform,
             renderer: gui_renderer,
+            color_attachment_format: format,
+            depth_attachment_format: depth_format,
+            fonts: FontDefinitions::default(),
+            user_texture_sets: HashMap::new(),
+            user_texture_pool,
+            user_texture_dsl,
         })
     }
 
+    /// Registers a TTF or OTF font as the last fallback of `family`, use
+    /// [`Self::prioritize_font`] or [`Self::set_font_family_order`] to give it priority over
+    /// the default fonts.
+    ///
+    /// Must be called before the first [`Self::run`] to be used from the first frame.
+    pub fn add_font(&mut self, name: &str, bytes: &[u8], family: FontFamily) {
+        self.fonts.font_data.insert(
+            name.to_owned(),
+            Arc::new(FontData::from_owned(bytes.to_vec())),
+        );
+        self.fonts
+            .families
+            .entry(family)
+            .or_default()
+            .push(name.to_owned());
+        self.egui.set_fonts(self.fonts.clone());
+    }
+
+    /// Replaces the fonts used by `family`, in order of priority. Fonts are either fonts
+    /// registered with [`Self::add_font`] or egui's default ones.
+    ///
+    /// Must be called before the first [`Self::run`] to be used from the first frame.
+    pub fn set_font_family_order(&mut self, family: FontFamily, fonts: &[&str]) -> Result<()> {
+        if let Some(font) = fonts
+            .iter()
+            .find(|font| !self.fonts.font_data.contains_key(**font))
+        {
+            return Err(anyhow::anyhow!("Font {font} is not registered"));
+        }
+
+        self.fonts
+            .families
+            .insert(family, fonts.iter().map(|f| f.to_string()).collect());
+        self.egui.set_fonts(self.fonts.clone());
+
+        Ok(())
+    }
+
+    /// Makes a registered font the first font of `family`, before the fonts it already uses
+    /// which remain as fallbacks.
+    ///
+    /// Must be called before the first [`Self::run`] to be used from the first frame.
+    pub fn prioritize_font(&mut self, family: FontFamily, font: &str) -> Result<()> {
+        if !self.fonts.font_data.contains_key(font) {
+            return Err(anyhow::anyhow!("Font {font} is not registered"));
+        }
+
+        let fonts = self.fonts.families.entry(family).or_default();
+        fonts.retain(|f| f != font);
+        fonts.insert(0, font.to_owned());
+        self.egui.set_fonts(self.fonts.clone());
+
+        Ok(())
+    }
+
     pub fn handle_event(&mut self, window: &Window, event: &WindowEvent) {
         let _ = self.egui_winit.on_window_event(window, event);
     }
@@ -85,12 +181,67 @@ impl GuiContext {
         Ok(())
     }
 
+    /// Makes `view` displayable with `ui.image((id, size))`.
+    ///
+    /// The image must be in `SHADER_READ_ONLY_OPTIMAL` layout when the GUI is drawn. All
+    /// registered textures are unregistered when the swapchain is recreated so apps must
+    /// register their views again afterwards.
+    pub fn register_texture(&mut self, view: &ImageView, sampler: &Sampler) -> Result<TextureId> {
+        if self.user_texture_sets.len() as u32 >= MAX_USER_TEXTURES {
+            return Err(anyhow::anyhow!(
+                "Cannot register more than {MAX_USER_TEXTURES} gui textures"
+            ));
+        }
+
+        let set = self
+            .user_texture_pool
+            .allocate_set(&self.user_texture_dsl)?;
+        set.bind_texture(0, view, sampler, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+        let id = self.renderer.add_user_texture(set.inner);
+        self.user_texture_sets.insert(id, set);
+
+        Ok(id)
+    }
+
+    /// Forgets a texture registered with [`Self::register_texture`].
+    ///
+    /// The texture must not be used by pending command buffers.
+    pub fn unregister_texture(&mut self, id: TextureId) -> Result<()> {
+        self.renderer.remove_user_texture(id);
+        match self.user_texture_sets.remove(&id) {
+            Some(set) => self.user_texture_pool.free_sets([set]),
+            None => Ok(()),
+        }
+    }
+
+    /// Forgets all textures registered with [`Self::register_texture`].
+    ///
+    /// The textures must not be used by pending command buffers.
+    pub fn unregister_all_textures(&mut self) -> Result<()> {
+        for id in self.user_texture_sets.keys() {
+            self.renderer.remove_user_texture(*id);
+        }
+        self.user_texture_pool
+            .free_sets(self.user_texture_sets.drain().map(|(_, set)| set))
+    }
+
+    /// Meshes using a texture that is not registered anymore, which happens for one frame after
+    /// the swapchain is recreated, are dropped instead of failing the draw.
     pub fn tessellate(
         &self,
         shapes: Vec<ClippedShape>,
         pixels_per_point: f32,
     ) -> Vec<ClippedPrimitive> {
-        self.egui.tessellate(shapes, pixels_per_point)
+        let mut primitives = self.egui.tessellate(shapes, pixels_per_point);
+        primitives.retain(|p| match &p.primitive {
+            Primitive::Mesh(mesh) => match mesh.texture_id {
+                TextureId::User(_) => self.user_texture_sets.contains_key(&mesh.texture_id),
+                TextureId::Managed(_) => true,
+            },
+            Primitive::Callback(_) => true,
+        });
+        primitives
     }
 
     pub fn cmd_draw(
@@ -106,11 +257,40 @@ impl GuiContext {
         Ok(())
     }
 
-    pub fn update_framebuffer_params(&mut self, format: vk::Format) -> Result<()> {
+    /// Records the GUI into a secondary command buffer, to be executed inside a rendering pass
+    /// begun with `vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS` on a single sampled
+    /// color attachment, and a depth attachment if any, of the formats the renderer was
+    /// configured with.
+    pub fn record_secondary(
+        &mut self,
+        command_buffer: &CommandBuffer,
+        extent: vk::Extent2D,
+        pixels_per_point: f32,
+        primitives: &[ClippedPrimitive],
+    ) -> Result<()> {
+        command_buffer.begin_secondary(
+            Some(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+            &RenderingInheritance {
+                color_formats: &[self.color_attachment_format],
+                depth_format: self.depth_attachment_format,
+                samples: vk::SampleCountFlags::TYPE_1,
+            },
+        )?;
+        self.cmd_draw(command_buffer, extent, pixels_per_point, primitives)?;
+        command_buffer.end()
+    }
+
+    pub fn update_framebuffer_params(
+        &mut self,
+        format: vk::Format,
+        depth_format: Option<vk::Format>,
+    ) -> Result<()> {
         self.renderer.set_dynamic_rendering(DynamicRendering {
             color_attachment_format: format,
-            depth_attachment_format: None,
+            depth_attachment_format: depth_format,
         })?;
+        self.color_attachment_format = format;
+        self.depth_attachment_format = depth_format;
 
         Ok(())
     }
@@ -1,5 +1,6 @@
 use std::{
-    mem::{align_of, size_of_val},
+    mem::{align_of, size_of, size_of_val},
+    ptr::NonNull,
     sync::{Arc, Mutex},
 };
 
@@ -10,14 +11,19 @@ use gpu_allocator::{
     MemoryLocation,
 };
 
-use crate::{device::Device, utils::compute_aligned_size_of, Context};
+use crate::{device::Device, utils::compute_aligned_size_of, BufferBarrier, Context};
 
 pub struct Buffer {
     device: Arc<Device>,
     allocator: Arc<Mutex<Allocator>>,
     pub(crate) inner: vk::Buffer,
     allocation: Option<Allocation>,
+    pub(crate) dedicated_memory: Option<vk::DeviceMemory>,
     pub size: vk::DeviceSize,
+    /// Alignment of the elements of the buffer, 1 unless created with
+    /// [`Context::create_buffer_aligned`].
+    pub alignment: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
 }
 
 impl Buffer {
@@ -27,10 +33,12 @@ impl Buffer {
         usage: vk::BufferUsageFlags,
         memory_location: MemoryLocation,
         size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
     ) -> Result<Self> {
         let create_info = vk::BufferCreateInfo::default().size(size).usage(usage);
         let inner = unsafe { device.inner.create_buffer(&create_info, None)? };
-        let requirements = unsafe { device.inner.get_buffer_memory_requirements(inner) };
+        let mut requirements = unsafe { device.inner.get_buffer_memory_requirements(inner) };
+        requirements.alignment = requirements.alignment.max(alignment);
         let allocation = allocator.lock().unwrap().allocate(&AllocationCreateDesc {
             name: "buffer",
             requirements,
@@ -50,19 +58,92 @@ impl Buffer {
             allocator,
             inner,
             allocation: Some(allocation),
+            dedicated_memory: None,
+            size,
+            alignment,
+            usage,
+        })
+    }
+
+    /// Creates a buffer bound to its own device local memory allocation, outside of the allocator,
+    /// whose memory can be exported as `handle_type`.
+    pub(crate) fn new_exportable(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        usage: vk::BufferUsageFlags,
+        size: vk::DeviceSize,
+        handle_type: vk::ExternalMemoryHandleTypeFlags,
+    ) -> Result<Self> {
+        let mut external_info =
+            vk::ExternalMemoryBufferCreateInfo::default().handle_types(handle_type);
+        let create_info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(usage)
+            .push_next(&mut external_info);
+        let inner = unsafe { device.inner.create_buffer(&create_info, None)? };
+        let requirements = unsafe { device.inner.get_buffer_memory_requirements(inner) };
+
+        let Some(memory_type_index) = find_memory_type_index(
+            memory_properties,
+            requirements.memory_type_bits,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        ) else {
+            unsafe { device.inner.destroy_buffer(inner, None) };
+            return Err(anyhow::anyhow!(
+                "Could not find a device local memory type for exportable buffer"
+            ));
+        };
+
+        let mut dedicated_info = vk::MemoryDedicatedAllocateInfo::default().buffer(inner);
+        let mut export_info = vk::ExportMemoryAllocateInfo::default().handle_types(handle_type);
+        let allocate_info = vk::MemoryAllocateInfo::default()
+            .allocation_size(requirements.size)
+            .memory_type_index(memory_type_index)
+            .push_next(&mut dedicated_info)
+            .push_next(&mut export_info);
+
+        let memory = match unsafe { device.inner.allocate_memory(&allocate_info, None) } {
+            Ok(memory) => memory,
+            Err(err) => {
+                unsafe { device.inner.destroy_buffer(inner, None) };
+                return Err(err.into());
+            }
+        };
+
+        if let Err(err) = unsafe { device.inner.bind_buffer_memory(inner, memory, 0) } {
+            unsafe {
+                device.inner.destroy_buffer(inner, None);
+                device.inner.free_memory(memory, None);
+            }
+            return Err(err.into());
+        }
+
+        Ok(Self {
+            device,
+            allocator,
+            inner,
+            allocation: None,
+            dedicated_memory: Some(memory),
             size,
+            alignment: 1,
+            usage,
         })
     }
 
+    /// Size in bytes the buffer was created with.
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    /// Usage flags the buffer was created with.
+    pub fn usage(&self) -> vk::BufferUsageFlags {
+        self.usage
+    }
+
     pub fn copy_data_to_buffer<T: Copy>(&self, data: &[T]) -> Result<()> {
+        let data_ptr = self.write_ptr()?;
         unsafe {
-            let data_ptr = self
-                .allocation
-                .as_ref()
-                .unwrap()
-                .mapped_ptr()
-                .unwrap()
-                .as_ptr();
             let mut align =
                 ash::util::Align::new(data_ptr, align_of::<T>() as _, size_of_val(data) as _);
             align.copy_from_slice(data);
@@ -71,6 +152,31 @@ impl Buffer {
         Ok(())
     }
 
+    pub(crate) fn copy_bytes_to_buffer_at(
+        &self,
+        offset: vk::DeviceSize,
+        bytes: &[u8],
+    ) -> Result<()> {
+        if offset + bytes.len() as vk::DeviceSize > self.size {
+            return Err(anyhow::anyhow!(
+                "Cannot write {} bytes at offset {offset} in a buffer of {} bytes",
+                bytes.len(),
+                self.size
+            ));
+        }
+
+        let data_ptr = self.write_ptr()?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                (data_ptr as *mut u8).add(offset as _),
+                bytes.len(),
+            );
+        }
+
+        Ok(())
+    }
+
     pub fn copy_data_to_buffer_with_alignment<T: Copy>(
         &self,
         data: &[T],
@@ -78,14 +184,8 @@ impl Buffer {
     ) -> Result<()> {
         let size = data.len() as vk::DeviceSize * compute_aligned_size_of::<T>(alignment);
 
+        let data_ptr = self.write_ptr()?;
         unsafe {
-            let data_ptr = self
-                .allocation
-                .as_ref()
-                .unwrap()
-                .mapped_ptr()
-                .unwrap()
-                .as_ptr();
             let mut align = ash::util::Align::new(data_ptr, alignment, size);
             align.copy_from_slice(data);
         };
@@ -93,12 +193,210 @@ impl Buffer {
         Ok(())
     }
 
+    /// Mapped memory of the buffer, exportable and GPU only buffers cannot be written.
+    fn write_ptr(&self) -> Result<*mut std::ffi::c_void> {
+        let data_ptr = self
+            .allocation
+            .as_ref()
+            .and_then(Allocation::mapped_ptr)
+            .ok_or_else(|| anyhow::anyhow!("Cannot write to a buffer that is not host visible"))?
+            .as_ptr();
+
+        Ok(data_ptr)
+    }
+
+    /// Size of `T` rounded up to the alignment of the buffer.
+    ///
+    /// This is the stride of the elements written by
+    /// [`Buffer::copy_data_to_buffer_with_alignment`] and the step of their dynamic offsets.
+    pub fn aligned_size_of<T>(&self) -> vk::DeviceSize {
+        compute_aligned_size_of::<T>(self.alignment)
+    }
+
+    /// Gives direct access to the memory of a host visible buffer.
+    ///
+    /// The memory is mapped once by the allocator for the lifetime of the buffer, so the
+    /// returned guard is cheap and can be created each frame. It borrows the buffer mutably
+    /// so the mapped memory cannot be aliased through another guard.
+    pub fn map(&mut self) -> Result<MappedBuffer<'_>> {
+        let allocation = self
+            .allocation
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Cannot map a buffer without allocation"))?;
+        let ptr = allocation
+            .mapped_ptr()
+            .ok_or_else(|| anyhow::anyhow!("Cannot map a buffer that is not host visible"))?
+            .cast();
+
+        Ok(MappedBuffer {
+            is_coherent: allocation
+                .memory_properties()
+                .contains(vk::MemoryPropertyFlags::HOST_COHERENT),
+            ptr,
+            buffer: self,
+        })
+    }
+
+    /// Reads the content of a host visible buffer as a vec of `T`.
+    ///
+    /// Trailing bytes that don't fit a whole `T` are ignored. The device writes to read must
+    /// have completed. Use [`Context::read_buffer_to_vec`] for device local buffers.
+    pub fn read_data_to_vec<T: Copy>(&self) -> Result<Vec<T>> {
+        let allocation = self
+            .allocation
+            .as_ref()
+            .filter(|allocation| allocation.mapped_ptr().is_some())
+            .ok_or_else(|| anyhow::anyhow!("Cannot read from a buffer that is not host visible"))?;
+        let data_ptr = allocation.mapped_ptr().unwrap().as_ptr();
+
+        if !allocation
+            .memory_properties()
+            .contains(vk::MemoryPropertyFlags::HOST_COHERENT)
+        {
+            let range = self.mapped_memory_range(0, self.size);
+            unsafe {
+                self.device
+                    .inner
+                    .invalidate_mapped_memory_ranges(std::slice::from_ref(&range))?
+            };
+        }
+
+        let count = self.size as usize / size_of::<T>();
+        let mut data = Vec::<T>::with_capacity(count);
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data_ptr as *const u8,
+                data.as_mut_ptr() as *mut u8,
+                count * size_of::<T>(),
+            );
+            data.set_len(count);
+        }
+
+        Ok(data)
+    }
+
+    /// Range of the device memory covering `size` bytes at `offset` in the buffer,
+    /// widened to the non coherent atom size.
+    pub(crate) fn mapped_memory_range(
+        &self,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> vk::MappedMemoryRange<'static> {
+        let allocation = self.allocation.as_ref().unwrap();
+        let atom_size = self.device.non_coherent_atom_size;
+
+        let start = allocation.offset() + offset;
+        let end = start + size.min(self.size.saturating_sub(offset));
+        let aligned_start = start - start % atom_size;
+        let aligned_end = end.next_multiple_of(atom_size);
+        // The aligned range could go past the end of the memory object
+        let size = if aligned_end > allocation.offset() + allocation.size() {
+            vk::WHOLE_SIZE
+        } else {
+            aligned_end - aligned_start
+        };
+
+        vk::MappedMemoryRange::default()
+            .memory(unsafe { allocation.memory() })
+            .offset(aligned_start)
+            .size(size)
+    }
+
     pub fn get_device_address(&self) -> u64 {
         let addr_info = vk::BufferDeviceAddressInfo::default().buffer(self.inner);
         unsafe { self.device.inner.get_buffer_device_address(&addr_info) }
     }
 }
 
+/// Mapped memory of a host visible [`Buffer`], see [`Buffer::map`].
+///
+/// Writes to non coherent memory must be made visible to the device with
+/// [`MappedBuffer::flush_range`], and device writes must be made visible to the host
+/// with [`MappedBuffer::invalidate_range`]. Both are no-ops for coherent memory.
+pub struct MappedBuffer<'a> {
+    buffer: &'a mut Buffer,
+    ptr: NonNull<u8>,
+    is_coherent: bool,
+}
+
+impl MappedBuffer<'_> {
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.buffer.size as _) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.buffer.size as _) }
+    }
+
+    /// Copies `data` at `offset` bytes from the start of the buffer.
+    pub fn write<T: Copy>(&mut self, offset: vk::DeviceSize, data: &[T]) -> Result<()> {
+        let bytes =
+            unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, size_of_val(data)) };
+        let end = offset as usize + bytes.len();
+        let buffer_size = self.buffer.size;
+        self.as_mut_slice()
+            .get_mut(offset as usize..end)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Cannot write {} bytes at offset {offset} in a buffer of {} bytes",
+                    bytes.len(),
+                    buffer_size
+                )
+            })?
+            .copy_from_slice(bytes);
+
+        Ok(())
+    }
+
+    /// Makes host writes to `size` bytes at `offset` visible to the device.
+    pub fn flush_range(&self, offset: vk::DeviceSize, size: vk::DeviceSize) -> Result<()> {
+        if self.is_coherent {
+            return Ok(());
+        }
+
+        let range = self.buffer.mapped_memory_range(offset, size);
+        unsafe {
+            self.buffer
+                .device
+                .inner
+                .flush_mapped_memory_ranges(std::slice::from_ref(&range))?
+        };
+
+        Ok(())
+    }
+
+    /// Makes device writes to `size` bytes at `offset` visible to the host.
+    pub fn invalidate_range(&self, offset: vk::DeviceSize, size: vk::DeviceSize) -> Result<()> {
+        if self.is_coherent {
+            return Ok(());
+        }
+
+        let range = self.buffer.mapped_memory_range(offset, size);
+        unsafe {
+            self.buffer
+                .device
+                .inner
+                .invalidate_mapped_memory_ranges(std::slice::from_ref(&range))?
+        };
+
+        Ok(())
+    }
+}
+
+fn find_memory_type_index(
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    type_bits: u32,
+    flags: vk::MemoryPropertyFlags,
+) -> Option<u32> {
+    memory_properties.memory_types[..memory_properties.memory_type_count as usize]
+        .iter()
+        .enumerate()
+        .find(|(index, memory_type)| {
+            type_bits & (1 << index) != 0 && memory_type.property_flags.contains(flags)
+        })
+        .map(|(index, _)| index as _)
+}
+
 impl Context {
     pub fn create_buffer(
         &self,
@@ -112,17 +410,88 @@ impl Context {
             usage,
             memory_location,
             size,
+            1,
+        )
+    }
+
+    /// Creates a buffer whose size is rounded up to a multiple of `alignment` and whose memory
+    /// is bound at an offset aligned on it, so it can hold arrays of aligned elements, like
+    /// uniform buffers accessed through dynamic offsets.
+    ///
+    /// `alignment` must be a power of two.
+    pub fn create_buffer_aligned(
+        &self,
+        usage: vk::BufferUsageFlags,
+        memory_location: MemoryLocation,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Result<Buffer> {
+        if !alignment.is_power_of_two() {
+            return Err(anyhow::anyhow!(
+                "Buffer alignment must be a power of two, got {alignment}"
+            ));
+        }
+
+        Buffer::new(
+            self.device.clone(),
+            self.allocator.clone(),
+            usage,
+            memory_location,
+            size.next_multiple_of(alignment),
+            alignment,
         )
     }
+
+    /// Reads the content of `buffer` as a vec of `T`, even if it is not host visible.
+    ///
+    /// Host visible buffers are read directly. Other buffers are copied into a temporary
+    /// readback buffer on the graphics queue, so they must have been created with
+    /// `TRANSFER_SRC` usage. This waits for the copy to complete, and the copy waits for
+    /// the work previously submitted to the graphics queue.
+    pub fn read_buffer_to_vec<T: Copy>(&self, buffer: &Buffer) -> Result<Vec<T>> {
+        if buffer
+            .allocation
+            .as_ref()
+            .is_some_and(|allocation| allocation.mapped_ptr().is_some())
+        {
+            return buffer.read_data_to_vec();
+        }
+
+        if !buffer.usage().contains(vk::BufferUsageFlags::TRANSFER_SRC) {
+            return Err(anyhow::anyhow!(
+                "Cannot read back a device local buffer without TRANSFER_SRC usage"
+            ));
+        }
+
+        let readback_buffer = self.create_buffer(
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+            buffer.size(),
+        )?;
+
+        self.execute_one_time_commands(|cmd| {
+            cmd.pipeline_buffer_barriers(&[BufferBarrier {
+                buffer,
+                src_access_mask: vk::AccessFlags2::MEMORY_WRITE,
+                dst_access_mask: vk::AccessFlags2::TRANSFER_READ,
+                src_stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+                dst_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+            }]);
+            cmd.copy_buffer(buffer, &readback_buffer);
+        })?;
+
+        readback_buffer.read_data_to_vec()
+    }
 }
 
 impl Drop for Buffer {
     fn drop(&mut self) {
         unsafe { self.device.inner.destroy_buffer(self.inner, None) };
-        self.allocator
-            .lock()
-            .unwrap()
-            .free(self.allocation.take().unwrap())
-            .unwrap();
+        if let Some(allocation) = self.allocation.take() {
+            self.allocator.lock().unwrap().free(allocation).unwrap();
+        }
+        if let Some(memory) = self.dedicated_memory.take() {
+            unsafe { self.device.inner.free_memory(memory, None) };
+        }
     }
 }
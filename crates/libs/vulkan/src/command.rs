@@ -1,12 +1,12 @@
-use std::sync::Arc;
+use std::{mem::size_of, sync::Arc};
 
 use anyhow::Result;
 use ash::vk::{self, IndexType};
 
 use crate::{
-    device::Device, Buffer, ComputePipeline, Context, DescriptorSet, GraphicsPipeline, Image,
-    ImageView, PipelineLayout, QueueFamily, RayTracingContext, RayTracingPipeline,
-    ShaderBindingTable, TimestampQueryPool,
+    debug::debug_label_name, device::Device, Buffer, ComputePipeline, Context, DescriptorSet,
+    GraphicsPipeline, Image, ImageView, PipelineLayout, QueueFamily, RayTracingContext,
+    RayTracingPipeline, ShaderBindingTable, TimestampQueryPool,
 };
 
 pub struct CommandPool {
@@ -119,6 +119,49 @@ impl CommandBuffer {
         Ok(())
     }
 
+    /// Begins a secondary command buffer executed with [`CommandBuffer::execute_commands`]
+    /// inside a rendering pass begun with `vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS`.
+    ///
+    /// Nothing is inherited from the primary command buffer but the rendering pass, so pipelines,
+    /// descriptor sets, vertex buffers and dynamic states must be bound again. Secondary command
+    /// buffers can be recorded on different threads as long as each thread allocates them from
+    /// its own [`CommandPool`]. The formats and sample count of `inheritance` must match the
+    /// attachments of that pass.
+    pub fn begin_secondary(
+        &self,
+        flags: Option<vk::CommandBufferUsageFlags>,
+        inheritance: &RenderingInheritance,
+    ) -> Result<()> {
+        let mut rendering_info = vk::CommandBufferInheritanceRenderingInfo::default()
+            .color_attachment_formats(inheritance.color_formats)
+            .depth_attachment_format(inheritance.depth_format.unwrap_or(vk::Format::UNDEFINED))
+            .rasterization_samples(inheritance.samples);
+        let inheritance_info =
+            vk::CommandBufferInheritanceInfo::default().push_next(&mut rendering_info);
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(
+                flags.unwrap_or(vk::CommandBufferUsageFlags::empty())
+                    | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE,
+            )
+            .inheritance_info(&inheritance_info);
+        unsafe {
+            self.device
+                .inner
+                .begin_command_buffer(self.inner, &begin_info)?
+        };
+
+        Ok(())
+    }
+
+    pub fn execute_commands(&self, command_buffers: &[&CommandBuffer]) {
+        if command_buffers.is_empty() {
+            return;
+        }
+
+        let buffers = command_buffers.iter().map(|b| b.inner).collect::<Vec<_>>();
+        unsafe { self.device.inner.cmd_execute_commands(self.inner, &buffers) };
+    }
+
     pub fn end(&self) -> Result<()> {
         unsafe { self.device.inner.end_command_buffer(self.inner)? };
 
@@ -166,10 +209,53 @@ impl CommandBuffer {
     }
 
     pub fn bind_vertex_buffer(&self, vertex_buffer: &Buffer) {
+        self.bind_vertex_buffers(0, &[vertex_buffer], &[0]);
+    }
+
+    /// Binds `buffers` to consecutive bindings starting at `first_binding`.
+    ///
+    /// Each buffer is read from its matching offset in `offsets`. For example with positions
+    /// and normals in separate buffers, matching a [`crate::Vertex`] returning two bindings:
+    ///
+    /// ```ignore
+    /// fn bindings() -> Vec<vk::VertexInputBindingDescription> {
+    ///     vec![
+    ///         vk::VertexInputBindingDescription {
+    ///             binding: 0,
+    ///             stride: size_of::<[f32; 3]>() as _,
+    ///             input_rate: vk::VertexInputRate::VERTEX,
+    ///         },
+    ///         vk::VertexInputBindingDescription {
+    ///             binding: 1,
+    ///             stride: size_of::<[f32; 3]>() as _,
+    ///             input_rate: vk::VertexInputRate::VERTEX,
+    ///         },
+    ///     ]
+    /// }
+    ///
+    /// buffer.bind_vertex_buffers(0, &[&positions, &normals], &[0, 0]);
+    /// ```
+    pub fn bind_vertex_buffers(
+        &self,
+        first_binding: u32,
+        buffers: &[&Buffer],
+        offsets: &[vk::DeviceSize],
+    ) {
+        debug_assert!(
+            !buffers.is_empty(),
+            "At least one vertex buffer must be bound"
+        );
+        debug_assert_eq!(
+            buffers.len(),
+            offsets.len(),
+            "Each vertex buffer needs an offset"
+        );
+
+        let buffers = buffers.iter().map(|b| b.inner).collect::<Vec<_>>();
         unsafe {
             self.device
                 .inner
-                .cmd_bind_vertex_buffers(self.inner, 0, &[vertex_buffer.inner], &[0])
+                .cmd_bind_vertex_buffers(self.inner, first_binding, &buffers, offsets)
         };
     }
 
@@ -181,19 +267,85 @@ impl CommandBuffer {
         };
     }
 
+    /// Opens a labelled region of commands, closed with [`CommandBuffer::end_debug_label`].
+    /// Regions can be nested. Labels are skipped when `VK_EXT_debug_utils` is not available.
+    pub fn begin_debug_label(&self, name: &str, color: [f32; 4]) {
+        let Some(debug_utils) = &self.device.debug_utils else {
+            return;
+        };
+
+        let name = debug_label_name(name);
+        let label = vk::DebugUtilsLabelEXT::default()
+            .label_name(&name)
+            .color(color);
+
+        unsafe { debug_utils.cmd_begin_debug_utils_label(self.inner, &label) };
+    }
+
+    pub fn end_debug_label(&self) {
+        if let Some(debug_utils) = &self.device.debug_utils {
+            unsafe { debug_utils.cmd_end_debug_utils_label(self.inner) };
+        }
+    }
+
+    /// Inserts a single label between commands.
+    pub fn insert_debug_label(&self, name: &str, color: [f32; 4]) {
+        let Some(debug_utils) = &self.device.debug_utils else {
+            return;
+        };
+
+        let name = debug_label_name(name);
+        let label = vk::DebugUtilsLabelEXT::default()
+            .label_name(&name)
+            .color(color);
+
+        unsafe { debug_utils.cmd_insert_debug_utils_label(self.inner, &label) };
+    }
+
     pub fn draw(&self, vertex_count: u32) {
+        self.draw_instanced(vertex_count, 1, 0, 0);
+    }
+
+    pub fn draw_instanced(
+        &self,
+        vertex_count: u32,
+        instance_count: u32,
+        first_vertex: u32,
+        first_instance: u32,
+    ) {
         unsafe {
-            self.device
-                .inner
-                .cmd_draw(self.inner, vertex_count, 1, 0, 0)
+            self.device.inner.cmd_draw(
+                self.inner,
+                vertex_count,
+                instance_count,
+                first_vertex,
+                first_instance,
+            )
         };
     }
 
     pub fn draw_indexed(&self, index_count: u32) {
+        self.draw_indexed_instanced(index_count, 1, 0, 0, 0);
+    }
+
+    /// `vertex_offset` is added to each index before fetching the vertex.
+    pub fn draw_indexed_instanced(
+        &self,
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32,
+    ) {
         unsafe {
-            self.device
-                .inner
-                .cmd_draw_indexed(self.inner, index_count, 1, 0, 0, 0)
+            self.device.inner.cmd_draw_indexed(
+                self.inner,
+                index_count,
+                instance_count,
+                first_index,
+                vertex_offset,
+                first_instance,
+            )
         };
     }
 
@@ -205,6 +357,32 @@ impl CommandBuffer {
         }
     }
 
+    /// Dispatches with the group counts of the `vk::DispatchIndirectCommand` stored at
+    /// `offset` in `buffer`, which can be written by a previous dispatch.
+    pub fn dispatch_indirect(&self, buffer: &Buffer, offset: vk::DeviceSize) {
+        debug_assert!(
+            buffer
+                .usage()
+                .contains(vk::BufferUsageFlags::INDIRECT_BUFFER),
+            "Indirect dispatch requires INDIRECT_BUFFER usage but buffer has {:?}",
+            buffer.usage()
+        );
+        debug_assert!(
+            offset % 4 == 0
+                && offset + size_of::<vk::DispatchIndirectCommand>() as vk::DeviceSize
+                    <= buffer.size(),
+            "Indirect dispatch offset {offset} is not a multiple of 4 or exceeds the buffer"
+        );
+
+        unsafe {
+            self.device
+                .inner
+                .cmd_dispatch_indirect(self.inner, buffer.inner, offset);
+        }
+    }
+
+    /// Binds sets without dynamic descriptors, see
+    /// [`CommandBuffer::bind_descriptor_sets_with_dynamic_offsets`] otherwise.
     pub fn bind_descriptor_sets(
         &self,
         bind_point: vk::PipelineBindPoint,
@@ -212,19 +390,15 @@ impl CommandBuffer {
         first_set: u32,
         sets: &[&DescriptorSet],
     ) {
-        let sets = sets.iter().map(|s| s.inner).collect::<Vec<_>>();
-        unsafe {
-            self.device.inner.cmd_bind_descriptor_sets(
-                self.inner,
-                bind_point,
-                layout.inner,
-                first_set,
-                &sets,
-                &[],
-            )
-        }
+        self.bind_descriptor_sets_with_dynamic_offsets(bind_point, layout, first_set, sets, &[]);
     }
 
+    /// Binds `sets` to consecutive set numbers starting at `first_set`.
+    ///
+    /// `dynamic_offsets` holds one offset per dynamic uniform or storage buffer descriptor of
+    /// all sets: the offsets of the first set come first, and within a set they are ordered by
+    /// binding number then by array element. Each offset must be a multiple of the matching
+    /// `min_*_buffer_offset_alignment` limit.
     pub fn bind_descriptor_sets_with_dynamic_offsets(
         &self,
         bind_point: vk::PipelineBindPoint,
@@ -233,6 +407,12 @@ impl CommandBuffer {
         sets: &[&DescriptorSet],
         dynamic_offsets: &[u32],
     ) {
+        debug_assert_eq!(
+            sets.iter().map(|s| s.dynamic_descriptor_count).sum::<u32>() as usize,
+            dynamic_offsets.len(),
+            "Expected one dynamic offset per dynamic descriptor of the bound sets"
+        );
+
         let sets = sets.iter().map(|s| s.inner).collect::<Vec<_>>();
         unsafe {
             self.device.inner.cmd_bind_descriptor_sets(
@@ -246,6 +426,52 @@ impl CommandBuffer {
         }
     }
 
+    /// Pushes `constants`, a `#[repr(C)]` value laid out like the push constant block of the
+    /// shaders.
+    pub fn push_constants<T: Copy>(
+        &self,
+        layout: &PipelineLayout,
+        stage_flags: vk::ShaderStageFlags,
+        offset: u32,
+        constants: &T,
+    ) {
+        let data = unsafe {
+            std::slice::from_raw_parts(constants as *const T as *const u8, size_of::<T>())
+        };
+        unsafe {
+            self.device.inner.cmd_push_constants(
+                self.inner,
+                layout.inner,
+                stage_flags,
+                offset,
+                data,
+            )
+        }
+    }
+
+    /// Global memory barriers, for resources that are not a [`Buffer`] or an [`Image`]
+    /// of the app, like acceleration structures.
+    pub fn pipeline_memory_barriers(&self, barriers: &[MemoryBarrier]) {
+        let barriers = barriers
+            .iter()
+            .map(|b| {
+                vk::MemoryBarrier2::default()
+                    .src_stage_mask(b.src_stage_mask)
+                    .src_access_mask(b.src_access_mask)
+                    .dst_stage_mask(b.dst_stage_mask)
+                    .dst_access_mask(b.dst_access_mask)
+            })
+            .collect::<Vec<_>>();
+
+        let dependency_info = vk::DependencyInfo::default().memory_barriers(&barriers);
+
+        unsafe {
+            self.device
+                .inner
+                .cmd_pipeline_barrier2(self.inner, &dependency_info)
+        };
+    }
+
     pub fn pipeline_buffer_barriers(&self, barriers: &[BufferBarrier]) {
         let barriers = barriers
             .iter()
@@ -270,18 +496,54 @@ impl CommandBuffer {
         };
     }
 
+    /// Copies the whole `src_buffer` at the start of `dst_buffer`, which must be at least as large.
     pub fn copy_buffer(&self, src_buffer: &Buffer, dst_buffer: &Buffer) {
+        debug_assert!(
+            dst_buffer.size() >= src_buffer.size(),
+            "Cannot copy {} bytes into a buffer of {} bytes",
+            src_buffer.size(),
+            dst_buffer.size()
+        );
+
+        let region = vk::BufferCopy::default().size(src_buffer.size());
+        self.copy_buffer_regions(src_buffer, dst_buffer, std::slice::from_ref(&region));
+    }
+
+    /// Copies `regions` of `src_buffer` into `dst_buffer`.
+    pub fn copy_buffer_regions(
+        &self,
+        src_buffer: &Buffer,
+        dst_buffer: &Buffer,
+        regions: &[vk::BufferCopy],
+    ) {
+        debug_assert_transfer_usage(src_buffer, vk::BufferUsageFlags::TRANSFER_SRC);
+        debug_assert_transfer_usage(dst_buffer, vk::BufferUsageFlags::TRANSFER_DST);
+        debug_assert!(
+            regions.iter().all(|r| {
+                r.src_offset + r.size <= src_buffer.size()
+                    && r.dst_offset + r.size <= dst_buffer.size()
+            }),
+            "Buffer copy regions exceed the size of the source or destination buffer"
+        );
+
         unsafe {
-            let region = vk::BufferCopy::default().size(src_buffer.size);
             self.device.inner.cmd_copy_buffer(
                 self.inner,
                 src_buffer.inner,
                 dst_buffer.inner,
-                std::slice::from_ref(&region),
+                regions,
             )
         };
     }
 
+    pub(crate) fn pipeline_barrier(&self, dependency_info: &vk::DependencyInfo) {
+        unsafe {
+            self.device
+                .inner
+                .cmd_pipeline_barrier2(self.inner, dependency_info)
+        };
+    }
+
     pub fn pipeline_image_barriers(&self, barriers: &[ImageBarrier]) {
         let barriers = barriers
             .iter()
@@ -295,11 +557,11 @@ impl CommandBuffer {
                     .new_layout(b.new_layout)
                     .image(b.image.inner)
                     .subresource_range(vk::ImageSubresourceRange {
-                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        aspect_mask: b.image.aspect_mask(),
                         base_mip_level: 0,
-                        level_count: 1,
+                        level_count: vk::REMAINING_MIP_LEVELS,
                         base_array_layer: 0,
-                        layer_count: 1,
+                        layer_count: vk::REMAINING_ARRAY_LAYERS,
                     })
             })
             .collect::<Vec<_>>();
@@ -352,9 +614,30 @@ impl CommandBuffer {
     }
 
     pub fn copy_buffer_to_image(&self, src: &Buffer, dst: &Image, layout: vk::ImageLayout) {
+        self.copy_buffer_to_image_layer(src, dst, layout, 0);
+    }
+
+    /// Copies the data at `src_offset` in `src` into the first mip level and layer of `dst`.
+    ///
+    /// `dst` must not have a combined depth/stencil format since the copy writes a single aspect.
+    pub fn copy_buffer_to_image_at(
+        &self,
+        src: &Buffer,
+        src_offset: vk::DeviceSize,
+        dst: &Image,
+        layout: vk::ImageLayout,
+    ) {
+        debug_assert_transfer_usage(src, vk::BufferUsageFlags::TRANSFER_SRC);
+        debug_assert!(
+            dst.aspect_mask() != vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+            "Cannot copy a buffer to an image of combined depth/stencil format {:?}",
+            dst.format
+        );
+
         let region = vk::BufferImageCopy::default()
+            .buffer_offset(src_offset)
             .image_subresource(vk::ImageSubresourceLayers {
-                aspect_mask: vk::ImageAspectFlags::COLOR,
+                aspect_mask: dst.aspect_mask(),
                 mip_level: 0,
                 base_array_layer: 0,
                 layer_count: 1,
@@ -372,6 +655,179 @@ impl CommandBuffer {
         };
     }
 
+    /// Copies `src` into the first mip level of one array layer (or cube face) of `dst`.
+    pub fn copy_buffer_to_image_layer(
+        &self,
+        src: &Buffer,
+        dst: &Image,
+        layout: vk::ImageLayout,
+        array_layer: u32,
+    ) {
+        debug_assert_transfer_usage(src, vk::BufferUsageFlags::TRANSFER_SRC);
+
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: array_layer,
+                layer_count: 1,
+            })
+            .image_extent(dst.extent);
+
+        unsafe {
+            self.device.inner.cmd_copy_buffer_to_image(
+                self.inner,
+                src.inner,
+                dst.inner,
+                layout,
+                std::slice::from_ref(&region),
+            );
+        };
+    }
+
+    /// Blits the first mip level of `src_image` over the whole of `dst_image`, converting
+    /// between formats (linear to sRGB for example) and scaling with `filter` when extents differ.
+    pub fn blit_image(
+        &self,
+        src_image: &Image,
+        src_layout: vk::ImageLayout,
+        dst_image: &Image,
+        dst_layout: vk::ImageLayout,
+        filter: vk::Filter,
+    ) {
+        let subresource = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_array_layer: 0,
+            mip_level: 0,
+            layer_count: 1,
+        };
+        let corner = |image: &Image| vk::Offset3D {
+            x: image.extent.width as _,
+            y: image.extent.height as _,
+            z: 1,
+        };
+        let region = vk::ImageBlit::default()
+            .src_subresource(subresource)
+            .src_offsets([vk::Offset3D::default(), corner(src_image)])
+            .dst_subresource(subresource)
+            .dst_offsets([vk::Offset3D::default(), corner(dst_image)]);
+
+        unsafe {
+            self.device.inner.cmd_blit_image(
+                self.inner,
+                src_image.inner,
+                src_layout,
+                dst_image.inner,
+                dst_layout,
+                std::slice::from_ref(&region),
+                filter,
+            )
+        };
+    }
+
+    /// Copies `region` of the first mip level and array layer of `src` into `dst`.
+    ///
+    /// `buffer_row_length` is the number of texels between the start of two rows in `dst`,
+    /// use it when rows are padded (to match an encoder pitch for example). 0 means the
+    /// rows are tightly packed according to `region.extent.width`.
+    pub fn copy_image_to_buffer(
+        &self,
+        src: &Image,
+        layout: vk::ImageLayout,
+        dst: &Buffer,
+        aspect_mask: vk::ImageAspectFlags,
+        region: vk::Rect2D,
+        buffer_row_length: u32,
+    ) {
+        debug_assert_transfer_usage(dst, vk::BufferUsageFlags::TRANSFER_DST);
+
+        let region = vk::BufferImageCopy::default()
+            .buffer_row_length(buffer_row_length)
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_offset(vk::Offset3D {
+                x: region.offset.x,
+                y: region.offset.y,
+                z: 0,
+            })
+            .image_extent(vk::Extent3D {
+                width: region.extent.width,
+                height: region.extent.height,
+                depth: 1,
+            });
+
+        unsafe {
+            self.device.inner.cmd_copy_image_to_buffer(
+                self.inner,
+                src.inner,
+                layout,
+                dst.inner,
+                std::slice::from_ref(&region),
+            );
+        };
+    }
+
+    /// Clears all mip levels and array layers of a color image outside of a rendering pass.
+    ///
+    /// `image` must have been created with `TRANSFER_DST` usage and `layout` must be either
+    /// `TRANSFER_DST_OPTIMAL` or `GENERAL`. Use a barrier with `CLEAR` as destination stage
+    /// and `TRANSFER_WRITE` as destination access to bring it there.
+    pub fn clear_color_image(&self, image: &Image, layout: vk::ImageLayout, color: [f32; 4]) {
+        debug_assert!(
+            matches!(
+                layout,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL | vk::ImageLayout::GENERAL
+            ),
+            "Cannot clear an image in layout {layout:?}"
+        );
+
+        let range = full_subresource_range(vk::ImageAspectFlags::COLOR);
+        unsafe {
+            self.device.inner.cmd_clear_color_image(
+                self.inner,
+                image.inner,
+                layout,
+                &vk::ClearColorValue { float32: color },
+                std::slice::from_ref(&range),
+            )
+        };
+    }
+
+    /// Clears all mip levels and array layers of a depth (and stencil) image outside of a
+    /// rendering pass. The stencil aspect is only cleared if the format has one.
+    ///
+    /// Same usage and layout requirements as [`CommandBuffer::clear_color_image`].
+    pub fn clear_depth_image(
+        &self,
+        image: &Image,
+        layout: vk::ImageLayout,
+        depth: f32,
+        stencil: u32,
+    ) {
+        debug_assert!(
+            matches!(
+                layout,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL | vk::ImageLayout::GENERAL
+            ),
+            "Cannot clear an image in layout {layout:?}"
+        );
+
+        let range = full_subresource_range(image.aspect_mask());
+        unsafe {
+            self.device.inner.cmd_clear_depth_stencil_image(
+                self.inner,
+                image.inner,
+                layout,
+                &vk::ClearDepthStencilValue { depth, stencil },
+                std::slice::from_ref(&range),
+            )
+        };
+    }
+
     pub fn build_acceleration_structures(
         &self,
         as_build_geo_info: &vk::AccelerationStructureBuildGeometryInfoKHR,
@@ -412,35 +868,67 @@ impl CommandBuffer {
         };
     }
 
+    /// Begins rendering to the attachments. Use [`CommandBuffer::begin_rendering_with_flags`]
+    /// with `vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS` to execute secondary
+    /// command buffers in the pass, which then can't record draws inline.
     pub fn begin_rendering(
         &self,
         color_attachments: &[RenderingAttachment],
         depth_attachment: Option<RenderingAttachment>,
         extent: vk::Extent2D,
+    ) {
+        self.begin_rendering_with_flags(
+            color_attachments,
+            depth_attachment,
+            extent,
+            vk::RenderingFlags::empty(),
+        );
+    }
+
+    /// Begins rendering with `vk::RenderingFlags`, allowing a render pass instance to be
+    /// suspended and resumed across command buffers.
+    ///
+    /// With `CONTENTS_SECONDARY_COMMAND_BUFFERS` the pass must only contain
+    /// [`CommandBuffer::execute_commands`] calls, with secondary command buffers begun
+    /// with [`CommandBuffer::begin_secondary`].
+    ///
+    /// A pass begun with `SUSPENDING` must be resumed by a pass begun with `RESUMING` with the
+    /// same attachments, extent and flags (other than the suspend/resume bits). The suspended and
+    /// resumed command buffers must be submitted in order within the same submission batch,
+    /// with no action or synchronization commands recorded between the suspending
+    /// [`CommandBuffer::end_rendering`] and the resuming begin. Load operations only apply when
+    /// the pass is first begun and store operations when it is finally ended.
+    pub fn begin_rendering_with_flags(
+        &self,
+        color_attachments: &[RenderingAttachment],
+        depth_attachment: Option<RenderingAttachment>,
+        extent: vk::Extent2D,
+        flags: vk::RenderingFlags,
     ) {
         let color_attachment_infos = color_attachments
             .iter()
             .map(|a| {
-                vk::RenderingAttachmentInfo::default()
+                let info = vk::RenderingAttachmentInfo::default()
                     .image_view(a.view.inner)
                     .image_layout(vk::ImageLayout::ATTACHMENT_OPTIMAL)
                     .load_op(a.load_op)
-                    .store_op(vk::AttachmentStoreOp::STORE)
+                    .store_op(a.store_op)
                     .clear_value(a.clear_value.map(vk::ClearValue::from).unwrap_or(
                         vk::ClearValue {
                             color: vk::ClearColorValue { float32: [1.0; 4] },
                         },
-                    ))
+                    ));
+                with_resolve(info, a.resolve, vk::ImageLayout::ATTACHMENT_OPTIMAL)
             })
             .collect::<Vec<_>>();
 
-        let depth_attachment_info =
-            depth_attachment.map(|a| {
+        let depth_attachment_info = depth_attachment.map(|a| {
+            let info =
                 vk::RenderingAttachmentInfo::default()
                     .image_view(a.view.inner)
                     .image_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
                     .load_op(a.load_op)
-                    .store_op(vk::AttachmentStoreOp::STORE)
+                    .store_op(a.store_op)
                     .clear_value(a.clear_value.map(vk::ClearValue::from).unwrap_or(
                         vk::ClearValue {
                             depth_stencil: vk::ClearDepthStencilValue {
@@ -448,10 +936,12 @@ impl CommandBuffer {
                                 stencil: 0,
                             },
                         },
-                    ))
-            });
+                    ));
+            with_resolve(info, a.resolve, vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+        });
 
         let mut rendering_info = vk::RenderingInfo::default()
+            .flags(flags)
             .render_area(vk::Rect2D {
                 offset: vk::Offset2D { x: 0, y: 0 },
                 extent,
@@ -474,6 +964,23 @@ impl CommandBuffer {
     }
 
     pub fn set_viewport(&self, extent: vk::Extent2D) {
+        self.set_viewport_with_depth_range(extent, 0.0, 1.0);
+    }
+
+    /// Like [`Self::set_viewport`] but maps depth to `min_depth..=max_depth` instead of `0..=1`.
+    ///
+    /// Setting both bounds to 1 pins everything drawn to the far plane.
+    pub fn set_viewport_with_depth_range(
+        &self,
+        extent: vk::Extent2D,
+        min_depth: f32,
+        max_depth: f32,
+    ) {
+        debug_assert!(
+            (0.0..=1.0).contains(&min_depth) && (0.0..=1.0).contains(&max_depth),
+            "Viewport depth range {min_depth}..={max_depth} must be within 0..=1"
+        );
+
         unsafe {
             self.device.inner.cmd_set_viewport(
                 self.inner,
@@ -481,7 +988,8 @@ impl CommandBuffer {
                 &[vk::Viewport {
                     width: extent.width as _,
                     height: extent.height as _,
-                    max_depth: 1.0,
+                    min_depth,
+                    max_depth,
                     ..Default::default()
                 }],
             )
@@ -501,6 +1009,12 @@ impl CommandBuffer {
         };
     }
 
+    /// Sets the cull mode of pipelines created with the `CULL_MODE` dynamic state,
+    /// which overrides the cull mode of their create info.
+    pub fn set_cull_mode(&self, cull_mode: vk::CullModeFlags) {
+        unsafe { self.device.inner.cmd_set_cull_mode(self.inner, cull_mode) };
+    }
+
     pub fn reset_all_timestamp_queries_from_pool<const C: usize>(
         &self,
         pool: &TimestampQueryPool<C>,
@@ -528,6 +1042,14 @@ impl CommandBuffer {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryBarrier {
+    pub src_access_mask: vk::AccessFlags2,
+    pub dst_access_mask: vk::AccessFlags2,
+    pub src_stage_mask: vk::PipelineStageFlags2,
+    pub dst_stage_mask: vk::PipelineStageFlags2,
+}
+
 #[derive(Clone, Copy)]
 pub struct BufferBarrier<'a> {
     pub buffer: &'a Buffer,
@@ -537,6 +1059,14 @@ pub struct BufferBarrier<'a> {
     pub dst_stage_mask: vk::PipelineStageFlags2,
 }
 
+/// Attachments of the rendering pass a secondary command buffer is executed in.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderingInheritance<'a> {
+    pub color_formats: &'a [vk::Format],
+    pub depth_format: Option<vk::Format>,
+    pub samples: vk::SampleCountFlags,
+}
+
 #[derive(Clone, Copy)]
 pub struct ImageBarrier<'a> {
     pub image: &'a Image,
@@ -552,7 +1082,62 @@ pub struct ImageBarrier<'a> {
 pub struct RenderingAttachment<'a> {
     pub view: &'a ImageView,
     pub load_op: vk::AttachmentLoadOp,
+    /// Use `DONT_CARE` when the content is not read after rendering to save bandwidth.
+    /// The resolve attachment, if any, is written whatever the store op.
+    pub store_op: vk::AttachmentStoreOp,
+    /// Used with `CLEAR` load op. Defaults to white for colors and 1.0 for depth.
     pub clear_value: Option<ClearValue>,
+    pub resolve: Option<ResolveAttachment<'a>>,
+}
+
+impl<'a> RenderingAttachment<'a> {
+    /// Attachment cleared to `clear_value` and stored.
+    pub fn clear(view: &'a ImageView, clear_value: ClearValue) -> Self {
+        Self {
+            view,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::STORE,
+            clear_value: Some(clear_value),
+            resolve: None,
+        }
+    }
+
+    /// Attachment whose previous content is loaded and stored.
+    pub fn load(view: &'a ImageView) -> Self {
+        Self {
+            view,
+            load_op: vk::AttachmentLoadOp::LOAD,
+            store_op: vk::AttachmentStoreOp::STORE,
+            clear_value: None,
+            resolve: None,
+        }
+    }
+
+    /// Attachment whose previous content is discarded and stored, for passes writing all pixels.
+    pub fn dont_care(view: &'a ImageView) -> Self {
+        Self {
+            view,
+            load_op: vk::AttachmentLoadOp::DONT_CARE,
+            store_op: vk::AttachmentStoreOp::STORE,
+            clear_value: None,
+            resolve: None,
+        }
+    }
+
+    pub fn store_op(self, store_op: vk::AttachmentStoreOp) -> Self {
+        Self { store_op, ..self }
+    }
+
+    pub fn resolve(self, resolve: Option<ResolveAttachment<'a>>) -> Self {
+        Self { resolve, ..self }
+    }
+}
+
+/// Image a multisampled attachment is resolved into at the end of rendering.
+#[derive(Copy, Clone)]
+pub struct ResolveAttachment<'a> {
+    pub view: &'a ImageView,
+    pub mode: vk::ResolveModeFlags,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -591,3 +1176,35 @@ impl From<ClearValue> for vk::ClearValue {
         }
     }
 }
+
+fn with_resolve<'a>(
+    info: vk::RenderingAttachmentInfo<'a>,
+    resolve: Option<ResolveAttachment>,
+    layout: vk::ImageLayout,
+) -> vk::RenderingAttachmentInfo<'a> {
+    match resolve {
+        Some(r) => info
+            .resolve_mode(r.mode)
+            .resolve_image_view(r.view.inner)
+            .resolve_image_layout(layout),
+        None => info,
+    }
+}
+
+fn debug_assert_transfer_usage(buffer: &Buffer, usage: vk::BufferUsageFlags) {
+    debug_assert!(
+        buffer.usage().contains(usage),
+        "Buffer used in a copy requires {usage:?} usage but has {:?}",
+        buffer.usage()
+    );
+}
+
+fn full_subresource_range(aspect_mask: vk::ImageAspectFlags) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange {
+        aspect_mask,
+        base_mip_level: 0,
+        level_count: vk::REMAINING_MIP_LEVELS,
+        base_array_layer: 0,
+        layer_count: vk::REMAINING_ARRAY_LAYERS,
+    }
+}
@@ -1,4 +1,7 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use anyhow::Result;
 use ash::{vk, Entry};
@@ -10,8 +13,9 @@ use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 
 use crate::{
     device::{Device, DeviceFeatures},
+    error::{DeviceLostExt, GpuTimeout},
     instance::Instance,
-    physical_device::PhysicalDevice,
+    physical_device::{PhysicalDevice, SubgroupProperties},
     queue::{Queue, QueueFamily},
     surface::Surface,
     CommandBuffer, CommandPool, RayTracingContext, Version, VERSION_1_0,
@@ -23,19 +27,26 @@ pub struct Context {
     pub ray_tracing: Option<Arc<RayTracingContext>>,
     pub graphics_queue: Queue,
     pub present_queue: Queue,
+    /// Queue of a compute only family, when the device has one.
+    pub compute_queue: Option<Queue>,
+    /// Queue of a transfer only family, when the device has one.
+    pub transfer_queue: Option<Queue>,
     pub device: Arc<Device>,
     pub present_queue_family: QueueFamily,
     pub graphics_queue_family: QueueFamily,
+    pub compute_queue_family: Option<QueueFamily>,
+    pub transfer_queue_family: Option<QueueFamily>,
     pub physical_device: PhysicalDevice,
     pub(crate) supported_surface_formats: Vec<vk::SurfaceFormatKHR>,
-    pub surface: Surface,
+    /// `None` for headless contexts.
+    pub surface: Option<Surface>,
     pub instance: Instance,
     _entry: Entry,
 }
 
 pub struct ContextBuilder<'a> {
-    window_handle: &'a dyn HasWindowHandle,
-    display_handle: &'a dyn HasDisplayHandle,
+    window_handle: Option<&'a dyn HasWindowHandle>,
+    display_handle: Option<&'a dyn HasDisplayHandle>,
     vulkan_version: Version,
     app_name: &'a str,
     required_instance_extensions: &'a [&'a str],
@@ -50,8 +61,19 @@ impl<'a> ContextBuilder<'a> {
         display_handle: &'a dyn HasDisplayHandle,
     ) -> Self {
         Self {
-            window_handle,
-            display_handle,
+            window_handle: Some(window_handle),
+            display_handle: Some(display_handle),
+            ..Self::headless()
+        }
+    }
+
+    /// Builder for a context without surface, to render offscreen.
+    ///
+    /// Present queue is the graphics queue and swapchains cannot be created.
+    pub fn headless() -> Self {
+        Self {
+            window_handle: None,
+            display_handle: None,
             vulkan_version: VERSION_1_0,
             app_name: "",
             required_instance_extensions: &[],
@@ -129,24 +151,45 @@ impl Context {
         )?;
 
         // Vulkan surface
-        let surface = Surface::new(&entry, &instance, window_handle, display_handle)?;
-
-        let physical_devices = instance.enumerate_physical_devices(&surface)?;
-        let (physical_device, graphics_queue_family, present_queue_family) =
-            select_suitable_physical_device(
-                physical_devices,
-                required_device_extensions,
-                &required_device_features,
-            )?;
+        let surface = window_handle
+            .zip(display_handle)
+            .map(|(window_handle, display_handle)| {
+                Surface::new(&entry, &instance, window_handle, display_handle)
+            })
+            .transpose()?;
+
+        let physical_devices = instance.enumerate_physical_devices(surface.as_ref())?;
+        let SelectedPhysicalDevice {
+            physical_device,
+            graphics_queue_family,
+            present_queue_family,
+            compute_queue_family,
+            transfer_queue_family,
+        } = select_suitable_physical_device(
+            physical_devices,
+            required_device_extensions,
+            &required_device_features,
+            surface.is_some(),
+        )?;
         log::info!("Selected physical device: {:?}", physical_device.name);
+        for (index, heap) in physical_device.memory_heaps.iter().enumerate() {
+            log::debug!(
+                "Memory heap {index}: {} MiB {:?}",
+                heap.size / (1024 * 1024),
+                heap.flags
+            );
+        }
+        log::debug!(
+            "Device local memory: {} MiB",
+            physical_device.device_local_memory_size() / (1024 * 1024)
+        );
+        required_device_features.log_report(&physical_device.supported_device_features);
 
-        let supported_surface_formats = unsafe {
-            surface
-                .inner
-                .get_physical_device_surface_formats(physical_device.inner, surface.surface_khr)?
-        };
+        let supported_surface_formats = physical_device.supported_surface_formats.clone();
 
-        let queue_families = [graphics_queue_family, present_queue_family];
+        let mut queue_families = vec![graphics_queue_family, present_queue_family];
+        queue_families.extend(compute_queue_family);
+        queue_families.extend(transfer_queue_family);
         let device = Arc::new(Device::new(
             &instance,
             &physical_device,
@@ -157,6 +200,13 @@ impl Context {
         )?);
         let graphics_queue = device.get_queue(graphics_queue_family, 0);
         let present_queue = device.get_queue(present_queue_family, 0);
+        let compute_queue = compute_queue_family.map(|family| device.get_queue(family, 0));
+        let transfer_queue = transfer_queue_family.map(|family| device.get_queue(family, 0));
+        log::debug!(
+            "Async compute queue family: {:?}. Transfer queue family: {:?}",
+            compute_queue_family.map(|f| f.index),
+            transfer_queue_family.map(|f| f.index)
+        );
 
         let ray_tracing = with_raytracing_context.then(|| {
             let ray_tracing =
@@ -199,9 +249,13 @@ impl Context {
             ray_tracing,
             present_queue,
             graphics_queue,
+            compute_queue,
+            transfer_queue,
             device,
             present_queue_family,
             graphics_queue_family,
+            compute_queue_family,
+            transfer_queue_family,
             physical_device,
             supported_surface_formats,
             surface,
@@ -211,19 +265,35 @@ impl Context {
     }
 }
 
+struct SelectedPhysicalDevice {
+    physical_device: PhysicalDevice,
+    graphics_queue_family: QueueFamily,
+    present_queue_family: QueueFamily,
+    compute_queue_family: Option<QueueFamily>,
+    transfer_queue_family: Option<QueueFamily>,
+}
+
 fn select_suitable_physical_device(
     devices: &[PhysicalDevice],
     required_extensions: &[&str],
     required_device_features: &DeviceFeatures,
-) -> Result<(PhysicalDevice, QueueFamily, QueueFamily)> {
+    with_surface: bool,
+) -> Result<SelectedPhysicalDevice> {
     log::debug!("Choosing Vulkan physical device");
 
     let mut graphics = None;
     let mut present = None;
+    let mut compute = None;
+    let mut transfer = None;
 
     let device = devices
         .iter()
         .find(|device| {
+            graphics = None;
+            present = None;
+            compute = None;
+            transfer = None;
+
             // Does device has graphics and present queues
             for family in device.queue_families.iter().filter(|f| f.has_queues()) {
                 if family.supports_graphics()
@@ -238,35 +308,99 @@ fn select_suitable_physical_device(
                     present = Some(*family);
                 }
 
-                if graphics.is_some() && present.is_some() {
-                    break;
+                // Headless contexts don't present so use the graphics queue
+                if !with_surface {
+                    present = graphics;
+                }
+
+                if family.is_async_compute()
+                    && family.supports_timestamp_queries()
+                    && compute.is_none()
+                {
+                    compute = Some(*family);
+                }
+
+                if family.is_dedicated_transfer() && transfer.is_none() {
+                    transfer = Some(*family);
                 }
             }
 
             // Does device support desired extensions
             let extention_support = device.supports_extensions(required_extensions);
 
+            let surface_support = !with_surface
+                || (!device.supported_surface_formats.is_empty()
+                    && !device.supported_present_modes.is_empty());
+
             graphics.is_some()
                 && present.is_some()
                 && extention_support
-                && !device.supported_surface_formats.is_empty()
-                && !device.supported_present_modes.is_empty()
+                && surface_support
                 && device
                     .supported_device_features
                     .is_compatible_with(required_device_features)
         })
         .ok_or_else(|| anyhow::anyhow!("Could not find a suitable device"))?;
 
-    Ok((device.clone(), graphics.unwrap(), present.unwrap()))
+    Ok(SelectedPhysicalDevice {
+        physical_device: device.clone(),
+        graphics_queue_family: graphics.unwrap(),
+        present_queue_family: present.unwrap(),
+        compute_queue_family: compute,
+        transfer_queue_family: transfer,
+    })
 }
 
 impl Context {
     pub fn device_wait_idle(&self) -> Result<()> {
-        unsafe { self.device.inner.device_wait_idle()? };
+        unsafe { self.device.inner.device_wait_idle() }.or_device_lost("device wait idle")?;
 
         Ok(())
     }
 
+    /// Like [`Context::device_wait_idle`] but fails with [`GpuTimeout`] instead of blocking
+    /// forever when the queues don't become idle within `timeout`.
+    ///
+    /// Vulkan has no timeout on device idle so an empty batch signaling a fence is submitted
+    /// to each queue and the fences are waited for. On timeout the fences are leaked since
+    /// they can't be destroyed while their submissions are pending.
+    pub fn device_wait_idle_with_timeout(&self, timeout: Duration) -> Result<()> {
+        let mut queues = vec![&self.graphics_queue, &self.present_queue];
+        queues.extend(self.compute_queue.as_ref());
+        queues.extend(self.transfer_queue.as_ref());
+        queues.dedup_by_key(|q| q.inner);
+
+        let fences = queues
+            .iter()
+            .map(|queue| {
+                let fence = self.create_fence(None)?;
+                queue.signal_fence(&fence)?;
+                Ok(fence)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let fence_handles = fences.iter().map(|f| f.inner).collect::<Vec<_>>();
+        let timeout_ns = timeout.as_nanos().min(u64::MAX as u128) as u64;
+        let result = unsafe {
+            self.device
+                .inner
+                .wait_for_fences(&fence_handles, true, timeout_ns)
+        };
+
+        if let Err(vk::Result::TIMEOUT) = result {
+            fences.into_iter().for_each(std::mem::forget);
+            return Err(GpuTimeout {
+                operation: "device wait idle",
+                timeout,
+            }
+            .into());
+        }
+        result.or_device_lost("device wait idle")?;
+
+        // Queues are idle, this also waits for the presentation engine
+        self.device_wait_idle()
+    }
+
     pub fn execute_one_time_commands<R, F: FnOnce(&CommandBuffer) -> R>(
         &self,
         executor: F,
@@ -296,11 +430,77 @@ impl Context {
         Ok(executor_result)
     }
 
+    /// The async compute queue or the graphics queue when the device has no compute only family.
+    pub fn async_compute_queue(&self) -> &Queue {
+        self.compute_queue.as_ref().unwrap_or(&self.graphics_queue)
+    }
+
+    /// Family of [`Context::async_compute_queue`].
+    pub fn async_compute_queue_family(&self) -> QueueFamily {
+        self.compute_queue_family
+            .unwrap_or(self.graphics_queue_family)
+    }
+
+    /// Features supported by images with optimal tiling of the given format.
+    pub fn optimal_tiling_format_features(&self, format: vk::Format) -> vk::FormatFeatureFlags {
+        unsafe {
+            self.instance
+                .inner
+                .get_physical_device_format_properties(self.physical_device.inner, format)
+                .optimal_tiling_features
+        }
+    }
+
     pub fn supported_surface_formats(&self) -> &[vk::SurfaceFormatKHR] {
         &self.supported_surface_formats
     }
 
+    /// Usage flags supported by swapchain images for the surface.
+    ///
+    /// Queried on each call since surface capabilities can change (when the window moves to
+    /// another monitor for example). Fails for headless contexts.
+    pub fn surface_supported_usage_flags(&self) -> Result<vk::ImageUsageFlags> {
+        let surface = self
+            .surface
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Headless contexts don't have a surface"))?;
+
+        let capabilities = unsafe {
+            surface.inner.get_physical_device_surface_capabilities(
+                self.physical_device.inner,
+                surface.surface_khr,
+            )?
+        };
+
+        Ok(capabilities.supported_usage_flags)
+    }
+
+    pub fn supported_present_modes(&self) -> &[vk::PresentModeKHR] {
+        &self.physical_device.supported_present_modes
+    }
+
     pub fn physical_device_limits(&self) -> &vk::PhysicalDeviceLimits {
-        &self.physical_device.limits
+        self.physical_device.limits()
+    }
+
+    /// Number of nanoseconds per timestamp query increment.
+    pub fn timestamp_period(&self) -> f32 {
+        self.physical_device.timestamp_period()
+    }
+
+    /// Memory heaps of the device, to avoid allocating more than what is available.
+    ///
+    /// Sizes are the total size of each heap, not what is left of it.
+    pub fn memory_heaps(&self) -> &[vk::MemoryHeap] {
+        self.physical_device.memory_heaps()
+    }
+
+    /// Total size of the device local memory heaps.
+    pub fn device_local_memory_size(&self) -> vk::DeviceSize {
+        self.physical_device.device_local_memory_size()
+    }
+
+    pub fn subgroup_properties(&self) -> &SubgroupProperties {
+        &self.physical_device.subgroup_properties
     }
 }
@@ -0,0 +1,79 @@
+use std::ffi::CString;
+
+use anyhow::Result;
+use ash::vk::{self, Handle};
+
+use crate::{
+    AccelerationStructure, Buffer, CommandBuffer, ComputePipeline, Context, DescriptorSet,
+    GraphicsPipeline, Image, ImageView, PipelineLayout, RayTracingPipeline, Sampler,
+};
+
+/// Objects that can be named with [`Context::set_object_name`].
+pub trait DebugObject {
+    type Handle: Handle;
+
+    fn handle(&self) -> Self::Handle;
+}
+
+macro_rules! impl_debug_object {
+    ($($object:ty => $handle:ty),* $(,)?) => {
+        $(
+            impl DebugObject for $object {
+                type Handle = $handle;
+
+                fn handle(&self) -> Self::Handle {
+                    self.inner
+                }
+            }
+        )*
+    };
+}
+
+impl_debug_object!(
+    AccelerationStructure => vk::AccelerationStructureKHR,
+    Buffer => vk::Buffer,
+    CommandBuffer => vk::CommandBuffer,
+    ComputePipeline => vk::Pipeline,
+    DescriptorSet => vk::DescriptorSet,
+    GraphicsPipeline => vk::Pipeline,
+    Image => vk::Image,
+    ImageView => vk::ImageView,
+    PipelineLayout => vk::PipelineLayout,
+    RayTracingPipeline => vk::Pipeline,
+    Sampler => vk::Sampler,
+);
+
+impl Context {
+    /// Names `object` so that validation messages and tools like RenderDoc show `name`.
+    ///
+    /// Does nothing when `VK_EXT_debug_utils` is not available.
+    pub fn set_object_name<T: DebugObject>(&self, object: &T, name: &str) -> Result<()> {
+        let Some(debug_utils) = &self.device.debug_utils else {
+            return Ok(());
+        };
+
+        let name = debug_label_name(name);
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::default()
+            .object_handle(object.handle())
+            .object_name(&name);
+
+        unsafe { debug_utils.set_debug_utils_object_name(&name_info)? };
+
+        Ok(())
+    }
+}
+
+/// Names and labels are only a debugging aid so interior NULs are dropped instead of failing.
+pub(crate) fn debug_label_name(name: &str) -> CString {
+    CString::new(name.replace('\0', "")).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::debug_label_name;
+
+    #[test]
+    fn debug_label_name_drops_interior_nul() {
+        assert_eq!(debug_label_name("Sky\0box").to_str(), Ok("Skybox"));
+    }
+}
@@ -8,6 +8,7 @@ use crate::{device::Device, AccelerationStructure, Buffer, Context, ImageView, S
 pub struct DescriptorSetLayout {
     device: Arc<Device>,
     pub(crate) inner: vk::DescriptorSetLayout,
+    dynamic_descriptor_count: u32,
 }
 
 impl DescriptorSetLayout {
@@ -15,13 +16,130 @@ impl DescriptorSetLayout {
         device: Arc<Device>,
         bindings: &[vk::DescriptorSetLayoutBinding],
     ) -> Result<Self> {
-        let dsl_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(bindings);
+        Self::new_with_flags(
+            device,
+            bindings,
+            None,
+            vk::DescriptorSetLayoutCreateFlags::empty(),
+        )
+    }
+
+    pub(crate) fn new_with_flags(
+        device: Arc<Device>,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+        binding_flags: Option<&[vk::DescriptorBindingFlags]>,
+        flags: vk::DescriptorSetLayoutCreateFlags,
+    ) -> Result<Self> {
+        let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::default();
+        let mut dsl_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .bindings(bindings)
+            .flags(flags);
+        if let Some(binding_flags) = binding_flags {
+            binding_flags_info = binding_flags_info.binding_flags(binding_flags);
+            dsl_info = dsl_info.push_next(&mut binding_flags_info);
+        }
+
         let inner = unsafe { device.inner.create_descriptor_set_layout(&dsl_info, None)? };
 
-        Ok(Self { device, inner })
+        let dynamic_descriptor_count = bindings
+            .iter()
+            .filter(|b| {
+                matches!(
+                    b.descriptor_type,
+                    vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
+                        | vk::DescriptorType::STORAGE_BUFFER_DYNAMIC
+                )
+            })
+            .map(|b| b.descriptor_count)
+            .sum();
+
+        Ok(Self {
+            device,
+            inner,
+            dynamic_descriptor_count,
+        })
+    }
+
+    /// Number of dynamic uniform and storage buffer descriptors, which is the number
+    /// of dynamic offsets to pass when binding sets with this layout.
+    pub fn dynamic_descriptor_count(&self) -> u32 {
+        self.dynamic_descriptor_count
     }
 }
 
+fn check_binding_flags(
+    device: &Device,
+    bindings: &[vk::DescriptorSetLayoutBinding],
+    binding_flags: &[vk::DescriptorBindingFlags],
+    flags: vk::DescriptorSetLayoutCreateFlags,
+) -> Result<()> {
+    if bindings.len() != binding_flags.len() {
+        return Err(anyhow::anyhow!(
+            "Got {} binding flags for {} bindings",
+            binding_flags.len(),
+            bindings.len()
+        ));
+    }
+
+    let features = &device.enabled_features;
+    for (binding, binding_flags) in bindings.iter().zip(binding_flags) {
+        let requirements = [
+            (
+                vk::DescriptorBindingFlags::PARTIALLY_BOUND,
+                features.descriptor_binding_partially_bound,
+                "descriptorBindingPartiallyBound",
+            ),
+            (
+                vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT,
+                features.descriptor_binding_variable_descriptor_count,
+                "descriptorBindingVariableDescriptorCount",
+            ),
+            (
+                vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING,
+                features.descriptor_binding_update_unused_while_pending,
+                "descriptorBindingUpdateUnusedWhilePending",
+            ),
+            (
+                vk::DescriptorBindingFlags::UPDATE_AFTER_BIND,
+                features.descriptor_binding_update_after_bind,
+                "descriptorBinding*UpdateAfterBind",
+            ),
+        ];
+        for (flag, enabled, feature) in requirements {
+            if binding_flags.contains(flag) && !enabled {
+                return Err(anyhow::anyhow!(
+                    "Binding {} uses {flag:?} but the {feature} feature is not enabled",
+                    binding.binding
+                ));
+            }
+        }
+
+        if binding_flags.contains(vk::DescriptorBindingFlags::UPDATE_AFTER_BIND) {
+            if !matches!(
+                binding.descriptor_type,
+                vk::DescriptorType::SAMPLED_IMAGE
+                    | vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+                    | vk::DescriptorType::STORAGE_IMAGE
+                    | vk::DescriptorType::STORAGE_BUFFER
+            ) {
+                return Err(anyhow::anyhow!(
+                    "Binding {} of type {:?} cannot be updated after bind",
+                    binding.binding,
+                    binding.descriptor_type
+                ));
+            }
+
+            if !flags.contains(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL) {
+                return Err(anyhow::anyhow!(
+                    "Layouts with update after bind bindings need the UPDATE_AFTER_BIND_POOL flag"
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
 impl Drop for DescriptorSetLayout {
     fn drop(&mut self) {
         unsafe {
@@ -35,6 +153,7 @@ impl Drop for DescriptorSetLayout {
 pub struct DescriptorPool {
     device: Arc<Device>,
     pub(crate) inner: vk::DescriptorPool,
+    flags: vk::DescriptorPoolCreateFlags,
 }
 
 impl DescriptorPool {
@@ -42,13 +161,19 @@ impl DescriptorPool {
         device: Arc<Device>,
         max_sets: u32,
         pool_sizes: &[vk::DescriptorPoolSize],
+        flags: vk::DescriptorPoolCreateFlags,
     ) -> Result<Self> {
         let pool_info = vk::DescriptorPoolCreateInfo::default()
+            .flags(flags)
             .max_sets(max_sets)
             .pool_sizes(pool_sizes);
         let inner = unsafe { device.inner.create_descriptor_pool(&pool_info, None)? };
 
-        Ok(Self { device, inner })
+        Ok(Self {
+            device,
+            inner,
+            flags,
+        })
     }
 
     pub fn allocate_sets(
@@ -70,6 +195,7 @@ impl DescriptorPool {
             .map(|inner| DescriptorSet {
                 device: self.device.clone(),
                 inner,
+                dynamic_descriptor_count: layout.dynamic_descriptor_count,
             })
             .collect::<Vec<_>>();
 
@@ -79,6 +205,70 @@ impl DescriptorPool {
     pub fn allocate_set(&self, layout: &DescriptorSetLayout) -> Result<DescriptorSet> {
         Ok(self.allocate_sets(layout, 1)?.into_iter().next().unwrap())
     }
+
+    /// Allocates a set whose last binding has the `VARIABLE_DESCRIPTOR_COUNT` flag,
+    /// with `descriptor_count` descriptors for this binding.
+    ///
+    /// `descriptor_count` must not exceed the count the binding was declared with.
+    pub fn allocate_set_with_variable_count(
+        &self,
+        layout: &DescriptorSetLayout,
+        descriptor_count: u32,
+    ) -> Result<DescriptorSet> {
+        let mut variable_count_info =
+            vk::DescriptorSetVariableDescriptorCountAllocateInfo::default()
+                .descriptor_counts(std::slice::from_ref(&descriptor_count));
+        let set_alloc_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(self.inner)
+            .set_layouts(std::slice::from_ref(&layout.inner))
+            .push_next(&mut variable_count_info);
+        let inner = unsafe {
+            self.device
+                .inner
+                .allocate_descriptor_sets(&set_alloc_info)?[0]
+        };
+
+        Ok(DescriptorSet {
+            device: self.device.clone(),
+            inner,
+            dynamic_descriptor_count: layout.dynamic_descriptor_count,
+        })
+    }
+
+    /// Returns `sets` to the pool so their descriptors can be allocated again.
+    ///
+    /// The pool must have been created with `FREE_DESCRIPTOR_SET`, the sets must have been
+    /// allocated from this pool and must not be used by pending command buffers.
+    pub fn free_sets(&self, sets: impl IntoIterator<Item = DescriptorSet>) -> Result<()> {
+        if !self
+            .flags
+            .contains(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+        {
+            return Err(anyhow::anyhow!(
+                "Descriptor pool was not created with the FREE_DESCRIPTOR_SET flag"
+            ));
+        }
+
+        let sets = sets.into_iter().map(|s| s.inner).collect::<Vec<_>>();
+        if !sets.is_empty() {
+            unsafe { self.device.inner.free_descriptor_sets(self.inner, &sets)? };
+        }
+
+        Ok(())
+    }
+
+    /// Returns all sets allocated from this pool to it, whatever the pool flags.
+    ///
+    /// Previously allocated sets become invalid and must not be used by pending command buffers.
+    pub fn reset(&self) -> Result<()> {
+        unsafe {
+            self.device
+                .inner
+                .reset_descriptor_pool(self.inner, vk::DescriptorPoolResetFlags::empty())?
+        };
+
+        Ok(())
+    }
 }
 
 impl Drop for DescriptorPool {
@@ -89,10 +279,54 @@ impl Drop for DescriptorPool {
 
 pub struct DescriptorSet {
     device: Arc<Device>,
-    pub(crate) inner: vk::DescriptorSet,
+    pub inner: vk::DescriptorSet,
+    pub(crate) dynamic_descriptor_count: u32,
 }
 
 impl DescriptorSet {
+    /// Writes a combined image sampler to `binding`.
+    pub fn bind_texture(
+        &self,
+        binding: u32,
+        view: &ImageView,
+        sampler: &Sampler,
+        layout: vk::ImageLayout,
+    ) {
+        self.update(&[WriteDescriptorSet {
+            binding,
+            kind: WriteDescriptorSetKind::CombinedImageSampler {
+                view,
+                sampler,
+                layout,
+            },
+        }]);
+    }
+
+    /// Writes the whole of `buffer` as a uniform buffer to `binding`.
+    pub fn bind_uniform_buffer(&self, binding: u32, buffer: &Buffer) {
+        self.update(&[WriteDescriptorSet {
+            binding,
+            kind: WriteDescriptorSetKind::UniformBuffer { buffer },
+        }]);
+    }
+
+    /// Writes the whole of `buffer` as a storage buffer to `binding`.
+    pub fn bind_storage_buffer(&self, binding: u32, buffer: &Buffer) {
+        self.update(&[WriteDescriptorSet {
+            binding,
+            kind: WriteDescriptorSetKind::StorageBuffer { buffer },
+        }]);
+    }
+
+    /// Writes a storage image to `binding`.
+    pub fn bind_storage_image(&self, binding: u32, view: &ImageView, layout: vk::ImageLayout) {
+        self.update(&[WriteDescriptorSet {
+            binding,
+            kind: WriteDescriptorSetKind::StorageImage { view, layout },
+        }]);
+    }
+
+    /// Applies several writes at once. Prefer the `bind_*` methods for a single binding.
     // TODO: see how to re-implement version from above (1 call to update_descriptor_sets)
     pub fn update(&self, writes: &[WriteDescriptorSet]) {
         use WriteDescriptorSetKind::*;
@@ -190,6 +424,33 @@ impl DescriptorSet {
 
                     unsafe { self.device.inner.update_descriptor_sets(&[wds], &[]) };
                 }
+                CombinedImageSamplerArray {
+                    views,
+                    sampler,
+                    layout,
+                } => {
+                    if views.is_empty() {
+                        continue;
+                    }
+
+                    let img_infos = views
+                        .iter()
+                        .map(|view| {
+                            vk::DescriptorImageInfo::default()
+                                .image_view(view.inner)
+                                .sampler(sampler.inner)
+                                .image_layout(layout)
+                        })
+                        .collect::<Vec<_>>();
+
+                    let wds = vk::WriteDescriptorSet::default()
+                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                        .dst_binding(write.binding)
+                        .dst_set(self.inner)
+                        .image_info(&img_infos);
+
+                    unsafe { self.device.inner.update_descriptor_sets(&[wds], &[]) };
+                }
             };
         }
     }
@@ -203,12 +464,47 @@ impl Context {
         DescriptorSetLayout::new(self.device.clone(), bindings)
     }
 
+    /// Creates a descriptor set layout with one `vk::DescriptorBindingFlags` per binding.
+    ///
+    /// Returns an error if a flag requires a descriptor indexing feature that is not enabled.
+    pub fn create_descriptor_set_layout_with_flags(
+        &self,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+        binding_flags: &[vk::DescriptorBindingFlags],
+        flags: vk::DescriptorSetLayoutCreateFlags,
+    ) -> Result<DescriptorSetLayout> {
+        check_binding_flags(&self.device, bindings, binding_flags, flags)?;
+
+        DescriptorSetLayout::new_with_flags(
+            self.device.clone(),
+            bindings,
+            Some(binding_flags),
+            flags,
+        )
+    }
+
     pub fn create_descriptor_pool(
         &self,
         max_sets: u32,
         pool_sizes: &[vk::DescriptorPoolSize],
     ) -> Result<DescriptorPool> {
-        DescriptorPool::new(self.device.clone(), max_sets, pool_sizes)
+        self.create_descriptor_pool_with_flags(
+            max_sets,
+            pool_sizes,
+            vk::DescriptorPoolCreateFlags::empty(),
+        )
+    }
+
+    /// Sets from layouts created with `UPDATE_AFTER_BIND_POOL` must be allocated from
+    /// a pool with the `UPDATE_AFTER_BIND` flag. Use `FREE_DESCRIPTOR_SET` to be able
+    /// to call [`DescriptorPool::free_sets`].
+    pub fn create_descriptor_pool_with_flags(
+        &self,
+        max_sets: u32,
+        pool_sizes: &[vk::DescriptorPoolSize],
+        flags: vk::DescriptorPoolCreateFlags,
+    ) -> Result<DescriptorPool> {
+        DescriptorPool::new(self.device.clone(), max_sets, pool_sizes, flags)
     }
 }
 
@@ -242,4 +538,10 @@ pub enum WriteDescriptorSetKind<'a> {
         sampler: &'a Sampler,
         layout: vk::ImageLayout,
     },
+    /// Writes `views` to the first elements of an array binding, all with the same sampler.
+    CombinedImageSamplerArray {
+        views: &'a [&'a ImageView],
+        sampler: &'a Sampler,
+        layout: vk::ImageLayout,
+    },
 }
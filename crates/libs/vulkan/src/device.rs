@@ -1,7 +1,7 @@
 use std::{ffi::CString, sync::Arc};
 
 use anyhow::Result;
-use ash::{vk, Device as AshDevice};
+use ash::{ext::debug_utils, vk, Device as AshDevice};
 
 use crate::{
     instance::Instance,
@@ -11,6 +11,16 @@ use crate::{
 
 pub struct Device {
     pub inner: AshDevice,
+    /// `None` when the instance was created without `VK_EXT_debug_utils`.
+    pub(crate) debug_utils: Option<debug_utils::Device>,
+    pub(crate) enabled_features: DeviceFeatures,
+    pub(crate) enabled_extensions: Vec<String>,
+    /// Alignment of the ranges of non coherent memory to flush or invalidate.
+    pub(crate) non_coherent_atom_size: vk::DeviceSize,
+    /// Maximum number of control points per tessellation patch.
+    pub(crate) max_tessellation_patch_size: u32,
+    /// Size in bytes of the push constants shared by all stages of a pipeline.
+    pub(crate) max_push_constants_size: u32,
 }
 
 impl Device {
@@ -26,6 +36,7 @@ impl Device {
 
         let queue_create_infos = {
             let mut indices = queue_families.iter().map(|f| f.index).collect::<Vec<_>>();
+            indices.sort_unstable();
             indices.dedup();
 
             indices
@@ -49,6 +60,11 @@ impl Device {
 
         let features = vk::PhysicalDeviceFeatures {
             independent_blend: device_features.independent_blend.into(),
+            fill_mode_non_solid: device_features.fill_mode_non_solid.into(),
+            wide_lines: device_features.wide_lines.into(),
+            sampler_anisotropy: device_features.sampler_anisotropy.into(),
+            geometry_shader: device_features.geometry_shader.into(),
+            tessellation_shader: device_features.tessellation_shader.into(),
             ..Default::default()
         };
         let mut ray_tracing_feature = vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default()
@@ -56,8 +72,22 @@ impl Device {
         let mut acceleration_struct_feature =
             vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default()
                 .acceleration_structure(device_features.acceleration_structure);
+        let mut vulkan_11_features = vk::PhysicalDeviceVulkan11Features::default()
+            .shader_draw_parameters(device_features.shader_draw_parameters);
+        let update_after_bind = device_features.descriptor_binding_update_after_bind;
         let mut vulkan_12_features = vk::PhysicalDeviceVulkan12Features::default()
             .runtime_descriptor_array(device_features.runtime_descriptor_array)
+            .descriptor_binding_partially_bound(device_features.descriptor_binding_partially_bound)
+            .descriptor_binding_variable_descriptor_count(
+                device_features.descriptor_binding_variable_descriptor_count,
+            )
+            .descriptor_binding_update_unused_while_pending(
+                device_features.descriptor_binding_update_unused_while_pending,
+            )
+            .descriptor_binding_sampled_image_update_after_bind(update_after_bind)
+            .descriptor_binding_storage_image_update_after_bind(update_after_bind)
+            .descriptor_binding_storage_buffer_update_after_bind(update_after_bind)
+            .timeline_semaphore(device_features.timeline_semaphore)
             .buffer_device_address(device_features.buffer_device_address);
         let mut vulkan_13_features = vk::PhysicalDeviceVulkan13Features::default()
             .dynamic_rendering(device_features.dynamic_rendering)
@@ -65,6 +95,7 @@ impl Device {
 
         let mut features = vk::PhysicalDeviceFeatures2::default()
             .features(features)
+            .push_next(&mut vulkan_11_features)
             .push_next(&mut vulkan_12_features)
             .push_next(&mut vulkan_13_features);
 
@@ -85,7 +116,23 @@ impl Device {
                 .create_device(physical_device.inner, &device_create_info, None)?
         };
 
-        Ok(Self { inner })
+        let debug_utils = instance
+            .is_debug_utils_enabled()
+            .then(|| debug_utils::Device::new(&instance.inner, &inner));
+
+        Ok(Self {
+            inner,
+            debug_utils,
+            enabled_features: *device_features,
+            enabled_extensions: required_extensions.iter().map(|e| e.to_string()).collect(),
+            non_coherent_atom_size: physical_device.limits.non_coherent_atom_size,
+            max_tessellation_patch_size: physical_device.limits.max_tessellation_patch_size,
+            max_push_constants_size: physical_device.limits.max_push_constants_size,
+        })
+    }
+
+    pub fn is_extension_enabled(&self, extension: &str) -> bool {
+        self.enabled_extensions.iter().any(|e| e == extension)
     }
 
     pub fn get_queue(self: &Arc<Self>, queue_family: QueueFamily, queue_index: u32) -> Queue {
@@ -111,6 +158,18 @@ pub struct DeviceFeatures {
     pub dynamic_rendering: bool,
     pub synchronization2: bool,
     pub independent_blend: bool,
+    pub fill_mode_non_solid: bool,
+    pub wide_lines: bool,
+    pub sampler_anisotropy: bool,
+    pub geometry_shader: bool,
+    pub tessellation_shader: bool,
+    pub shader_draw_parameters: bool,
+    pub descriptor_binding_partially_bound: bool,
+    pub descriptor_binding_variable_descriptor_count: bool,
+    pub descriptor_binding_update_unused_while_pending: bool,
+    /// Update after bind for sampled images, storage images and storage buffers.
+    pub descriptor_binding_update_after_bind: bool,
+    pub timeline_semaphore: bool,
 }
 
 impl DeviceFeatures {
@@ -122,5 +181,78 @@ impl DeviceFeatures {
             && (!requirements.dynamic_rendering || self.dynamic_rendering)
             && (!requirements.synchronization2 || self.synchronization2)
             && (!requirements.independent_blend || self.independent_blend)
+            && (!requirements.fill_mode_non_solid || self.fill_mode_non_solid)
+            && (!requirements.wide_lines || self.wide_lines)
+            && (!requirements.sampler_anisotropy || self.sampler_anisotropy)
+            && (!requirements.geometry_shader || self.geometry_shader)
+            && (!requirements.tessellation_shader || self.tessellation_shader)
+            && (!requirements.shader_draw_parameters || self.shader_draw_parameters)
+            && (!requirements.descriptor_binding_partially_bound
+                || self.descriptor_binding_partially_bound)
+            && (!requirements.descriptor_binding_variable_descriptor_count
+                || self.descriptor_binding_variable_descriptor_count)
+            && (!requirements.descriptor_binding_update_unused_while_pending
+                || self.descriptor_binding_update_unused_while_pending)
+            && (!requirements.descriptor_binding_update_after_bind
+                || self.descriptor_binding_update_after_bind)
+            && (!requirements.timeline_semaphore || self.timeline_semaphore)
+    }
+
+    /// Name and value of each feature.
+    fn named_flags(&self) -> [(&'static str, bool); 18] {
+        [
+            ("ray_tracing_pipeline", self.ray_tracing_pipeline),
+            ("acceleration_structure", self.acceleration_structure),
+            ("runtime_descriptor_array", self.runtime_descriptor_array),
+            ("buffer_device_address", self.buffer_device_address),
+            ("dynamic_rendering", self.dynamic_rendering),
+            ("synchronization2", self.synchronization2),
+            ("independent_blend", self.independent_blend),
+            ("fill_mode_non_solid", self.fill_mode_non_solid),
+            ("wide_lines", self.wide_lines),
+            ("sampler_anisotropy", self.sampler_anisotropy),
+            ("geometry_shader", self.geometry_shader),
+            ("tessellation_shader", self.tessellation_shader),
+            ("shader_draw_parameters", self.shader_draw_parameters),
+            (
+                "descriptor_binding_partially_bound",
+                self.descriptor_binding_partially_bound,
+            ),
+            (
+                "descriptor_binding_variable_descriptor_count",
+                self.descriptor_binding_variable_descriptor_count,
+            ),
+            (
+                "descriptor_binding_update_unused_while_pending",
+                self.descriptor_binding_update_unused_while_pending,
+            ),
+            (
+                "descriptor_binding_update_after_bind",
+                self.descriptor_binding_update_after_bind,
+            ),
+            ("timeline_semaphore", self.timeline_semaphore),
+        ]
+    }
+
+    /// Logs a table of the requested features against the ones supported by the device.
+    ///
+    /// Requested features are the ones enabled on the device.
+    pub(crate) fn log_report(&self, supported: &Self) {
+        if !log::log_enabled!(log::Level::Debug) {
+            return;
+        }
+
+        let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+        let mut report = format!("{:<48} {:<10} supported", "Device feature", "enabled");
+        for ((name, enabled), (_, supported)) in
+            self.named_flags().into_iter().zip(supported.named_flags())
+        {
+            report.push_str(&format!(
+                "\n{name:<48} {:<10} {}",
+                yes_no(enabled),
+                yes_no(supported)
+            ));
+        }
+        log::debug!("{report}");
     }
 }
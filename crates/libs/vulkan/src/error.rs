@@ -0,0 +1,58 @@
+use std::{fmt, time::Duration};
+
+use anyhow::Result;
+use ash::{prelude::VkResult, vk};
+
+/// Error returned when the device is lost (`VK_ERROR_DEVICE_LOST`).
+///
+/// This usually happens when the driver resets the GPU because a submission took too long
+/// (TDR). The device and all the resources created from it can't be used anymore.
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceLost {
+    /// The operation that reported the loss.
+    pub operation: &'static str,
+}
+
+impl fmt::Display for DeviceLost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Device lost during {}", self.operation)
+    }
+}
+
+impl std::error::Error for DeviceLost {}
+
+/// Error returned when waiting for the GPU took longer than the allowed timeout.
+///
+/// The GPU is probably stuck, for example on an infinite loop in a shader.
+#[derive(Debug, Clone, Copy)]
+pub struct GpuTimeout {
+    /// The operation that timed out.
+    pub operation: &'static str,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for GpuTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "GPU did not complete {} within {:?}",
+            self.operation, self.timeout
+        )
+    }
+}
+
+impl std::error::Error for GpuTimeout {}
+
+pub(crate) trait DeviceLostExt<T> {
+    /// Maps `ERROR_DEVICE_LOST` to [`DeviceLost`]. Other errors are kept as is.
+    fn or_device_lost(self, operation: &'static str) -> Result<T>;
+}
+
+impl<T> DeviceLostExt<T> for VkResult<T> {
+    fn or_device_lost(self, operation: &'static str) -> Result<T> {
+        self.map_err(|err| match err {
+            vk::Result::ERROR_DEVICE_LOST => DeviceLost { operation }.into(),
+            err => err.into(),
+        })
+    }
+}
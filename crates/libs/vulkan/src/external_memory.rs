@@ -0,0 +1,122 @@
+use anyhow::Result;
+use ash::vk;
+
+use crate::{Buffer, Context};
+
+/// Device extension required to export memory on the current platform.
+/// `VK_KHR_external_memory` itself is core since Vulkan 1.1.
+#[cfg(unix)]
+pub const EXTERNAL_MEMORY_EXTENSION: &str = "VK_KHR_external_memory_fd";
+#[cfg(windows)]
+pub const EXTERNAL_MEMORY_EXTENSION: &str = "VK_KHR_external_memory_win32";
+
+/// Opaque file descriptor on Unix and opaque NT handle on Windows.
+#[cfg(unix)]
+pub const EXTERNAL_MEMORY_HANDLE_TYPE: vk::ExternalMemoryHandleTypeFlags =
+    vk::ExternalMemoryHandleTypeFlags::OPAQUE_FD;
+#[cfg(windows)]
+pub const EXTERNAL_MEMORY_HANDLE_TYPE: vk::ExternalMemoryHandleTypeFlags =
+    vk::ExternalMemoryHandleTypeFlags::OPAQUE_WIN32;
+
+#[cfg(unix)]
+pub type ExternalMemoryHandle = std::os::fd::RawFd;
+#[cfg(windows)]
+pub type ExternalMemoryHandle = vk::HANDLE;
+
+impl Context {
+    /// Creates a device local buffer whose memory can be exported with [`Context::export_buffer_memory`].
+    ///
+    /// The device must have been created with [`EXTERNAL_MEMORY_EXTENSION`].
+    pub fn create_exportable_buffer(
+        &self,
+        usage: vk::BufferUsageFlags,
+        size: vk::DeviceSize,
+    ) -> Result<Buffer> {
+        self.check_external_memory_extension()?;
+
+        let external_buffer_info = vk::PhysicalDeviceExternalBufferInfo::default()
+            .usage(usage)
+            .handle_type(EXTERNAL_MEMORY_HANDLE_TYPE);
+        let mut external_buffer_props = vk::ExternalBufferProperties::default();
+        unsafe {
+            self.instance
+                .inner
+                .get_physical_device_external_buffer_properties(
+                    self.physical_device.inner,
+                    &external_buffer_info,
+                    &mut external_buffer_props,
+                )
+        };
+        if !external_buffer_props
+            .external_memory_properties
+            .external_memory_features
+            .contains(vk::ExternalMemoryFeatureFlags::EXPORTABLE)
+        {
+            return Err(anyhow::anyhow!(
+                "Buffers with usage {usage:?} cannot be exported as {EXTERNAL_MEMORY_HANDLE_TYPE:?}"
+            ));
+        }
+
+        let memory_properties = unsafe {
+            self.instance
+                .inner
+                .get_physical_device_memory_properties(self.physical_device.inner)
+        };
+
+        Buffer::new_exportable(
+            self.device.clone(),
+            self.allocator.clone(),
+            &memory_properties,
+            usage,
+            size,
+            EXTERNAL_MEMORY_HANDLE_TYPE,
+        )
+    }
+
+    /// Exports the memory of a buffer created with [`Context::create_exportable_buffer`].
+    ///
+    /// Each call returns a new handle owned by the caller. On Unix the fd must be closed
+    /// unless it is imported, which transfers its ownership. On Windows the handle must
+    /// always be closed with `CloseHandle`.
+    pub fn export_buffer_memory(&self, buffer: &Buffer) -> Result<ExternalMemoryHandle> {
+        self.check_external_memory_extension()?;
+
+        let memory = buffer
+            .dedicated_memory
+            .ok_or_else(|| anyhow::anyhow!("Buffer was not created as exportable"))?;
+
+        #[cfg(unix)]
+        let handle = {
+            let get_info = vk::MemoryGetFdInfoKHR::default()
+                .memory(memory)
+                .handle_type(EXTERNAL_MEMORY_HANDLE_TYPE);
+            let external_memory_fd =
+                ash::khr::external_memory_fd::Device::new(&self.instance.inner, &self.device.inner);
+            unsafe { external_memory_fd.get_memory_fd(&get_info)? }
+        };
+
+        #[cfg(windows)]
+        let handle = {
+            let get_info = vk::MemoryGetWin32HandleInfoKHR::default()
+                .memory(memory)
+                .handle_type(EXTERNAL_MEMORY_HANDLE_TYPE);
+            let external_memory_win32 = ash::khr::external_memory_win32::Device::new(
+                &self.instance.inner,
+                &self.device.inner,
+            );
+            unsafe { external_memory_win32.get_memory_win32_handle(&get_info)? }
+        };
+
+        Ok(handle)
+    }
+
+    fn check_external_memory_extension(&self) -> Result<()> {
+        if !self.device.is_extension_enabled(EXTERNAL_MEMORY_EXTENSION) {
+            return Err(anyhow::anyhow!(
+                "{EXTERNAL_MEMORY_EXTENSION} must be enabled to export memory"
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -7,7 +7,7 @@ use gpu_allocator::{
     MemoryLocation,
 };
 
-use crate::{device::Device, Context};
+use crate::{device::Device, CommandBuffer, Context, Sampler};
 
 pub struct Image {
     device: Arc<Device>,
@@ -16,6 +16,8 @@ pub struct Image {
     allocation: Option<Allocation>,
     pub format: vk::Format,
     pub extent: vk::Extent3D,
+    pub mip_levels: u32,
+    pub array_layers: u32,
     is_swapchain: bool, // if set, image should not be destroyed
 }
 
@@ -24,41 +26,28 @@ pub struct ImageView {
     pub(crate) inner: vk::ImageView,
 }
 
+/// Depth image usable both as a depth attachment and as a sampled image.
+pub struct DepthTexture {
+    pub image: Image,
+    pub view: ImageView,
+    pub sampler: Sampler,
+}
+
 impl Image {
-    pub(crate) fn new_2d(
+    pub(crate) fn new(
         device: Arc<Device>,
         allocator: Arc<Mutex<Allocator>>,
-        usage: vk::ImageUsageFlags,
+        image_info: &vk::ImageCreateInfo,
         memory_location: MemoryLocation,
-        format: vk::Format,
-        width: u32,
-        height: u32,
     ) -> Result<Self> {
-        let extent = vk::Extent3D {
-            width,
-            height,
-            depth: 1,
-        };
-
-        let image_info = vk::ImageCreateInfo::default()
-            .image_type(vk::ImageType::TYPE_2D)
-            .format(format)
-            .extent(extent)
-            .mip_levels(1)
-            .array_layers(1)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .tiling(vk::ImageTiling::OPTIMAL)
-            .usage(usage)
-            .initial_layout(vk::ImageLayout::UNDEFINED);
-
-        let inner = unsafe { device.inner.create_image(&image_info, None)? };
+        let inner = unsafe { device.inner.create_image(image_info, None)? };
         let requirements = unsafe { device.inner.get_image_memory_requirements(inner) };
 
         let allocation = allocator.lock().unwrap().allocate(&AllocationCreateDesc {
             name: "image",
             requirements,
             location: memory_location,
-            linear: true,
+            linear: image_info.tiling == vk::ImageTiling::LINEAR,
             allocation_scheme: AllocationScheme::GpuAllocatorManaged,
         })?;
 
@@ -73,8 +62,10 @@ impl Image {
             allocator,
             inner,
             allocation: Some(allocation),
-            format,
-            extent,
+            format: image_info.format,
+            extent: image_info.extent,
+            mip_levels: image_info.mip_levels,
+            array_layers: image_info.array_layers,
             is_swapchain: false,
         })
     }
@@ -99,21 +90,69 @@ impl Image {
             allocation: None,
             format,
             extent,
+            mip_levels: 1,
+            array_layers: 1,
             is_swapchain: true,
         }
     }
 
     pub fn create_image_view(&self, aspect_mask: vk::ImageAspectFlags) -> Result<ImageView> {
+        self.create_view(
+            vk::ImageViewType::TYPE_2D,
+            aspect_mask,
+            0,
+            1,
+            self.mip_levels,
+        )
+    }
+
+    /// Creates a 2D view of the first mip level of one array layer (or cube face),
+    /// usable as a rendering attachment.
+    pub fn create_layer_image_view(
+        &self,
+        aspect_mask: vk::ImageAspectFlags,
+        array_layer: u32,
+    ) -> Result<ImageView> {
+        if array_layer >= self.array_layers {
+            return Err(anyhow::anyhow!(
+                "Cannot create a view of layer {array_layer} of an image with {} layers",
+                self.array_layers
+            ));
+        }
+
+        self.create_view(vk::ImageViewType::TYPE_2D, aspect_mask, array_layer, 1, 1)
+    }
+
+    /// Creates a cube view of the six layers of an image created with [`Context::create_cube_image`].
+    pub fn create_cube_image_view(&self, aspect_mask: vk::ImageAspectFlags) -> Result<ImageView> {
+        if self.array_layers < 6 {
+            return Err(anyhow::anyhow!(
+                "Cannot create a cube view of an image with {} layers",
+                self.array_layers
+            ));
+        }
+
+        self.create_view(vk::ImageViewType::CUBE, aspect_mask, 0, 6, self.mip_levels)
+    }
+
+    fn create_view(
+        &self,
+        view_type: vk::ImageViewType,
+        aspect_mask: vk::ImageAspectFlags,
+        base_array_layer: u32,
+        layer_count: u32,
+        level_count: u32,
+    ) -> Result<ImageView> {
         let view_info = vk::ImageViewCreateInfo::default()
             .image(self.inner)
-            .view_type(vk::ImageViewType::TYPE_2D)
+            .view_type(view_type)
             .format(self.format)
             .subresource_range(vk::ImageSubresourceRange {
                 aspect_mask,
                 base_mip_level: 0,
-                level_count: 1,
-                base_array_layer: 0,
-                layer_count: 1,
+                level_count,
+                base_array_layer,
+                layer_count,
             });
 
         let inner = unsafe { self.device.inner.create_image_view(&view_info, None)? };
@@ -132,6 +171,161 @@ impl Image {
             height: self.extent.height,
         }
     }
+
+    pub(crate) fn aspect_mask(&self) -> vk::ImageAspectFlags {
+        match self.format {
+            vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => {
+                vk::ImageAspectFlags::DEPTH
+            }
+            vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D32_SFLOAT_S8_UINT => {
+                vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+            }
+            vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+            _ => vk::ImageAspectFlags::COLOR,
+        }
+    }
+
+    /// Records the commands generating all mip levels of the image from level 0.
+    ///
+    /// All levels must be in TRANSFER_DST_OPTIMAL, level 0 containing the source data.
+    /// The image needs the TRANSFER_SRC and TRANSFER_DST usages. Fails without recording
+    /// anything if its format does not support linear filtering for blits. The whole image is
+    /// left in SHADER_READ_ONLY_OPTIMAL.
+    pub fn generate_mipmaps(&self, context: &Context, cmd: &CommandBuffer) -> Result<()> {
+        if !context
+            .optimal_tiling_format_features(self.format)
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+        {
+            return Err(anyhow::anyhow!(
+                "Format {:?} does not support linear filtering, mipmaps cannot be blitted",
+                self.format
+            ));
+        }
+
+        let aspect_mask = self.aspect_mask();
+        let level_barrier =
+            |level: u32,
+             old_layout: vk::ImageLayout,
+             new_layout: vk::ImageLayout,
+             src: (vk::PipelineStageFlags2, vk::AccessFlags2),
+             dst: (vk::PipelineStageFlags2, vk::AccessFlags2)| {
+                vk::ImageMemoryBarrier2::default()
+                    .src_stage_mask(src.0)
+                    .src_access_mask(src.1)
+                    .old_layout(old_layout)
+                    .dst_stage_mask(dst.0)
+                    .dst_access_mask(dst.1)
+                    .new_layout(new_layout)
+                    .image(self.inner)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask,
+                        base_mip_level: level,
+                        level_count: 1,
+                        base_array_layer: 0,
+                        layer_count: self.array_layers,
+                    })
+            };
+        let transfer_write = (
+            vk::PipelineStageFlags2::TRANSFER,
+            vk::AccessFlags2::TRANSFER_WRITE,
+        );
+        let transfer_read = (
+            vk::PipelineStageFlags2::TRANSFER,
+            vk::AccessFlags2::TRANSFER_READ,
+        );
+        let shader_read = (
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+            vk::AccessFlags2::SHADER_READ,
+        );
+        let barrier = |barrier: vk::ImageMemoryBarrier2| {
+            let dependency_info =
+                vk::DependencyInfo::default().image_memory_barriers(std::slice::from_ref(&barrier));
+            unsafe {
+                self.device
+                    .inner
+                    .cmd_pipeline_barrier2(cmd.inner, &dependency_info)
+            };
+        };
+
+        let mut width = self.extent.width as i32;
+        let mut height = self.extent.height as i32;
+
+        for level in 1..self.mip_levels {
+            // previous level was written by the upload or the previous blit
+            barrier(level_barrier(
+                level - 1,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                transfer_write,
+                transfer_read,
+            ));
+
+            let next_width = (width / 2).max(1);
+            let next_height = (height / 2).max(1);
+
+            let region = vk::ImageBlit2::default()
+                .src_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask,
+                    mip_level: level - 1,
+                    base_array_layer: 0,
+                    layer_count: self.array_layers,
+                })
+                .src_offsets([
+                    vk::Offset3D::default(),
+                    vk::Offset3D {
+                        x: width,
+                        y: height,
+                        z: 1,
+                    },
+                ])
+                .dst_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask,
+                    mip_level: level,
+                    base_array_layer: 0,
+                    layer_count: self.array_layers,
+                })
+                .dst_offsets([
+                    vk::Offset3D::default(),
+                    vk::Offset3D {
+                        x: next_width,
+                        y: next_height,
+                        z: 1,
+                    },
+                ]);
+            let blit_info = vk::BlitImageInfo2::default()
+                .src_image(self.inner)
+                .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .dst_image(self.inner)
+                .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .regions(std::slice::from_ref(&region))
+                .filter(vk::Filter::LINEAR);
+            unsafe { self.device.inner.cmd_blit_image2(cmd.inner, &blit_info) };
+
+            barrier(level_barrier(
+                level - 1,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                transfer_read,
+                shader_read,
+            ));
+
+            width = next_width;
+            height = next_height;
+        }
+
+        // last level was only written to
+        barrier(level_barrier(
+            self.mip_levels - 1,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            transfer_write,
+            shader_read,
+        ));
+
+        Ok(())
+    }
 }
 
 impl Context {
@@ -142,19 +336,189 @@ impl Context {
         format: vk::Format,
         width: u32,
         height: u32,
+        mip_levels: u32,
     ) -> Result<Image> {
-        Image::new_2d(
+        Image::new(
             self.device.clone(),
             self.allocator.clone(),
+            &image_2d_info(
+                usage,
+                format,
+                width,
+                height,
+                mip_levels,
+                vk::SampleCountFlags::TYPE_1,
+            ),
+            memory_location,
+        )
+    }
+
+    /// Creates an image from a complete `vk::ImageCreateInfo` for cases [`Context::create_image`]
+    /// can't express (mip levels, array layers, 3D images, cube compatible images, ...).
+    ///
+    /// `memory_location` must be compatible with `tiling` and `usage`. Images with
+    /// `vk::ImageTiling::OPTIMAL` are only meant to be accessed by the device so they should use
+    /// `MemoryLocation::GpuOnly`. Host visible locations (`CpuToGpu`, `GpuToCpu`) only make
+    /// sense with `vk::ImageTiling::LINEAR`, which most implementations only support for simple
+    /// 2D images with transfer usages.
+    pub fn create_image_with_info(
+        &self,
+        image_info: &vk::ImageCreateInfo,
+        memory_location: MemoryLocation,
+    ) -> Result<Image> {
+        Image::new(
+            self.device.clone(),
+            self.allocator.clone(),
+            image_info,
+            memory_location,
+        )
+    }
+
+    /// Creates a cube compatible image with six square layers, one per face, in the
+    /// +X, -X, +Y, -Y, +Z, -Z order.
+    pub fn create_cube_image(
+        &self,
+        usage: vk::ImageUsageFlags,
+        memory_location: MemoryLocation,
+        format: vk::Format,
+        size: u32,
+        mip_levels: u32,
+    ) -> Result<Image> {
+        let max_layers = self.physical_device_limits().max_image_array_layers;
+        if max_layers < 6 {
+            return Err(anyhow::anyhow!(
+                "Cube images need 6 array layers but the device supports only {max_layers}"
+            ));
+        }
+
+        let image_info = image_2d_info(
             usage,
+            format,
+            size,
+            size,
+            mip_levels,
+            vk::SampleCountFlags::TYPE_1,
+        )
+        .array_layers(6)
+        .flags(vk::ImageCreateFlags::CUBE_COMPATIBLE);
+
+        self.create_image_with_info(&image_info, memory_location)
+    }
+
+    /// Creates a single layer 2D image with `samples` samples per texel.
+    ///
+    /// Returns an error if `samples` is not supported by the framebuffer color or depth
+    /// attachments the image is used as. Color and depth attachments used in the same
+    /// rendering pass must have the same sample count.
+    pub fn create_multisampled_image(
+        &self,
+        usage: vk::ImageUsageFlags,
+        memory_location: MemoryLocation,
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        samples: vk::SampleCountFlags,
+    ) -> Result<Image> {
+        let limits = self.physical_device_limits();
+        if usage.contains(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            && !limits.framebuffer_color_sample_counts.contains(samples)
+        {
+            return Err(anyhow::anyhow!(
+                "Sample count {samples:?} is not supported for color attachments"
+            ));
+        }
+        if usage.contains(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
+            && !limits.framebuffer_depth_sample_counts.contains(samples)
+        {
+            return Err(anyhow::anyhow!(
+                "Sample count {samples:?} is not supported for depth attachments"
+            ));
+        }
+
+        Image::new(
+            self.device.clone(),
+            self.allocator.clone(),
+            &image_2d_info(usage, format, width, height, 1, samples),
             memory_location,
+        )
+    }
+
+    /// Creates a depth image that can be used as a depth attachment and sampled from shaders.
+    ///
+    /// When `compare_op` is set, the sampler is a comparison sampler (for `sampler2DShadow`).
+    pub fn create_depth_texture(
+        &self,
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        compare_op: Option<vk::CompareOp>,
+    ) -> Result<DepthTexture> {
+        let required_features = vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE;
+        if !self
+            .optimal_tiling_format_features(format)
+            .contains(required_features)
+        {
+            return Err(anyhow::anyhow!(
+                "Format {format:?} cannot be used as a sampled depth attachment"
+            ));
+        }
+
+        let image = self.create_image(
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            MemoryLocation::GpuOnly,
             format,
             width,
             height,
-        )
+            1,
+        )?;
+
+        // sampled views can only have one aspect, even for depth/stencil formats
+        let view = image.create_image_view(vk::ImageAspectFlags::DEPTH)?;
+
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .compare_enable(compare_op.is_some())
+            .compare_op(compare_op.unwrap_or(vk::CompareOp::ALWAYS));
+        let sampler = self.create_sampler(&sampler_info)?;
+
+        Ok(DepthTexture {
+            image,
+            view,
+            sampler,
+        })
     }
 }
 
+fn image_2d_info(
+    usage: vk::ImageUsageFlags,
+    format: vk::Format,
+    width: u32,
+    height: u32,
+    mip_levels: u32,
+    samples: vk::SampleCountFlags,
+) -> vk::ImageCreateInfo<'static> {
+    vk::ImageCreateInfo::default()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(format)
+        .extent(vk::Extent3D {
+            width,
+            height,
+            depth: 1,
+        })
+        .mip_levels(mip_levels)
+        .array_layers(1)
+        .samples(samples)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(usage)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+}
+
 impl Drop for Image {
     fn drop(&mut self) {
         if self.is_swapchain {
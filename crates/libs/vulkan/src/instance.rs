@@ -8,15 +8,15 @@ use crate::{physical_device::PhysicalDevice, surface::Surface, Version};
 
 pub struct Instance {
     pub(crate) inner: AshInstance,
-    debug_utils: debug_utils::Instance,
-    debug_utils_messenger: vk::DebugUtilsMessengerEXT,
+    /// `None` when the loader does not expose `VK_EXT_debug_utils`.
+    debug_utils: Option<(debug_utils::Instance, vk::DebugUtilsMessengerEXT)>,
     physical_devices: Vec<PhysicalDevice>,
 }
 
 impl Instance {
     pub(crate) fn new(
         entry: &Entry,
-        display_handle: &dyn HasDisplayHandle,
+        display_handle: Option<&dyn HasDisplayHandle>,
         api_version: Version,
         app_name: &str,
         required_extensions: &[&str],
@@ -29,10 +29,19 @@ impl Instance {
             .api_version(api_version.make_api_version());
 
         // Extension support
-        let mut extension_names =
-            ash_window::enumerate_required_extensions(display_handle.display_handle()?.as_raw())?
-                .to_vec();
-        extension_names.push(debug_utils::NAME.as_ptr());
+        let mut extension_names = match display_handle {
+            Some(display_handle) => ash_window::enumerate_required_extensions(
+                display_handle.display_handle()?.as_raw(),
+            )?
+            .to_vec(),
+            None => vec![],
+        };
+        let debug_utils_supported = is_extension_supported(entry, debug_utils::NAME)?;
+        if debug_utils_supported {
+            extension_names.push(debug_utils::NAME.as_ptr());
+        } else {
+            log::warn!("VK_EXT_debug_utils is not supported, debug names and labels are disabled");
+        }
 
         let required_extensions_c = required_extensions
             .iter()
@@ -51,34 +60,26 @@ impl Instance {
         let inner = unsafe { entry.create_instance(&instance_create_info, None)? };
 
         // Vulkan debug report
-        let create_info = vk::DebugUtilsMessengerCreateInfoEXT::default()
-            .flags(vk::DebugUtilsMessengerCreateFlagsEXT::empty())
-            .message_severity(
-                vk::DebugUtilsMessageSeverityFlagsEXT::INFO
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
-            )
-            .message_type(
-                vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
-                    | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
-                    | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
-            )
-            .pfn_user_callback(Some(vulkan_debug_callback));
-        let debug_utils = debug_utils::Instance::new(entry, &inner);
-        let debug_utils_messenger =
-            unsafe { debug_utils.create_debug_utils_messenger(&create_info, None)? };
+        let debug_utils = if debug_utils_supported {
+            Some(create_debug_utils_messenger(entry, &inner)?)
+        } else {
+            None
+        };
 
         Ok(Self {
             inner,
             debug_utils,
-            debug_utils_messenger,
             physical_devices: vec![],
         })
     }
 
+    pub(crate) fn is_debug_utils_enabled(&self) -> bool {
+        self.debug_utils.is_some()
+    }
+
     pub(crate) fn enumerate_physical_devices(
         &mut self,
-        surface: &Surface,
+        surface: Option<&Surface>,
     ) -> Result<&[PhysicalDevice]> {
         if self.physical_devices.is_empty() {
             let physical_devices = unsafe { self.inner.enumerate_physical_devices()? };
@@ -101,6 +102,37 @@ impl Instance {
     }
 }
 
+fn create_debug_utils_messenger(
+    entry: &Entry,
+    instance: &AshInstance,
+) -> Result<(debug_utils::Instance, vk::DebugUtilsMessengerEXT)> {
+    let create_info = vk::DebugUtilsMessengerCreateInfoEXT::default()
+        .flags(vk::DebugUtilsMessengerCreateFlagsEXT::empty())
+        .message_severity(
+            vk::DebugUtilsMessageSeverityFlagsEXT::INFO
+                | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+        )
+        .message_type(
+            vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+        )
+        .pfn_user_callback(Some(vulkan_debug_callback));
+    let debug_utils = debug_utils::Instance::new(entry, instance);
+    let messenger = unsafe { debug_utils.create_debug_utils_messenger(&create_info, None)? };
+
+    Ok((debug_utils, messenger))
+}
+
+fn is_extension_supported(entry: &Entry, name: &CStr) -> Result<bool> {
+    let supported = unsafe { entry.enumerate_instance_extension_properties(None)? };
+
+    Ok(supported
+        .iter()
+        .any(|s| unsafe { CStr::from_ptr(s.extension_name.as_ptr()) } == name))
+}
+
 fn check_extensions_support(entry: &Entry, required: &[*const i8]) -> Result<()> {
     let supported = unsafe { entry.enumerate_instance_extension_properties(None)? };
 
@@ -139,8 +171,9 @@ unsafe extern "system" fn vulkan_debug_callback(
 impl Drop for Instance {
     fn drop(&mut self) {
         unsafe {
-            self.debug_utils
-                .destroy_debug_utils_messenger(self.debug_utils_messenger, None);
+            if let Some((debug_utils, messenger)) = &self.debug_utils {
+                debug_utils.destroy_debug_utils_messenger(*messenger, None);
+            }
             self.inner.destroy_instance(None);
         }
     }
@@ -5,8 +5,11 @@ pub extern crate gpu_allocator;
 mod buffer;
 mod command;
 mod context;
+mod debug;
 mod descriptor;
 mod device;
+mod error;
+mod external_memory;
 mod image;
 mod instance;
 mod physical_device;
@@ -18,15 +21,20 @@ mod sampler;
 mod surface;
 mod swapchain;
 mod sync;
+mod uploader;
 
 pub mod utils;
 
 pub use buffer::*;
 pub use command::*;
 pub use context::*;
+pub use debug::*;
 pub use descriptor::*;
 pub use device::*;
+pub use error::*;
+pub use external_memory::*;
 pub use image::*;
+pub use physical_device::*;
 pub use pipeline::*;
 pub use query::*;
 pub use queue::*;
@@ -34,6 +42,7 @@ pub use ray_tracing::*;
 pub use sampler::*;
 pub use swapchain::*;
 pub use sync::*;
+pub use uploader::*;
 
 pub const VERSION_1_0: Version = Version::from_major_minor(1, 0);
 pub const VERSION_1_1: Version = Version::from_major_minor(1, 1);
@@ -11,6 +11,8 @@ pub struct PhysicalDevice {
     pub(crate) name: String,
     pub(crate) device_type: vk::PhysicalDeviceType,
     pub(crate) limits: vk::PhysicalDeviceLimits,
+    pub(crate) memory_heaps: Vec<vk::MemoryHeap>,
+    pub(crate) subgroup_properties: SubgroupProperties,
     pub(crate) queue_families: Vec<QueueFamily>,
     pub(crate) supported_extensions: Vec<String>,
     pub(crate) supported_surface_formats: Vec<vk::SurfaceFormatKHR>,
@@ -18,13 +20,30 @@ pub struct PhysicalDevice {
     pub(crate) supported_device_features: DeviceFeatures,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct SubgroupProperties {
+    pub size: u32,
+    pub supported_stages: vk::ShaderStageFlags,
+    pub supported_operations: vk::SubgroupFeatureFlags,
+    pub quad_operations_in_all_stages: bool,
+}
+
 impl PhysicalDevice {
     pub(crate) fn new(
         instance: &Instance,
-        surface: &Surface,
+        surface: Option<&Surface>,
         inner: vk::PhysicalDevice,
     ) -> Result<Self> {
-        let props = unsafe { instance.get_physical_device_properties(inner) };
+        let mut subgroup_props = vk::PhysicalDeviceSubgroupProperties::default();
+        let mut props2 = vk::PhysicalDeviceProperties2::default().push_next(&mut subgroup_props);
+        unsafe { instance.get_physical_device_properties2(inner, &mut props2) };
+        let props = props2.properties;
+        let subgroup_properties = SubgroupProperties {
+            size: subgroup_props.subgroup_size,
+            supported_stages: subgroup_props.supported_stages,
+            supported_operations: subgroup_props.supported_operations,
+            quad_operations_in_all_stages: subgroup_props.quad_operations_in_all_stages == vk::TRUE,
+        };
 
         let name = unsafe {
             CStr::from_ptr(props.device_name.as_ptr())
@@ -36,18 +55,24 @@ impl PhysicalDevice {
         let device_type = props.device_type;
         let limits = props.limits;
 
+        let memory_properties = unsafe { instance.get_physical_device_memory_properties(inner) };
+        let memory_heaps = memory_properties.memory_heaps_as_slice().to_vec();
+
         let queue_family_properties =
             unsafe { instance.get_physical_device_queue_family_properties(inner) };
         let queue_families = queue_family_properties
             .into_iter()
             .enumerate()
             .map(|(index, p)| {
-                let present_support = unsafe {
-                    surface.inner.get_physical_device_surface_support(
-                        inner,
-                        index as _,
-                        surface.surface_khr,
-                    )?
+                let present_support = match surface {
+                    Some(surface) => unsafe {
+                        surface.inner.get_physical_device_surface_support(
+                            inner,
+                            index as _,
+                            surface.surface_khr,
+                        )?
+                    },
+                    None => false,
                 };
 
                 Ok(QueueFamily::new(index as _, p, present_support))
@@ -64,21 +89,24 @@ impl PhysicalDevice {
             })
             .collect();
 
-        let supported_surface_formats = unsafe {
-            surface
-                .inner
-                .get_physical_device_surface_formats(inner, surface.surface_khr)?
-        };
-
-        let supported_present_modes = unsafe {
-            surface
-                .inner
-                .get_physical_device_surface_present_modes(inner, surface.surface_khr)?
+        let (supported_surface_formats, supported_present_modes) = match surface {
+            Some(surface) => unsafe {
+                (
+                    surface
+                        .inner
+                        .get_physical_device_surface_formats(inner, surface.surface_khr)?,
+                    surface
+                        .inner
+                        .get_physical_device_surface_present_modes(inner, surface.surface_khr)?,
+                )
+            },
+            None => (vec![], vec![]),
         };
 
         let mut ray_tracing_feature = vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default();
         let mut acceleration_struct_feature =
             vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default();
+        let mut features11 = vk::PhysicalDeviceVulkan11Features::default();
         let mut features12 = vk::PhysicalDeviceVulkan12Features::default()
             .runtime_descriptor_array(true)
             .buffer_device_address(true);
@@ -86,18 +114,39 @@ impl PhysicalDevice {
         let mut features = vk::PhysicalDeviceFeatures2::default()
             .push_next(&mut ray_tracing_feature)
             .push_next(&mut acceleration_struct_feature)
+            .push_next(&mut features11)
             .push_next(&mut features12)
             .push_next(&mut features13);
         unsafe { instance.get_physical_device_features2(inner, &mut features) };
 
         let supported_device_features = DeviceFeatures {
             independent_blend: features.features.independent_blend == vk::TRUE,
+            fill_mode_non_solid: features.features.fill_mode_non_solid == vk::TRUE,
+            wide_lines: features.features.wide_lines == vk::TRUE,
+            sampler_anisotropy: features.features.sampler_anisotropy == vk::TRUE,
+            geometry_shader: features.features.geometry_shader == vk::TRUE,
+            tessellation_shader: features.features.tessellation_shader == vk::TRUE,
+            shader_draw_parameters: features11.shader_draw_parameters == vk::TRUE,
+            descriptor_binding_partially_bound: features12.descriptor_binding_partially_bound
+                == vk::TRUE,
+            descriptor_binding_variable_descriptor_count: features12
+                .descriptor_binding_variable_descriptor_count
+                == vk::TRUE,
+            descriptor_binding_update_unused_while_pending: features12
+                .descriptor_binding_update_unused_while_pending
+                == vk::TRUE,
+            descriptor_binding_update_after_bind: features12
+                .descriptor_binding_sampled_image_update_after_bind
+                == vk::TRUE
+                && features12.descriptor_binding_storage_image_update_after_bind == vk::TRUE
+                && features12.descriptor_binding_storage_buffer_update_after_bind == vk::TRUE,
             ray_tracing_pipeline: ray_tracing_feature.ray_tracing_pipeline == vk::TRUE,
             acceleration_structure: acceleration_struct_feature.acceleration_structure == vk::TRUE,
             runtime_descriptor_array: features12.runtime_descriptor_array == vk::TRUE,
             buffer_device_address: features12.buffer_device_address == vk::TRUE,
             dynamic_rendering: features13.dynamic_rendering == vk::TRUE,
             synchronization2: features13.synchronization2 == vk::TRUE,
+            timeline_semaphore: features12.timeline_semaphore == vk::TRUE,
         };
 
         Ok(Self {
@@ -105,6 +154,8 @@ impl PhysicalDevice {
             name,
             device_type,
             limits,
+            memory_heaps,
+            subgroup_properties,
             queue_families,
             supported_extensions,
             supported_surface_formats,
@@ -121,4 +172,35 @@ impl PhysicalDevice {
             .collect::<Vec<_>>();
         extensions.iter().all(|e| supported_extensions.contains(e))
     }
+
+    pub fn limits(&self) -> &vk::PhysicalDeviceLimits {
+        &self.limits
+    }
+
+    pub fn memory_heaps(&self) -> &[vk::MemoryHeap] {
+        &self.memory_heaps
+    }
+
+    /// Total size of the device local heaps. For discrete GPUs, this is the amount of VRAM.
+    pub fn device_local_memory_size(&self) -> vk::DeviceSize {
+        self.memory_heaps
+            .iter()
+            .filter(|h| h.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|h| h.size)
+            .sum()
+    }
+
+    /// Number of nanoseconds per timestamp query increment.
+    pub fn timestamp_period(&self) -> f32 {
+        self.limits.timestamp_period
+    }
+
+    /// Sample counts supported by both color and depth framebuffer attachments.
+    pub fn supported_sample_counts(&self) -> vk::SampleCountFlags {
+        self.limits.framebuffer_color_sample_counts & self.limits.framebuffer_depth_sample_counts
+    }
+
+    pub fn supports_sample_count(&self, samples: vk::SampleCountFlags) -> bool {
+        self.supported_sample_counts().contains(samples)
+    }
 }
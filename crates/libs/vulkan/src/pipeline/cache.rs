@@ -0,0 +1,86 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::Result;
+use ash::vk;
+
+use crate::{device::Device, Context};
+
+/// Cache of compiled pipelines that can be saved to disk to speed up the next launches.
+pub struct PipelineCache {
+    device: Arc<Device>,
+    pub(crate) inner: vk::PipelineCache,
+}
+
+impl PipelineCache {
+    pub(crate) fn new(device: Arc<Device>, initial_data: &[u8]) -> Result<Self> {
+        let create_info = vk::PipelineCacheCreateInfo::default().initial_data(initial_data);
+        let inner = unsafe { device.inner.create_pipeline_cache(&create_info, None)? };
+
+        Ok(Self { device, inner })
+    }
+
+    pub fn data(&self) -> Result<Vec<u8>> {
+        let data = unsafe { self.device.inner.get_pipeline_cache_data(self.inner)? };
+
+        Ok(data)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let data = self.data()?;
+        std::fs::write(path, &data)?;
+        log::debug!(
+            "Saved {} bytes of pipeline cache to {}",
+            data.len(),
+            path.display()
+        );
+
+        Ok(())
+    }
+}
+
+impl Context {
+    pub fn create_pipeline_cache(&self) -> Result<PipelineCache> {
+        PipelineCache::new(self.device.clone(), &[])
+    }
+
+    /// Creates a pipeline cache from the data saved at `path`.
+    ///
+    /// Starts with an empty cache when the file does not exist or its data is rejected.
+    /// Drivers ignore data created by another device or driver version.
+    pub fn load_pipeline_cache(&self, path: impl AsRef<Path>) -> Result<PipelineCache> {
+        let path = path.as_ref();
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                log::debug!("No pipeline cache found at {}", path.display());
+                return self.create_pipeline_cache();
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        match PipelineCache::new(self.device.clone(), &data) {
+            Ok(cache) => {
+                log::debug!(
+                    "Loaded {} bytes of pipeline cache from {}",
+                    data.len(),
+                    path.display()
+                );
+                Ok(cache)
+            }
+            Err(err) => {
+                log::warn!(
+                    "Failed to load pipeline cache from {}. Cause: {err}",
+                    path.display()
+                );
+                self.create_pipeline_cache()
+            }
+        }
+    }
+}
+
+impl Drop for PipelineCache {
+    fn drop(&mut self) {
+        unsafe { self.device.inner.destroy_pipeline_cache(self.inner, None) };
+    }
+}
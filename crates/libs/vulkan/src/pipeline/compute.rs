@@ -1,9 +1,10 @@
-use std::{ffi::CString, sync::Arc};
+use std::{ffi::CString, path::Path, sync::Arc, time::Instant};
 
 use anyhow::Result;
 use ash::vk;
 
-use crate::{device::Device, Context, PipelineLayout, ShaderModule};
+use super::{read_shader_files, take_created_pipeline};
+use crate::{device::Device, Context, PipelineCache, PipelineLayout, ShaderModule, Specialization};
 
 pub struct ComputePipeline {
     device: Arc<Device>,
@@ -13,6 +14,8 @@ pub struct ComputePipeline {
 #[derive(Debug, Clone, Copy)]
 pub struct ComputePipelineCreateInfo<'a> {
     pub shader_source: &'a [u8],
+    pub entry_point: &'a str,
+    pub specialization: Option<&'a [(u32, &'a [u8])]>,
 }
 
 impl ComputePipeline {
@@ -20,40 +23,86 @@ impl ComputePipeline {
         device: Arc<Device>,
         layout: &PipelineLayout,
         create_info: ComputePipelineCreateInfo,
+        cache: Option<&PipelineCache>,
     ) -> Result<Self> {
-        let entry_point_name = CString::new("main").unwrap();
+        let entry_point_name = CString::new(create_info.entry_point)?;
         let shader_module = ShaderModule::from_bytes(device.clone(), create_info.shader_source)?;
-        let shader_stage_info = vk::PipelineShaderStageCreateInfo::default()
+        let specialization = create_info.specialization.map(Specialization::new);
+        let specialization_info = specialization.as_ref().map(Specialization::info);
+
+        let mut shader_stage_info = vk::PipelineShaderStageCreateInfo::default()
             .stage(vk::ShaderStageFlags::COMPUTE)
             .module(shader_module.inner)
             .name(&entry_point_name);
+        if let Some(info) = &specialization_info {
+            shader_stage_info = shader_stage_info.specialization_info(info);
+        }
 
         let pipeline_info = vk::ComputePipelineCreateInfo::default()
             .stage(shader_stage_info)
             .layout(layout.inner);
 
-        let inner = unsafe {
-            device
-                .inner
-                .create_compute_pipelines(
-                    vk::PipelineCache::null(),
-                    std::slice::from_ref(&pipeline_info),
-                    None,
-                )
-                .map_err(|e| e.1)?[0]
+        let started = Instant::now();
+        let result = unsafe {
+            device.inner.create_compute_pipelines(
+                cache.map_or(vk::PipelineCache::null(), |c| c.inner),
+                std::slice::from_ref(&pipeline_info),
+                None,
+            )
         };
+        let inner = take_created_pipeline(&device, "compute", started, result)?;
 
         Ok(Self { device, inner })
     }
 }
 
+impl ComputePipeline {
+    /// Same as [`GraphicsPipeline::reload`](crate::GraphicsPipeline::reload) for a compute
+    /// pipeline.
+    pub fn reload(
+        &mut self,
+        context: &Context,
+        layout: &PipelineLayout,
+        create_info: ComputePipelineCreateInfo,
+        cache: Option<&PipelineCache>,
+    ) -> Result<()> {
+        let pipeline = context.create_compute_pipeline(layout, create_info, cache)?;
+        context.device_wait_idle()?;
+        *self = pipeline;
+
+        Ok(())
+    }
+}
+
 impl Context {
     pub fn create_compute_pipeline(
         &self,
         layout: &PipelineLayout,
         create_info: ComputePipelineCreateInfo,
+        cache: Option<&PipelineCache>,
     ) -> Result<ComputePipeline> {
-        ComputePipeline::new(self.device.clone(), layout, create_info)
+        ComputePipeline::new(self.device.clone(), layout, create_info, cache)
+    }
+
+    /// Like [`Context::create_compute_pipeline`] but the SPIR-V is read from `shader_path`
+    /// and `create_info.shader_source` is ignored.
+    pub fn create_compute_pipeline_from_file(
+        &self,
+        layout: &PipelineLayout,
+        create_info: ComputePipelineCreateInfo,
+        shader_path: impl AsRef<Path>,
+        cache: Option<&PipelineCache>,
+    ) -> Result<ComputePipeline> {
+        let source = read_shader_files(1, &[shader_path])?.remove(0);
+
+        self.create_compute_pipeline(
+            layout,
+            ComputePipelineCreateInfo {
+                shader_source: &source,
+                ..create_info
+            },
+            cache,
+        )
     }
 }
 
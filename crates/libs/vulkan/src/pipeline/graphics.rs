@@ -1,9 +1,10 @@
-use std::{ffi::CString, sync::Arc};
+use std::{ffi::CString, path::Path, sync::Arc, time::Instant};
 
 use anyhow::Result;
 use ash::vk;
 
-use crate::{device::Device, Context, PipelineLayout, ShaderModule};
+use super::{read_shader_files, take_created_pipeline};
+use crate::{device::Device, Context, PipelineCache, PipelineLayout, ShaderModule, Specialization};
 
 pub struct GraphicsPipeline {
     device: Arc<Device>,
@@ -15,13 +16,44 @@ pub struct GraphicsPipelineCreateInfo<'a> {
     pub shaders: &'a [GraphicsShaderCreateInfo<'a>],
     pub primitive_topology: vk::PrimitiveTopology,
     pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+    pub polygon_mode: vk::PolygonMode,
+    pub line_width: f32,
+    /// Requires the `tessellation_shader` device feature and the `PATCH_LIST` topology.
+    pub tessellation: Option<TessellationInfo>,
+    pub samples: vk::SampleCountFlags,
     pub extent: Option<vk::Extent2D>,
     pub color_attachments: ColorAttachmentsInfo<'a>,
     pub depth: Option<DepthInfo>,
     pub dynamic_states: Option<&'a [vk::DynamicState]>,
 }
 
+/// Filled, non culled triangle list with one sample and no attachment.
+impl Default for GraphicsPipelineCreateInfo<'_> {
+    fn default() -> Self {
+        Self {
+            shaders: &[],
+            primitive_topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            polygon_mode: vk::PolygonMode::FILL,
+            line_width: 1.0,
+            tessellation: None,
+            samples: vk::SampleCountFlags::TYPE_1,
+            extent: None,
+            color_attachments: ColorAttachmentsInfo::default(),
+            depth: None,
+            dynamic_states: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
+pub struct TessellationInfo {
+    pub patch_control_points: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
 pub struct ColorAttachmentsInfo<'a> {
     pub formats: &'a [vk::Format],
     pub blends: &'a [vk::PipelineColorBlendAttachmentState],
@@ -32,6 +64,19 @@ pub struct DepthInfo {
     pub format: vk::Format,
     pub enable_depth_test: bool,
     pub enable_depth_write: bool,
+    pub depth_compare_op: vk::CompareOp,
+}
+
+/// D32 depth tested with `LESS_OR_EQUAL` and written.
+impl Default for DepthInfo {
+    fn default() -> Self {
+        Self {
+            format: vk::Format::D32_SFLOAT,
+            enable_depth_test: true,
+            enable_depth_write: true,
+            depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+        }
+    }
 }
 
 pub trait Vertex {
@@ -39,10 +84,37 @@ pub trait Vertex {
     fn attributes() -> Vec<vk::VertexInputAttributeDescription>;
 }
 
+/// Vertex without any input, for pipelines generating or pulling vertices in the vertex shader.
+pub struct EmptyVertex;
+
+impl Vertex for EmptyVertex {
+    fn bindings() -> Vec<vk::VertexInputBindingDescription> {
+        vec![]
+    }
+
+    fn attributes() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![]
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct GraphicsShaderCreateInfo<'a> {
     pub source: &'a [u8],
     pub stage: vk::ShaderStageFlags,
+    pub entry_point: &'a str,
+    pub specialization: Option<&'a [(u32, &'a [u8])]>,
+}
+
+/// Unspecialized `main` entry point, `source` and `stage` still have to be set.
+impl Default for GraphicsShaderCreateInfo<'_> {
+    fn default() -> Self {
+        Self {
+            source: &[],
+            stage: vk::ShaderStageFlags::empty(),
+            entry_point: "main",
+            specialization: None,
+        }
+    }
 }
 
 impl GraphicsPipeline {
@@ -50,20 +122,116 @@ impl GraphicsPipeline {
         device: Arc<Device>,
         layout: &PipelineLayout,
         create_info: GraphicsPipelineCreateInfo,
+        cache: Option<&PipelineCache>,
     ) -> Result<Self> {
+        let blends = create_info.color_attachments.blends;
+        if !device.enabled_features.independent_blend
+            && blends.windows(2).any(|w| !is_same_blend(&w[0], &w[1]))
+        {
+            return Err(anyhow::anyhow!(
+                "Color attachments use different blend states but the independentBlend feature is not enabled"
+            ));
+        }
+
+        if create_info.polygon_mode != vk::PolygonMode::FILL
+            && !device.enabled_features.fill_mode_non_solid
+        {
+            return Err(anyhow::anyhow!(
+                "Polygon mode {:?} requires the fillModeNonSolid feature",
+                create_info.polygon_mode
+            ));
+        }
+
+        if create_info.line_width != 1.0 && !device.enabled_features.wide_lines {
+            return Err(anyhow::anyhow!(
+                "Line width {} requires the wideLines feature",
+                create_info.line_width
+            ));
+        }
+
+        let stages = create_info
+            .shaders
+            .iter()
+            .fold(vk::ShaderStageFlags::empty(), |acc, s| acc | s.stage);
+
+        if stages.contains(vk::ShaderStageFlags::GEOMETRY)
+            && !device.enabled_features.geometry_shader
+        {
+            return Err(anyhow::anyhow!(
+                "Geometry shader stage requires the geometryShader feature"
+            ));
+        }
+
+        let tessellation_stages = vk::ShaderStageFlags::TESSELLATION_CONTROL
+            | vk::ShaderStageFlags::TESSELLATION_EVALUATION;
+        let is_patch_list = create_info.primitive_topology == vk::PrimitiveTopology::PATCH_LIST;
+        if let Some(tessellation) = create_info.tessellation {
+            if !device.enabled_features.tessellation_shader {
+                return Err(anyhow::anyhow!(
+                    "Tessellation requires the tessellationShader feature"
+                ));
+            }
+            if !is_patch_list {
+                return Err(anyhow::anyhow!(
+                    "Tessellation requires the PATCH_LIST topology but got {:?}",
+                    create_info.primitive_topology
+                ));
+            }
+            if !stages.contains(tessellation_stages) {
+                return Err(anyhow::anyhow!(
+                    "Tessellation requires both tessellation control and evaluation shader stages"
+                ));
+            }
+            let max_patch_size = device.max_tessellation_patch_size;
+            if tessellation.patch_control_points == 0
+                || tessellation.patch_control_points > max_patch_size
+            {
+                return Err(anyhow::anyhow!(
+                    "Patch control points must be between 1 and {max_patch_size} but got {}",
+                    tessellation.patch_control_points
+                ));
+            }
+        } else if is_patch_list || stages.intersects(tessellation_stages) {
+            return Err(anyhow::anyhow!(
+                "PATCH_LIST topology and tessellation shader stages require tessellation info"
+            ));
+        }
+
         // shaders
         let mut shader_modules = vec![];
         let mut shader_stages_infos = vec![];
 
-        let entry_point_name = CString::new("main").unwrap();
+        let entry_point_names = create_info
+            .shaders
+            .iter()
+            .map(|s| CString::new(s.entry_point))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let specializations = create_info
+            .shaders
+            .iter()
+            .map(|s| s.specialization.map(Specialization::new))
+            .collect::<Vec<_>>();
+        let specialization_infos = specializations
+            .iter()
+            .map(|s| s.as_ref().map(Specialization::info))
+            .collect::<Vec<_>>();
 
-        for shader in create_info.shaders.iter() {
+        for ((shader, entry_point_name), specialization_info) in create_info
+            .shaders
+            .iter()
+            .zip(&entry_point_names)
+            .zip(&specialization_infos)
+        {
             let module = ShaderModule::from_bytes(device.clone(), shader.source)?;
 
-            let stage = vk::PipelineShaderStageCreateInfo::default()
+            let mut stage = vk::PipelineShaderStageCreateInfo::default()
                 .stage(shader.stage)
                 .module(module.inner)
-                .name(&entry_point_name);
+                .name(entry_point_name);
+            if let Some(info) = specialization_info {
+                stage = stage.specialization_info(info);
+            }
 
             shader_modules.push(module);
             shader_stages_infos.push(stage);
@@ -80,6 +248,12 @@ impl GraphicsPipeline {
             .topology(create_info.primitive_topology)
             .primitive_restart_enable(false);
 
+        // tessellation
+        let tessellation_info = create_info.tessellation.map(|t| {
+            vk::PipelineTessellationStateCreateInfo::default()
+                .patch_control_points(t.patch_control_points)
+        });
+
         // viewport/scissors
         let viewports = create_info
             .extent
@@ -114,10 +288,10 @@ impl GraphicsPipeline {
         let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
             .depth_clamp_enable(false)
             .rasterizer_discard_enable(false)
-            .polygon_mode(vk::PolygonMode::FILL)
-            .line_width(1.0)
+            .polygon_mode(create_info.polygon_mode)
+            .line_width(create_info.line_width)
             .cull_mode(create_info.cull_mode)
-            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .front_face(create_info.front_face)
             .depth_bias_enable(false)
             .depth_bias_constant_factor(0.0)
             .depth_bias_clamp(0.0)
@@ -126,7 +300,7 @@ impl GraphicsPipeline {
         // msaa
         let multisampling_info = vk::PipelineMultisampleStateCreateInfo::default()
             .sample_shading_enable(false)
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+            .rasterization_samples(create_info.samples)
             .min_sample_shading(1.0)
             .alpha_to_coverage_enable(false)
             .alpha_to_one_enable(false);
@@ -143,7 +317,7 @@ impl GraphicsPipeline {
             vk::PipelineDepthStencilStateCreateInfo::default()
                 .depth_test_enable(d.enable_depth_test)
                 .depth_write_enable(d.enable_depth_write)
-                .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+                .depth_compare_op(d.depth_compare_op)
                 .depth_bounds_test_enable(false)
                 .min_depth_bounds(0.0)
                 .max_depth_bounds(1.0)
@@ -180,28 +354,95 @@ impl GraphicsPipeline {
             pipeline_info = pipeline_info.depth_stencil_state(info);
         }
 
-        let inner = unsafe {
-            device
-                .inner
-                .create_graphics_pipelines(
-                    vk::PipelineCache::null(),
-                    std::slice::from_ref(&pipeline_info),
-                    None,
-                )
-                .map_err(|e| e.1)?[0]
+        // tessellation
+        if let Some(info) = &tessellation_info {
+            pipeline_info = pipeline_info.tessellation_state(info);
+        }
+
+        let started = Instant::now();
+        let result = unsafe {
+            device.inner.create_graphics_pipelines(
+                cache.map_or(vk::PipelineCache::null(), |c| c.inner),
+                std::slice::from_ref(&pipeline_info),
+                None,
+            )
         };
+        let inner = take_created_pipeline(&device, "graphics", started, result)?;
 
         Ok(Self { device, inner })
     }
 }
 
+fn is_same_blend(
+    a: &vk::PipelineColorBlendAttachmentState,
+    b: &vk::PipelineColorBlendAttachmentState,
+) -> bool {
+    a.blend_enable == b.blend_enable
+        && a.src_color_blend_factor == b.src_color_blend_factor
+        && a.dst_color_blend_factor == b.dst_color_blend_factor
+        && a.color_blend_op == b.color_blend_op
+        && a.src_alpha_blend_factor == b.src_alpha_blend_factor
+        && a.dst_alpha_blend_factor == b.dst_alpha_blend_factor
+        && a.alpha_blend_op == b.alpha_blend_op
+        && a.color_write_mask == b.color_write_mask
+}
+
+impl GraphicsPipeline {
+    /// Recreates the pipeline in place, typically from recompiled shaders.
+    ///
+    /// When creation fails the error is returned and the current pipeline is kept. Otherwise
+    /// the device is waited for to be idle before the current pipeline is destroyed, so it
+    /// can still be in use by frames in flight.
+    pub fn reload<V: Vertex>(
+        &mut self,
+        context: &Context,
+        layout: &PipelineLayout,
+        create_info: GraphicsPipelineCreateInfo,
+        cache: Option<&PipelineCache>,
+    ) -> Result<()> {
+        let pipeline = context.create_graphics_pipeline::<V>(layout, create_info, cache)?;
+        context.device_wait_idle()?;
+        *self = pipeline;
+
+        Ok(())
+    }
+}
+
 impl Context {
     pub fn create_graphics_pipeline<V: Vertex>(
         &self,
         layout: &PipelineLayout,
         create_info: GraphicsPipelineCreateInfo,
+        cache: Option<&PipelineCache>,
     ) -> Result<GraphicsPipeline> {
-        GraphicsPipeline::new::<V>(self.device.clone(), layout, create_info)
+        GraphicsPipeline::new::<V>(self.device.clone(), layout, create_info, cache)
+    }
+
+    /// Like [`Context::create_graphics_pipeline`] but the SPIR-V of each shader is read from
+    /// `shader_paths`, in the order of `create_info.shaders` whose `source` is ignored.
+    pub fn create_graphics_pipeline_from_files<V: Vertex>(
+        &self,
+        layout: &PipelineLayout,
+        create_info: GraphicsPipelineCreateInfo,
+        shader_paths: &[impl AsRef<Path>],
+        cache: Option<&PipelineCache>,
+    ) -> Result<GraphicsPipeline> {
+        let sources = read_shader_files(create_info.shaders.len(), shader_paths)?;
+        let shaders = create_info
+            .shaders
+            .iter()
+            .zip(&sources)
+            .map(|(shader, source)| GraphicsShaderCreateInfo { source, ..*shader })
+            .collect::<Vec<_>>();
+
+        self.create_graphics_pipeline::<V>(
+            layout,
+            GraphicsPipelineCreateInfo {
+                shaders: &shaders,
+                ..create_info
+            },
+            cache,
+        )
     }
 }
 
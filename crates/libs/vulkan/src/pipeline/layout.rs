@@ -8,35 +8,101 @@ use crate::{device::Device, Context, DescriptorSetLayout};
 pub struct PipelineLayout {
     device: Arc<Device>,
     pub(crate) inner: vk::PipelineLayout,
+    push_constant_ranges: Vec<vk::PushConstantRange>,
 }
 
 impl PipelineLayout {
     pub(crate) fn new(
         device: Arc<Device>,
         descriptor_set_layouts: &[&DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange],
     ) -> Result<Self> {
+        check_push_constant_ranges(&device, push_constant_ranges)?;
+
         let layouts = descriptor_set_layouts
             .iter()
             .map(|l| l.inner)
             .collect::<Vec<_>>();
 
-        let pipe_layout_info = vk::PipelineLayoutCreateInfo::default().set_layouts(&layouts);
+        let pipe_layout_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&layouts)
+            .push_constant_ranges(push_constant_ranges);
         let inner = unsafe {
             device
                 .inner
                 .create_pipeline_layout(&pipe_layout_info, None)?
         };
 
-        Ok(Self { device, inner })
+        Ok(Self {
+            device,
+            inner,
+            push_constant_ranges: push_constant_ranges.to_vec(),
+        })
+    }
+
+    pub fn push_constant_ranges(&self) -> &[vk::PushConstantRange] {
+        &self.push_constant_ranges
     }
 }
 
+/// Ranges of different stages may overlap but a stage can only appear in one range.
+fn check_push_constant_ranges(device: &Device, ranges: &[vk::PushConstantRange]) -> Result<()> {
+    let max_size = device.max_push_constants_size;
+
+    for (index, range) in ranges.iter().enumerate() {
+        if range.stage_flags.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Push constant range {index} has no shader stage"
+            ));
+        }
+        if range.size == 0 || range.offset % 4 != 0 || range.size % 4 != 0 {
+            return Err(anyhow::anyhow!(
+                "Push constant range {index} (offset {}, size {}) must have an offset and a non zero size that are multiples of 4",
+                range.offset,
+                range.size
+            ));
+        }
+        if range.offset as u64 + range.size as u64 > max_size as u64 {
+            return Err(anyhow::anyhow!(
+                "Push constant range {index} (offset {}, size {}) exceeds the device's maxPushConstantsSize of {max_size} bytes",
+                range.offset,
+                range.size
+            ));
+        }
+
+        if let Some((other, _)) = ranges[..index]
+            .iter()
+            .enumerate()
+            .find(|(_, other)| other.stage_flags.intersects(range.stage_flags))
+        {
+            return Err(anyhow::anyhow!(
+                "Push constant ranges {other} and {index} share stages {:?}",
+                ranges[other].stage_flags & range.stage_flags
+            ));
+        }
+    }
+
+    Ok(())
+}
+
 impl Context {
     pub fn create_pipeline_layout(
         &self,
         descriptor_set_layouts: &[&DescriptorSetLayout],
     ) -> Result<PipelineLayout> {
-        PipelineLayout::new(self.device.clone(), descriptor_set_layouts)
+        PipelineLayout::new(self.device.clone(), descriptor_set_layouts, &[])
+    }
+
+    pub fn create_pipeline_layout_with_push_constants(
+        &self,
+        descriptor_set_layouts: &[&DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange],
+    ) -> Result<PipelineLayout> {
+        PipelineLayout::new(
+            self.device.clone(),
+            descriptor_set_layouts,
+            push_constant_ranges,
+        )
     }
 }
 
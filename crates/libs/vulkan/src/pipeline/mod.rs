@@ -1,9 +1,59 @@
+mod cache;
 mod compute;
 mod graphics;
 mod layout;
 mod shader;
 
+pub use cache::*;
 pub use compute::*;
 pub use graphics::*;
 pub use layout::*;
 pub use shader::*;
+
+use std::time::Instant;
+
+use anyhow::Result;
+use ash::vk;
+
+use crate::device::Device;
+
+/// Extracts the single pipeline created by a `vkCreate*Pipelines` call.
+///
+/// On failure, the index and error of each pipeline that could not be created are logged
+/// and the pipelines that were created are destroyed so they don't leak.
+/// `started` is when creation began, to log how long it took.
+pub(crate) fn take_created_pipeline(
+    device: &Device,
+    kind: &str,
+    started: Instant,
+    result: std::result::Result<Vec<vk::Pipeline>, (Vec<vk::Pipeline>, vk::Result)>,
+) -> Result<vk::Pipeline> {
+    match result {
+        Ok(pipelines) => {
+            log::debug!("Created {kind} pipeline in {:?}", started.elapsed());
+            let mut pipelines = pipelines.into_iter();
+            let pipeline = pipelines
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("No {kind} pipeline was created"))?;
+            destroy_pipelines(device, pipelines);
+            Ok(pipeline)
+        }
+        Err((pipelines, err)) => {
+            for (index, _) in pipelines
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| **p == vk::Pipeline::null())
+            {
+                log::error!("Failed to create {kind} pipeline at index {index}: {err}");
+            }
+            destroy_pipelines(device, pipelines);
+            Err(anyhow::anyhow!("Failed to create {kind} pipeline: {err}"))
+        }
+    }
+}
+
+fn destroy_pipelines(device: &Device, pipelines: impl IntoIterator<Item = vk::Pipeline>) {
+    for pipeline in pipelines.into_iter().filter(|p| *p != vk::Pipeline::null()) {
+        unsafe { device.inner.destroy_pipeline(pipeline, None) };
+    }
+}
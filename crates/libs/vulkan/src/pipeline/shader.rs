@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
 use anyhow::Result;
 use ash::vk;
@@ -21,6 +21,72 @@ impl ShaderModule {
     }
 }
 
+/// First word of every SPIR-V module.
+const SPIRV_MAGIC_NUMBER: u32 = 0x07230203;
+
+/// Reads a SPIR-V file, failing if it does not start with the SPIR-V magic number
+/// (a GLSL source passed by mistake for example).
+pub fn read_shader_file(path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    let path = path.as_ref();
+    let source = std::fs::read(path)
+        .map_err(|err| anyhow::anyhow!("Failed to read shader {}. Cause: {err}", path.display()))?;
+
+    if !source.starts_with(&SPIRV_MAGIC_NUMBER.to_le_bytes()) {
+        return Err(anyhow::anyhow!(
+            "Shader {} is not a SPIR-V file",
+            path.display()
+        ));
+    }
+
+    Ok(source)
+}
+
+/// Reads the SPIR-V files replacing the sources of a pipeline's `shader_count` shaders.
+pub(crate) fn read_shader_files(
+    shader_count: usize,
+    paths: &[impl AsRef<Path>],
+) -> Result<Vec<Vec<u8>>> {
+    if paths.len() != shader_count {
+        return Err(anyhow::anyhow!(
+            "Expected {shader_count} shader files but got {}",
+            paths.len()
+        ));
+    }
+
+    paths.iter().map(read_shader_file).collect()
+}
+
+/// Owns the map entries and data of a `vk::SpecializationInfo` built from
+/// (constant id, bytes) pairs so they outlive pipeline creation.
+pub(crate) struct Specialization {
+    entries: Vec<vk::SpecializationMapEntry>,
+    data: Vec<u8>,
+}
+
+impl Specialization {
+    pub(crate) fn new(constants: &[(u32, &[u8])]) -> Self {
+        let mut entries = Vec::with_capacity(constants.len());
+        let mut data = Vec::new();
+
+        for (constant_id, bytes) in constants {
+            entries.push(vk::SpecializationMapEntry {
+                constant_id: *constant_id,
+                offset: data.len() as _,
+                size: bytes.len(),
+            });
+            data.extend_from_slice(bytes);
+        }
+
+        Self { entries, data }
+    }
+
+    pub(crate) fn info(&self) -> vk::SpecializationInfo<'_> {
+        vk::SpecializationInfo::default()
+            .map_entries(&self.entries)
+            .data(&self.data)
+    }
+}
+
 impl Context {
     pub fn create_shader_module(&self, source: &[u8]) -> Result<ShaderModule> {
         ShaderModule::from_bytes(self.device.clone(), source)
@@ -34,3 +100,30 @@ impl Drop for ShaderModule {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_temp_file(name: &str, bytes: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_file_starting_with_spirv_magic_number() {
+        let mut bytes = SPIRV_MAGIC_NUMBER.to_le_bytes().to_vec();
+        bytes.extend([0; 16]);
+        let path = write_temp_file("vulkan_read_shader_file_valid.spv", &bytes);
+
+        assert_eq!(read_shader_file(&path).unwrap(), bytes);
+    }
+
+    #[test]
+    fn rejects_file_without_spirv_magic_number() {
+        let path = write_temp_file("vulkan_read_shader_file_glsl.spv", b"#version 450\n");
+
+        assert!(read_shader_file(&path).is_err());
+    }
+}
@@ -1,18 +1,23 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use anyhow::Result;
 use ash::vk;
 
-use crate::{Context, Device};
+use crate::{error::DeviceLostExt, Context, Device};
 
 pub struct TimestampQueryPool<const C: usize> {
     device: Arc<Device>,
     pub(crate) inner: vk::QueryPool,
     timestamp_period: f64,
+    timestamp_mask: u64,
 }
 
 impl<const C: usize> TimestampQueryPool<C> {
-    pub(crate) fn new(device: Arc<Device>, timestamp_period: f64) -> Result<Self> {
+    pub(crate) fn new(
+        device: Arc<Device>,
+        timestamp_period: f64,
+        timestamp_mask: u64,
+    ) -> Result<Self> {
         let create_info = vk::QueryPoolCreateInfo::default()
             .query_type(vk::QueryType::TIMESTAMP)
             .query_count(C as _);
@@ -23,15 +28,18 @@ impl<const C: usize> TimestampQueryPool<C> {
             device,
             inner,
             timestamp_period,
+            timestamp_mask,
         })
     }
 }
 
 impl Context {
+    /// Creates a pool for timestamps written by command buffers submitted to the graphics queue.
     pub fn create_timestamp_query_pool<const C: usize>(&self) -> Result<TimestampQueryPool<C>> {
         TimestampQueryPool::new(
             self.device.clone(),
-            self.physical_device.limits.timestamp_period as _,
+            self.timestamp_period() as _,
+            self.graphics_queue_family.timestamp_mask(),
         )
     }
 }
@@ -51,6 +59,8 @@ impl<const C: usize> TimestampQueryPool<C> {
         }
     }
 
+    /// Waits for and returns the raw timestamps, in device ticks.
+    /// Use [`TimestampQueryPool::elapsed`] to convert them to a duration.
     pub fn wait_for_all_results(&self) -> Result<[u64; C]> {
         let mut data = [0u64; C];
 
@@ -60,14 +70,82 @@ impl<const C: usize> TimestampQueryPool<C> {
                 0,
                 &mut data,
                 vk::QueryResultFlags::WAIT | vk::QueryResultFlags::TYPE_64,
-            )?;
+            )
         }
+        .or_device_lost("query results wait")?;
 
-        let mut result = [0u64; C];
-        for (index, timestamp) in data.iter().enumerate() {
-            result[index] = (*timestamp as f64 * self.timestamp_period) as u64;
+        Ok(data.map(|timestamp| timestamp & self.timestamp_mask))
+    }
+
+    /// Returns the results if all queries are available, or `None` without waiting otherwise.
+    ///
+    /// Unlike [`TimestampQueryPool::wait_for_all_results`], this never stalls so it can be
+    /// polled every frame. Queries that were never written are reported as not ready.
+    pub fn try_get_results(&self) -> Result<Option<[u64; C]>> {
+        // Each query writes its value followed by its availability
+        let mut data = [[0u64; 2]; C];
+
+        let result = unsafe {
+            self.device.inner.get_query_pool_results(
+                self.inner,
+                0,
+                &mut data,
+                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WITH_AVAILABILITY,
+            )
+        };
+        match result {
+            Ok(()) | Err(vk::Result::NOT_READY) => {}
+            Err(err) => return Err(err).or_device_lost("query results read"),
+        }
+
+        if data.iter().any(|[_, available]| *available == 0) {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            data.map(|[timestamp, _]| timestamp & self.timestamp_mask),
+        ))
+    }
+
+    /// Like [`TimestampQueryPool::try_get_results`] for the `count` queries starting at `first`,
+    /// so queries that were not written this time don't keep the others from being read.
+    pub fn try_get_range_results(&self, first: u32, count: u32) -> Result<Option<Vec<u64>>> {
+        assert!(
+            first as usize + count as usize <= C,
+            "Query range {first}..{} must be within 0..{C}",
+            first + count
+        );
+
+        let mut data = vec![[0u64; 2]; count as usize];
+
+        let result = unsafe {
+            self.device.inner.get_query_pool_results(
+                self.inner,
+                first,
+                &mut data,
+                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WITH_AVAILABILITY,
+            )
+        };
+        match result {
+            Ok(()) | Err(vk::Result::NOT_READY) => {}
+            Err(err) => return Err(err).or_device_lost("query results read"),
+        }
+
+        if data.iter().any(|[_, available]| *available == 0) {
+            return Ok(None);
         }
 
-        Ok(result)
+        Ok(Some(
+            data.iter()
+                .map(|[timestamp, _]| timestamp & self.timestamp_mask)
+                .collect(),
+        ))
+    }
+
+    /// Time between two timestamps of this pool, scaled by the device timestamp period.
+    /// The difference wraps around the valid bits of the timestamps.
+    pub fn elapsed(&self, start: u64, end: u64) -> Duration {
+        let ticks = end.wrapping_sub(start) & self.timestamp_mask;
+        Duration::from_nanos((ticks as f64 * self.timestamp_period).round() as u64)
     }
 }
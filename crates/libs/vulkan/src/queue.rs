@@ -3,7 +3,7 @@ use std::sync::Arc;
 use anyhow::Result;
 use ash::vk;
 
-use crate::{device::Device, CommandBuffer, Fence, Semaphore};
+use crate::{device::Device, error::DeviceLostExt, CommandBuffer, Fence, Semaphore};
 
 #[derive(Debug, Clone, Copy)]
 pub struct QueueFamily {
@@ -29,6 +29,18 @@ impl QueueFamily {
         self.inner.queue_flags.contains(vk::QueueFlags::COMPUTE)
     }
 
+    /// Compute family without graphics support, which can run work concurrently with graphics.
+    pub fn is_async_compute(&self) -> bool {
+        self.supports_compute() && !self.supports_graphics()
+    }
+
+    /// Transfer family without graphics nor compute support, usually backed by DMA engines.
+    pub fn is_dedicated_transfer(&self) -> bool {
+        self.inner.queue_flags.contains(vk::QueueFlags::TRANSFER)
+            && !self.supports_graphics()
+            && !self.supports_compute()
+    }
+
     pub fn supports_graphics(&self) -> bool {
         self.inner.queue_flags.contains(vk::QueueFlags::GRAPHICS)
     }
@@ -44,6 +56,14 @@ impl QueueFamily {
     pub fn supports_timestamp_queries(&self) -> bool {
         self.inner.timestamp_valid_bits > 0
     }
+
+    /// Mask of the meaningful bits of the timestamps written by queues of this family.
+    pub fn timestamp_mask(&self) -> u64 {
+        match self.inner.timestamp_valid_bits {
+            bits if bits < 64 => (1 << bits) - 1,
+            _ => u64::MAX,
+        }
+    }
 }
 
 pub struct Queue {
@@ -63,41 +83,65 @@ impl Queue {
         signal_semaphore: Option<SemaphoreSubmitInfo>,
         fence: &Fence,
     ) -> Result<()> {
-        let wait_semaphore_submit_info = wait_semaphore.map(|s| {
-            vk::SemaphoreSubmitInfo::default()
-                .semaphore(s.semaphore.inner)
-                .stage_mask(s.stage_mask)
-        });
-
-        let signal_semaphore_submit_info = signal_semaphore.map(|s| {
-            vk::SemaphoreSubmitInfo::default()
-                .semaphore(s.semaphore.inner)
-                .stage_mask(s.stage_mask)
-        });
+        self.submit_with_semaphores(
+            command_buffer,
+            wait_semaphore.as_slice(),
+            signal_semaphore.as_slice(),
+            Some(fence),
+        )
+    }
+
+    /// Submits with any number of binary or timeline semaphores.
+    ///
+    /// Timeline semaphores allow handing work off between queues without fences: the producer
+    /// queue signals a value that the consumer queue waits for. Resources with exclusive sharing
+    /// mode still need queue family ownership transfers when the families differ.
+    pub fn submit_with_semaphores(
+        &self,
+        command_buffer: &CommandBuffer,
+        wait_semaphores: &[SemaphoreSubmitInfo],
+        signal_semaphores: &[SemaphoreSubmitInfo],
+        fence: Option<&Fence>,
+    ) -> Result<()> {
+        let wait_semaphore_submit_infos = wait_semaphores
+            .iter()
+            .map(SemaphoreSubmitInfo::to_vk)
+            .collect::<Vec<_>>();
+
+        let signal_semaphore_submit_infos = signal_semaphores
+            .iter()
+            .map(SemaphoreSubmitInfo::to_vk)
+            .collect::<Vec<_>>();
 
         let cmd_buffer_submit_info =
             vk::CommandBufferSubmitInfo::default().command_buffer(command_buffer.inner);
 
         let submit_info = vk::SubmitInfo2::default()
-            .command_buffer_infos(std::slice::from_ref(&cmd_buffer_submit_info));
-
-        let submit_info = match wait_semaphore_submit_info.as_ref() {
-            Some(info) => submit_info.wait_semaphore_infos(std::slice::from_ref(info)),
-            None => submit_info,
-        };
-
-        let submit_info = match signal_semaphore_submit_info.as_ref() {
-            Some(info) => submit_info.signal_semaphore_infos(std::slice::from_ref(info)),
-            None => submit_info,
-        };
+            .command_buffer_infos(std::slice::from_ref(&cmd_buffer_submit_info))
+            .wait_semaphore_infos(&wait_semaphore_submit_infos)
+            .signal_semaphore_infos(&signal_semaphore_submit_infos);
 
         unsafe {
             self.device.inner.queue_submit2(
                 self.inner,
                 std::slice::from_ref(&submit_info),
-                fence.inner,
-            )?
-        };
+                fence.map_or(vk::Fence::null(), |f| f.inner),
+            )
+        }
+        .or_device_lost("queue submission")?;
+
+        Ok(())
+    }
+
+    /// Submits an empty batch signaling `fence` once all work previously submitted
+    /// to this queue has completed.
+    pub fn signal_fence(&self, fence: &Fence) -> Result<()> {
+        unsafe {
+            self.device
+                .inner
+                .queue_submit2(self.inner, &[], fence.inner)
+        }
+        .or_device_lost("queue submission")?;
 
         Ok(())
     }
@@ -106,4 +150,15 @@ impl Queue {
 pub struct SemaphoreSubmitInfo<'a> {
     pub semaphore: &'a Semaphore,
     pub stage_mask: vk::PipelineStageFlags2,
+    /// Value to wait for or to signal. Ignored for binary semaphores.
+    pub value: u64,
+}
+
+impl SemaphoreSubmitInfo<'_> {
+    fn to_vk(&self) -> vk::SemaphoreSubmitInfo<'static> {
+        vk::SemaphoreSubmitInfo::default()
+            .semaphore(self.semaphore.inner)
+            .stage_mask(self.stage_mask)
+            .value(self.value)
+    }
 }
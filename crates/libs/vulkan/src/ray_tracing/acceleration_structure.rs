@@ -4,13 +4,83 @@ use anyhow::Result;
 use ash::vk;
 use gpu_allocator::MemoryLocation;
 
-use crate::{Buffer, Context, RayTracingContext};
+use crate::{
+    utils::create_gpu_only_buffer_from_data, Buffer, CommandBuffer, Context, MemoryBarrier,
+    RayTracingContext,
+};
+
+const DEFAULT_BUILD_FLAGS: vk::BuildAccelerationStructureFlagsKHR =
+    vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE;
+
+/// Maximum value of the 24 bits instance custom index and shader binding table offset.
+const MAX_INSTANCE_24_BITS_VALUE: u32 = (1 << 24) - 1;
+
+/// Instance of a bottom level acceleration structure in a top level one.
+///
+/// Use [`TlasInstance::new`] for an identity transform and struct update syntax for the rest.
+#[derive(Clone, Copy)]
+pub struct TlasInstance {
+    pub blas_address: u64,
+    /// Row major 3x4 matrix.
+    pub transform: vk::TransformMatrixKHR,
+    /// Read as `gl_InstanceCustomIndexEXT` in shaders. 24 bits.
+    pub custom_index: u32,
+    /// Added to the index of the hit group record, see [`ShaderBindingTable`](crate::ShaderBindingTable).
+    /// 24 bits.
+    pub sbt_offset: u32,
+    /// Rays only hit instances whose mask shares bits with the cull mask of `traceRayEXT`.
+    pub mask: u8,
+    pub flags: vk::GeometryInstanceFlagsKHR,
+}
+
+impl TlasInstance {
+    pub fn new(blas_address: u64) -> Self {
+        #[rustfmt::skip]
+        let transform = vk::TransformMatrixKHR { matrix: [
+            1.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0
+        ]};
+
+        Self {
+            blas_address,
+            transform,
+            custom_index: 0,
+            sbt_offset: 0,
+            mask: 0xFF,
+            flags: vk::GeometryInstanceFlagsKHR::empty(),
+        }
+    }
+}
+
+impl From<TlasInstance> for vk::AccelerationStructureInstanceKHR {
+    fn from(instance: TlasInstance) -> Self {
+        Self {
+            transform: instance.transform,
+            instance_custom_index_and_mask: vk::Packed24_8::new(
+                instance.custom_index,
+                instance.mask,
+            ),
+            instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(
+                instance.sbt_offset,
+                instance.flags.as_raw() as _,
+            ),
+            acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
+                device_handle: instance.blas_address,
+            },
+        }
+    }
+}
 
 pub struct AccelerationStructure {
     ray_tracing: Arc<RayTracingContext>,
     pub(crate) inner: vk::AccelerationStructureKHR,
     _buffer: Buffer,
     pub address: u64,
+    level: vk::AccelerationStructureTypeKHR,
+    flags: vk::BuildAccelerationStructureFlagsKHR,
+    /// Sized for updates, only created with the `ALLOW_UPDATE` build flag.
+    update_scratch_buffer: Option<Buffer>,
 }
 
 impl AccelerationStructure {
@@ -21,10 +91,11 @@ impl AccelerationStructure {
         as_geometry: &[vk::AccelerationStructureGeometryKHR],
         as_ranges: &[vk::AccelerationStructureBuildRangeInfoKHR],
         max_primitive_counts: &[u32],
+        flags: vk::BuildAccelerationStructureFlagsKHR,
     ) -> Result<Self> {
         let build_geo_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
             .ty(level)
-            .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+            .flags(flags)
             .geometries(as_geometry);
 
         let mut build_size = vk::AccelerationStructureBuildSizesInfoKHR::default();
@@ -39,23 +110,13 @@ impl AccelerationStructure {
                 )
         };
 
-        let buffer = context.create_buffer(
-            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
-                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
-            MemoryLocation::GpuOnly,
+        let (inner, buffer) = create_storage(
+            context,
+            &ray_tracing,
+            level,
             build_size.acceleration_structure_size,
         )?;
 
-        let create_info = vk::AccelerationStructureCreateInfoKHR::default()
-            .buffer(buffer.inner)
-            .size(build_size.acceleration_structure_size)
-            .ty(level);
-        let inner = unsafe {
-            ray_tracing
-                .acceleration_structure_fn
-                .create_acceleration_structure(&create_info, None)?
-        };
-
         let scratch_buffer = context.create_buffer(
             vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
             MemoryLocation::GpuOnly,
@@ -66,7 +127,7 @@ impl AccelerationStructure {
         let build_geo_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
             .ty(level)
             .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
-            .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+            .flags(flags)
             .geometries(as_geometry)
             .dst_acceleration_structure(inner)
             .scratch_data(vk::DeviceOrHostAddressKHR {
@@ -77,21 +138,121 @@ impl AccelerationStructure {
             cmd_buffer.build_acceleration_structures(&build_geo_info, as_ranges);
         })?;
 
-        let address_info =
-            vk::AccelerationStructureDeviceAddressInfoKHR::default().acceleration_structure(inner);
-        let address = unsafe {
-            ray_tracing
-                .acceleration_structure_fn
-                .get_acceleration_structure_device_address(&address_info)
-        };
+        let address = device_address(&ray_tracing, inner);
+
+        let update_scratch_buffer = flags
+            .contains(vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE)
+            .then(|| {
+                context.create_buffer(
+                    vk::BufferUsageFlags::STORAGE_BUFFER
+                        | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                    MemoryLocation::GpuOnly,
+                    build_size.update_scratch_size,
+                )
+            })
+            .transpose()?;
 
         Ok(Self {
             ray_tracing,
             inner,
             _buffer: buffer,
             address,
+            level,
+            flags,
+            update_scratch_buffer,
         })
     }
+
+    /// Records an in place update of the acceleration structure from new geometry data, like
+    /// new instance transforms for a top level acceleration structure.
+    ///
+    /// Cheaper than a rebuild but the geometry count, primitive counts and flags must match
+    /// the build, and the quality of the structure degrades as geometry moves away from it.
+    /// Requires the `ALLOW_UPDATE` build flag.
+    ///
+    /// Records barriers so previous updates and ray tracing shaders reading the structure
+    /// complete before the update, and ray tracing shaders see its result.
+    pub fn update(
+        &self,
+        cmd: &CommandBuffer,
+        as_geometry: &[vk::AccelerationStructureGeometryKHR],
+        as_ranges: &[vk::AccelerationStructureBuildRangeInfoKHR],
+    ) -> Result<()> {
+        let scratch_buffer = self.update_scratch_buffer.as_ref().ok_or_else(|| {
+            anyhow::anyhow!("Acceleration structure was not built with the ALLOW_UPDATE flag")
+        })?;
+
+        let build_geo_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+            .ty(self.level)
+            .mode(vk::BuildAccelerationStructureModeKHR::UPDATE)
+            .flags(self.flags)
+            .geometries(as_geometry)
+            .src_acceleration_structure(self.inner)
+            .dst_acceleration_structure(self.inner)
+            .scratch_data(vk::DeviceOrHostAddressKHR {
+                device_address: scratch_buffer.get_device_address(),
+            });
+
+        cmd.pipeline_memory_barriers(&[MemoryBarrier {
+            src_access_mask: vk::AccessFlags2::ACCELERATION_STRUCTURE_READ_KHR
+                | vk::AccessFlags2::ACCELERATION_STRUCTURE_WRITE_KHR,
+            dst_access_mask: vk::AccessFlags2::ACCELERATION_STRUCTURE_READ_KHR
+                | vk::AccessFlags2::ACCELERATION_STRUCTURE_WRITE_KHR,
+            src_stage_mask: vk::PipelineStageFlags2::RAY_TRACING_SHADER_KHR
+                | vk::PipelineStageFlags2::ACCELERATION_STRUCTURE_BUILD_KHR,
+            dst_stage_mask: vk::PipelineStageFlags2::ACCELERATION_STRUCTURE_BUILD_KHR,
+        }]);
+
+        cmd.build_acceleration_structures(&build_geo_info, as_ranges);
+
+        cmd.pipeline_memory_barriers(&[MemoryBarrier {
+            src_access_mask: vk::AccessFlags2::ACCELERATION_STRUCTURE_WRITE_KHR,
+            dst_access_mask: vk::AccessFlags2::ACCELERATION_STRUCTURE_READ_KHR,
+            src_stage_mask: vk::PipelineStageFlags2::ACCELERATION_STRUCTURE_BUILD_KHR,
+            dst_stage_mask: vk::PipelineStageFlags2::RAY_TRACING_SHADER_KHR,
+        }]);
+
+        Ok(())
+    }
+}
+
+fn create_storage(
+    context: &Context,
+    ray_tracing: &RayTracingContext,
+    level: vk::AccelerationStructureTypeKHR,
+    size: vk::DeviceSize,
+) -> Result<(vk::AccelerationStructureKHR, Buffer)> {
+    let buffer = context.create_buffer(
+        vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+            | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+        MemoryLocation::GpuOnly,
+        size,
+    )?;
+
+    let create_info = vk::AccelerationStructureCreateInfoKHR::default()
+        .buffer(buffer.inner)
+        .size(size)
+        .ty(level);
+    let inner = unsafe {
+        ray_tracing
+            .acceleration_structure_fn
+            .create_acceleration_structure(&create_info, None)?
+    };
+
+    Ok((inner, buffer))
+}
+
+fn device_address(
+    ray_tracing: &RayTracingContext,
+    acceleration_structure: vk::AccelerationStructureKHR,
+) -> u64 {
+    let address_info = vk::AccelerationStructureDeviceAddressInfoKHR::default()
+        .acceleration_structure(acceleration_structure);
+    unsafe {
+        ray_tracing
+            .acceleration_structure_fn
+            .get_acceleration_structure_device_address(&address_info)
+    }
 }
 
 impl Context {
@@ -100,9 +261,25 @@ impl Context {
         as_geometry: &[vk::AccelerationStructureGeometryKHR],
         as_ranges: &[vk::AccelerationStructureBuildRangeInfoKHR],
         max_primitive_counts: &[u32],
+    ) -> Result<AccelerationStructure> {
+        self.create_bottom_level_acceleration_structure_with_flags(
+            as_geometry,
+            as_ranges,
+            max_primitive_counts,
+            DEFAULT_BUILD_FLAGS,
+        )
+    }
+
+    /// Use `ALLOW_COMPACTION` in `flags` to be able to call [`Context::compact_blas`].
+    pub fn create_bottom_level_acceleration_structure_with_flags(
+        &self,
+        as_geometry: &[vk::AccelerationStructureGeometryKHR],
+        as_ranges: &[vk::AccelerationStructureBuildRangeInfoKHR],
+        max_primitive_counts: &[u32],
+        flags: vk::BuildAccelerationStructureFlagsKHR,
     ) -> Result<AccelerationStructure> {
         let ray_tracing = self.ray_tracing.clone().expect(
-            "Cannot call Context::create_bottom_level_acceleration_structure when ray tracing is not enabled",
+            "Cannot call Context::create_bottom_level_acceleration_structure_with_flags when ray tracing is not enabled",
         );
 
         AccelerationStructure::new(
@@ -112,17 +289,177 @@ impl Context {
             as_geometry,
             as_ranges,
             max_primitive_counts,
+            flags,
         )
     }
 
+    /// Copies a bottom level acceleration structure built with the `ALLOW_COMPACTION` flag
+    /// into a new one using only the memory it needs, often much less than what the build
+    /// required. The original is dropped once the copy completed.
+    ///
+    /// Top level acceleration structures and instances must reference the address of the
+    /// compacted structure, so compact before building them.
+    pub fn compact_blas(
+        &self,
+        mut acceleration_structure: AccelerationStructure,
+    ) -> Result<AccelerationStructure> {
+        let ray_tracing = self
+            .ray_tracing
+            .clone()
+            .expect("Cannot call Context::compact_blas when ray tracing is not enabled");
+
+        if acceleration_structure.level != vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL {
+            return Err(anyhow::anyhow!(
+                "Only bottom level acceleration structures can be compacted"
+            ));
+        }
+        if !acceleration_structure
+            .flags
+            .contains(vk::BuildAccelerationStructureFlagsKHR::ALLOW_COMPACTION)
+        {
+            return Err(anyhow::anyhow!(
+                "Acceleration structure was not built with the ALLOW_COMPACTION flag"
+            ));
+        }
+
+        let compacted_size = self.query_compacted_size(&ray_tracing, &acceleration_structure)?;
+
+        let (inner, buffer) = create_storage(
+            self,
+            &ray_tracing,
+            acceleration_structure.level,
+            compacted_size,
+        )?;
+
+        let copy_info = vk::CopyAccelerationStructureInfoKHR::default()
+            .src(acceleration_structure.inner)
+            .dst(inner)
+            .mode(vk::CopyAccelerationStructureModeKHR::COMPACT);
+        self.execute_one_time_commands(|cmd_buffer| unsafe {
+            ray_tracing
+                .acceleration_structure_fn
+                .cmd_copy_acceleration_structure(cmd_buffer.inner, &copy_info)
+        })?;
+
+        log::debug!(
+            "Compacted BLAS from {} to {compacted_size} bytes",
+            acceleration_structure._buffer.size
+        );
+
+        let address = device_address(&ray_tracing, inner);
+
+        Ok(AccelerationStructure {
+            ray_tracing,
+            inner,
+            _buffer: buffer,
+            address,
+            level: acceleration_structure.level,
+            flags: acceleration_structure.flags,
+            update_scratch_buffer: acceleration_structure.update_scratch_buffer.take(),
+        })
+    }
+
+    fn query_compacted_size(
+        &self,
+        ray_tracing: &RayTracingContext,
+        acceleration_structure: &AccelerationStructure,
+    ) -> Result<vk::DeviceSize> {
+        let create_info = vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::ACCELERATION_STRUCTURE_COMPACTED_SIZE_KHR)
+            .query_count(1);
+        let query_pool = unsafe { self.device.inner.create_query_pool(&create_info, None)? };
+
+        let result = self
+            .execute_one_time_commands(|cmd_buffer| unsafe {
+                self.device
+                    .inner
+                    .cmd_reset_query_pool(cmd_buffer.inner, query_pool, 0, 1);
+                ray_tracing
+                    .acceleration_structure_fn
+                    .cmd_write_acceleration_structures_properties(
+                        cmd_buffer.inner,
+                        &[acceleration_structure.inner],
+                        vk::QueryType::ACCELERATION_STRUCTURE_COMPACTED_SIZE_KHR,
+                        query_pool,
+                        0,
+                    );
+            })
+            .and_then(|_| {
+                let mut size = [0u64];
+                unsafe {
+                    self.device.inner.get_query_pool_results(
+                        query_pool,
+                        0,
+                        &mut size,
+                        vk::QueryResultFlags::WAIT | vk::QueryResultFlags::TYPE_64,
+                    )?
+                };
+                Ok(size[0])
+            });
+
+        unsafe { self.device.inner.destroy_query_pool(query_pool, None) };
+
+        result
+    }
+
     pub fn create_top_level_acceleration_structure(
         &self,
         as_geometry: &[vk::AccelerationStructureGeometryKHR],
         as_ranges: &[vk::AccelerationStructureBuildRangeInfoKHR],
         max_primitive_counts: &[u32],
+    ) -> Result<AccelerationStructure> {
+        self.create_top_level_acceleration_structure_with_flags(
+            as_geometry,
+            as_ranges,
+            max_primitive_counts,
+            DEFAULT_BUILD_FLAGS,
+        )
+    }
+
+    /// Builds a top level acceleration structure over `instances`.
+    ///
+    /// The instance buffer is only used for the build and released after. Use
+    /// [`Context::create_top_level_acceleration_structure_with_flags`] with your own instance
+    /// buffer to update the structure later.
+    pub fn create_top_level_acceleration_structure_from_instances(
+        &self,
+        instances: &[TlasInstance],
+    ) -> Result<AccelerationStructure> {
+        let vk_instances = to_vk_instances(instances)?;
+        let instance_buffer = create_gpu_only_buffer_from_data(
+            self,
+            vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
+            &vk_instances,
+        )?;
+
+        let as_geometry = vk::AccelerationStructureGeometryKHR::default()
+            .geometry_type(vk::GeometryTypeKHR::INSTANCES)
+            .flags(vk::GeometryFlagsKHR::OPAQUE)
+            .geometry(vk::AccelerationStructureGeometryDataKHR {
+                instances: vk::AccelerationStructureGeometryInstancesDataKHR::default()
+                    .array_of_pointers(false)
+                    .data(vk::DeviceOrHostAddressConstKHR {
+                        device_address: instance_buffer.get_device_address(),
+                    }),
+            });
+        let instance_count = instances.len() as u32;
+        let as_range =
+            vk::AccelerationStructureBuildRangeInfoKHR::default().primitive_count(instance_count);
+
+        self.create_top_level_acceleration_structure(&[as_geometry], &[as_range], &[instance_count])
+    }
+
+    /// Use `ALLOW_UPDATE` in `flags` to be able to call [`AccelerationStructure::update`].
+    pub fn create_top_level_acceleration_structure_with_flags(
+        &self,
+        as_geometry: &[vk::AccelerationStructureGeometryKHR],
+        as_ranges: &[vk::AccelerationStructureBuildRangeInfoKHR],
+        max_primitive_counts: &[u32],
+        flags: vk::BuildAccelerationStructureFlagsKHR,
     ) -> Result<AccelerationStructure> {
         let ray_tracing = self.ray_tracing.clone().expect(
-            "Cannot call Context::create_top_level_acceleration_structure when ray tracing is not enabled",
+            "Cannot call Context::create_top_level_acceleration_structure_with_flags when ray tracing is not enabled",
         );
 
         AccelerationStructure::new(
@@ -132,10 +469,36 @@ impl Context {
             as_geometry,
             as_ranges,
             max_primitive_counts,
+            flags,
         )
     }
 }
 
+/// Checks that `instances` is not empty and that their 24 bits fields fit before converting them.
+fn to_vk_instances(
+    instances: &[TlasInstance],
+) -> Result<Vec<vk::AccelerationStructureInstanceKHR>> {
+    if instances.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Cannot build a top level acceleration structure without instances"
+        ));
+    }
+    if let Some(instance) = instances.iter().find(|i| {
+        i.custom_index > MAX_INSTANCE_24_BITS_VALUE || i.sbt_offset > MAX_INSTANCE_24_BITS_VALUE
+    }) {
+        return Err(anyhow::anyhow!(
+            "Instance custom index {} and SBT offset {} must fit in 24 bits",
+            instance.custom_index,
+            instance.sbt_offset
+        ));
+    }
+
+    Ok(instances
+        .iter()
+        .map(|i| vk::AccelerationStructureInstanceKHR::from(*i))
+        .collect())
+}
+
 impl Drop for AccelerationStructure {
     fn drop(&mut self) {
         unsafe {
@@ -145,3 +508,53 @@ impl Drop for AccelerationStructure {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_every_instance() {
+        let instances = [1, 2, 3].map(|i| TlasInstance {
+            custom_index: i * 10,
+            sbt_offset: i,
+            ..TlasInstance::new(i as u64 * 0x100)
+        });
+
+        let vk_instances = to_vk_instances(&instances).unwrap();
+
+        assert_eq!(vk_instances.len(), 3);
+        for (instance, vk_instance) in instances.iter().zip(&vk_instances) {
+            assert_eq!(
+                vk_instance.instance_custom_index_and_mask.low_24(),
+                instance.custom_index
+            );
+            assert_eq!(vk_instance.instance_custom_index_and_mask.high_8(), 0xFF);
+            assert_eq!(
+                vk_instance
+                    .instance_shader_binding_table_record_offset_and_flags
+                    .low_24(),
+                instance.sbt_offset
+            );
+            assert_eq!(
+                unsafe { vk_instance.acceleration_structure_reference.device_handle },
+                instance.blas_address
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_custom_index_over_24_bits() {
+        let instance = TlasInstance {
+            custom_index: MAX_INSTANCE_24_BITS_VALUE + 1,
+            ..TlasInstance::new(0x100)
+        };
+
+        assert!(to_vk_instances(&[instance]).is_err());
+    }
+
+    #[test]
+    fn rejects_empty_instances() {
+        assert!(to_vk_instances(&[]).is_err());
+    }
+}
@@ -1,11 +1,14 @@
-use std::{ffi::CString, sync::Arc};
+use std::{ffi::CString, path::Path, sync::Arc, time::Instant};
 
 use anyhow::Result;
 use ash::vk;
 
 use crate::{device::Device, Context};
 
-use crate::{PipelineLayout, RayTracingContext, ShaderModule};
+use crate::{
+    pipeline::{read_shader_files, take_created_pipeline},
+    PipelineCache, PipelineLayout, RayTracingContext, ShaderModule,
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct RayTracingPipelineCreateInfo<'a> {
@@ -17,14 +20,22 @@ pub struct RayTracingPipelineCreateInfo<'a> {
 pub struct RayTracingShaderCreateInfo<'a> {
     pub source: &'a [u8],
     pub stage: vk::ShaderStageFlags,
+    pub entry_point: &'a str,
     pub group: RayTracingShaderGroup,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Group a shader belongs to.
+///
+/// Consecutive `ClosestHit`, `AnyHit` and `Intersection` shaders are merged into a single hit
+/// group, until one of them repeats or a general shader is declared. Hit groups with an
+/// intersection shader are procedural, the others are triangle hit groups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RayTracingShaderGroup {
     RayGen,
     Miss,
     ClosestHit,
+    AnyHit,
+    Intersection,
 }
 
 pub struct RayTracingPipeline {
@@ -33,11 +44,13 @@ pub struct RayTracingPipeline {
     pub(crate) shader_group_info: RayTracingShaderGroupInfo,
 }
 
+/// Number of shader groups of each kind, in declaration order.
 #[derive(Debug, Clone, Copy, Default)]
 pub struct RayTracingShaderGroupInfo {
     pub group_count: u32,
     pub raygen_shader_count: u32,
     pub miss_shader_count: u32,
+    /// Number of hit groups, not of hit shaders.
     pub hit_shader_count: u32,
 }
 
@@ -47,69 +60,98 @@ impl RayTracingPipeline {
         ray_tracing: &RayTracingContext,
         layout: &PipelineLayout,
         create_info: RayTracingPipelineCreateInfo,
+        cache: Option<&PipelineCache>,
     ) -> Result<Self> {
-        let mut shader_group_info = RayTracingShaderGroupInfo {
-            group_count: create_info.shaders.len() as _,
-            ..Default::default()
-        };
+        let mut shader_group_info = RayTracingShaderGroupInfo::default();
 
         let mut modules = vec![];
         let mut stages = vec![];
-        let mut groups = vec![];
+        let mut groups = Vec::<vk::RayTracingShaderGroupCreateInfoKHR>::new();
 
-        let entry_point_name = CString::new("main").unwrap();
+        let entry_point_names = create_info
+            .shaders
+            .iter()
+            .map(|s| CString::new(s.entry_point))
+            .collect::<Result<Vec<_>, _>>()?;
 
-        for (shader_index, shader) in create_info.shaders.iter().enumerate() {
+        for (shader_index, (shader, entry_point_name)) in create_info
+            .shaders
+            .iter()
+            .zip(&entry_point_names)
+            .enumerate()
+        {
             let module = ShaderModule::from_bytes(device.clone(), shader.source)?;
 
             let stage = vk::PipelineShaderStageCreateInfo::default()
                 .stage(shader.stage)
                 .module(module.inner)
-                .name(&entry_point_name);
-
-            match shader.group {
-                RayTracingShaderGroup::RayGen => shader_group_info.raygen_shader_count += 1,
-                RayTracingShaderGroup::Miss => shader_group_info.miss_shader_count += 1,
-                RayTracingShaderGroup::ClosestHit => shader_group_info.hit_shader_count += 1,
-            };
-
-            let mut group = vk::RayTracingShaderGroupCreateInfoKHR::default()
-                .ty(vk::RayTracingShaderGroupTypeKHR::GENERAL)
-                .general_shader(vk::SHADER_UNUSED_KHR)
-                .closest_hit_shader(vk::SHADER_UNUSED_KHR)
-                .any_hit_shader(vk::SHADER_UNUSED_KHR)
-                .intersection_shader(vk::SHADER_UNUSED_KHR);
-            group = match shader.group {
-                RayTracingShaderGroup::RayGen | RayTracingShaderGroup::Miss => {
-                    group.general_shader(shader_index as _)
-                }
-                RayTracingShaderGroup::ClosestHit => group
+                .name(entry_point_name);
+
+            let shader_index = shader_index as u32;
+            let is_hit_shader = !matches!(
+                shader.group,
+                RayTracingShaderGroup::RayGen | RayTracingShaderGroup::Miss
+            );
+
+            // Hit shaders go into the previous hit group while their slot is free
+            let open_hit_group = groups.last_mut().filter(|g| {
+                is_hit_shader
+                    && g.ty != vk::RayTracingShaderGroupTypeKHR::GENERAL
+                    && hit_group_slot(g, shader.group) == vk::SHADER_UNUSED_KHR
+            });
+
+            if let Some(group) = open_hit_group {
+                set_hit_group_shader(group, shader.group, shader_index);
+            } else {
+                let mut group = vk::RayTracingShaderGroupCreateInfoKHR::default()
                     .ty(vk::RayTracingShaderGroupTypeKHR::TRIANGLES_HIT_GROUP)
-                    .closest_hit_shader(shader_index as _),
-            };
+                    .general_shader(vk::SHADER_UNUSED_KHR)
+                    .closest_hit_shader(vk::SHADER_UNUSED_KHR)
+                    .any_hit_shader(vk::SHADER_UNUSED_KHR)
+                    .intersection_shader(vk::SHADER_UNUSED_KHR);
+                match shader.group {
+                    RayTracingShaderGroup::RayGen => {
+                        shader_group_info.raygen_shader_count += 1;
+                        group = group
+                            .ty(vk::RayTracingShaderGroupTypeKHR::GENERAL)
+                            .general_shader(shader_index);
+                    }
+                    RayTracingShaderGroup::Miss => {
+                        shader_group_info.miss_shader_count += 1;
+                        group = group
+                            .ty(vk::RayTracingShaderGroupTypeKHR::GENERAL)
+                            .general_shader(shader_index);
+                    }
+                    hit => {
+                        shader_group_info.hit_shader_count += 1;
+                        set_hit_group_shader(&mut group, hit, shader_index);
+                    }
+                }
+                groups.push(group);
+            }
 
             modules.push(module);
             stages.push(stage);
-            groups.push(group);
         }
 
+        shader_group_info.group_count = groups.len() as _;
+
         let pipe_info = vk::RayTracingPipelineCreateInfoKHR::default()
             .layout(layout.inner)
             .stages(&stages)
             .groups(&groups)
             .max_pipeline_ray_recursion_depth(2);
 
-        let inner = unsafe {
-            ray_tracing
-                .pipeline_fn
-                .create_ray_tracing_pipelines(
-                    vk::DeferredOperationKHR::null(),
-                    vk::PipelineCache::null(),
-                    std::slice::from_ref(&pipe_info),
-                    None,
-                )
-                .map_err(|e| e.1)?[0]
+        let started = Instant::now();
+        let result = unsafe {
+            ray_tracing.pipeline_fn.create_ray_tracing_pipelines(
+                vk::DeferredOperationKHR::null(),
+                cache.map_or(vk::PipelineCache::null(), |c| c.inner),
+                std::slice::from_ref(&pipe_info),
+                None,
+            )
         };
+        let inner = take_created_pipeline(&device, "ray tracing", started, result)?;
 
         Ok(Self {
             device,
@@ -119,17 +161,94 @@ impl RayTracingPipeline {
     }
 }
 
+fn hit_group_slot(
+    group: &vk::RayTracingShaderGroupCreateInfoKHR,
+    shader_group: RayTracingShaderGroup,
+) -> u32 {
+    match shader_group {
+        RayTracingShaderGroup::ClosestHit => group.closest_hit_shader,
+        RayTracingShaderGroup::AnyHit => group.any_hit_shader,
+        RayTracingShaderGroup::Intersection => group.intersection_shader,
+        RayTracingShaderGroup::RayGen | RayTracingShaderGroup::Miss => group.general_shader,
+    }
+}
+
+fn set_hit_group_shader(
+    group: &mut vk::RayTracingShaderGroupCreateInfoKHR,
+    shader_group: RayTracingShaderGroup,
+    shader_index: u32,
+) {
+    match shader_group {
+        RayTracingShaderGroup::ClosestHit => group.closest_hit_shader = shader_index,
+        RayTracingShaderGroup::AnyHit => group.any_hit_shader = shader_index,
+        RayTracingShaderGroup::Intersection => {
+            group.ty = vk::RayTracingShaderGroupTypeKHR::PROCEDURAL_HIT_GROUP;
+            group.intersection_shader = shader_index;
+        }
+        RayTracingShaderGroup::RayGen | RayTracingShaderGroup::Miss => {
+            unreachable!("{shader_group:?} shaders are not part of hit groups")
+        }
+    }
+}
+
 impl Context {
     pub fn create_ray_tracing_pipeline(
         &self,
         layout: &PipelineLayout,
         create_info: RayTracingPipelineCreateInfo,
+        cache: Option<&PipelineCache>,
     ) -> Result<RayTracingPipeline> {
         let ray_tracing = self.ray_tracing.as_ref().expect(
             "Cannot call Context::create_ray_tracing_pipeline when ray tracing is not enabled",
         );
 
-        RayTracingPipeline::new(self.device.clone(), ray_tracing, layout, create_info)
+        RayTracingPipeline::new(self.device.clone(), ray_tracing, layout, create_info, cache)
+    }
+
+    /// Like [`Context::create_ray_tracing_pipeline`] but the SPIR-V of each shader is read from
+    /// `shader_paths`, in the order of `create_info.shaders` whose `source` is ignored.
+    pub fn create_ray_tracing_pipeline_from_files(
+        &self,
+        layout: &PipelineLayout,
+        create_info: RayTracingPipelineCreateInfo,
+        shader_paths: &[impl AsRef<Path>],
+        cache: Option<&PipelineCache>,
+    ) -> Result<RayTracingPipeline> {
+        let sources = read_shader_files(create_info.shaders.len(), shader_paths)?;
+        let shaders = create_info
+            .shaders
+            .iter()
+            .zip(&sources)
+            .map(|(shader, source)| RayTracingShaderCreateInfo { source, ..*shader })
+            .collect::<Vec<_>>();
+
+        self.create_ray_tracing_pipeline(
+            layout,
+            RayTracingPipelineCreateInfo {
+                shaders: &shaders,
+                ..create_info
+            },
+            cache,
+        )
+    }
+}
+
+impl RayTracingPipeline {
+    /// Same as [`GraphicsPipeline::reload`](crate::GraphicsPipeline::reload) for a ray tracing
+    /// pipeline. Shader group handles change so shader binding tables created from the previous
+    /// pipeline must be recreated.
+    pub fn reload(
+        &mut self,
+        context: &Context,
+        layout: &PipelineLayout,
+        create_info: RayTracingPipelineCreateInfo,
+        cache: Option<&PipelineCache>,
+    ) -> Result<()> {
+        let pipeline = context.create_ray_tracing_pipeline(layout, create_info, cache)?;
+        context.device_wait_idle()?;
+        *self = pipeline;
+
+        Ok(())
     }
 }
 
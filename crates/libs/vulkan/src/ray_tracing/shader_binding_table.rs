@@ -4,6 +4,24 @@ use gpu_allocator::MemoryLocation;
 
 use crate::{utils::compute_aligned_size, Buffer, Context, RayTracingContext, RayTracingPipeline};
 
+/// Raygen, miss and hit regions, each holding one record per shader group of that kind in the
+/// order the groups were declared in the pipeline.
+///
+/// Records of a region share a stride of `shaderGroupHandleSize` plus the largest inline data
+/// of the region, aligned to `shaderGroupHandleAlignment`. Regions start on
+/// `shaderGroupBaseAlignment`.
+///
+/// The hit group used for an intersection is the record at index
+///
+/// ```text
+/// instance_shader_binding_table_record_offset + geometry_index * sbtRecordStride + sbtRecordOffset
+/// ```
+///
+/// where the instance offset is set in [`vk::AccelerationStructureInstanceKHR`], the geometry
+/// index is the index of the geometry in its bottom level acceleration structure and the last
+/// two are arguments of `traceRayEXT`. Giving each instance a different offset is how
+/// instances select different materials. Likewise, the `missIndex` argument of `traceRayEXT`
+/// selects the miss record.
 pub struct ShaderBindingTable {
     _buffer: Buffer,
     pub(crate) raygen_region: vk::StridedDeviceAddressRegionKHR,
@@ -11,11 +29,28 @@ pub struct ShaderBindingTable {
     pub(crate) hit_region: vk::StridedDeviceAddressRegionKHR,
 }
 
+/// Inline data appended after the handle of each shader record, read in shaders with
+///
+/// ```glsl
+/// layout(shaderRecordEXT, std430) buffer ShaderRecord { uint materialIndex; };
+/// ```
+///
+/// Each slice must either be empty or contain one entry per shader group of that kind,
+/// in the order the groups were declared in the pipeline. Records of a kind share the
+/// same stride so shorter data is padded with zeros.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShaderRecords<'a> {
+    pub raygen: &'a [&'a [u8]],
+    pub miss: &'a [&'a [u8]],
+    pub hit: &'a [&'a [u8]],
+}
+
 impl ShaderBindingTable {
     pub(crate) fn new(
         context: &Context,
         ray_tracing: &RayTracingContext,
         pipeline: &RayTracingPipeline,
+        records: ShaderRecords,
     ) -> Result<Self> {
         let desc = pipeline.shader_group_info;
 
@@ -24,10 +59,8 @@ impl ShaderBindingTable {
         let handle_alignment = ray_tracing
             .pipeline_properties
             .shader_group_handle_alignment;
-        let aligned_handle_size = compute_aligned_size(handle_size, handle_alignment);
-        let handle_pad = aligned_handle_size - handle_size;
-
         let group_alignment = ray_tracing.pipeline_properties.shader_group_base_alignment;
+        let max_stride = ray_tracing.pipeline_properties.max_shader_group_stride;
 
         // Get Handles
         let data_size = desc.group_count * handle_size;
@@ -42,53 +75,63 @@ impl ShaderBindingTable {
                 )?
         };
 
-        // Region sizes
-        let raygen_region_size = compute_aligned_size(
-            desc.raygen_shader_count * aligned_handle_size,
-            group_alignment,
-        );
+        let groups = [
+            ("raygen", desc.raygen_shader_count, records.raygen),
+            ("miss", desc.miss_shader_count, records.miss),
+            ("hit", desc.hit_shader_count, records.hit),
+        ];
 
-        let miss_region_size = compute_aligned_size(
-            desc.miss_shader_count * aligned_handle_size,
-            group_alignment,
-        );
-        let hit_region_size =
-            compute_aligned_size(desc.hit_shader_count * aligned_handle_size, group_alignment);
+        // Record strides & region sizes
+        let mut strides = [0; 3];
+        let mut region_sizes = [0; 3];
+        for (i, (kind, shader_count, data)) in groups.iter().enumerate() {
+            if !data.is_empty() && data.len() != *shader_count as usize {
+                return Err(anyhow::anyhow!(
+                    "Got {} {kind} shader records but the pipeline has {shader_count} {kind} groups",
+                    data.len()
+                ));
+            }
+
+            let max_data_size = data.iter().map(|d| d.len() as u32).max().unwrap_or(0);
+            let stride = compute_aligned_size(handle_size + max_data_size, handle_alignment);
+            if stride > max_stride {
+                return Err(anyhow::anyhow!(
+                    "{kind} shader record stride {stride} exceeds the maximum of {max_stride}"
+                ));
+            }
+
+            strides[i] = stride;
+            region_sizes[i] = compute_aligned_size(shader_count * stride, group_alignment);
+        }
 
         // Create sbt data
-        let buffer_size = raygen_region_size + miss_region_size + hit_region_size;
+        let buffer_size = region_sizes.iter().sum::<u32>();
         let mut stb_data = Vec::<u8>::with_capacity(buffer_size as _);
-        let groups_shader_count = [
-            desc.raygen_shader_count,
-            desc.miss_shader_count,
-            desc.hit_shader_count,
-        ];
 
         let mut offset = 0;
         // for each groups
-        for group_shader_count in groups_shader_count {
-            let group_size = group_shader_count * aligned_handle_size;
-            let aligned_group_size = compute_aligned_size(group_size, group_alignment);
-            let group_pad = aligned_group_size - group_size;
+        for (i, (_, shader_count, data)) in groups.iter().enumerate() {
+            let region_start = stb_data.len();
 
             // for each handle
-            for _ in 0..group_shader_count {
+            for shader_index in 0..*shader_count as usize {
+                let record_start = stb_data.len();
+
                 //copy handle
-                for _ in 0..handle_size as usize {
-                    stb_data.push(handles[offset]);
-                    offset += 1;
-                }
+                stb_data.extend_from_slice(&handles[offset..offset + handle_size as usize]);
+                offset += handle_size as usize;
 
-                // pad handle to alignment
-                for _ in 0..handle_pad {
-                    stb_data.push(0);
+                // copy record data
+                if let Some(data) = data.get(shader_index) {
+                    stb_data.extend_from_slice(data);
                 }
+
+                // pad record to stride
+                stb_data.resize(record_start + strides[i] as usize, 0);
             }
 
             // pad group to alignment
-            for _ in 0..group_pad {
-                stb_data.push(0);
-            }
+            stb_data.resize(region_start + region_sizes[i] as usize, 0);
         }
 
         // Create buffer
@@ -102,6 +145,9 @@ impl ShaderBindingTable {
 
         let address = buffer.get_device_address();
 
+        let [raygen_region_size, miss_region_size, hit_region_size] = region_sizes;
+        let [_, miss_stride, hit_stride] = strides;
+
         // see https://nvpro-samples.github.io/vk_raytracing_tutorial_KHR/Images/sbt_0.png
         let raygen_region = vk::StridedDeviceAddressRegionKHR::default()
             .device_address(address)
@@ -111,12 +157,12 @@ impl ShaderBindingTable {
         let miss_region = vk::StridedDeviceAddressRegionKHR::default()
             .device_address(address + raygen_region.size)
             .size(miss_region_size as _)
-            .stride(aligned_handle_size as _);
+            .stride(miss_stride as _);
 
         let hit_region = vk::StridedDeviceAddressRegionKHR::default()
             .device_address(address + raygen_region.size + miss_region.size)
             .size(hit_region_size as _)
-            .stride(aligned_handle_size as _);
+            .stride(hit_stride as _);
 
         Ok(Self {
             _buffer: buffer,
@@ -136,6 +182,19 @@ impl Context {
             "Cannot call Context::create_shader_binding_table when ray tracing is not enabled",
         );
 
-        ShaderBindingTable::new(self, ray_tracing, pipeline)
+        ShaderBindingTable::new(self, ray_tracing, pipeline, Default::default())
+    }
+
+    /// Creates a shader binding table with inline data appended to the shader group handles.
+    pub fn create_shader_binding_table_with_records(
+        &self,
+        pipeline: &RayTracingPipeline,
+        records: ShaderRecords,
+    ) -> Result<ShaderBindingTable> {
+        let ray_tracing = self.ray_tracing.as_ref().expect(
+            "Cannot call Context::create_shader_binding_table_with_records when ray tracing is not enabled",
+        );
+
+        ShaderBindingTable::new(self, ray_tracing, pipeline, records)
     }
 }
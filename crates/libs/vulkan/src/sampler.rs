@@ -18,10 +18,92 @@ impl Sampler {
     }
 }
 
+/// Common sampler settings, turned into a [`vk::SamplerCreateInfo`] by
+/// [`Context::create_sampler_from_info`].
+///
+/// Presets sample all mip levels of the image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerInfo {
+    pub filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode: vk::SamplerAddressMode,
+    /// Requires the `sampler_anisotropy` device feature. Clamped to the device limit.
+    pub max_anisotropy: Option<f32>,
+    pub min_lod: f32,
+    pub max_lod: f32,
+}
+
+impl SamplerInfo {
+    pub fn linear_repeat() -> Self {
+        Self {
+            filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode: vk::SamplerAddressMode::REPEAT,
+            max_anisotropy: None,
+            min_lod: 0.0,
+            max_lod: vk::LOD_CLAMP_NONE,
+        }
+    }
+
+    pub fn linear_clamp() -> Self {
+        Self {
+            address_mode: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            ..Self::linear_repeat()
+        }
+    }
+
+    /// Linear repeat sampler with up to `max_anisotropy` samples.
+    pub fn anisotropic(max_anisotropy: f32) -> Self {
+        Self::linear_repeat().max_anisotropy(Some(max_anisotropy))
+    }
+
+    pub fn address_mode(mut self, address_mode: vk::SamplerAddressMode) -> Self {
+        self.address_mode = address_mode;
+        self
+    }
+
+    pub fn max_anisotropy(mut self, max_anisotropy: Option<f32>) -> Self {
+        self.max_anisotropy = max_anisotropy;
+        self
+    }
+
+    pub fn lod_range(mut self, min_lod: f32, max_lod: f32) -> Self {
+        self.min_lod = min_lod;
+        self.max_lod = max_lod;
+        self
+    }
+}
+
 impl Context {
     pub fn create_sampler(&self, create_info: &vk::SamplerCreateInfo) -> Result<Sampler> {
         Sampler::new(self.device.clone(), create_info)
     }
+
+    pub fn create_sampler_from_info(&self, info: &SamplerInfo) -> Result<Sampler> {
+        if info.max_anisotropy.is_some() && !self.device.enabled_features.sampler_anisotropy {
+            return Err(anyhow::anyhow!(
+                "Anisotropic filtering requires the samplerAnisotropy feature"
+            ));
+        }
+
+        let max_anisotropy = info
+            .max_anisotropy
+            .map(|a| a.clamp(1.0, self.physical_device_limits().max_sampler_anisotropy));
+
+        let create_info = vk::SamplerCreateInfo::default()
+            .mag_filter(info.filter)
+            .min_filter(info.filter)
+            .mipmap_mode(info.mipmap_mode)
+            .address_mode_u(info.address_mode)
+            .address_mode_v(info.address_mode)
+            .address_mode_w(info.address_mode)
+            .anisotropy_enable(max_anisotropy.is_some())
+            .max_anisotropy(max_anisotropy.unwrap_or(1.0))
+            .min_lod(info.min_lod)
+            .max_lod(info.max_lod);
+
+        self.create_sampler(&create_info)
+    }
 }
 
 impl Drop for Sampler {
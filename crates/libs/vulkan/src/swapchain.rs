@@ -3,7 +3,10 @@ use std::sync::Arc;
 use anyhow::Result;
 use ash::{khr::swapchain, vk};
 
-use crate::{device::Device, Context, Image, ImageView, Queue, Semaphore};
+use crate::{
+    device::Device, error::DeviceLostExt, surface::Surface, Context, Image, ImageBarrier,
+    ImageView, Queue, Semaphore,
+};
 
 pub struct AcquiredImage {
     pub index: u32,
@@ -18,15 +21,22 @@ pub struct Swapchain {
     pub format: vk::Format,
     pub color_space: vk::ColorSpaceKHR,
     pub present_mode: vk::PresentModeKHR,
+    pub usage: vk::ImageUsageFlags,
     pub images: Vec<Image>,
     pub views: Vec<ImageView>,
 }
 
 impl Swapchain {
-    pub fn new(context: &Context, width: u32, height: u32) -> Result<Self> {
+    pub fn new(
+        context: &Context,
+        width: u32,
+        height: u32,
+        present_mode: vk::PresentModeKHR,
+    ) -> Result<Self> {
         log::debug!("Creating vulkan swapchain");
 
         let device = context.device.clone();
+        let surface = require_surface(context)?;
 
         // Swapchain format
         let formats = &context.supported_surface_formats;
@@ -37,36 +47,24 @@ impl Swapchain {
                 format.format == vk::Format::R8G8B8A8_SRGB
                     && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
             })
+            .or_else(|| {
+                formats.iter().find(|format| {
+                    is_srgb_format(format.format)
+                        && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+                })
+            })
             .unwrap_or(&formats[0]);
         log::debug!("Swapchain format: {format:?}");
 
         // Swapchain present mode
-        let present_mode = {
-            let present_modes = unsafe {
-                context
-                    .surface
-                    .inner
-                    .get_physical_device_surface_present_modes(
-                        context.physical_device.inner,
-                        context.surface.surface_khr,
-                    )?
-            };
-            if present_modes.contains(&vk::PresentModeKHR::IMMEDIATE) {
-                vk::PresentModeKHR::IMMEDIATE
-            } else {
-                vk::PresentModeKHR::FIFO
-            }
-        };
+        let present_mode = choose_present_mode(context, present_mode);
         log::debug!("Swapchain present mode: {present_mode:?}");
 
         let capabilities = unsafe {
-            context
-                .surface
-                .inner
-                .get_physical_device_surface_capabilities(
-                    context.physical_device.inner,
-                    context.surface.surface_khr,
-                )?
+            surface.inner.get_physical_device_surface_capabilities(
+                context.physical_device.inner,
+                surface.surface_khr,
+            )?
         };
 
         // Swapchain extent
@@ -87,6 +85,10 @@ impl Swapchain {
         let image_count = capabilities.min_image_count + 1;
         log::debug!("Swapchain image count: {image_count:?}");
 
+        // Swapchain usage
+        let usage = choose_usage(&capabilities);
+        log::debug!("Swapchain usage: {usage:?}");
+
         // Swapchain
         let families_indices = [
             context.graphics_queue_family.index,
@@ -95,15 +97,13 @@ impl Swapchain {
 
         let create_info = {
             let mut builder = vk::SwapchainCreateInfoKHR::default()
-                .surface(context.surface.surface_khr)
+                .surface(surface.surface_khr)
                 .min_image_count(image_count)
                 .image_format(format.format)
                 .image_color_space(format.color_space)
                 .image_extent(extent)
                 .image_array_layers(1)
-                .image_usage(
-                    vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST,
-                );
+                .image_usage(usage);
 
             builder = if context.graphics_queue_family.index != context.present_queue_family.index {
                 builder
@@ -151,6 +151,7 @@ impl Swapchain {
             format: format.format,
             color_space: format.color_space,
             present_mode,
+            usage,
             images,
             views,
         })
@@ -162,9 +163,12 @@ impl Swapchain {
         width: u32,
         height: u32,
         format: Option<vk::SurfaceFormatKHR>,
+        present_mode: Option<vk::PresentModeKHR>,
     ) -> Result<()> {
         log::debug!("Resizing vulkan swapchain to {width}x{height}");
 
+        let surface = require_surface(context)?;
+
         self.destroy();
 
         if let Some(format) = format {
@@ -176,14 +180,16 @@ impl Swapchain {
             }
         }
 
+        if let Some(present_mode) = present_mode {
+            self.present_mode = choose_present_mode(context, present_mode);
+            log::debug!("Swapchain present mode: {:?}", self.present_mode);
+        }
+
         let capabilities = unsafe {
-            context
-                .surface
-                .inner
-                .get_physical_device_surface_capabilities(
-                    context.physical_device.inner,
-                    context.surface.surface_khr,
-                )?
+            surface.inner.get_physical_device_surface_capabilities(
+                context.physical_device.inner,
+                surface.surface_khr,
+            )?
         };
 
         // Swapchain extent
@@ -203,6 +209,9 @@ impl Swapchain {
         // Swapchain image count
         let image_count = capabilities.min_image_count;
 
+        // Swapchain usage
+        let usage = choose_usage(&capabilities);
+
         // Swapchain
         let families_indices = [
             context.graphics_queue_family.index,
@@ -211,15 +220,13 @@ impl Swapchain {
 
         let create_info = {
             let mut builder = vk::SwapchainCreateInfoKHR::default()
-                .surface(context.surface.surface_khr)
+                .surface(surface.surface_khr)
                 .min_image_count(image_count)
                 .image_format(self.format)
                 .image_color_space(self.color_space)
                 .image_extent(extent)
                 .image_array_layers(1)
-                .image_usage(
-                    vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST,
-                );
+                .image_usage(usage);
 
             builder = if context.graphics_queue_family.index != context.present_queue_family.index {
                 builder
@@ -260,12 +267,58 @@ impl Swapchain {
 
         self.swapchain_khr = swapchain_khr;
         self.extent = extent;
+        self.usage = usage;
         self.images = images;
         self.views = views;
 
         Ok(())
     }
 
+    /// Transitions all images from `UNDEFINED` to `PRESENT_SRC_KHR` so they are in a known
+    /// layout before the first frame. Images are recreated by [`Swapchain::update`] so this
+    /// must be called again after it.
+    pub fn initialize_layouts(&self, context: &Context) -> Result<()> {
+        let barriers = self
+            .frames()
+            .map(|(_, image, _)| ImageBarrier {
+                image,
+                old_layout: vk::ImageLayout::UNDEFINED,
+                new_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+                src_access_mask: vk::AccessFlags2::NONE,
+                dst_access_mask: vk::AccessFlags2::NONE,
+                src_stage_mask: vk::PipelineStageFlags2::NONE,
+                dst_stage_mask: vk::PipelineStageFlags2::NONE,
+            })
+            .collect::<Vec<_>>();
+
+        context.execute_one_time_commands(|cmd| cmd.pipeline_image_barriers(&barriers))
+    }
+
+    pub fn surface_format(&self) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format: self.format,
+            color_space: self.color_space,
+        }
+    }
+
+    /// Returns each swapchain image with its index and view.
+    pub fn frames(&self) -> impl Iterator<Item = (usize, &Image, &ImageView)> {
+        self.images
+            .iter()
+            .zip(&self.views)
+            .enumerate()
+            .map(|(index, (image, view))| (index, image, view))
+    }
+
+    /// Returns the swapchain image at `index` with its view.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not the index of a swapchain image.
+    pub fn frame(&self, index: usize) -> (&Image, &ImageView) {
+        (&self.images[index], &self.views[index])
+    }
+
     pub fn acquire_next_image(&self, timeout: u64, semaphore: &Semaphore) -> Result<AcquiredImage> {
         let (index, is_suboptimal) = unsafe {
             self.inner.acquire_next_image(
@@ -273,8 +326,9 @@ impl Swapchain {
                 timeout,
                 semaphore.inner,
                 vk::Fence::null(),
-            )?
-        };
+            )
+        }
+        .or_device_lost("swapchain image acquisition")?;
 
         Ok(AcquiredImage {
             index,
@@ -297,7 +351,8 @@ impl Swapchain {
             .swapchains(&swapchains)
             .image_indices(&images_indices);
 
-        let result = unsafe { self.inner.queue_present(queue.inner, &present_info)? };
+        let result = unsafe { self.inner.queue_present(queue.inner, &present_info) }
+            .or_device_lost("presentation")?;
 
         Ok(result)
     }
@@ -311,6 +366,41 @@ impl Swapchain {
     }
 }
 
+/// Whether writes to images of `format` encode linear values to sRGB.
+pub fn is_srgb_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8G8B8A8_SRGB | vk::Format::B8G8R8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32
+    )
+}
+
+fn require_surface(context: &Context) -> Result<&Surface> {
+    context
+        .surface
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Cannot create a swapchain with a headless context"))
+}
+
+fn choose_present_mode(context: &Context, preferred: vk::PresentModeKHR) -> vk::PresentModeKHR {
+    // FIFO is the only mode required to be supported
+    if context
+        .physical_device
+        .supported_present_modes
+        .contains(&preferred)
+    {
+        preferred
+    } else {
+        log::warn!("Present mode {preferred:?} is not supported. Falling back to FIFO.");
+        vk::PresentModeKHR::FIFO
+    }
+}
+
+/// Images can also be used as transfer source when the surface supports it (for screenshots).
+fn choose_usage(capabilities: &vk::SurfaceCapabilitiesKHR) -> vk::ImageUsageFlags {
+    let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST;
+    usage | (capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC)
+}
+
 impl Drop for Swapchain {
     fn drop(&mut self) {
         self.destroy();
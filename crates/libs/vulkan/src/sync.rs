@@ -2,7 +2,7 @@ use anyhow::Result;
 use ash::vk;
 use std::sync::Arc;
 
-use crate::{device::Device, Context};
+use crate::{device::Device, error::DeviceLostExt, Context};
 
 pub struct Semaphore {
     device: Arc<Device>,
@@ -16,12 +16,62 @@ impl Semaphore {
 
         Ok(Self { device, inner })
     }
+
+    pub(crate) fn new_timeline(device: Arc<Device>, initial_value: u64) -> Result<Self> {
+        if !device.enabled_features.timeline_semaphore {
+            return Err(anyhow::anyhow!(
+                "The timelineSemaphore feature must be enabled to create timeline semaphores"
+            ));
+        }
+
+        let mut type_info = vk::SemaphoreTypeCreateInfo::default()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(initial_value);
+        let semaphore_info = vk::SemaphoreCreateInfo::default().push_next(&mut type_info);
+        let inner = unsafe { device.inner.create_semaphore(&semaphore_info, None)? };
+
+        Ok(Self { device, inner })
+    }
+
+    /// Current counter value of a timeline semaphore.
+    pub fn value(&self) -> Result<u64> {
+        let value = unsafe { self.device.inner.get_semaphore_counter_value(self.inner)? };
+
+        Ok(value)
+    }
+
+    /// Signals a timeline semaphore from the host.
+    pub fn signal(&self, value: u64) -> Result<()> {
+        let signal_info = vk::SemaphoreSignalInfo::default()
+            .semaphore(self.inner)
+            .value(value);
+        unsafe { self.device.inner.signal_semaphore(&signal_info)? };
+
+        Ok(())
+    }
+
+    /// Waits on the host until a timeline semaphore reaches `value`.
+    pub fn wait(&self, value: u64, timeout: Option<u64>) -> Result<()> {
+        let timeout = timeout.unwrap_or(u64::MAX);
+
+        let wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(std::slice::from_ref(&self.inner))
+            .values(std::slice::from_ref(&value));
+        unsafe { self.device.inner.wait_semaphores(&wait_info, timeout) }
+            .or_device_lost("semaphore wait")?;
+
+        Ok(())
+    }
 }
 
 impl Context {
     pub fn create_semaphore(&self) -> Result<Semaphore> {
         Semaphore::new(self.device.clone())
     }
+
+    pub fn create_timeline_semaphore(&self, initial_value: u64) -> Result<Semaphore> {
+        Semaphore::new_timeline(self.device.clone(), initial_value)
+    }
 }
 
 impl Drop for Semaphore {
@@ -53,8 +103,9 @@ impl Fence {
         unsafe {
             self.device
                 .inner
-                .wait_for_fences(&[self.inner], true, timeout)?
-        };
+                .wait_for_fences(&[self.inner], true, timeout)
+        }
+        .or_device_lost("fence wait")?;
 
         Ok(())
     }
@@ -0,0 +1,354 @@
+use std::mem::size_of_val;
+
+use anyhow::Result;
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+
+use crate::{
+    Buffer, CommandBuffer, CommandPool, Context, Fence, Image, Queue, QueueFamily, Semaphore,
+    SemaphoreSubmitInfo,
+};
+
+/// Offsets in the staging buffer are aligned for any texel size.
+const STAGING_ALIGNMENT: vk::DeviceSize = 16;
+
+/// Batches uploads of device local buffers and images through a reusable staging buffer.
+///
+/// Copies run on the dedicated transfer queue when the device has one, or on the graphics queue.
+/// Nothing is submitted until [`Uploader::flush`] is called, or the staging buffer is full.
+/// Returned buffers and uploaded images must not be used before the uploader is flushed.
+///
+/// With a dedicated transfer queue, ownership of the resources is released by the transfer
+/// queue and acquired by the graphics queue when flushing.
+pub struct Uploader<'a> {
+    context: &'a Context,
+    staging_buffer: Buffer,
+    staging_offset: vk::DeviceSize,
+    transfer_queue: &'a Queue,
+    transfer_queue_family: QueueFamily,
+    _transfer_command_pool: CommandPool,
+    transfer_command_buffer: CommandBuffer,
+    acquire: Option<Acquire>,
+    fence: Fence,
+    is_recording: bool,
+}
+
+/// Graphics queue side of the queue family ownership transfers.
+struct Acquire {
+    _command_pool: CommandPool,
+    command_buffer: CommandBuffer,
+    semaphore: Semaphore,
+    buffer_barriers: Vec<vk::BufferMemoryBarrier2<'static>>,
+    image_barriers: Vec<vk::ImageMemoryBarrier2<'static>>,
+}
+
+impl<'a> Uploader<'a> {
+    pub(crate) fn new(context: &'a Context, staging_size: vk::DeviceSize) -> Result<Self> {
+        let staging_buffer = context.create_buffer(
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+            staging_size,
+        )?;
+
+        let (transfer_queue, transfer_queue_family) = match context.transfer_queue.as_ref() {
+            Some(queue) => (queue, context.transfer_queue_family.unwrap()),
+            None => (&context.graphics_queue, context.graphics_queue_family),
+        };
+
+        let reset_flags = Some(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
+        let transfer_command_pool =
+            context.create_command_pool(transfer_queue_family, reset_flags)?;
+        let transfer_command_buffer =
+            transfer_command_pool.allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)?;
+
+        let acquire = if transfer_queue_family.index != context.graphics_queue_family.index {
+            let command_pool =
+                context.create_command_pool(context.graphics_queue_family, reset_flags)?;
+            let command_buffer =
+                command_pool.allocate_command_buffer(vk::CommandBufferLevel::PRIMARY)?;
+            Some(Acquire {
+                _command_pool: command_pool,
+                command_buffer,
+                semaphore: context.create_semaphore()?,
+                buffer_barriers: vec![],
+                image_barriers: vec![],
+            })
+        } else {
+            None
+        };
+
+        let fence = context.create_fence(None)?;
+
+        Ok(Self {
+            context,
+            staging_buffer,
+            staging_offset: 0,
+            transfer_queue,
+            transfer_queue_family,
+            _transfer_command_pool: transfer_command_pool,
+            transfer_command_buffer,
+            acquire,
+            fence,
+            is_recording: false,
+        })
+    }
+
+    /// Creates a device local buffer with `usage` and records the upload of `data` into it.
+    ///
+    /// Data larger than the staging buffer is uploaded in several chunks, flushing in between.
+    pub fn upload_buffer<T: Copy>(
+        &mut self,
+        usage: vk::BufferUsageFlags,
+        data: &[T],
+    ) -> Result<Buffer> {
+        let bytes =
+            unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, size_of_val(data)) };
+
+        let buffer = self.context.create_buffer(
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+            bytes.len() as _,
+        )?;
+
+        let mut dst_offset = 0;
+        for chunk in bytes.chunks(self.staging_buffer.size as _) {
+            let src_offset = self.stage(chunk)?;
+            self.transfer_command_buffer.copy_buffer_regions(
+                &self.staging_buffer,
+                &buffer,
+                &[vk::BufferCopy {
+                    src_offset,
+                    dst_offset,
+                    size: chunk.len() as _,
+                }],
+            );
+            dst_offset += chunk.len() as vk::DeviceSize;
+        }
+
+        if let Some(acquire) = self.acquire.as_mut() {
+            let barrier = vk::BufferMemoryBarrier2::default()
+                .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .src_queue_family_index(self.transfer_queue_family.index)
+                .dst_queue_family_index(self.context.graphics_queue_family.index)
+                .buffer(buffer.inner)
+                .offset(0)
+                .size(vk::WHOLE_SIZE);
+
+            let dependency_info = vk::DependencyInfo::default()
+                .buffer_memory_barriers(std::slice::from_ref(&barrier));
+            self.transfer_command_buffer
+                .pipeline_barrier(&dependency_info);
+
+            acquire.buffer_barriers.push(
+                barrier
+                    .src_stage_mask(vk::PipelineStageFlags2::NONE)
+                    .src_access_mask(vk::AccessFlags2::NONE)
+                    .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                    .dst_access_mask(vk::AccessFlags2::MEMORY_READ),
+            );
+        }
+
+        Ok(buffer)
+    }
+
+    /// Records the upload of `data` into the first mip level and layer of `image`,
+    /// which is then transitioned to `final_layout`.
+    ///
+    /// `data` must fit in the staging buffer. Combined depth/stencil images are rejected since
+    /// a buffer to image copy writes a single aspect.
+    pub fn upload_image(
+        &mut self,
+        image: &Image,
+        data: &[u8],
+        final_layout: vk::ImageLayout,
+    ) -> Result<()> {
+        if image.aspect_mask() == vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL {
+            return Err(anyhow::anyhow!(
+                "Cannot upload to an image of combined depth/stencil format {:?}",
+                image.format
+            ));
+        }
+
+        let src_offset = self.stage(data)?;
+
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: image.aspect_mask(),
+            base_mip_level: 0,
+            level_count: vk::REMAINING_MIP_LEVELS,
+            base_array_layer: 0,
+            layer_count: vk::REMAINING_ARRAY_LAYERS,
+        };
+
+        let to_transfer_dst = vk::ImageMemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::NONE)
+            .src_access_mask(vk::AccessFlags2::NONE)
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .dst_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .image(image.inner)
+            .subresource_range(subresource_range);
+        self.transfer_command_buffer.pipeline_barrier(
+            &vk::DependencyInfo::default()
+                .image_memory_barriers(std::slice::from_ref(&to_transfer_dst)),
+        );
+
+        self.transfer_command_buffer.copy_buffer_to_image_at(
+            &self.staging_buffer,
+            src_offset,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+
+        let to_final_layout = vk::ImageMemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(final_layout)
+            .image(image.inner)
+            .subresource_range(subresource_range);
+
+        match self.acquire.as_mut() {
+            Some(acquire) => {
+                // Layout transition happens between the release and the acquire
+                let release = to_final_layout
+                    .src_queue_family_index(self.transfer_queue_family.index)
+                    .dst_queue_family_index(self.context.graphics_queue_family.index);
+                self.transfer_command_buffer.pipeline_barrier(
+                    &vk::DependencyInfo::default()
+                        .image_memory_barriers(std::slice::from_ref(&release)),
+                );
+
+                acquire.image_barriers.push(
+                    release
+                        .src_stage_mask(vk::PipelineStageFlags2::NONE)
+                        .src_access_mask(vk::AccessFlags2::NONE)
+                        .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                        .dst_access_mask(vk::AccessFlags2::MEMORY_READ),
+                );
+            }
+            None => {
+                let barrier = to_final_layout
+                    .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                    .dst_access_mask(vk::AccessFlags2::MEMORY_READ);
+                self.transfer_command_buffer.pipeline_barrier(
+                    &vk::DependencyInfo::default()
+                        .image_memory_barriers(std::slice::from_ref(&barrier)),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Submits the recorded uploads and waits for them to complete.
+    pub fn flush(&mut self) -> Result<()> {
+        if !self.is_recording {
+            return Ok(());
+        }
+
+        self.transfer_command_buffer.end()?;
+
+        match self.acquire.as_mut() {
+            Some(acquire) => {
+                self.transfer_queue.submit_with_semaphores(
+                    &self.transfer_command_buffer,
+                    &[],
+                    &[SemaphoreSubmitInfo {
+                        semaphore: &acquire.semaphore,
+                        stage_mask: vk::PipelineStageFlags2::TRANSFER,
+                        value: 0,
+                    }],
+                    None,
+                )?;
+
+                acquire.command_buffer.reset()?;
+                acquire
+                    .command_buffer
+                    .begin(Some(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT))?;
+                acquire.command_buffer.pipeline_barrier(
+                    &vk::DependencyInfo::default()
+                        .buffer_memory_barriers(&acquire.buffer_barriers)
+                        .image_memory_barriers(&acquire.image_barriers),
+                );
+                acquire.command_buffer.end()?;
+
+                self.context.graphics_queue.submit_with_semaphores(
+                    &acquire.command_buffer,
+                    &[SemaphoreSubmitInfo {
+                        semaphore: &acquire.semaphore,
+                        stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+                        value: 0,
+                    }],
+                    &[],
+                    Some(&self.fence),
+                )?;
+
+                acquire.buffer_barriers.clear();
+                acquire.image_barriers.clear();
+            }
+            None => {
+                self.transfer_queue.submit(
+                    &self.transfer_command_buffer,
+                    None,
+                    None,
+                    &self.fence,
+                )?;
+            }
+        }
+
+        self.fence.wait(None)?;
+        self.fence.reset()?;
+
+        self.staging_offset = 0;
+        self.is_recording = false;
+
+        Ok(())
+    }
+
+    /// Copies `bytes` into the staging buffer and returns their offset.
+    /// Flushes first when they don't fit in the remaining space.
+    fn stage(&mut self, bytes: &[u8]) -> Result<vk::DeviceSize> {
+        let size = bytes.len() as vk::DeviceSize;
+        if size > self.staging_buffer.size {
+            return Err(anyhow::anyhow!(
+                "Cannot upload {size} bytes with a staging buffer of {} bytes",
+                self.staging_buffer.size
+            ));
+        }
+
+        let mut offset = self.staging_offset.next_multiple_of(STAGING_ALIGNMENT);
+        if offset + size > self.staging_buffer.size {
+            self.flush()?;
+            offset = 0;
+        }
+
+        if !self.is_recording {
+            self.transfer_command_buffer.reset()?;
+            self.transfer_command_buffer
+                .begin(Some(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT))?;
+            self.is_recording = true;
+        }
+
+        self.staging_buffer.copy_bytes_to_buffer_at(offset, bytes)?;
+        self.staging_offset = offset + size;
+
+        Ok(offset)
+    }
+}
+
+impl Context {
+    /// Creates an uploader whose staging buffer holds `staging_size` bytes.
+    pub fn create_uploader(&self, staging_size: vk::DeviceSize) -> Result<Uploader<'_>> {
+        Uploader::new(self, staging_size)
+    }
+}
+
+impl Drop for Uploader<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            log::error!("Failed to flush uploader on drop. Cause: {err}");
+        }
+    }
+}
@@ -55,10 +55,11 @@ pub fn create_gpu_only_buffer_from_data_with_alignment<T: Copy>(
     )?;
     staging_buffer.copy_data_to_buffer_with_alignment(data, alignment)?;
 
-    let buffer = context.create_buffer(
+    let buffer = context.create_buffer_aligned(
         usage | vk::BufferUsageFlags::TRANSFER_DST,
         MemoryLocation::GpuOnly,
         size,
+        alignment,
     )?;
 
     context.execute_one_time_commands(|cmd_buffer| {
@@ -68,7 +69,40 @@ pub fn create_gpu_only_buffer_from_data_with_alignment<T: Copy>(
     Ok(buffer)
 }
 
+/// Number of mip levels of a full mip chain for an image of the given size.
+pub fn compute_mip_levels(width: u32, height: u32) -> u32 {
+    // floor(log2(max(w, h))) + 1
+    u32::BITS - width.max(height).max(1).leading_zeros()
+}
+
 pub fn compute_aligned_size_of<T: Sized>(alignment: vk::DeviceSize) -> vk::DeviceSize {
     let elem_size = size_of::<T>() as vk::DeviceSize;
     (elem_size + (alignment - 1)) & !(alignment - 1)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::compute_mip_levels;
+
+    #[test]
+    fn mip_levels_of_single_texel_image() {
+        assert_eq!(compute_mip_levels(1, 1), 1);
+    }
+
+    #[test]
+    fn mip_levels_follow_largest_side_of_non_square_image() {
+        assert_eq!(compute_mip_levels(256, 16), 9);
+        assert_eq!(compute_mip_levels(16, 256), 9);
+    }
+
+    #[test]
+    fn mip_levels_of_non_power_of_two_image() {
+        // 300, 150, 75, 37, 18, 9, 4, 2, 1
+        assert_eq!(compute_mip_levels(300, 200), 9);
+    }
+
+    #[test]
+    fn mip_levels_of_empty_image() {
+        assert_eq!(compute_mip_levels(0, 0), 1);
+    }
+}